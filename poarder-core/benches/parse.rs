@@ -0,0 +1,51 @@
+//! Parser benchmarks on generated feeds of increasing size. Run with
+//! `cargo bench -p poarder-core`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use poarder_core::parse_rss;
+
+/// A feed with `items` items carrying the elements real feeds tend to have.
+fn feed(items: usize) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:podcast="https://podcastindex.org/namespace/1.0">
+<channel>
+<title>Benchmark Show</title>
+<itunes:author>poarder</itunes:author>
+<podcast:funding url="https://example.com/support">Support the show</podcast:funding>
+"#);
+    for i in 0..items {
+        xml += &format!(r#"<item>
+<title>Episode {i}: A reasonably long title &amp; some escaping</title>
+<guid isPermaLink="false">guid-{i}</guid>
+<pubDate>Mon, 02 Jan 2023 10:{:02}:00 +0000</pubDate>
+<description><![CDATA[<p>Show notes for episode {i}, with <a href="https://example.com/{i}">links</a> and a few sentences of text that make items as long as they are in the wild.</p>]]></description>
+<itunes:duration>01:02:03</itunes:duration>
+<itunes:summary>Summary of episode {i}</itunes:summary>
+<podcast:transcript url="https://example.com/{i}.vtt" type="text/vtt"/>
+<podcast:soundbite startTime="60" duration="30">Highlight</podcast:soundbite>
+<enclosure url="https://cdn.example.com/episodes/{i}.mp3" length="{}" type="audio/mpeg"/>
+</item>
+"#, i % 60, 50_000_000 + i);
+    }
+    xml += "</channel>\n</rss>\n";
+    xml
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_rss");
+    group.sample_size(20);
+    for items in [100, 1_000, 10_000] {
+        let xml = feed(items);
+        group.throughput(Throughput::Bytes(xml.len() as u64));
+        group.bench_with_input(BenchmarkId::new("keep_raw", items), &xml, |b, xml| {
+            b.iter(|| parse_rss(xml, true).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("low_memory", items), &xml, |b, xml| {
+            b.iter(|| parse_rss(xml, false).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,108 @@
+//! `auth-refresh-cmd`: fresh credentials for subscription feeds whose signed
+//! URLs or tokens expire. The command runs whenever a request is refused
+//! with 401 or 403, and the request is retried once with its output.
+
+use log::{debug, info};
+use reqwest::header::{HeaderName, HeaderValue};
+use std::error::Error;
+use std::process::Stdio;
+use std::sync::{Arc, RwLock};
+use tokio::process::Command;
+
+/// Headers and URL query parameters added to every request.
+#[derive(Debug, Default)]
+pub struct Credentials {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    params: Vec<(String, String)>,
+}
+
+impl Credentials {
+    /// Parse the command's output: `Name: value` lines are headers and
+    /// `?key=value` lines are query parameters.
+    fn parse(output: &str) -> Result<Credentials, Box<dyn Error + Send + Sync>> {
+        let mut credentials = Credentials::default();
+        for line in output.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(param) = line.strip_prefix('?') {
+                let (key, value) = param.split_once('=').unwrap_or((param, ""));
+                credentials.params.push((key.to_string(), value.to_string()));
+                continue
+            }
+            let (name, value) = line.split_once(':')
+                .ok_or_else(|| format!("auth-refresh-cmd printed a line that is neither a header nor ?key=value: {}", line))?;
+            credentials.headers.push((HeaderName::from_bytes(name.trim().as_bytes())?, HeaderValue::from_str(value.trim())?));
+        }
+        Ok(credentials)
+    }
+
+    /// Add the headers to `request` and set the parameters in its URL,
+    /// replacing any expired values already there.
+    pub fn apply(&self, request: &mut reqwest::Request) {
+        for (name, value) in &self.headers {
+            request.headers_mut().insert(name.clone(), value.clone());
+        }
+        if self.params.is_empty() {
+            return
+        }
+
+        let kept: Vec<(String, String)> = request.url().query_pairs()
+            .filter(|(key, _)| !self.params.iter().any(|(param, _)| param == key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        request.url_mut().query_pairs_mut()
+            .clear()
+            .extend_pairs(kept)
+            .extend_pairs(&self.params);
+    }
+}
+
+#[derive(Debug)]
+pub struct AuthRefresh {
+    command: Vec<String>,
+    /// Current credentials with a generation count, so a request refused
+    /// with stale credentials doesn't run the command again.
+    current: RwLock<(u64, Arc<Credentials>)>,
+    refreshing: tokio::sync::Mutex<()>,
+}
+
+impl AuthRefresh {
+    pub fn new(command: &[String]) -> AuthRefresh {
+        AuthRefresh {
+            command: command.to_vec(),
+            current: RwLock::new((0, Arc::new(Credentials::default()))),
+            refreshing: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn current(&self) -> (u64, Arc<Credentials>) {
+        let current = self.current.read().unwrap();
+        (current.0, current.1.clone())
+    }
+
+    /// Run the command, unless the credentials have already been replaced
+    /// since generation `seen`.
+    pub async fn refresh(&self, seen: u64) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let _refreshing = self.refreshing.lock().await;
+        if self.current().0 != seen {
+            return Ok(())
+        }
+
+        let (program, args) = self.command.split_first().ok_or("auth-refresh-cmd is empty")?;
+        info!("Refreshing credentials with {}", program);
+        debug!("Running {} {:?}", program, args);
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .await
+            .map_err(|e| format!("could not run auth-refresh-cmd ({}): {}", program, e))?;
+        if !output.status.success() {
+            return Err(format!("auth-refresh-cmd failed with {}", output.status).into())
+        }
+
+        let credentials = Credentials::parse(&String::from_utf8_lossy(&output.stdout))?;
+        let mut current = self.current.write().unwrap();
+        *current = (seen + 1, Arc::new(credentials));
+        Ok(())
+    }
+}
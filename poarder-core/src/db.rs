@@ -0,0 +1,139 @@
+//! Record of every archived episode, by GUID, in `state/episodes.db`
+//! (SQLite). Whether an episode is archived is decided from here rather
+//! than from its file name, so renamed episodes aren't downloaded again.
+//! Archives from before the database are filled in as their episodes are
+//! next seen. Every use of the database holds the archive's state lock (see
+//! [`state::lock`]), so instances sharing the archive over NFS, where
+//! SQLite's own locks can't be relied on, take turns.
+
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::state;
+use crate::Episode;
+
+/// An archived episode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub guid: String,
+    pub enclosure_url: String,
+    /// File name in the episode directories.
+    pub file: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+    /// When it was downloaded, RFC 3339 UTC.
+    pub downloaded: String,
+}
+
+#[derive(Debug)]
+pub struct EpisodeDb {
+    conn: Mutex<Connection>,
+    state_dir: PathBuf,
+}
+
+/// The key an episode is recorded under: its GUID, or its enclosure URL
+/// for feeds without GUIDs.
+pub fn key(episode: &Episode) -> &str {
+    episode.guid.as_deref().unwrap_or(&episode.url)
+}
+
+impl EpisodeDb {
+    pub fn open(state_dir: &Path) -> rusqlite::Result<EpisodeDb> {
+        let _lock = state::lock(state_dir).map_err(lock_error)?;
+        let conn = Connection::open(state_dir.join("episodes.db"))?;
+        // The default rollback journal, unlike WAL, works over NFS.
+        conn.busy_timeout(std::time::Duration::from_secs(30))?;
+        conn.execute_batch("
+            CREATE TABLE IF NOT EXISTS episodes (
+                guid TEXT PRIMARY KEY,
+                enclosure_url TEXT NOT NULL,
+                file TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                sha256 TEXT,
+                downloaded TEXT NOT NULL
+            );
+        ")?;
+        Ok(EpisodeDb { conn: Mutex::new(conn), state_dir: state_dir.to_path_buf() })
+    }
+
+    /// Run `f` on the database, holding the archive's state lock.
+    fn locked<T>(&self, f: impl FnOnce(&Connection) -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+        let conn = self.conn.lock().unwrap();
+        let _lock = state::lock(&self.state_dir).map_err(lock_error)?;
+        f(&conn)
+    }
+
+    pub fn get(&self, episode: &Episode) -> rusqlite::Result<Option<Record>> {
+        self.locked(|conn| conn.query_row(
+            "SELECT guid, enclosure_url, file, size, sha256, downloaded FROM episodes WHERE guid = ?1",
+            params![key(episode)],
+            |row| Ok(Record {
+                guid: row.get(0)?,
+                enclosure_url: row.get(1)?,
+                file: row.get(2)?,
+                size: row.get(3)?,
+                sha256: row.get(4)?,
+                downloaded: row.get(5)?,
+            }),
+        ).optional())
+    }
+
+    /// File names of every archived episode.
+    pub fn files(&self) -> rusqlite::Result<Vec<String>> {
+        self.locked(|conn| {
+            let mut statement = conn.prepare("SELECT file FROM episodes")?;
+            let files = statement.query_map([], |row| row.get(0))?.collect();
+            files
+        })
+    }
+
+    /// Every archived episode, by file name.
+    pub fn records(&self) -> rusqlite::Result<Vec<Record>> {
+        self.locked(|conn| {
+            let mut statement = conn.prepare("SELECT guid, enclosure_url, file, size, sha256, downloaded FROM episodes ORDER BY file")?;
+            let records = statement.query_map([], |row| Ok(Record {
+                guid: row.get(0)?,
+                enclosure_url: row.get(1)?,
+                file: row.get(2)?,
+                size: row.get(3)?,
+                sha256: row.get(4)?,
+                downloaded: row.get(5)?,
+            }))?.collect();
+            records
+        })
+    }
+
+    /// Note that the episode archived in `from` is now in `to`.
+    pub fn rename(&self, from: &str, to: &str) -> rusqlite::Result<()> {
+        self.locked(|conn| conn.execute("UPDATE episodes SET file = ?2 WHERE file = ?1", params![from, to]))?;
+        Ok(())
+    }
+
+    /// Record the episode under `key` with its enclosure at `enclosure_url`,
+    /// archived as `file` before there was a database, unless it is
+    /// recorded already. Returns whether it was.
+    pub fn adopt(&self, key: &str, enclosure_url: &str, file: &str, size: u64, sha256: Option<&str>) -> rusqlite::Result<bool> {
+        let added = self.locked(|conn| conn.execute(
+            "INSERT OR IGNORE INTO episodes (guid, enclosure_url, file, size, sha256, downloaded) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![key, enclosure_url, file, size, sha256, Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)],
+        ))?;
+        Ok(added > 0)
+    }
+
+    /// Record `episode` as archived in `file`, replacing any earlier record.
+    pub fn record(&self, episode: &Episode, file: &str, size: u64, sha256: Option<&str>) -> rusqlite::Result<()> {
+        self.locked(|conn| conn.execute(
+            "INSERT OR REPLACE INTO episodes (guid, enclosure_url, file, size, sha256, downloaded) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![key(episode), episode.url, file, size, sha256, Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)],
+        ))?;
+        Ok(())
+    }
+}
+
+/// `e`, from taking the state lock, as the error SQLite gives for a locked
+/// database.
+fn lock_error(e: std::io::Error) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY), Some(format!("could not lock the archive state: {}", e)))
+}
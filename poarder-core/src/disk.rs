@@ -0,0 +1,284 @@
+//! Low-level helpers for getting episodes onto disk efficiently.
+
+use log::{debug, error, info};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Free space below which an output directory is treated as full.
+const MIN_FREE_BYTES: u64 = 1 << 20;
+/// How often a paused writer checks whether the disk is usable again.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Reserve `len` bytes for `file` up front, so large episodes land in
+/// contiguous extents and a full disk is reported before any data is
+/// written. The file's size is left alone, so an interrupted download's
+/// `.part` file is only as long as what was received and can be resumed.
+/// Filesystems without `fallocate` support (and non-Linux systems) silently
+/// skip preallocation.
+#[cfg(target_os = "linux")]
+pub fn preallocate(file: &File, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let len = match libc::off_t::try_from(len) {
+        Ok(len) if len > 0 => len,
+        _ => return Ok(())
+    };
+
+    if unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len) } == 0 {
+        return Ok(())
+    }
+
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => {
+            debug!("fallocate not supported here; writing without preallocation");
+            Ok(())
+        },
+        _ => Err(e)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_file: &File, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// Move a finished `.part` file to its final name. When `from` is on another
+/// filesystem (e.g. a `--tmp-dir` on local scratch space), the data is first
+/// copied next to `to` and renamed from there, so the archive never holds a
+/// half-copied episode under its final name.
+pub fn move_into_place(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            debug!("{} is on another filesystem; copying it into place", from.display());
+            let staging = to.with_extension("part");
+            let copied = fs::copy(from, &staging).and_then(|_| fs::rename(&staging, to));
+            if copied.is_err() {
+                let _ = fs::remove_file(&staging);
+            }
+            copied?;
+            fs::remove_file(from)
+        },
+        result => result
+    }
+}
+
+/// Delete the `.part` files in `dir`, and the directories a name template
+/// makes in it, that nothing has written to for `max_age`, returning how
+/// many went. Downloads in progress keep theirs fresh.
+pub fn remove_stale_parts(dir: &Path, max_age: Duration) -> io::Result<usize> {
+    let mut removed = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            match remove_stale_parts(&path, max_age) {
+                Ok(count) => removed += count,
+                Err(e) => error!("Failed to clean up {}. Error: {}", path.display(), e)
+            }
+            continue
+        }
+        if path.extension().is_none_or(|extension| extension != "part") {
+            continue
+        }
+        let age = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(modified) => modified.elapsed().unwrap_or_default(),
+            Err(_) => continue
+        };
+        if age < max_age {
+            continue
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                info!("Removed stale partial download {}", path.display());
+                removed += 1;
+            },
+            Err(e) => error!("Failed to remove {}. Error: {}", path.display(), e)
+        }
+    }
+    Ok(removed)
+}
+
+/// Whether `e` means the whole filesystem is unusable (full, over quota or
+/// read-only) rather than something being wrong with one file.
+pub fn is_storage_unavailable(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded | io::ErrorKind::ReadOnlyFilesystem)
+}
+
+fn advice(e: &io::Error) -> &'static str {
+    match e.kind() {
+        io::ErrorKind::ReadOnlyFilesystem => "remount it read-write or choose another output directory",
+        io::ErrorKind::QuotaExceeded => "raise the disk quota or free some space",
+        _ => "free some space or move the archive to a larger disk",
+    }
+}
+
+/// Check that `dir` can take at least `needed` more bytes: there is free
+/// space and a file can actually be created there.
+pub fn probe(dir: &Path, needed: u64) -> io::Result<()> {
+    if fs2::available_space(dir)? < needed.max(MIN_FREE_BYTES) {
+        return Err(io::Error::new(io::ErrorKind::StorageFull, "not enough free space"))
+    }
+
+    let probe_path = dir.join(".poarder-probe");
+    File::create(&probe_path)?;
+    fs::remove_file(probe_path)
+}
+
+/// Tracks output directories that have run out of space or gone read-only,
+/// so the pipeline reports each once and stops writing there, rather than
+/// failing every remaining episode with the same I/O error.
+#[derive(Default)]
+pub struct StorageGate {
+    blocked: Mutex<HashSet<PathBuf>>,
+}
+
+impl StorageGate {
+    /// Mark `dir` unusable because of `e`. Returns true the first time, after
+    /// logging what happened and how to fix it.
+    pub fn block(&self, dir: &Path, e: &io::Error) -> bool {
+        let newly_blocked = self.blocked.lock().unwrap().insert(dir.to_path_buf());
+        if newly_blocked {
+            error!("Cannot write to {}: {}. To fix: {}.", dir.display(), e, advice(e));
+        }
+        newly_blocked
+    }
+
+    pub fn is_blocked(&self, dir: &Path) -> bool {
+        self.blocked.lock().unwrap().contains(dir)
+    }
+
+    pub fn all_blocked<'a>(&self, mut dirs: impl Iterator<Item = &'a PathBuf>) -> bool {
+        let blocked = self.blocked.lock().unwrap();
+        dirs.all(|dir| blocked.contains(dir))
+    }
+
+    /// Wait until `dir` has room for `needed` bytes again, then unblock it.
+    pub async fn wait_until_writable(&self, dir: &Path, needed: u64) {
+        info!("Pausing writes to {} until it is usable again", dir.display());
+        loop {
+            tokio::time::sleep(RECHECK_INTERVAL).await;
+            match probe(dir, needed) {
+                Ok(()) => break,
+                Err(e) => debug!("{} still unusable: {}", dir.display(), e)
+            }
+        }
+        info!("{} is writable again; resuming", dir.display());
+        self.unblock(dir);
+    }
+
+    pub fn unblock(&self, dir: &Path) {
+        self.blocked.lock().unwrap().remove(dir);
+    }
+}
+
+/// Mode and ownership applied to the files and directories poarder creates
+/// in an archive, for when it runs as a service user but the files are read
+/// by someone else (e.g. a media server).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Permissions {
+    pub file_mode: Option<u32>,
+    pub dir_mode: Option<u32>,
+    pub owner: Option<Owner>,
+}
+
+impl Permissions {
+    pub fn apply_file(&self, path: &Path) -> io::Result<()> {
+        self.apply(path, self.file_mode)
+    }
+
+    pub fn apply_dir(&self, path: &Path) -> io::Result<()> {
+        self.apply(path, self.dir_mode)
+    }
+
+    #[cfg(unix)]
+    fn apply(&self, path: &Path, mode: Option<u32>) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+        }
+        if let Some(owner) = &self.owner {
+            std::os::unix::fs::chown(path, owner.uid, owner.gid)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply(&self, _path: &Path, _mode: Option<u32>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parse an octal permission mode such as `0644` or `755`.
+pub fn parse_mode(text: &str) -> Result<u32, String> {
+    match u32::from_str_radix(text.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        Ok(_) => Err(format!("{} is not a valid permission mode", text)),
+        Err(e) => Err(format!("invalid mode {:?}: {}", text, e)),
+    }
+}
+
+/// Owner given as `user`, `user:group` or `:group`, by name or numeric ID.
+#[derive(Debug, Clone, Copy)]
+pub struct Owner {
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl Owner {
+    pub fn parse(spec: &str) -> Result<Owner, String> {
+        let (user, group) = match spec.split_once(':') {
+            Some((user, group)) => (user, group),
+            None => (spec, "")
+        };
+
+        let uid = match user {
+            "" => None,
+            user => Some(user.parse().or_else(|_| lookup_user(user))?)
+        };
+        let gid = match group {
+            "" => None,
+            group => Some(group.parse().or_else(|_| lookup_group(group))?)
+        };
+
+        match (uid, gid) {
+            (None, None) => Err("expected user, user:group or :group".to_string()),
+            _ => Ok(Owner { uid, gid })
+        }
+    }
+}
+
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Result<u32, String> {
+    let c_name = std::ffi::CString::new(name).map_err(|e| e.to_string())?;
+    let entry = unsafe { libc::getpwnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("no such user: {}", name))
+    }
+    Ok(unsafe { (*entry).pw_uid })
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Result<u32, String> {
+    let c_name = std::ffi::CString::new(name).map_err(|e| e.to_string())?;
+    let entry = unsafe { libc::getgrnam(c_name.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("no such group: {}", name))
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(not(unix))]
+fn lookup_user(name: &str) -> Result<u32, String> {
+    Err(format!("cannot look up user {} on this platform", name))
+}
+
+#[cfg(not(unix))]
+fn lookup_group(name: &str) -> Result<u32, String> {
+    Err(format!("cannot look up group {} on this platform", name))
+}
@@ -0,0 +1,243 @@
+//! Estimated time to finish a run's downloads, from the enclosure lengths
+//! of the episodes still to download and the throughput seen so far.
+
+use chrono::Local;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Episode;
+
+/// Progress of one feed's downloads, or of a whole run (the parent of each
+/// feed's).
+pub struct Eta {
+    name: String,
+    parent: Option<Arc<Eta>>,
+    children: Mutex<Vec<Arc<Eta>>>,
+    /// Bytes still expected: the sum of `expected`, and of the children's.
+    remaining: AtomicI64,
+    received: AtomicU64,
+    /// What is still to come of each unfinished episode, by enclosure URL.
+    expected: Mutex<HashMap<String, Expected>>,
+    /// Unfinished episodes whose size isn't known at all.
+    unknown: AtomicU64,
+    /// Episodes downloaded this run and their total size, for guessing
+    /// at the unknown ones.
+    downloaded: AtomicU64,
+    downloaded_bytes: AtomicU64,
+    first_byte: Mutex<Option<Instant>>,
+}
+
+struct Expected {
+    planned: u64,
+    /// Planned size less what has arrived; below zero when an episode turns
+    /// out bigger than planned.
+    bytes: i64,
+    /// Planned at 0 for want of any length to go on.
+    unknown: bool,
+}
+
+impl fmt::Debug for Eta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Eta").field("name", &self.name).field("remaining", &self.remaining).finish()
+    }
+}
+
+/// Where an [`Eta`] stands.
+pub struct Estimate {
+    pub episodes: usize,
+    pub unknown: u64,
+    pub remaining_bytes: u64,
+    /// Bytes per second since the first byte arrived.
+    pub rate: f64,
+    pub remaining: Option<Duration>,
+}
+
+impl Eta {
+    pub fn new(name: &str) -> Arc<Eta> {
+        Arc::new(Eta::with_parent(name, None))
+    }
+
+    fn with_parent(name: &str, parent: Option<Arc<Eta>>) -> Eta {
+        Eta {
+            name: name.to_string(),
+            parent,
+            children: Mutex::new(Vec::new()),
+            remaining: AtomicI64::new(0),
+            received: AtomicU64::new(0),
+            expected: Mutex::new(HashMap::new()),
+            unknown: AtomicU64::new(0),
+            downloaded: AtomicU64::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+            first_byte: Mutex::new(None),
+        }
+    }
+
+    /// A tracker for part of this one's work (a feed of the run).
+    pub fn child(self: &Arc<Self>, name: &str) -> Arc<Eta> {
+        let child = Arc::new(Eta::with_parent(name, Some(self.clone())));
+        self.children.lock().unwrap().push(child.clone());
+        child
+    }
+
+    /// Count `episodes` as still to download. Those without an enclosure
+    /// length are guessed at the others' mean.
+    pub fn plan<'a>(&self, episodes: impl Iterator<Item = &'a Episode>) {
+        let episodes: Vec<&Episode> = episodes.collect();
+        let known: Vec<u64> = episodes.iter().filter_map(|episode| episode.enclosure_length).filter(|len| *len > 0).collect();
+        let mean = match known.len() {
+            0 => None,
+            n => Some(known.iter().sum::<u64>() / n as u64)
+        };
+        let mut expected = self.expected.lock().unwrap();
+        for episode in episodes {
+            if expected.contains_key(&episode.url) {
+                continue
+            }
+            let size = match episode.enclosure_length.filter(|len| *len > 0).or(mean) {
+                Some(size) => size,
+                None => {
+                    self.add_unknown(1);
+                    0
+                }
+            };
+            expected.insert(episode.url.clone(), Expected { planned: size, bytes: size as i64, unknown: size == 0 });
+            self.add_remaining(size as i64);
+        }
+    }
+
+    /// `bytes` of the episode with enclosure `url` arrived.
+    pub fn received(&self, url: &str, bytes: u64) {
+        if let Some(expected) = self.expected.lock().unwrap().get_mut(url) {
+            expected.bytes -= bytes as i64;
+        }
+        self.add_remaining(-(bytes as i64));
+        self.count(bytes);
+    }
+
+    /// Throughput, for this tracker and its parents.
+    fn count(&self, bytes: u64) {
+        self.first_byte.lock().unwrap().get_or_insert_with(Instant::now);
+        self.received.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(parent) = &self.parent {
+            parent.count(bytes);
+        }
+    }
+
+    /// The episode with enclosure `url` is done with, one way or another.
+    pub fn finished(&self, url: &str) {
+        let Some(expected) = self.expected.lock().unwrap().remove(url) else {
+            return
+        };
+        if expected.unknown {
+            self.add_unknown(-1);
+        }
+        self.add_remaining(-expected.bytes);
+        let received = expected.planned as i64 - expected.bytes;
+        if received > 0 {
+            self.downloaded.fetch_add(1, Ordering::Relaxed);
+            self.downloaded_bytes.fetch_add(received as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn add_remaining(&self, bytes: i64) {
+        self.remaining.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(parent) = &self.parent {
+            parent.add_remaining(bytes);
+        }
+    }
+
+    fn add_unknown(&self, episodes: i64) {
+        match episodes {
+            n if n < 0 => self.unknown.fetch_sub(n.unsigned_abs(), Ordering::Relaxed),
+            n => self.unknown.fetch_add(n as u64, Ordering::Relaxed)
+        };
+        if let Some(parent) = &self.parent {
+            parent.add_unknown(episodes);
+        }
+    }
+
+    pub fn estimate(&self) -> Estimate {
+        let episodes = match self.children.lock().unwrap().as_slice() {
+            [] => self.expected.lock().unwrap().len(),
+            children => children.iter().map(|child| child.expected.lock().unwrap().len()).sum()
+        };
+        let remaining_bytes = self.remaining_bytes();
+        let elapsed = self.first_byte.lock().unwrap().map(|first| first.elapsed().as_secs_f64()).unwrap_or_default();
+        let rate = match elapsed {
+            elapsed if elapsed >= 1.0 => self.received.load(Ordering::Relaxed) as f64 / elapsed,
+            _ => 0.0
+        };
+        let remaining = (rate > 0.0).then(|| Duration::from_secs_f64(remaining_bytes as f64 / rate));
+        Estimate { episodes, unknown: self.unknown.load(Ordering::Relaxed), remaining_bytes, rate, remaining }
+    }
+
+    /// Bytes left, with episodes of unknown size guessed at the mean of those
+    /// downloaded so far.
+    fn remaining_bytes(&self) -> u64 {
+        let children = self.children.lock().unwrap();
+        if !children.is_empty() {
+            return children.iter().map(|child| child.remaining_bytes()).sum()
+        }
+        let mut remaining = self.remaining.load(Ordering::Relaxed).max(0) as u64;
+        if let Some(mean) = self.downloaded_bytes.load(Ordering::Relaxed).checked_div(self.downloaded.load(Ordering::Relaxed)) {
+            remaining += self.unknown.load(Ordering::Relaxed) * mean;
+        }
+        remaining
+    }
+
+    /// One line on where this stands, e.g. `Show: 34 episodes (1.2 GB) left
+    /// at 3.4 MB/s, done in about 2h 10m (Thu 23:40)`; `None` once there is
+    /// nothing left.
+    pub fn report(&self) -> Option<String> {
+        let estimate = self.estimate();
+        if estimate.episodes == 0 {
+            return None
+        }
+        let plural = if estimate.episodes == 1 { "" } else { "s" };
+        let mut line = format!("{}: {} episode{} ({}) left", self.name, estimate.episodes, plural, bytes(estimate.remaining_bytes));
+        if estimate.unknown > 0 {
+            line += &format!(", {} of them of unknown size", estimate.unknown);
+        }
+        match estimate.remaining {
+            Some(remaining) => {
+                let done_at = Local::now() + chrono::Duration::from_std(remaining).unwrap_or_default();
+                line += &format!(" at {}/s, done in about {} ({})", bytes(estimate.rate as u64), duration(remaining), done_at.format("%a %H:%M"));
+            },
+            None => line += ", no throughput measured yet"
+        }
+        Some(line)
+    }
+
+    /// [`report`](Eta::report) for this and each unfinished child.
+    pub fn reports(&self) -> Vec<String> {
+        let mut reports: Vec<String> = self.report().into_iter().collect();
+        let children = self.children.lock().unwrap();
+        if children.len() > 1 {
+            reports.extend(children.iter().filter_map(|child| child.report()));
+        }
+        reports
+    }
+}
+
+/// `n` bytes for people, e.g. `1.2 GB`.
+pub fn bytes(n: u64) -> String {
+    match n {
+        n if n >= 1 << 30 => format!("{:.1} GB", n as f64 / (1u64 << 30) as f64),
+        n if n >= 1 << 20 => format!("{:.1} MB", n as f64 / (1u64 << 20) as f64),
+        n if n >= 1 << 10 => format!("{:.1} KB", n as f64 / (1u64 << 10) as f64),
+        n => format!("{} B", n)
+    }
+}
+
+fn duration(d: Duration) -> String {
+    let minutes = d.as_secs().div_ceil(60);
+    match (minutes / (24 * 60), minutes / 60 % 24, minutes % 60) {
+        (0, 0, m) if m <= 1 => "a minute".to_string(),
+        (0, 0, m) => format!("{}m", m),
+        (0, h, m) => format!("{}h {}m", h, m),
+        (d, h, _) => format!("{}d {}h", d, h)
+    }
+}
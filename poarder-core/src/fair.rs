@@ -0,0 +1,109 @@
+//! Download slots shared by the feeds of one run and handed out round-robin,
+//! so a feed with hundreds of episodes queued (a backfill, say) can't keep a
+//! daily show waiting until it is done.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+/// A fixed number of concurrent downloads, shared fairly between feeds. A
+/// feed with weight `n` gets up to `n` slots in a row when it's its turn.
+pub struct FairShare {
+    weights: HashMap<String, u32>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    free: usize,
+    /// Feeds with downloads waiting, in turn order.
+    turns: VecDeque<String>,
+    waiting: HashMap<String, Queue>,
+}
+
+#[derive(Default)]
+struct Queue {
+    waiters: VecDeque<oneshot::Sender<FairPermit>>,
+    /// Slots handed out in the feed's current turn.
+    granted: u32,
+}
+
+impl fmt::Debug for FairShare {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock().unwrap();
+        f.debug_struct("FairShare").field("free", &state.free).field("turns", &state.turns).finish()
+    }
+}
+
+/// A download slot; the next feed in turn gets it when this is dropped.
+pub struct FairPermit {
+    share: Option<Arc<FairShare>>,
+}
+
+impl FairShare {
+    /// `slots` downloads at once; feeds missing from `weights` weigh 1.
+    pub fn new(slots: usize, weights: HashMap<String, u32>) -> Arc<FairShare> {
+        Arc::new(FairShare {
+            weights,
+            state: Mutex::new(State { free: slots.max(1), ..State::default() }),
+        })
+    }
+
+    /// Wait for a slot for a download from `feed`.
+    pub async fn acquire(self: &Arc<Self>, feed: &str) -> FairPermit {
+        let granted = {
+            let mut state = self.state.lock().unwrap();
+            if state.free > 0 {
+                state.free -= 1;
+                return FairPermit { share: Some(self.clone()) }
+            }
+            let (tx, rx) = oneshot::channel();
+            let queue = state.waiting.entry(feed.to_string()).or_default();
+            queue.waiters.push_back(tx);
+            if queue.waiters.len() == 1 {
+                state.turns.push_back(feed.to_string());
+            }
+            rx
+        };
+        // The sender is only dropped along with the whole share.
+        granted.await.unwrap_or(FairPermit { share: None })
+    }
+
+    /// Pass a freed slot to the feed whose turn it is.
+    fn release(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let Some(feed) = state.turns.front().cloned() else {
+                state.free += 1;
+                return
+            };
+            let weight = self.weights.get(&feed).copied().unwrap_or(1).max(1);
+            let queue = state.waiting.get_mut(&feed).unwrap();
+            let waiter = queue.waiters.pop_front().unwrap();
+            queue.granted += 1;
+            if queue.waiters.is_empty() {
+                state.waiting.remove(&feed);
+                state.turns.pop_front();
+            }
+            else if queue.granted >= weight {
+                queue.granted = 0;
+                state.turns.rotate_left(1);
+            }
+
+            match waiter.send(FairPermit { share: Some(self.clone()) }) {
+                Ok(()) => return,
+                // Gave up waiting; the slot goes to the next one.
+                Err(mut permit) => permit.share = None
+            }
+        }
+    }
+}
+
+impl Drop for FairPermit {
+    fn drop(&mut self) {
+        if let Some(share) = self.share.take() {
+            share.release();
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Recording and replaying HTTP responses, for reproducible bug reports and
+//! offline development against weird feeds.
+//!
+//! Each response is stored as `<key>.json` (URL, status, headers) plus
+//! `<key>.body`, where `<key>` is the SHA-256 of the request URL (prefixed
+//! with the method for anything but `GET`).
+
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::auth::AuthRefresh;
+use crate::hosts;
+
+#[derive(Debug, Clone)]
+pub enum HttpMode {
+    Live,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedResponse {
+    url: String,
+    status: u16,
+    headers: Vec<(String, String)>,
+}
+
+/// HTTP client that can transparently record or replay responses.
+#[derive(Debug, Clone)]
+pub struct Http {
+    client: reqwest::Client,
+    mode: HttpMode,
+    /// Credentials, and the origin (scheme, host and port) they are for.
+    auth: Option<(Arc<AuthRefresh>, reqwest::Url)>,
+}
+
+impl Http {
+    pub fn new(client: reqwest::Client, mode: HttpMode) -> Http {
+        if let HttpMode::Record(dir) = &mode {
+            if let Err(e) = fs::create_dir_all(dir) {
+                error!("Failed to create {}. Error: {}", dir.display(), e);
+            }
+        }
+        Http { client, mode, auth: None }
+    }
+
+    /// Refresh credentials with `auth-refresh-cmd` when a request to the
+    /// origin of `feed_url` is refused. Requests to other hosts, such as
+    /// CDNs or artwork hosts, never get them, nor do replayed responses.
+    pub fn with_auth(mut self, command: &[String], feed_url: &str) -> Http {
+        if command.is_empty() {
+            return self
+        }
+        match reqwest::Url::parse(feed_url) {
+            Ok(url) => self.auth = Some((Arc::new(AuthRefresh::new(command)), url)),
+            Err(e) => warn!("Not using auth-refresh-cmd for {}, which is not a valid URL. Error: {}", feed_url, e)
+        }
+        self
+    }
+
+    pub fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn head(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.head(url)
+    }
+
+    pub async fn send(&self, request: reqwest::RequestBuilder) -> reqwest::Result<reqwest::Response> {
+        let request = request.build()?;
+        let auth = match (&self.auth, &self.mode) {
+            (Some((auth, feed_url)), HttpMode::Live | HttpMode::Record(_)) if request.url().origin() == feed_url.origin() => auth,
+            _ => return self.execute(request).await
+        };
+
+        let retry = request.try_clone();
+        let (generation, credentials) = auth.current();
+        let mut request = request;
+        credentials.apply(&mut request);
+        let resp = self.execute(request).await?;
+        let status = resp.status();
+        if status != reqwest::StatusCode::UNAUTHORIZED && status != reqwest::StatusCode::FORBIDDEN {
+            return Ok(resp)
+        }
+        let mut retry = match retry {
+            Some(retry) => retry,
+            None => return Ok(resp)
+        };
+
+        warn!("{} answered {}; refreshing credentials", hosts::host_of(resp.url().as_str()), status);
+        if let Err(e) = auth.refresh(generation).await {
+            error!("Could not refresh credentials. Error: {}", e);
+            return Ok(resp)
+        }
+        auth.current().1.apply(&mut retry);
+        self.execute(retry).await
+    }
+
+    async fn execute(&self, request: reqwest::Request) -> reqwest::Result<reqwest::Response> {
+        match &self.mode {
+            HttpMode::Live => self.client.execute(request).await,
+            HttpMode::Record(dir) => {
+                let method = request.method().clone();
+                let url = request.url().to_string();
+                let resp = self.client.execute(request).await?;
+                let status = resp.status().as_u16();
+                let headers = header_pairs(resp.headers());
+                let body = resp.bytes().await?;
+
+                let recorded = RecordedResponse { url, status, headers };
+                if let Err(e) = save(dir, &method, &recorded, &body) {
+                    error!("Failed to record response for {}. Error: {}", recorded.url, e);
+                }
+                Ok(rebuild(recorded, body.to_vec()))
+            },
+            HttpMode::Replay(dir) => {
+                let url = request.url().to_string();
+                match load(dir, request.method(), &url) {
+                    Ok((recorded, body)) => {
+                        debug!("Replaying {} from {}", url, dir.display());
+                        Ok(rebuild(recorded, body))
+                    },
+                    Err(e) => {
+                        error!("No recorded response for {} in {}. Error: {}", url, dir.display(), e);
+                        Ok(rebuild(RecordedResponse { url, status: 404, headers: Vec::new() }, Vec::new()))
+                    }
+                }
+            },
+        }
+    }
+}
+
+fn key(method: &reqwest::Method, url: &str) -> String {
+    let request = match *method {
+        reqwest::Method::GET => url.to_string(),
+        _ => format!("{} {}", method, url)
+    };
+    Sha256::digest(request.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn header_pairs(headers: &reqwest::header::HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+fn save(dir: &Path, method: &reqwest::Method, recorded: &RecordedResponse, body: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let key = key(method, &recorded.url);
+    fs::write(dir.join(format!("{}.body", key)), body)?;
+    fs::write(dir.join(format!("{}.json", key)), serde_json::to_string_pretty(recorded)?)?;
+    Ok(())
+}
+
+fn load(dir: &Path, method: &reqwest::Method, url: &str) -> Result<(RecordedResponse, Vec<u8>), Box<dyn std::error::Error>> {
+    let key = key(method, url);
+    let recorded = serde_json::from_str(&fs::read_to_string(dir.join(format!("{}.json", key)))?)?;
+    let body = fs::read(dir.join(format!("{}.body", key)))?;
+    Ok((recorded, body))
+}
+
+fn rebuild(recorded: RecordedResponse, body: Vec<u8>) -> reqwest::Response {
+    use reqwest::ResponseBuilderExt;
+
+    let mut builder = http::Response::builder().status(recorded.status);
+    if let Ok(url) = reqwest::Url::parse(&recorded.url) {
+        builder = builder.url(url);
+    }
+    for (name, value) in &recorded.headers {
+        builder = builder.header(name, value);
+    }
+
+    let response = builder.body(body)
+        .unwrap_or_else(|_| http::Response::builder().status(500).body(Vec::new()).unwrap());
+    reqwest::Response::from(response)
+}
@@ -0,0 +1,131 @@
+//! `podcast:funding` and `podcast:value` (Value4Value) tags, kept with each
+//! episode so the archive records how to support its creators.
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+use std::error::Error;
+
+use crate::Episode;
+
+/// A `<podcast:funding>` link, e.g. to a Patreon page.
+#[derive(Debug, Clone, Serialize)]
+pub struct Funding {
+    pub url: String,
+    pub text: String,
+}
+
+/// A `<podcast:valueRecipient>`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Recipient {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub recipient_type: Option<String>,
+    pub address: Option<String>,
+    pub split: Option<u32>,
+    pub custom_key: Option<String>,
+    pub custom_value: Option<String>,
+    pub fee: bool,
+}
+
+/// A `<podcast:value>` block.
+#[derive(Debug, Clone, Serialize)]
+pub struct Value {
+    #[serde(rename = "type")]
+    pub value_type: Option<String>,
+    pub method: Option<String>,
+    pub suggested: Option<String>,
+    pub recipients: Vec<Recipient>,
+}
+
+fn attr(element: &BytesStart, reader: &Reader<&[u8]>, name: &str) -> Option<String> {
+    element.try_get_attribute(name).ok().flatten()?
+        .decode_and_unescape_value(reader).ok().map(|value| value.to_string())
+}
+
+/// Read a `<podcast:funding>` whose start tag was just returned.
+pub fn read_funding(reader: &mut Reader<&[u8]>, element: &BytesStart) -> Result<Funding, Box<dyn Error>> {
+    let url = attr(element, reader, "url").unwrap_or_default();
+    let text = crate::element_text(&reader.read_text(element.name())?);
+    Ok(Funding { url, text })
+}
+
+/// Read a `<podcast:value>` whose start tag was just returned.
+pub fn read_value(reader: &mut Reader<&[u8]>, element: &BytesStart) -> Result<Value, Box<dyn Error>> {
+    let mut value = Value {
+        value_type: attr(element, reader, "type"),
+        method: attr(element, reader, "method"),
+        suggested: attr(element, reader, "suggested"),
+        recipients: Vec::new(),
+    };
+
+    let end = element.to_end().into_owned();
+    loop {
+        match reader.read_event()? {
+            Event::Start(recipient) | Event::Empty(recipient) if recipient.name().as_ref() == b"podcast:valueRecipient" => {
+                value.recipients.push(Recipient {
+                    name: attr(&recipient, reader, "name"),
+                    recipient_type: attr(&recipient, reader, "type"),
+                    address: attr(&recipient, reader, "address"),
+                    split: attr(&recipient, reader, "split").and_then(|split| split.trim().parse().ok()),
+                    custom_key: attr(&recipient, reader, "customKey"),
+                    custom_value: attr(&recipient, reader, "customValue"),
+                    fee: attr(&recipient, reader, "fee").is_some_and(|fee| fee == "true"),
+                });
+            },
+            Event::End(e) if e.name() == end.name() => break,
+            Event::Eof => break,
+            _ => ()
+        }
+    }
+    Ok(value)
+}
+
+/// Show-level funding links and value block, which apply to every episode
+/// that doesn't set its own.
+fn parse_channel(rss_xml: &str) -> (Vec<Funding>, Option<Value>) {
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    let mut funding = Vec::new();
+    let mut value = None;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => match element.name().as_ref() {
+                b"item" | b"entry" => break,
+                b"podcast:funding" => funding.extend(read_funding(&mut reader, &element).ok()),
+                b"podcast:value" if value.is_none() => value = read_value(&mut reader, &element).ok(),
+                // Live items can carry their own value blocks.
+                b"podcast:liveItem" if reader.read_to_end(element.name()).is_err() => break,
+                _ => ()
+            },
+            Ok(Event::Empty(element)) if element.name().as_ref() == b"podcast:value" && value.is_none() => {
+                value = Some(Value {
+                    value_type: attr(&element, &reader, "type"),
+                    method: attr(&element, &reader, "method"),
+                    suggested: attr(&element, &reader, "suggested"),
+                    recipients: Vec::new(),
+                });
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => ()
+        }
+    }
+
+    (funding, value)
+}
+
+/// Give each episode the show's funding links, and its value block where the
+/// episode has none of its own.
+pub fn inherit_show<'a>(rss_xml: &str, episodes: impl Iterator<Item = &'a mut Episode>) {
+    let (funding, value) = parse_channel(rss_xml);
+    if funding.is_empty() && value.is_none() {
+        return
+    }
+    for episode in episodes {
+        episode.funding.extend(funding.iter().cloned());
+        if episode.value.is_none() {
+            episode.value = value.clone();
+        }
+    }
+}
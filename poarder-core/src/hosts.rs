@@ -0,0 +1,161 @@
+//! Per-domain download policies ("host profiles").
+//!
+//! Hosts differ a lot under parallel load: archive.org throttles hard, Anchor
+//! rejects bursts, Libsyn is happy with a handful of connections. A profile
+//! caps concurrency, spaces out requests and decides how often to retry. The
+//! built-in profiles can be overridden or extended in the config file:
+//!
+//! ```toml
+//! [hosts."archive.org"]
+//! max-concurrency = 1
+//! min-interval-ms = 2000
+//! ```
+
+use log::{debug, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HostProfile {
+    /// Simultaneous downloads from the host; `None` leaves it to `--task-count`.
+    pub max_concurrency: Option<usize>,
+    /// Minimum gap between starting two requests to the host.
+    pub min_interval_ms: u64,
+    /// Attempts after the first before giving up on an episode.
+    pub retries: u32,
+    /// Delay before the first retry; doubled on each further attempt.
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for HostProfile {
+    fn default() -> HostProfile {
+        HostProfile {
+            max_concurrency: None,
+            min_interval_ms: 0,
+            retries: 2,
+            retry_backoff_ms: 1000,
+        }
+    }
+}
+
+fn builtin_profiles() -> HashMap<String, HostProfile> {
+    let mut profiles = HashMap::new();
+    profiles.insert("archive.org".to_string(), HostProfile {
+        max_concurrency: Some(2),
+        min_interval_ms: 1000,
+        retries: 5,
+        retry_backoff_ms: 10_000,
+    });
+    profiles.insert("libsyn.com".to_string(), HostProfile {
+        max_concurrency: Some(4),
+        ..HostProfile::default()
+    });
+    profiles.insert("anchor.fm".to_string(), HostProfile {
+        max_concurrency: Some(2),
+        min_interval_ms: 500,
+        retries: 3,
+        retry_backoff_ms: 5000,
+    });
+    profiles
+}
+
+struct HostState {
+    semaphore: Option<Arc<Semaphore>>,
+    next_start: Mutex<Instant>,
+}
+
+/// Holds a host's concurrency slot until dropped.
+pub struct HostPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// Applies host profiles to outgoing requests.
+pub struct HostLimiter {
+    profiles: HashMap<String, HostProfile>,
+    states: std::sync::Mutex<HashMap<String, Arc<HostState>>>,
+}
+
+impl HostLimiter {
+    /// Built-in profiles with `overrides` from the config layered on top.
+    pub fn new(overrides: &HashMap<String, HostProfile>) -> HostLimiter {
+        let mut profiles = builtin_profiles();
+        profiles.extend(overrides.iter().map(|(k, v)| (k.to_lowercase(), v.clone())));
+
+        HostLimiter { profiles, states: std::sync::Mutex::new(HashMap::new()) }
+    }
+
+    /// Profile key for `url`: the most specific configured domain suffix of
+    /// its host, or the bare host when none matches.
+    fn key_for(&self, url: &str) -> String {
+        let host = host_of(url);
+        let mut domain = host.as_str();
+        loop {
+            if self.profiles.contains_key(domain) {
+                return domain.to_string()
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return host
+            }
+        }
+    }
+
+    pub fn profile_for(&self, url: &str) -> HostProfile {
+        self.profiles.get(&self.key_for(url)).cloned().unwrap_or_default()
+    }
+
+    /// Wait for a concurrency slot and the host's minimum request interval.
+    pub async fn acquire(&self, url: &str) -> HostPermit {
+        let key = self.key_for(url);
+        let profile = self.profile_for(url);
+
+        let state = self.states.lock().unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(HostState {
+                semaphore: profile.max_concurrency.map(|n| Arc::new(Semaphore::new(n.max(1)))),
+                next_start: Mutex::new(Instant::now()),
+            }))
+            .clone();
+
+        let permit = match &state.semaphore {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None
+        };
+
+        if profile.min_interval_ms > 0 {
+            let mut next_start = state.next_start.lock().await;
+            let now = Instant::now();
+            if *next_start > now {
+                debug!("Waiting {:?} before next request to {}", *next_start - now, key);
+                tokio::time::sleep_until(*next_start).await;
+            }
+            *next_start = Instant::now() + Duration::from_millis(profile.min_interval_ms);
+        }
+
+        HostPermit { _permit: permit }
+    }
+}
+
+pub fn host_of(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => url.host_str().unwrap_or("").to_lowercase(),
+        Err(e) => {
+            warn!("Could not parse URL {}. Error: {}", url, e);
+            String::new()
+        }
+    }
+}
+
+/// Whether a failed request is worth retrying: transport errors, throttling
+/// and server-side errors, but not e.g. a 404.
+pub fn should_retry(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.as_u16() == 429 || status.is_server_error(),
+        None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+    }
+}
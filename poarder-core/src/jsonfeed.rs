@@ -0,0 +1,257 @@
+//! [JSON Feed](https://jsonfeed.org/version/1.1) documents: items with
+//! `attachments` become episodes, like RSS items with an enclosure.
+
+use chrono::DateTime;
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::collections::LinkedList;
+use std::error::Error;
+
+use crate::{Episode, ItemStep, MalformedItem};
+
+#[derive(Deserialize)]
+struct Document<'a> {
+    version: String,
+    title: Option<String>,
+    icon: Option<String>,
+    #[serde(default)]
+    authors: Vec<Author>,
+    /// JSON Feed 1.0's single author.
+    author: Option<Author>,
+    #[serde(borrow, default)]
+    items: Vec<&'a RawValue>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Author {
+    name: Option<String>,
+}
+
+/// The first named of `authors` and `author`.
+fn author_name(authors: &[Author], author: &Option<Author>) -> Option<String> {
+    authors.iter().chain(author).find_map(|author| author.name.clone())
+}
+
+#[derive(Deserialize)]
+struct Item {
+    id: serde_json::Value,
+    url: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content_text: Option<String>,
+    content_html: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    authors: Vec<Author>,
+    author: Option<Author>,
+    image: Option<String>,
+    banner_image: Option<String>,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    url: String,
+    mime_type: Option<String>,
+    size_in_bytes: Option<u64>,
+    duration_in_seconds: Option<f64>,
+}
+
+/// Parse the items of a JSON Feed, with `before`, `stop` and `malformed` as
+/// in [`parse_rss_with`](crate::parse_rss_with); an item's raw text is its
+/// JSON.
+pub fn parse_with(
+    json: &str,
+    keep_raw: bool,
+    mut before: impl FnMut(&str) -> ItemStep,
+    mut stop: impl FnMut(&Episode) -> bool,
+    mut malformed: impl FnMut(MalformedItem),
+) -> Result<LinkedList<Episode>, Box<dyn Error>> {
+    let document: Document = serde_json::from_str(json)?;
+    if !document.version.starts_with("https://jsonfeed.org/version/1") {
+        return Err(format!("unsupported JSON Feed version {}", document.version).into())
+    }
+
+    let mut episodes = LinkedList::new();
+    for (i, raw) in document.items.into_iter().enumerate() {
+        match before(raw.get()) {
+            ItemStep::Parse => (),
+            ItemStep::Skip => continue,
+            ItemStep::Stop => break
+        }
+        match parse_item(raw.get(), keep_raw) {
+            Ok(Some(mut episode)) => {
+                episode.show = document.title.clone();
+                episode.author = episode.author.or_else(|| author_name(&document.authors, &document.author));
+                episode.image = episode.image.or_else(|| document.icon.clone());
+                let done = stop(&episode);
+                episodes.push_back(episode);
+                if done {
+                    break
+                }
+            },
+            Ok(None) => (),
+            Err(e) => {
+                let label = format!("item {}", i + 1);
+                warn!("Malformed item: {}: {}", label, e);
+                // Items borrow from `json`, so where one starts is where its text is.
+                let offset = raw.get().as_ptr() as usize - json.as_ptr() as usize;
+                malformed(MalformedItem { label, reason: e.to_string(), raw: raw.get().to_string(), offset });
+            }
+        }
+    }
+    Ok(episodes)
+}
+
+/// The feed's `title`, if `json` is a JSON Feed with one.
+pub fn title(json: &str) -> Option<String> {
+    serde_json::from_str::<Document>(json).ok()?.title
+}
+
+/// The feed's author, if it names one.
+pub fn author(json: &str) -> Option<String> {
+    let document = serde_json::from_str::<Document>(json).ok()?;
+    author_name(&document.authors, &document.author)
+}
+
+/// The item as an episode, or `None` if it has nothing to download.
+fn parse_item(json: &str, keep_raw: bool) -> Result<Option<Episode>, Box<dyn Error>> {
+    let item: Item = serde_json::from_str(json)?;
+    let id = match item.id {
+        serde_json::Value::String(id) => id,
+        id => id.to_string()
+    };
+    // Prefer audio, then video, as podcast clients do.
+    let attachment = item.attachments.iter()
+        .find(|attachment| attachment.mime_type.as_deref().is_some_and(|mime_type| mime_type.starts_with("audio/")))
+        .or_else(|| item.attachments.iter().find(|attachment| attachment.mime_type.as_deref().is_some_and(|mime_type| mime_type.starts_with("video/"))))
+        .or(item.attachments.first());
+    let Some(attachment) = attachment else {
+        debug!("JSON Feed item {} has no attachments", id);
+        return Ok(None)
+    };
+
+    let published = match item.date_published.or(item.date_modified) {
+        Some(date) => DateTime::parse_from_rfc3339(date.trim())?,
+        None => return Err(format!("item {} has no date", id).into())
+    };
+    let title = match item.title.or_else(|| item.summary.clone()) {
+        Some(title) => title,
+        None => return Err(format!("item {} has no title", id).into())
+    };
+
+    Ok(Some(Episode {
+        url: attachment.url.clone(),
+        title,
+        datetime: published.naive_local(),
+        published,
+        guid: Some(id),
+        link: item.url,
+        description: item.content_text.or(item.summary).or(item.content_html),
+        duration: attachment.duration_in_seconds.map(|seconds| (seconds.round() as u64).to_string()),
+        chapters_url: None,
+        season: None,
+        number: None,
+        show: None,
+        author: author_name(&item.authors, &item.author),
+        image: item.image.or(item.banner_image),
+        enclosure_type: attachment.mime_type.clone(),
+        enclosure_length: attachment.size_in_bytes,
+        funding: Vec::new(),
+        value: None,
+        soundbites: Vec::new(),
+        checksum: None,
+        podcast_tags: Vec::new(),
+        show_podcast_tags: Vec::new(),
+        needs_resolver: false,
+        raw: if keep_raw { json.to_string() } else { String::new() },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"{
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": "Show",
+        "authors": [{"url": "https://example.com"}, {"name": "Host"}],
+        "icon": "https://example.com/icon.png",
+        "items": [
+            {
+                "id": 7,
+                "title": "Video",
+                "date_published": "2024-01-02T03:04:05+02:00",
+                "attachments": [
+                    {"url": "https://example.com/7.mp4", "mime_type": "video/mp4"},
+                    {"url": "https://example.com/7.mp3", "mime_type": "audio/mpeg", "size_in_bytes": 1000, "duration_in_seconds": 61.6}
+                ]
+            },
+            {"id": "no-media", "title": "Post", "date_published": "2024-01-01T00:00:00Z"},
+            {"id": "no-date", "title": "Broken", "attachments": [{"url": "https://example.com/b.mp3"}]},
+            {
+                "id": "summary",
+                "summary": "Only a summary",
+                "date_modified": "2023-12-31T00:00:00Z",
+                "author": {"name": "Guest"},
+                "attachments": [{"url": "https://example.com/s.ogg"}]
+            }
+        ]
+    }"#;
+
+    fn parse(json: &str) -> Result<Vec<Episode>, Box<dyn Error>> {
+        parse_with(json, false, |_| ItemStep::Parse, |_| false, |_| ()).map(|episodes| episodes.into_iter().collect())
+    }
+
+    #[test]
+    fn items() {
+        let mut malformed = Vec::new();
+        let episodes: Vec<Episode> = parse_with(FEED, false, |_| ItemStep::Parse, |_| false, |item| malformed.push(item))
+            .unwrap().into_iter().collect();
+        assert_eq!(episodes.len(), 2);
+
+        let first = &episodes[0];
+        assert_eq!(first.url, "https://example.com/7.mp3");
+        assert_eq!(first.guid.as_deref(), Some("7"));
+        assert_eq!(first.published.to_rfc3339(), "2024-01-02T03:04:05+02:00");
+        assert_eq!(first.duration.as_deref(), Some("62"));
+        assert_eq!(first.enclosure_length, Some(1000));
+        assert_eq!(first.show.as_deref(), Some("Show"));
+        assert_eq!(first.author.as_deref(), Some("Host"));
+        assert_eq!(first.image.as_deref(), Some("https://example.com/icon.png"));
+
+        let second = &episodes[1];
+        assert_eq!(second.title, "Only a summary");
+        assert_eq!(second.author.as_deref(), Some("Guest"));
+
+        assert_eq!(malformed.len(), 1);
+        assert_eq!(malformed[0].label, "item 3");
+        assert!(FEED[malformed[0].offset..].starts_with(&malformed[0].raw));
+    }
+
+    #[test]
+    fn stops() {
+        let mut seen = 0;
+        let episodes = parse_with(FEED, false, |_| { seen += 1; if seen > 1 { ItemStep::Stop } else { ItemStep::Parse } }, |_| false, |_| ()).unwrap();
+        assert_eq!(episodes.len(), 1);
+        let episodes = parse_with(FEED, false, |_| ItemStep::Parse, |_| true, |_| ()).unwrap();
+        assert_eq!(episodes.len(), 1);
+    }
+
+    #[test]
+    fn feed_fields() {
+        assert_eq!(title(FEED).as_deref(), Some("Show"));
+        assert_eq!(author(FEED).as_deref(), Some("Host"));
+        assert_eq!(title("<rss/>"), None);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        assert!(parse(r#"{"version": "https://jsonfeed.org/version/2", "items": []}"#).is_err());
+        assert!(parse(r#"{"items": []}"#).is_err());
+        assert!(parse(r#"{"version": "https://jsonfeed.org/version/1", "items": []}"#).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,638 @@
+//! Podcast archiving as a library: fetch a [`Feed`], then archive its
+//! [`Episode`]s with a [`Downloader`]. The `poarder` command line tool is a
+//! thin wrapper around this crate.
+
+pub mod auth;
+pub mod db;
+pub mod disk;
+pub mod eta;
+pub mod fair;
+pub mod fixtures;
+pub mod funding;
+pub mod hosts;
+pub mod jsonfeed;
+pub mod limits;
+pub mod naming;
+pub mod passthrough;
+pub mod pipeline;
+pub mod plugins;
+#[cfg(feature = "progress")]
+pub mod progress;
+pub mod resolvers;
+pub mod schema;
+pub mod shard;
+pub mod shutdown;
+pub mod sidecar;
+pub mod soundbites;
+pub mod state;
+pub mod stats;
+#[cfg(feature = "id3")]
+pub mod tags;
+pub mod throttle;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+
+use chrono::{DateTime, NaiveDateTime, FixedOffset};
+use chrono::format::ParseError;
+use log::{debug, error, warn};
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::LinkedList;
+use std::{error::Error, fmt};
+
+pub use fixtures::Http;
+pub use pipeline::Downloader;
+
+/// One episode of a feed.
+#[derive(Debug, Clone, Serialize)]
+pub struct Episode {
+    pub url: String,
+    pub title: String,
+    pub datetime: NaiveDateTime,
+    /// Publish time with the feed's UTC offset; `datetime` drops it.
+    pub published: DateTime<FixedOffset>,
+    pub guid: Option<String>,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    /// `<itunes:duration>` as given, e.g. `01:02:03` or `3723`.
+    pub duration: Option<String>,
+    /// `<podcast:chapters>` URL.
+    pub chapters_url: Option<String>,
+    /// `<itunes:season>` number.
+    pub season: Option<u32>,
+    /// `<itunes:episode>` number.
+    pub number: Option<u32>,
+    /// Title of the show, from the feed's own `<title>`.
+    pub show: Option<String>,
+    /// `<itunes:author>` of the item, else of the show.
+    pub author: Option<String>,
+    /// `<itunes:image>` of the item, else of the show.
+    pub image: Option<String>,
+    pub enclosure_type: Option<String>,
+    pub enclosure_length: Option<u64>,
+    /// `<podcast:funding>` links, the show's included.
+    pub funding: Vec<funding::Funding>,
+    /// `<podcast:value>` block, falling back to the show's.
+    pub value: Option<funding::Value>,
+    pub soundbites: Vec<soundbites::Soundbite>,
+    /// SHA-256 of the enclosure published in the feed (`<media:hash>` or a
+    /// custom `<...:sha256>` tag), lowercase hex.
+    pub checksum: Option<String>,
+    /// Every `podcast:` element of the item, verbatim.
+    pub podcast_tags: Vec<String>,
+    /// The show's `podcast:` elements, verbatim.
+    pub show_podcast_tags: Vec<String>,
+    /// `url` is a web page (e.g. a YouTube video) that a `[[resolvers]]`
+    /// command must turn into a media URL before downloading.
+    #[serde(skip)]
+    pub needs_resolver: bool,
+
+    pub raw: String,
+}
+
+#[derive(Debug)]
+struct RssFormatError {
+    /// What the item lacks, e.g. `no pubDate`.
+    reason: String
+}
+
+impl Error for RssFormatError {}
+
+impl fmt::Display for RssFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+fn format_error(reason: &str) -> Box<dyn Error> {
+    Box::new(RssFormatError { reason: reason.to_string() })
+}
+
+/// A fetched feed and its episodes.
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub url: String,
+    /// The feed document as served.
+    pub xml: String,
+    pub episodes: Vec<Episode>,
+}
+
+impl Feed {
+    /// Download and parse the feed at `url`, within the default
+    /// [`FeedLimits`](limits::FeedLimits).
+    pub async fn fetch(http: &Http, url: &str) -> Result<Feed, Box<dyn Error>> {
+        let resp = http.send(http.get(url)).await?.error_for_status()?;
+        let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let limits = limits::FeedLimits::default();
+        let xml = limits.read(resp).await?;
+        limits::check_content(content_type.as_deref(), &xml)?;
+        let format = FeedFormat::detect(content_type.as_deref(), &xml);
+        limits.check(&xml, format)?;
+        let episodes = parse_feed_with(&xml, format, true, |_| ItemStep::Parse, |_| false, |_| ())?.into_iter().collect();
+        Ok(Feed { url: url.to_string(), xml, episodes })
+    }
+
+    /// Parse an already downloaded feed, RSS, Atom or JSON Feed. See
+    /// [`parse_rss`] for `keep_raw`.
+    pub fn parse(url: &str, xml: String, keep_raw: bool) -> Result<Feed, Box<dyn Error>> {
+        let episodes = parse_feed(&xml, keep_raw)?.into_iter().collect();
+        Ok(Feed { url: url.to_string(), xml, episodes })
+    }
+}
+
+/// Syntax of a feed document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    /// RSS or Atom.
+    Rss,
+    Json,
+}
+
+impl FeedFormat {
+    /// The format of `document`, served as `content_type`: JSON Feed for
+    /// `application/feed+json` and `application/json`, RSS for XML types,
+    /// and otherwise whichever the document looks like.
+    pub fn detect(content_type: Option<&str>, document: &str) -> FeedFormat {
+        let mime_type = content_type.and_then(|content_type| content_type.split(';').next()).map(|mime_type| mime_type.trim().to_lowercase());
+        match mime_type.as_deref() {
+            Some("application/feed+json" | "application/json") => FeedFormat::Json,
+            Some(mime_type) if mime_type.ends_with("xml") => FeedFormat::Rss,
+            _ if document.trim_start_matches('\u{feff}').trim_start().starts_with('{') => FeedFormat::Json,
+            _ => FeedFormat::Rss
+        }
+    }
+}
+
+/// An item left out of a feed for being malformed.
+#[derive(Debug, Clone)]
+pub struct MalformedItem {
+    /// Its title, else its guid or place in the feed.
+    pub label: String,
+    /// What is wrong with it.
+    pub reason: String,
+    /// The item as it is in the feed: XML, or JSON for a JSON Feed.
+    pub raw: String,
+    /// Where the item starts in the feed, in bytes.
+    pub offset: usize,
+}
+
+/// Parse a feed in either format, going by its content. See [`parse_rss`].
+pub fn parse_feed(document: &str, keep_raw: bool) -> Result<LinkedList<Episode>, Box<dyn Error>> {
+    parse_feed_with(document, FeedFormat::detect(None, document), keep_raw, |_| ItemStep::Parse, |_| false, |_| ())
+}
+
+/// [`parse_rss_with`] for a feed in `format`.
+pub fn parse_feed_with(
+    document: &str,
+    format: FeedFormat,
+    keep_raw: bool,
+    before: impl FnMut(&str) -> ItemStep,
+    stop: impl FnMut(&Episode) -> bool,
+    malformed: impl FnMut(MalformedItem),
+) -> Result<LinkedList<Episode>, Box<dyn Error>> {
+    match format {
+        FeedFormat::Rss => parse_rss_with(document, keep_raw, before, stop, malformed),
+        FeedFormat::Json => jsonfeed::parse_with(document, keep_raw, before, stop, malformed),
+    }
+}
+
+/// Parse every `<item>` of an RSS feed, or `<entry>` of an Atom feed (e.g.
+/// YouTube channels). With `keep_raw` unset, `Episode::raw` is left empty to
+/// avoid holding a second copy of the feed in memory. Malformed items are
+/// logged and left out.
+pub fn parse_rss(rss_xml: &str, keep_raw: bool) -> Result<LinkedList<Episode>, Box<dyn Error>> {
+    parse_rss_with(rss_xml, keep_raw, |_| ItemStep::Parse, |_| false, |_| ())
+}
+
+/// What [`parse_rss_with`] does with an item, decided from its raw XML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemStep {
+    Parse,
+    /// Leave the item out.
+    Skip,
+    /// Leave the item out and stop reading the feed.
+    Stop,
+}
+
+/// Like [`parse_rss`], but `before` sees each item's XML (what would be
+/// `Episode::raw`) first and can leave it out, reading stops once `stop`
+/// returns true for a parsed episode (which is still included), and
+/// `malformed` hears of each item left out for being malformed.
+pub fn parse_rss_with(
+    rss_xml: &str,
+    keep_raw: bool,
+    mut before: impl FnMut(&str) -> ItemStep,
+    mut stop: impl FnMut(&Episode) -> bool,
+    mut malformed: impl FnMut(MalformedItem),
+) -> Result<LinkedList<Episode>, Box<dyn Error>> {
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    reader.expand_empty_elements(true);
+
+    let mut list_of_events = LinkedList::new();
+    // The first `<title>` outside any item is the show's.
+    let mut show = None;
+    let mut show_author = None;
+    let mut show_image = None;
+    let mut position = 0;
+    // The item as it is in the feed, from its start tag to its end tag.
+    let whole = |from: usize, to: usize| {
+        let text = &rss_xml[from..to];
+        (from + text.len() - text.trim_start().len(), text.trim().to_string())
+    };
+
+    loop {
+        let event_start = reader.buffer_position();
+        match reader.read_event() {
+            Ok(Event::Start(e)) if e.name().as_ref() == b"title" && show.is_none() => {
+                show = Some(element_text(&reader.read_text(e.name())?));
+            }
+            Ok(Event::Start(e)) if e.name().as_ref() == b"itunes:author" && show_author.is_none() => {
+                show_author = Some(element_text(&reader.read_text(e.name())?));
+            }
+            Ok(Event::Start(e)) if e.name().as_ref() == b"itunes:image" && show_image.is_none() => {
+                show_image = itunes_image(&reader, &e)?;
+            }
+            Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
+                // Items are read in place rather than cut out and parsed
+                // again; fields borrow from the feed until they're kept.
+                let start = reader.buffer_position();
+                let (fields, end) = read_item(&mut reader, rss_xml)?;
+                let item_xml = &rss_xml[start..end];
+                position += 1;
+                match before(item_xml) {
+                    ItemStep::Parse => (),
+                    ItemStep::Skip => continue,
+                    ItemStep::Stop => break
+                }
+                // A post without an enclosure isn't an episode, not a broken one.
+                if fields.url.is_none() {
+                    debug!("Item {} has no enclosure", position);
+                    continue
+                }
+                let label = fields.title.as_deref().or(fields.guid.as_deref())
+                    .map_or_else(|| format!("item {}", position), element_text);
+                match fields.into_episode(item_xml, keep_raw) {
+                    Ok(episode) => {
+                        let done = stop(&episode);
+                        list_of_events.push_back(episode);
+                        if done {
+                            break
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Malformed item: {}: {}", label, e);
+                        let (offset, raw) = whole(event_start, reader.buffer_position());
+                        malformed(MalformedItem { label, reason: e.to_string(), raw, offset });
+                    }
+                }
+            }
+            Ok(Event::Start(e)) if e.name().as_ref() == b"entry" => {
+                let txt = reader.read_text(e.name())?;
+                position += 1;
+                match before(txt.as_ref()) {
+                    ItemStep::Parse => (),
+                    ItemStep::Skip => continue,
+                    ItemStep::Stop => break
+                }
+                match parse_entry(txt.as_ref(), keep_raw) {
+                    Ok(episode) => {
+                        let done = stop(&episode);
+                        list_of_events.push_back(episode);
+                        if done {
+                            break
+                        }
+                    },
+                    Err(e) => {
+                        let label = format!("entry {}", position);
+                        warn!("Malformed item: {}: {}", label, e);
+                        let (offset, raw) = whole(event_start, reader.buffer_position());
+                        malformed(MalformedItem { label, reason: e.to_string(), raw, offset });
+                    }
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                error!("Error at position {}: {:?}", reader.buffer_position(), e);
+                return Err(Box::new(e))
+            },
+            _ => ()
+        }
+    }
+
+    for episode in list_of_events.iter_mut() {
+        episode.show = show.clone();
+        episode.author = episode.author.take().or_else(|| show_author.clone());
+        episode.image = episode.image.take().or_else(|| show_image.clone());
+    }
+    funding::inherit_show(rss_xml, list_of_events.iter_mut());
+    passthrough::inherit_show(rss_xml, list_of_events.iter_mut());
+    Ok(list_of_events)
+}
+
+pub fn parse_item(item_xml: &str, keep_raw: bool) -> Result<Episode, Box<dyn Error>> {
+    let mut reader = Reader::from_str(item_xml);
+    reader.expand_empty_elements(true);
+    let (fields, _) = read_item(&mut reader, item_xml)?;
+    fields.into_episode(item_xml, keep_raw)
+}
+
+/// An `<item>`'s fields as read, borrowing from the feed where they can;
+/// only what ends up in the [`Episode`] gets copied out.
+#[derive(Default)]
+struct ItemFields<'a> {
+    title: Option<Cow<'a, str>>,
+    pub_date: Option<Cow<'a, str>>,
+    url: Option<String>,
+    guid: Option<Cow<'a, str>>,
+    link: Option<Cow<'a, str>>,
+    description: Option<Cow<'a, str>>,
+    summary: Option<Cow<'a, str>>,
+    duration: Option<Cow<'a, str>>,
+    season: Option<Cow<'a, str>>,
+    number: Option<Cow<'a, str>>,
+    author: Option<Cow<'a, str>>,
+    image: Option<String>,
+    chapters_url: Option<String>,
+    enclosure_type: Option<String>,
+    enclosure_length: Option<u64>,
+    funding: Vec<funding::Funding>,
+    value: Option<funding::Value>,
+    soundbites: Vec<soundbites::Soundbite>,
+    checksum: Option<String>,
+    podcast_tags: Vec<&'a str>,
+}
+
+/// The `href` of an `<itunes:image>`.
+fn itunes_image(reader: &Reader<&[u8]>, element: &quick_xml::events::BytesStart) -> Result<Option<String>, Box<dyn Error>> {
+    match element.try_get_attribute("href")? {
+        Some(href) => Ok(Some(href.decode_and_unescape_value(reader)?.into_owned())),
+        None => Ok(None)
+    }
+}
+
+/// Read an item's elements from `reader`, which must expand empty elements,
+/// up to its `</item>` (or the end of `xml`, for a lone item). Returns the
+/// fields and where the item's content ends.
+fn read_item<'a>(reader: &mut Reader<&'a [u8]>, xml: &'a str) -> Result<(ItemFields<'a>, usize), Box<dyn Error>> {
+    let mut fields = ItemFields::default();
+
+    loop {
+        let start = reader.buffer_position();
+        let element = match reader.read_event() {
+            Ok(Event::Start(element)) => element,
+            Ok(Event::End(element)) if element.name().as_ref() == b"item" => return Ok((fields, start)),
+            Ok(Event::Eof) => return Ok((fields, start)),
+            Err(e) => {
+                error!("Error at position {}: {:?}", reader.buffer_position(), e);
+                return Err(Box::new(e))
+            },
+            _ => continue
+        };
+        let name = element.name();
+        match name.as_ref() {
+            b"title" => fields.title = Some(reader.read_text(name)?),
+            b"pubDate" => fields.pub_date = Some(reader.read_text(name)?),
+            b"enclosure" => {
+                for attr_result in element.attributes() {
+                    let attr = attr_result?;
+                    match attr.key.as_ref() {
+                        b"url" => fields.url = Some(attr.decode_and_unescape_value(reader)?.into_owned()),
+                        b"type" => fields.enclosure_type = Some(attr.decode_and_unescape_value(reader)?.into_owned()),
+                        b"length" => fields.enclosure_length = attr.decode_and_unescape_value(reader)?.trim().parse().ok(),
+                        _ => ()
+                    }
+                }
+            },
+            b"podcast:chapters" => {
+                if let Some(attr) = element.try_get_attribute("url")? {
+                    fields.chapters_url = Some(attr.decode_and_unescape_value(reader)?.into_owned());
+                }
+                reader.read_to_end(name)?;
+            },
+            b"itunes:image" => fields.image = itunes_image(reader, &element)?,
+            b"podcast:funding" => fields.funding.push(funding::read_funding(reader, &element)?),
+            b"podcast:value" => fields.value = Some(funding::read_value(reader, &element)?),
+            b"podcast:soundbite" => fields.soundbites.extend(soundbites::read(reader, &element)?),
+            name if name.starts_with(b"podcast:") => {
+                reader.read_to_end(element.name())?;
+            },
+            b"media:hash" => {
+                let sha256 = match element.try_get_attribute("algo")? {
+                    Some(algo) => matches!(algo.decode_and_unescape_value(reader)?.to_ascii_lowercase().as_str(), "sha-256" | "sha256"),
+                    None => false
+                };
+                let text = reader.read_text(name)?;
+                if sha256 {
+                    fields.checksum = sha256_hex(&unescape_text(&text));
+                }
+            },
+            name if name == b"sha256" || name.ends_with(b":sha256") => {
+                fields.checksum = sha256_hex(&unescape_text(&reader.read_text(element.name())?));
+            },
+            other => {
+                let field = match other {
+                    b"guid" => &mut fields.guid,
+                    b"link" => &mut fields.link,
+                    b"description" => &mut fields.description,
+                    b"itunes:summary" => &mut fields.summary,
+                    b"itunes:duration" => &mut fields.duration,
+                    b"itunes:season" => &mut fields.season,
+                    b"itunes:episode" => &mut fields.number,
+                    b"itunes:author" => &mut fields.author,
+                    _ => continue
+                };
+                *field = Some(reader.read_text(name)?);
+            }
+        }
+        // Every `podcast:` element is also kept verbatim (see `passthrough`).
+        if element.name().as_ref().starts_with(b"podcast:") {
+            fields.podcast_tags.push(xml[start..reader.buffer_position()].trim());
+        }
+    }
+}
+
+impl ItemFields<'_> {
+    fn into_episode(self, item_xml: &str, keep_raw: bool) -> Result<Episode, Box<dyn Error>> {
+        let (url, title, pub_date) = match (self.url, self.title, self.pub_date) {
+            (Some(url), Some(title), Some(pub_date)) => (url, title, pub_date),
+            (None, _, _) => return Err(format_error("no enclosure")),
+            (_, None, _) => return Err(format_error("no title")),
+            (_, _, None) => return Err(format_error("no pubDate")),
+        };
+        let published = match parse_date_time(&pub_date) {
+            Ok(published) => published,
+            Err(e) => return Err(format_error(&format!("bad pubDate \"{}\" ({})", pub_date.trim(), e)))
+        };
+        let text = |field: Option<Cow<str>>| field.map(|raw| unescape_text(&raw).into_owned());
+
+        Ok(Episode{
+            url,
+            title: title.into_owned(),
+            datetime: published.naive_local(),
+            published,
+            guid: text(self.guid),
+            link: text(self.link),
+            description: text(self.description.or(self.summary)),
+            duration: text(self.duration),
+            chapters_url: self.chapters_url,
+            season: self.season.and_then(|season| unescape_text(&season).parse().ok()),
+            number: self.number.and_then(|number| unescape_text(&number).parse().ok()),
+            show: None,
+            author: text(self.author),
+            image: self.image,
+            enclosure_type: self.enclosure_type,
+            enclosure_length: self.enclosure_length,
+            funding: self.funding,
+            value: self.value,
+            soundbites: self.soundbites,
+            checksum: self.checksum,
+            podcast_tags: self.podcast_tags.into_iter().map(str::to_string).collect(),
+            show_podcast_tags: Vec::new(),
+            needs_resolver: false,
+            raw: if keep_raw { item_xml.to_string() } else { String::new() },
+        })
+    }
+}
+
+/// Parse the inside of an Atom `<entry>`. Entries with a `rel="enclosure"`
+/// link are downloaded like RSS items; others (YouTube videos, say) point at
+/// their page and need a resolver.
+fn parse_entry(entry_xml: &str, keep_raw: bool) -> Result<Episode, Box<dyn Error>> {
+    let mut reader = Reader::from_str(entry_xml);
+    reader.expand_empty_elements(true);
+
+    let mut title = None;
+    let mut published = None;
+    let mut updated = None;
+    let mut id = None;
+    let mut page = None;
+    let mut enclosure: Option<(String, Option<String>, Option<u64>)> = None;
+    let mut description = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"link" => {
+                    let attr = |name: &str| -> Option<String> {
+                        element.try_get_attribute(name).ok().flatten()?
+                            .decode_and_unescape_value(&reader).ok().map(|value| value.to_string())
+                    };
+                    let href = match attr("href") {
+                        Some(href) => href,
+                        None => continue
+                    };
+                    match attr("rel").as_deref() {
+                        Some("enclosure") => enclosure = Some((href, attr("type"), attr("length").and_then(|l| l.parse().ok()))),
+                        None | Some("alternate") => page = Some(href),
+                        _ => ()
+                    }
+                },
+                b"title" => title = Some(element_text(&reader.read_text(element.name())?)),
+                b"published" => published = Some(element_text(&reader.read_text(element.name())?)),
+                b"updated" => updated = Some(element_text(&reader.read_text(element.name())?)),
+                b"id" => id = Some(element_text(&reader.read_text(element.name())?)),
+                b"summary" | b"content" | b"media:description" if description.is_none() => {
+                    description = Some(element_text(&reader.read_text(element.name())?))
+                },
+                _ => ()
+            },
+            Event::Eof => break,
+            _ => ()
+        }
+    }
+
+    let published = match published.or(updated) {
+        Some(date) => DateTime::parse_from_rfc3339(date.trim())?,
+        None => return Err(format_error("no published or updated date"))
+    };
+    let (url, needs_resolver, enclosure_type, enclosure_length) = match (enclosure, &page) {
+        (Some((url, mime_type, length)), _) => (url, false, mime_type, length),
+        (None, Some(page)) => (page.clone(), true, None, None),
+        (None, None) => return Err(format_error("no enclosure or page link"))
+    };
+    let title = match title {
+        Some(title) => title,
+        None => return Err(format_error("no title"))
+    };
+
+    Ok(Episode{
+        url,
+        title,
+        datetime: published.naive_local(),
+        published,
+        guid: id,
+        link: page,
+        description,
+        duration: None,
+        chapters_url: None,
+        season: None,
+        number: None,
+        show: None,
+        author: None,
+        image: None,
+        enclosure_type,
+        enclosure_length,
+        funding: Vec::new(),
+        value: None,
+        soundbites: Vec::new(),
+        checksum: None,
+        podcast_tags: passthrough::collect(entry_xml, false),
+        show_podcast_tags: Vec::new(),
+        needs_resolver,
+        raw: if keep_raw { entry_xml.to_string() } else { String::new() },
+    })
+}
+
+/// Text content of an element: CDATA unwrapped, entities decoded.
+pub fn element_text(raw: &str) -> String {
+    unescape_text(raw).into_owned()
+}
+
+/// [`element_text`], borrowing from `raw` unless entities need decoding.
+fn unescape_text(raw: &str) -> Cow<'_, str> {
+    let raw = raw.trim();
+    if let Some(cdata) = raw.strip_prefix("<![CDATA[").and_then(|rest| rest.strip_suffix("]]>")) {
+        return Cow::Borrowed(cdata)
+    }
+    quick_xml::escape::unescape(raw).unwrap_or(Cow::Borrowed(raw))
+}
+
+/// A SHA-256 given in a feed, as lowercase hex; `None` if it isn't one.
+fn sha256_hex(text: &str) -> Option<String> {
+    let hex = text.trim().to_lowercase();
+    if hex.len() == 64 && hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Some(hex)
+    }
+    warn!("Ignoring malformed SHA-256 in feed: {}", text.trim());
+    None
+}
+
+fn parse_date_time(datetime_str: &str) -> Result<DateTime<FixedOffset>, ParseError> {
+    DateTime::parse_from_rfc2822(datetime_str)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_by_content_type() {
+        assert_eq!(FeedFormat::detect(Some("application/feed+json"), "<rss/>"), FeedFormat::Json);
+        assert_eq!(FeedFormat::detect(Some("Application/JSON; charset=utf-8"), "<rss/>"), FeedFormat::Json);
+        assert_eq!(FeedFormat::detect(Some("application/rss+xml"), "{}"), FeedFormat::Rss);
+        assert_eq!(FeedFormat::detect(Some("text/xml;charset=UTF-8"), "{}"), FeedFormat::Rss);
+    }
+
+    #[test]
+    fn detect_by_document() {
+        assert_eq!(FeedFormat::detect(None, "\u{feff} \n{\"version\": \"\"}"), FeedFormat::Json);
+        assert_eq!(FeedFormat::detect(Some("text/plain"), "{}"), FeedFormat::Json);
+        assert_eq!(FeedFormat::detect(Some("application/octet-stream"), "<?xml version=\"1.0\"?><rss/>"), FeedFormat::Rss);
+        assert_eq!(FeedFormat::detect(None, ""), FeedFormat::Rss);
+    }
+}
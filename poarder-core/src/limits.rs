@@ -0,0 +1,219 @@
+//! Safety rails for fetching feeds. A misbehaving endpoint can serve an
+//! HTML error page, a body of gigabytes, or XML nested without end; each is
+//! refused with an error saying what is wrong with it, before it can fill
+//! memory or keep the parser busy.
+
+use log::debug;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use serde::Deserialize;
+use serde_json::value::RawValue;
+use std::{error::Error, fmt};
+
+use crate::FeedFormat;
+
+/// The most a feed may be: bytes served, items and nesting depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeedLimits {
+    pub max_size: usize,
+    pub max_items: usize,
+    pub max_depth: usize,
+}
+
+impl Default for FeedLimits {
+    /// Far beyond any real feed: the largest back catalogs run to tens of
+    /// megabytes and some thousands of items, nested a handful deep.
+    fn default() -> FeedLimits {
+        FeedLimits { max_size: 64 << 20, max_items: 100_000, max_depth: 64 }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum LimitError {
+    /// The body was, or was going to be, over `limit` bytes.
+    TooLarge { limit: usize },
+    TooManyItems { limit: usize },
+    TooDeep { limit: usize },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::TooLarge { limit } => write!(f, "the feed is larger than the limit of {} bytes", limit),
+            LimitError::TooManyItems { limit } => write!(f, "the feed has more than the limit of {} items", limit),
+            LimitError::TooDeep { limit } => write!(f, "the feed is nested deeper than the limit of {} levels", limit),
+        }
+    }
+}
+
+// Shown as the message, which is what a run that stops on one prints.
+impl fmt::Debug for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for LimitError {}
+
+impl FeedLimits {
+    /// The body of `resp`, decoded to text, unless it is over the size
+    /// limit. The encoding is the response's charset, else the XML
+    /// declaration's, else UTF-8; a byte order mark overrides either.
+    pub async fn read(&self, mut resp: reqwest::Response) -> Result<String, Box<dyn Error>> {
+        if resp.content_length().is_some_and(|length| length > self.max_size as u64) {
+            return Err(Box::new(LimitError::TooLarge { limit: self.max_size }))
+        }
+        let charset = resp.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|content_type| content_type.split(';').find_map(|param| param.trim().strip_prefix("charset=")))
+            .map(|charset| charset.trim_matches('"').to_string());
+
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if body.len() + chunk.len() > self.max_size {
+                return Err(Box::new(LimitError::TooLarge { limit: self.max_size }))
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        let label = charset.or_else(|| xml_encoding(&body));
+        let encoding = label.as_deref()
+            .and_then(|label| encoding_rs::Encoding::for_label(label.as_bytes()))
+            .unwrap_or(encoding_rs::UTF_8);
+        let (text, used, lossy) = encoding.decode(&body);
+        if lossy {
+            debug!("Feed has bytes invalid in {}; they were replaced", used.name());
+        }
+        Ok(text.into_owned())
+    }
+
+    /// Check `document`, a feed in `format`, against the item and nesting
+    /// limits. Cheaper than parsing it, so run before that.
+    pub fn check(&self, document: &str, format: FeedFormat) -> Result<(), LimitError> {
+        match format {
+            FeedFormat::Rss => self.check_xml(document),
+            FeedFormat::Json => self.check_json(document),
+        }
+    }
+
+    fn check_xml(&self, xml: &str) -> Result<(), LimitError> {
+        let mut reader = Reader::from_str(xml);
+        let mut depth = 0;
+        let mut items = 0;
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(e)) => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        return Err(LimitError::TooDeep { limit: self.max_depth })
+                    }
+                    if matches!(e.name().as_ref(), b"item" | b"entry") {
+                        items += 1;
+                        if items > self.max_items {
+                            return Err(LimitError::TooManyItems { limit: self.max_items })
+                        }
+                    }
+                },
+                Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+                // Syntax errors are the parser's to report.
+                Ok(Event::Eof) | Err(_) => return Ok(()),
+                _ => ()
+            }
+        }
+    }
+
+    fn check_json(&self, json: &str) -> Result<(), LimitError> {
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        for byte in json.bytes() {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' if in_string => escaped = true,
+                b'"' => in_string = !in_string,
+                _ if in_string => (),
+                b'{' | b'[' => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        return Err(LimitError::TooDeep { limit: self.max_depth })
+                    }
+                },
+                b'}' | b']' => depth = depth.saturating_sub(1),
+                _ => ()
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Items<'a> {
+            #[serde(borrow, default)]
+            items: Vec<&'a RawValue>,
+        }
+        match serde_json::from_str::<Items>(json) {
+            Ok(document) if document.items.len() > self.max_items => Err(LimitError::TooManyItems { limit: self.max_items }),
+            _ => Ok(())
+        }
+    }
+}
+
+/// The `encoding` of the XML declaration at the start of `body`, if any.
+fn xml_encoding(body: &[u8]) -> Option<String> {
+    let head = String::from_utf8_lossy(&body[..body.len().min(200)]);
+    let declaration = &head[head.find("<?xml")?..];
+    let declaration = &declaration[..declaration.find("?>")?];
+    let value = &declaration[declaration.find("encoding")? + "encoding".len()..];
+    let value = value.trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &value[1..];
+    Some(value[..value.find(quote)?].to_string())
+}
+
+/// Why a response isn't a feed at all.
+#[derive(Clone, PartialEq, Eq)]
+pub enum ContentError {
+    Empty,
+    /// A web page, e.g. the show's site, or an error or login page.
+    Html,
+    /// Neither markup nor JSON, e.g. a plain text error message.
+    Other { content_type: Option<String>, start: String },
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ContentError::Empty => write!(f, "the URL returned an empty response, not a feed"),
+            ContentError::Html => write!(f, "the URL returned HTML, not a feed"),
+            ContentError::Other { content_type: Some(content_type), start } => write!(f, "the URL returned {}, not a feed: {:?}", content_type, start),
+            ContentError::Other { content_type: None, start } => write!(f, "the URL returned something other than a feed: {:?}", start),
+        }
+    }
+}
+
+impl fmt::Debug for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Error for ContentError {}
+
+/// Check that `document`, served as `content_type`, looks like a feed
+/// before parsing it: XML or JSON rather than a web page or an error
+/// message. The document decides: a `text/html` type only counts against
+/// markup that names no feed element, as servers do mislabel feeds.
+pub fn check_content(content_type: Option<&str>, document: &str) -> Result<(), ContentError> {
+    let start = document.trim_start_matches('\u{feff}').trim_start();
+    let head = start.as_bytes()[..start.len().min(512)].to_ascii_lowercase();
+    let html_type = content_type.is_some_and(|content_type| content_type.trim().to_ascii_lowercase().starts_with("text/html"));
+    let names_feed = [&b"<rss"[..], b"<feed", b"<rdf:rdf"].iter().any(|tag| head.windows(tag.len()).any(|window| window == *tag));
+    match head.first() {
+        None => Err(ContentError::Empty),
+        Some(b'{') => Ok(()),
+        Some(b'<') if head.starts_with(b"<!doctype html") || head.starts_with(b"<html") => Err(ContentError::Html),
+        Some(b'<') if html_type && !names_feed => Err(ContentError::Html),
+        Some(b'<') => Ok(()),
+        Some(_) => Err(ContentError::Other {
+            content_type: content_type.map(|content_type| content_type.split(';').next().unwrap_or_default().trim().to_string()),
+            start: start.chars().take(80).collect(),
+        })
+    }
+}
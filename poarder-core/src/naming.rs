@@ -0,0 +1,275 @@
+//! Episode file names, built from a `--name-template` such as
+//! `{date}-{title}.{ext}`, optionally in a directory per year.
+
+use chrono::format::{Item, StrftimeItems};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::pipeline::{episode_extension, not_reserved, safe_name};
+use crate::Episode;
+
+/// The scheme episodes have always been named by: `<timestamp>-<title>.<ext>`.
+pub const DEFAULT_TEMPLATE: &str = "{date}-{title}.{ext}";
+
+/// The longest file name, in bytes, most file systems take.
+pub const DEFAULT_MAX_LENGTH: usize = 255;
+
+/// Bytes kept free after a stem for the longest ending it gets: an
+/// extension, `.part`, `.live.mp3` or `.episode.json`.
+const ENDING_ROOM: usize = 16;
+
+/// What a template starts with to put episodes in a directory per year.
+const YEAR_DIR: &str = "{year}/";
+
+/// Fields shortened, in turn, to fit a name in the maximum length.
+const SHORTENED: &[Field] = &[Field::Title, Field::Show, Field::Guid, Field::Date];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Date,
+    Year,
+    Title,
+    Episode,
+    Season,
+    Guid,
+    Show,
+    Ext,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "date" => Field::Date,
+            "year" => Field::Year,
+            "title" => Field::Title,
+            "episode" => Field::Episode,
+            "season" => Field::Season,
+            "guid" => Field::Guid,
+            "show" => Field::Show,
+            "ext" => Field::Ext,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug)]
+pub struct TemplateError(String);
+
+impl Error for TemplateError {}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bad name template: {}", self.0)
+    }
+}
+
+/// How episode files are named.
+#[derive(Debug, Clone)]
+pub struct NameTemplate {
+    segments: Vec<Segment>,
+    /// strftime format for `{date}`; a Unix timestamp when unset.
+    date_format: Option<String>,
+    /// Whether episodes go in a directory per year.
+    year_dir: bool,
+    /// Longest file name, in bytes.
+    max_length: usize,
+}
+
+impl Default for NameTemplate {
+    fn default() -> NameTemplate {
+        NameTemplate::new(DEFAULT_TEMPLATE, None).unwrap()
+    }
+}
+
+impl NameTemplate {
+    /// Parse `template`. It must name the extension (`{ext}`) and at least one
+    /// of `{date}`, `{title}` or `{guid}`, so episodes get names of their own,
+    /// and may start with `{year}/` to file them by year.
+    pub fn new(template: &str, date_format: Option<&str>) -> Result<NameTemplate, TemplateError> {
+        let mut segments = Vec::new();
+        let year_dir = template.starts_with(YEAR_DIR);
+        let file_template = template.strip_prefix(YEAR_DIR).unwrap_or(template);
+        let mut rest = file_template;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(TemplateError(format!("unmatched '}}' in \"{}\"", template)))
+            }
+            let close = match rest[open..].find('}') {
+                Some(close) => open + close,
+                None => return Err(TemplateError(format!("unmatched '{{' in \"{}\"", template)))
+            };
+            if open > 0 {
+                segments.push(Segment::Text(rest[..open].to_string()));
+            }
+            match Field::parse(&rest[open + 1..close]) {
+                Some(field) => segments.push(Segment::Field(field)),
+                None => return Err(TemplateError(format!("unknown placeholder {}", &rest[open..=close])))
+            }
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        let has = |wanted: &[Field]| segments.iter().any(|segment| matches!(segment, Segment::Field(field) if wanted.contains(field)));
+        if !has(&[Field::Ext]) {
+            return Err(TemplateError(format!("\"{}\" has no {{ext}}", template)))
+        }
+        if !has(&[Field::Date, Field::Title, Field::Guid]) {
+            return Err(TemplateError(format!("\"{}\" needs {{date}}, {{title}} or {{guid}} to tell episodes apart", template)))
+        }
+        if file_template.contains(['/', '\\']) {
+            return Err(TemplateError(format!("\"{}\" names a directory; templates name files only, optionally under {}", template, YEAR_DIR)))
+        }
+        let text = segments.iter().filter_map(|segment| match segment {
+            Segment::Text(text) => Some(text.as_str()),
+            Segment::Field(_) => None,
+        });
+        if let Some(bad) = text.flat_map(str::chars).find(|c| matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*') || c.is_control()) {
+            return Err(TemplateError(format!("\"{}\" has '{}', which Windows doesn't allow in file names", template, bad.escape_default())))
+        }
+        if let Some(date_format) = date_format {
+            if StrftimeItems::new(date_format).any(|item| matches!(item, Item::Error)) {
+                return Err(TemplateError(format!("bad date format \"{}\"", date_format)))
+            }
+        }
+
+        Ok(NameTemplate { segments, date_format: date_format.map(str::to_string), year_dir, max_length: DEFAULT_MAX_LENGTH })
+    }
+
+    /// Keep file names to `bytes`, shortening the title first. The default
+    /// suits both Linux and Windows file systems.
+    pub fn max_length(mut self, bytes: usize) -> NameTemplate {
+        self.max_length = bytes;
+        self
+    }
+
+    /// The episode's final file name, relative to the episodes directory:
+    /// under `<year>/` if the template files episodes by year.
+    pub fn file_name(&self, episode: &Episode) -> String {
+        let mut parts = self.render(episode, safe_name);
+        let stem_length = |parts: &[(Option<Field>, String)]| parts.iter()
+            .filter(|(field, _)| *field != Some(Field::Ext))
+            .map(|(_, value)| value.len())
+            .sum::<usize>();
+        let budget = self.max_length.saturating_sub(ENDING_ROOM);
+        for shortened in SHORTENED {
+            let excess = stem_length(&parts).saturating_sub(budget);
+            if excess == 0 {
+                break
+            }
+            for (_, value) in parts.iter_mut().filter(|(field, _)| field == &Some(*shortened)) {
+                let mut keep = value.len().saturating_sub(excess);
+                while !value.is_char_boundary(keep) {
+                    keep -= 1;
+                }
+                value.truncate(keep);
+                value.truncate(value.trim_end_matches(['.', '_', '-']).len());
+            }
+        }
+        self.directory(episode) + &not_reserved(parts.into_iter().map(|(_, value)| value).collect())
+    }
+
+    /// The episode's final file name without its extension.
+    pub fn stem(&self, episode: &Episode) -> String {
+        let mut name = self.file_name(episode);
+        if let Some(len) = name.strip_suffix(episode_extension(episode)).and_then(|stem| stem.strip_suffix('.')).map(str::len) {
+            name.truncate(len);
+        }
+        name
+    }
+
+    /// The episode's `.part` file name and final file name.
+    pub fn file_names(&self, episode: &Episode) -> (String, String) {
+        (format!("{}.part", self.stem(episode)), self.file_name(episode))
+    }
+
+    /// Names the episode may have been archived under before: by the default
+    /// template, the `.mp3` every episode got before extensions followed the
+    /// media type, and with the characters Windows rejects kept in, as names
+    /// were before they had to suit it. Excludes its current name.
+    pub fn earlier_names(&self, episode: &Episode) -> Vec<String> {
+        let current = self.file_name(episode);
+        let unportable = self.render(episode, unportable_name).into_iter().map(|(_, value)| value).fold(self.directory(episode), |name, value| name + &value);
+        let mut candidates = Vec::new();
+        for stem in [default_stem(episode, safe_name), default_stem(episode, unportable_name)] {
+            candidates.push(format!("{}.{}", stem, episode_extension(episode)));
+            candidates.push(format!("{}.mp3", stem));
+        }
+        candidates.push(unportable);
+        let mut names = Vec::new();
+        for name in candidates {
+            if name != current && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// The episode's file in `episodes_dir`, under its current name or an
+    /// earlier one.
+    pub fn existing_file(&self, episodes_dir: &Path, episode: &Episode) -> Option<PathBuf> {
+        std::iter::once(self.file_name(episode))
+            .chain(self.earlier_names(episode))
+            .map(|name| episodes_dir.join(name))
+            .find(|path| path.exists())
+    }
+
+    /// `<year>/` if the template files episodes by year, else nothing.
+    fn directory(&self, episode: &Episode) -> String {
+        match self.year_dir {
+            true => format!("{}/", self.value(Field::Year, episode, safe_name)),
+            false => String::new()
+        }
+    }
+
+    /// Each segment's text, with the field it came from, cleaning up free
+    /// text with `safe`.
+    fn render(&self, episode: &Episode, safe: fn(&str) -> String) -> Vec<(Option<Field>, String)> {
+        self.segments.iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => (None, text.clone()),
+                Segment::Field(field) => (Some(*field), self.value(*field, episode, safe)),
+            })
+            .collect()
+    }
+
+    fn value(&self, field: Field, episode: &Episode, safe: fn(&str) -> String) -> String {
+        match field {
+            Field::Date => match &self.date_format {
+                Some(date_format) => safe(&episode.datetime.format(date_format).to_string()),
+                None => episode.datetime.and_utc().timestamp().to_string()
+            },
+            Field::Year => episode.datetime.format("%Y").to_string(),
+            Field::Title => safe(&episode.title),
+            Field::Episode => episode.number.map(|number| number.to_string()).unwrap_or_default(),
+            Field::Season => episode.season.map(|season| season.to_string()).unwrap_or_default(),
+            Field::Guid => safe(episode.guid.as_deref().unwrap_or(&episode.url)),
+            Field::Show => episode.show.as_deref().map(safe).unwrap_or_default(),
+            Field::Ext => episode_extension(episode).to_string(),
+        }
+    }
+}
+
+/// `<timestamp>-<title>`, the default template's name without an extension.
+fn default_stem(episode: &Episode, safe: fn(&str) -> String) -> String {
+    format!("{}-{}", episode.datetime.and_utc().timestamp(), safe(&episode.title))
+}
+
+/// `title` cleaned up as names were before they had to suit Windows.
+fn unportable_name(title: &str) -> String {
+    title
+        .replace(" ", "_")
+        .replace(":", "-")
+        .replace("/", "-")
+        .replace("\"", "")
+        .replace("\'", "")
+        .replace("*", "a")
+}
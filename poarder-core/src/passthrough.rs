@@ -0,0 +1,57 @@
+//! Podcasting 2.0 (`podcast:`) elements kept verbatim, so tags poarder has
+//! no use for itself (location, season names, trailers, persons, ...)
+//! survive into sidecars and the generated feed.
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::Episode;
+
+const PREFIX: &[u8] = b"podcast:";
+
+/// Every outermost `podcast:` element in `xml`, as written. With
+/// `channel_only`, stops at the first item or entry and leaves out live
+/// items, which are episodes in their own right.
+pub fn collect(xml: &str, channel_only: bool) -> Vec<String> {
+    let mut reader = Reader::from_str(xml);
+    let mut tags = Vec::new();
+
+    loop {
+        let start = reader.buffer_position();
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                let name = element.name();
+                if channel_only && matches!(name.as_ref(), b"item" | b"entry") {
+                    break
+                }
+                if !name.as_ref().starts_with(PREFIX) {
+                    continue
+                }
+                if reader.read_to_end(name).is_err() {
+                    break
+                }
+                if !(channel_only && name.as_ref() == b"podcast:liveItem") {
+                    tags.push(xml[start..reader.buffer_position()].trim().to_string());
+                }
+            },
+            Ok(Event::Empty(element)) if element.name().as_ref().starts_with(PREFIX) => {
+                tags.push(xml[start..reader.buffer_position()].trim().to_string());
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => ()
+        }
+    }
+
+    tags
+}
+
+/// Give each episode the show's `podcast:` elements.
+pub fn inherit_show<'a>(rss_xml: &str, episodes: impl Iterator<Item = &'a mut Episode>) {
+    let tags = collect(rss_xml, true);
+    if tags.is_empty() {
+        return
+    }
+    for episode in episodes {
+        episode.show_podcast_tags = tags.clone();
+    }
+}
@@ -0,0 +1,1251 @@
+//! The per-episode download pipeline.
+//!
+//! Every episode moves through explicit states:
+//!
+//! ```text
+//! Discovered -> Filtered -> Queued -> Downloading -> Verifying -> Stored
+//!       \            \          \            \            \
+//!        `-----------`----------`------------`------------`--> Skipped{reason} / Failed{reason}
+//! ```
+//!
+//! Each transition is logged, and the final state of every episode is
+//! returned to the caller once the run completes.
+//!
+//! Network and disk work run as separate stages joined by a bounded channel.
+//! The network stage streams each download straight into a `.part` file, so
+//! memory use doesn't grow with episode size; the storage stage moves it
+//! into place and copies it to the other targets. Downloads pause when the
+//! storage stage falls behind (e.g. copying to a slow NAS), and it never
+//! waits on the network while work is queued.
+
+use futures::{future, stream, StreamExt};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::{JoinError, JoinSet};
+
+use crate::db::EpisodeDb;
+use crate::disk;
+use crate::eta::Eta;
+use crate::fair::FairShare;
+use crate::fixtures::Http;
+use crate::hosts::{self, HostLimiter};
+use crate::naming::NameTemplate;
+use crate::plugins::PluginHost;
+use crate::resolvers::Resolvers;
+use crate::shard::Checkpoint;
+use crate::shutdown::Shutdown;
+use crate::sidecar;
+use crate::state::{Claim, SharedState};
+use crate::stats::{HostStatsStore, TimeToArchiveStore};
+use crate::throttle::RateLimit;
+use crate::Episode;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
+pub enum EpisodeState {
+    Discovered,
+    Filtered,
+    Queued,
+    Downloading,
+    Verifying,
+    Stored,
+    Skipped(String),
+    Failed(String),
+}
+
+impl EpisodeState {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, EpisodeState::Stored | EpisodeState::Skipped(_) | EpisodeState::Failed(_))
+    }
+}
+
+impl fmt::Display for EpisodeState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EpisodeState::Discovered => write!(f, "discovered"),
+            EpisodeState::Filtered => write!(f, "filtered"),
+            EpisodeState::Queued => write!(f, "queued"),
+            EpisodeState::Downloading => write!(f, "downloading"),
+            EpisodeState::Verifying => write!(f, "verifying"),
+            EpisodeState::Stored => write!(f, "stored"),
+            EpisodeState::Skipped(reason) => write!(f, "skipped ({})", reason),
+            EpisodeState::Failed(reason) => write!(f, "failed ({})", reason),
+        }
+    }
+}
+
+/// An episode together with where it is in the pipeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct EpisodeJob {
+    pub episode: Episode,
+    pub state: EpisodeState,
+    /// Hex SHA-256 of the enclosure, computed as it was downloaded.
+    pub sha256: Option<String>,
+    /// Bytes downloaded for the episode this run, not counting those a
+    /// resumed `.part` file already had.
+    pub bytes: u64,
+}
+
+impl EpisodeJob {
+    pub fn new(episode: Episode) -> EpisodeJob {
+        debug!("{}: {}", episode.title, EpisodeState::Discovered);
+        EpisodeJob { episode, state: EpisodeState::Discovered, sha256: None, bytes: 0 }
+    }
+
+    pub fn advance(&mut self, next: EpisodeState) {
+        if self.state.is_terminal() {
+            warn!("{}: ignoring move from final state {} to {}", self.episode.title, self.state, next);
+            return
+        }
+
+        match &next {
+            EpisodeState::Failed(_) => error!("{}: {}", self.episode.title, next),
+            EpisodeState::Stored | EpisodeState::Skipped(_) => info!("{}: {}", self.episode.title, next),
+            _ => debug!("{}: {} -> {}", self.episode.title, self.state, next),
+        }
+        self.state = next;
+    }
+
+    fn skip(mut self, reason: impl Into<String>) -> Fetched {
+        self.advance(EpisodeState::Skipped(reason.into()));
+        Fetched { job: self, source: None, len: 0, expected_len: None, claim: None }
+    }
+
+    fn fail(mut self, reason: impl Into<String>) -> Fetched {
+        self.advance(EpisodeState::Failed(reason.into()));
+        Fetched { job: self, source: None, len: 0, expected_len: None, claim: None }
+    }
+}
+
+/// What a finished run achieved.
+#[derive(Debug, Default, Clone)]
+pub struct RunSummary {
+    pub stored: usize,
+    /// Episodes not downloaded, mostly because they were already archived.
+    pub skipped: usize,
+    pub failed: usize,
+    /// Bytes downloaded for the stored episodes.
+    pub bytes: u64,
+    /// Title and reason of each failed episode.
+    pub failures: Vec<(String, String)>,
+    /// Title of each episode downloaded despite suspicious metadata, and
+    /// what is suspicious about it.
+    pub anomalies: Vec<(String, String)>,
+    /// Feed items left out for being malformed, and what is wrong with each.
+    pub malformed: Vec<(String, String)>,
+    /// When the next scheduled live stream starts, with `--record-live`.
+    pub next_live: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl RunSummary {
+    /// Count the outcomes of `jobs`.
+    pub fn count(&mut self, jobs: &[EpisodeJob]) {
+        for job in jobs {
+            match &job.state {
+                EpisodeState::Stored => {
+                    self.stored += 1;
+                    self.bytes += job.bytes;
+                },
+                EpisodeState::Skipped(_) => self.skipped += 1,
+                EpisodeState::Failed(reason) => {
+                    self.failed += 1;
+                    self.failures.push((crate::element_text(&job.episode.title), reason.clone()));
+                },
+                _ => ()
+            }
+        }
+    }
+
+    /// Add the summary of another part of the run, e.g. another feed.
+    pub fn add(&mut self, other: RunSummary) {
+        self.stored += other.stored;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.failures.extend(other.failures);
+        self.anomalies.extend(other.anomalies);
+        self.malformed.extend(other.malformed);
+        self.next_live = match (self.next_live, other.next_live) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b)
+        };
+    }
+}
+
+/// E.g. `3 downloaded (120.5 MB), 40 skipped, 1 failed`.
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} downloaded ({}), {} skipped, {} failed", self.stored, crate::eta::bytes(self.bytes), self.skipped, self.failed)?;
+        if !self.anomalies.is_empty() {
+            write!(f, ", {} suspicious", self.anomalies.len())?;
+        }
+        if !self.malformed.is_empty() {
+            write!(f, ", {} malformed", self.malformed.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the pipeline stages share for a run.
+pub struct DownloadContext {
+    pub http: Arc<Http>,
+    /// Feed being archived; `None` for episodes grabbed by URL.
+    pub feed_url: Option<String>,
+    /// Episode directories of every target; the first is the primary archive.
+    pub episode_dirs: Vec<PathBuf>,
+    /// Where `.part` files are written; next to the episode when unset.
+    pub tmp_dir: Option<PathBuf>,
+    /// Output directories found full or read-only during this run.
+    pub storage: disk::StorageGate,
+    /// Pause on a full or read-only target instead of giving up on it.
+    pub wait_for_space: bool,
+    /// Stop the run at the first failed episode instead of carrying on.
+    pub fail_fast: bool,
+    /// Leave the `.part` files of failed downloads in place, even ones that
+    /// can't be resumed.
+    pub keep_partial: bool,
+    /// Fail downloads whose size isn't the `length` of the feed's enclosure.
+    pub check_enclosure_length: bool,
+    /// Stops the run early when requested, e.g. on Ctrl+C.
+    pub shutdown: Arc<Shutdown>,
+    pub permissions: disk::Permissions,
+    /// Progress record when this run handles one `--shard`.
+    pub checkpoint: Option<Checkpoint>,
+    pub replace_existing: bool,
+    /// Bytes buffered per write syscall when storing episodes.
+    pub write_buffer: usize,
+    /// Write episodes through io_uring instead of blocking writes.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub io_uring: bool,
+    /// Write ID3v2 tags from the feed into stored MP3s.
+    #[cfg(feature = "id3")]
+    pub id3: bool,
+    /// Embed each episode's `<itunes:image>` (else the show's) in its tags.
+    #[cfg(feature = "id3")]
+    pub embed_artwork: Option<Arc<crate::tags::Artwork>>,
+    pub shared_state: Option<SharedState>,
+    pub plugins: Arc<PluginHost>,
+    pub resolvers: Resolvers,
+    pub host_limiter: Arc<HostLimiter>,
+    /// Download slots shared with the other feeds of the run, if any.
+    pub fair_share: Option<Arc<FairShare>>,
+    /// Cap on the combined rate of every download sharing it.
+    pub max_rate: Option<Arc<RateLimit>>,
+    /// Cap on each download's own rate, in bytes per second.
+    pub max_rate_per_download: Option<u64>,
+    /// Tracks what is left to download, for estimating when it will be done.
+    pub eta: Option<Arc<Eta>>,
+    /// Progress bars for the downloads, when on a terminal.
+    #[cfg(feature = "progress")]
+    pub progress: Option<Arc<crate::progress::Progress>>,
+    pub host_stats: Arc<HostStatsStore>,
+    pub time_to_archive: Option<Arc<TimeToArchiveStore>>,
+    /// Record of archived episodes; without it, file names alone decide
+    /// what is already archived.
+    pub episodes_db: Option<Arc<EpisodeDb>>,
+    pub naming: NameTemplate,
+}
+
+impl DownloadContext {
+    /// A context with defaults: one target directory `episodes_dir`, no
+    /// plugins, resolvers or host overrides, and statistics kept in
+    /// `state_dir`. Adjust the public fields for anything else.
+    pub fn new(http: Http, episodes_dir: &Path, state_dir: &Path) -> Result<DownloadContext, Box<dyn std::error::Error>> {
+        fs::create_dir_all(episodes_dir)?;
+        fs::create_dir_all(state_dir)?;
+        Ok(DownloadContext {
+            http: Arc::new(http),
+            feed_url: None,
+            episode_dirs: vec![episodes_dir.to_path_buf()],
+            tmp_dir: None,
+            storage: disk::StorageGate::default(),
+            wait_for_space: false,
+            fail_fast: false,
+            keep_partial: false,
+            check_enclosure_length: false,
+            shutdown: Arc::new(Shutdown::default()),
+            permissions: disk::Permissions::default(),
+            checkpoint: None,
+            replace_existing: false,
+            write_buffer: 1 << 20,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            #[cfg(feature = "id3")]
+            id3: false,
+            #[cfg(feature = "id3")]
+            embed_artwork: None,
+            shared_state: None,
+            plugins: Arc::new(PluginHost::default()),
+            resolvers: Resolvers::new(&[]),
+            host_limiter: Arc::new(HostLimiter::new(&Default::default())),
+            fair_share: None,
+            max_rate: None,
+            max_rate_per_download: None,
+            eta: None,
+            #[cfg(feature = "progress")]
+            progress: None,
+            host_stats: Arc::new(HostStatsStore::open(state_dir)?),
+            time_to_archive: None,
+            episodes_db: Some(Arc::new(EpisodeDb::open(state_dir)?)),
+            naming: NameTemplate::default(),
+        })
+    }
+}
+
+/// Archives episodes: downloads each one not already stored and writes it
+/// to every target of its [`DownloadContext`].
+pub struct Downloader {
+    ctx: Arc<DownloadContext>,
+    task_count: usize,
+    write_queue: usize,
+}
+
+impl Downloader {
+    /// A downloader running 4 downloads at a time, with 2 finished ones
+    /// allowed to wait for the disk.
+    pub fn new(ctx: DownloadContext) -> Downloader {
+        Downloader { ctx: Arc::new(ctx), task_count: 4, write_queue: 2 }
+    }
+
+    /// Set how many downloads run at once and how many finished ones may
+    /// wait for the disk writer.
+    pub fn concurrency(mut self, task_count: usize, write_queue: usize) -> Downloader {
+        self.task_count = task_count.max(1);
+        self.write_queue = write_queue.max(1);
+        self
+    }
+
+    pub fn context(&self) -> &DownloadContext {
+        &self.ctx
+    }
+
+    /// Archive `episodes`, returning each with its final state.
+    pub async fn download(&self, episodes: Vec<Episode>) -> Vec<EpisodeJob> {
+        let jobs = episodes.into_iter()
+            .map(|episode| {
+                let mut job = EpisodeJob::new(episode);
+                job.advance(EpisodeState::Filtered);
+                job
+            })
+            .collect();
+        self.run(jobs).await
+    }
+
+    /// Push already filtered `jobs` through the pipeline.
+    pub async fn run(&self, jobs: Vec<EpisodeJob>) -> Vec<EpisodeJob> {
+        info!("Downloading {} episodes with {} tasks", jobs.len(), self.task_count);
+        run(self.ctx.clone(), jobs, self.task_count, self.write_queue).await
+    }
+
+    /// Tell plugins the run is over, stop them and save statistics.
+    /// `stored` is the number of episodes stored by this run.
+    pub async fn finish(self, stored: usize) {
+        self.ctx.plugins.run_complete(self.ctx.feed_url.as_deref(), stored).await;
+        self.ctx.plugins.shutdown().await;
+
+        if let Err(e) = self.ctx.host_stats.save() {
+            error!("Failed to save host statistics. Error: {}", e);
+        }
+        if let Some(time_to_archive) = &self.ctx.time_to_archive {
+            if let Err(e) = time_to_archive.save() {
+                error!("Failed to save time-to-archive statistics. Error: {}", e);
+            }
+        }
+    }
+}
+
+/// Output of the network stage, handed to the storage stage.
+pub struct Fetched {
+    pub job: EpisodeJob,
+    source: Option<Source>,
+    /// Size of the episode in bytes.
+    len: u64,
+    /// Size announced by the server, used to preallocate files.
+    expected_len: Option<u64>,
+    claim: Option<Claim>,
+}
+
+/// Where the storage stage gets an episode's contents from.
+enum Source {
+    /// A finished download, moved into place by the storage stage.
+    Part(PathBuf),
+    /// A copy already in one of the targets, for the targets missing it.
+    Copy(PathBuf),
+}
+
+/// Run `jobs` through the network and storage stages, with at most
+/// `task_count` downloads in flight and `write_queue` finished downloads
+/// waiting for the writer. Downloads get a task each only on a multi-thread
+/// runtime; on a current-thread one that would buy nothing, so they are
+/// polled in place. Either way nothing is left running once this returns.
+/// With `fail_fast`, the first failure cancels the downloads in flight
+/// (their `.part` files are kept for resuming) and the rest aren't started.
+/// A requested shutdown starts no more either, but lets the downloads in
+/// flight stop on their own and stores what they finished.
+pub async fn run(ctx: Arc<DownloadContext>, jobs: Vec<EpisodeJob>, task_count: usize, write_queue: usize) -> Vec<EpisodeJob> {
+    let (tx, mut rx) = mpsc::channel::<Fetched>(write_queue.max(1));
+    let spawn = tokio::runtime::Handle::current().runtime_flavor() != tokio::runtime::RuntimeFlavor::CurrentThread;
+    let failed = AtomicBool::new(false);
+
+    let writer = async {
+        let mut finished = Vec::new();
+        while let Some(fetched) = rx.recv().await {
+            let job = store(&ctx, fetched).await;
+            if let Some(checkpoint) = &ctx.checkpoint {
+                record_progress(&ctx, checkpoint, &job);
+            }
+            if ctx.fail_fast && matches!(job.state, EpisodeState::Failed(_)) {
+                failed.store(true, Ordering::Relaxed);
+            }
+            finished.push(job);
+        }
+        finished
+    };
+
+    let downloads = async {
+        let mut jobs = jobs.into_iter();
+        if spawn {
+            // Dropping the set aborts whatever is still in it, so no
+            // download can outlive the run.
+            let mut tasks = JoinSet::new();
+            while !failed.load(Ordering::Relaxed) {
+                if tasks.len() >= task_count {
+                    if let Some(fetched) = tasks.join_next().await {
+                        hand_over(&ctx, &tx, &failed, fetched).await;
+                    }
+                    continue
+                }
+                if ctx.shutdown.is_requested() {
+                    break
+                }
+                let Some(mut job) = jobs.next() else {
+                    break
+                };
+                job.advance(EpisodeState::Queued);
+                let ctx = ctx.clone();
+                tasks.spawn(async move { fetch(&ctx, job).await });
+            }
+            while !failed.load(Ordering::Relaxed) {
+                let Some(fetched) = tasks.join_next().await else {
+                    break
+                };
+                hand_over(&ctx, &tx, &failed, fetched).await;
+            }
+            if failed.load(Ordering::Relaxed) {
+                warn!("Stopping at the first failure: {} downloads cancelled, {} not started", tasks.len(), jobs.len());
+            }
+        }
+        else {
+            let shutdown = &ctx.shutdown;
+            stream::iter(std::iter::from_fn(|| match shutdown.is_requested() {
+                true => None,
+                false => jobs.next()
+            }))
+                .map(|mut job| {
+                    job.advance(EpisodeState::Queued);
+                    fetch(&ctx, job)
+                })
+                .buffer_unordered(task_count)
+                .take_while(|_| future::ready(!failed.load(Ordering::Relaxed)))
+                .for_each(|fetched| hand_over(&ctx, &tx, &failed, Ok(fetched)))
+                .await;
+            if failed.load(Ordering::Relaxed) {
+                warn!("Stopping at the first failure: {} downloads not started", jobs.len());
+            }
+        }
+        if ctx.shutdown.is_requested() && !failed.load(Ordering::Relaxed) && !jobs.as_slice().is_empty() {
+            warn!("Stopping: {} downloads not started", jobs.len());
+        }
+        drop(tx);
+    };
+
+    tokio::join!(downloads, writer).1
+}
+
+/// Pass a finished download on to the storage stage, waiting while its
+/// queue is full. A failed download sets `failed` right away under
+/// `fail_fast`, rather than once the storage stage gets to it.
+async fn hand_over(ctx: &DownloadContext, tx: &mpsc::Sender<Fetched>, failed: &AtomicBool, fetched: Result<Fetched, JoinError>) {
+    match fetched {
+        Ok(fetched) => {
+            if ctx.fail_fast && matches!(fetched.job.state, EpisodeState::Failed(_)) {
+                failed.store(true, Ordering::Relaxed);
+            }
+            if tx.send(fetched).await.is_err() {
+                error!("Writer stage stopped unexpectedly");
+            }
+        },
+        Err(e) => {
+            error!("Got a tokio::JoinError: {}", e);
+            if ctx.fail_fast {
+                failed.store(true, Ordering::Relaxed);
+            }
+        },
+    }
+}
+
+/// Add `job` to the shard checkpoint once its file is in the primary archive.
+fn record_progress(ctx: &DownloadContext, checkpoint: &Checkpoint, job: &EpisodeJob) {
+    let (_, name) = ctx.naming.file_names(&job.episode);
+    let archived = match &job.state {
+        EpisodeState::Stored => true,
+        EpisodeState::Skipped(_) => ctx.naming.existing_file(&ctx.episode_dirs[0], &job.episode).is_some(),
+        _ => false
+    };
+
+    if archived {
+        if let Err(e) = checkpoint.record(&name) {
+            error!("Failed to update shard checkpoint. Error: {}", e);
+        }
+    }
+}
+
+/// Whether every target already holds `episode`, going by the episode
+/// database or the file names, so [`fetch`] would skip it.
+pub fn is_archived(ctx: &DownloadContext, episode: &Episode) -> bool {
+    if ctx.replace_existing {
+        return false
+    }
+    let record = ctx.episodes_db.as_ref().and_then(|db| db.get(episode).ok().flatten());
+    ctx.episode_dirs.iter().all(|dir| match &record {
+        Some(record) if dir.join(&record.file).exists() => true,
+        _ => ctx.naming.existing_file(dir, episode).is_some()
+    })
+}
+
+/// Network stage: work out whether the episode is needed and download it.
+pub async fn fetch(ctx: &DownloadContext, job: EpisodeJob) -> Fetched {
+    let url = job.episode.url.clone();
+    let fetched = fetch_episode(ctx, job).await;
+    if let Some(eta) = &ctx.eta {
+        eta.finished(&url);
+    }
+    #[cfg(feature = "progress")]
+    if let Some(progress) = &ctx.progress {
+        progress.finished(&url);
+    }
+    fetched
+}
+
+async fn fetch_episode(ctx: &DownloadContext, job: EpisodeJob) -> Fetched {
+    let record = match &ctx.episodes_db {
+        Some(db) => db.get(&job.episode).unwrap_or_else(|e| {
+            warn!("{}: could not look up in the episode database. Error: {}", job.episode.title, e);
+            None
+        }),
+        None => None
+    };
+    if let Some(record) = record.as_ref().filter(|_| !ctx.replace_existing) {
+        if ctx.episode_dirs.iter().all(|dir| dir.join(&record.file).exists()) {
+            return job.skip(format!("archived as {}", record.file))
+        }
+    }
+
+    let (_, name_with_true_ext) = ctx.naming.file_names(&job.episode);
+    let (present, missing): (Vec<PathBuf>, Vec<PathBuf>) = ctx.episode_dirs.iter()
+        .map(|dir| ctx.naming.existing_file(dir, &job.episode).unwrap_or_else(|| dir.join(&name_with_true_ext)))
+        .partition(|path| path.exists());
+
+    if !ctx.replace_existing && missing.is_empty() {
+        if let (Some(db), None) = (&ctx.episodes_db, &record) {
+            // Archived before there was a database.
+            let size = fs::metadata(&present[0]).map_or(0, |metadata| metadata.len());
+            let file = ctx.episode_dirs.iter().find_map(|dir| present[0].strip_prefix(dir).ok())
+                .map_or_else(|| name_with_true_ext.clone(), |name| name.to_string_lossy().into_owned());
+            if let Err(e) = db.record(&job.episode, &file, size, None) {
+                warn!("{}: could not update the episode database. Error: {}", job.episode.title, e);
+            }
+        }
+        return job.skip(format!("{} exists", present[0].display()))
+    }
+
+    if !ctx.wait_for_space && ctx.storage.all_blocked(ctx.episode_dirs.iter()) {
+        return job.fail("no writable output directory")
+    }
+
+    let mut job = job;
+    if !ctx.replace_existing && !present.is_empty() {
+        // Fill in the mirrors from a copy we already have instead of downloading again.
+        job.advance(EpisodeState::Downloading);
+        info!("Copying {} from {}", &job.episode.title, present[0].display());
+        let source = present[0].clone();
+        return match tokio::task::spawn_blocking(move || sha256_file(&source)).await.unwrap_or_else(|e| Err(io::Error::other(e))) {
+            Ok((len, sha256)) => {
+                job.advance(EpisodeState::Verifying);
+                job.sha256 = Some(sha256);
+                Fetched { job, source: Some(Source::Copy(present[0].clone())), len, expected_len: Some(len), claim: None }
+            },
+            Err(e) => job.fail(format!("could not read {}: {}", present[0].display(), e))
+        }
+    }
+
+    let claim = match &ctx.shared_state {
+        Some(shared_state) => match shared_state.try_claim(ctx.feed_url.as_deref(), &name_with_true_ext) {
+            Ok(Some(claim)) => Some(claim),
+            Ok(None) => return job.skip("claimed by another instance"),
+            Err(e) => return job.fail(format!("could not claim: {}", e))
+        },
+        None => None
+    };
+
+    if !ctx.plugins.pre_download(&job.episode).await {
+        return job.skip("vetoed by plugin")
+    }
+
+    let media_url = match ctx.resolvers.find(&job.episode.url) {
+        Some(resolver) => match ctx.resolvers.resolve(resolver, &job.episode.url).await {
+            Ok(media_url) => media_url,
+            Err(e) => return job.fail(e.to_string())
+        },
+        None if job.episode.needs_resolver => {
+            let reason = format!("{} is a web page and no resolver is configured for it", job.episode.url);
+            return job.fail(reason)
+        },
+        None => job.episode.url.clone()
+    };
+
+    // Targets the episode goes to; the download lands in one of them.
+    let targets: Vec<&Path> = match ctx.replace_existing {
+        true => ctx.episode_dirs.iter().map(PathBuf::as_path).collect(),
+        false => ctx.episode_dirs.iter().filter(|dir| missing.iter().any(|path| path.starts_with(dir))).map(PathBuf::as_path).collect()
+    };
+    let (name_with_part_ext, _) = ctx.naming.file_names(&job.episode);
+
+    job.advance(EpisodeState::Downloading);
+    match download(ctx, &job.episode, &media_url, &name_with_part_ext, &targets).await {
+        Ok(downloaded) => {
+            debug!("Got {} bytes, SHA-256 {}", downloaded.len, downloaded.sha256);
+            if known_extension(&job.episode).is_none() {
+                // Name the file after what the server says it sent.
+                if let Some(content_type) = downloaded.content_type.filter(|content_type| extension_for_type(content_type).is_some()) {
+                    job.episode.enclosure_type = Some(content_type);
+                }
+            }
+            job.advance(EpisodeState::Verifying);
+            if let Some(checksum) = &job.episode.checksum {
+                if *checksum != downloaded.sha256 {
+                    discard_part(ctx, &downloaded.path);
+                    let reason = format!("SHA-256 {} does not match the feed's {}", downloaded.sha256, checksum);
+                    return job.fail(reason)
+                }
+                info!("{}: SHA-256 matches the feed", job.episode.title);
+            }
+            // Many feeds give a placeholder 0, or a size from before ads were inserted.
+            if let Some(length) = job.episode.enclosure_length.filter(|length| ctx.check_enclosure_length && *length > 0) {
+                if length != downloaded.len {
+                    let reason = format!("got {} bytes where the feed's enclosure says {}", downloaded.len, length);
+                    return job.fail(reason)
+                }
+            }
+            job.sha256 = Some(downloaded.sha256);
+            job.bytes = downloaded.len - downloaded.resumed_from;
+            Fetched {
+                job,
+                source: Some(Source::Part(downloaded.path)),
+                len: downloaded.len,
+                expected_len: downloaded.expected_len,
+                claim,
+            }
+        },
+        Err(e) => job.fail(e.to_string())
+    }
+}
+
+struct Downloaded {
+    /// The `.part` file holding the episode.
+    path: PathBuf,
+    len: u64,
+    /// Bytes already in the `.part` file when the download was resumed.
+    resumed_from: u64,
+    /// Content-Length, if the server sent one.
+    expected_len: Option<u64>,
+    content_type: Option<String>,
+    sha256: String,
+}
+
+#[derive(Debug)]
+enum DownloadError {
+    Http(reqwest::Error),
+    /// Writing the `.part` file failed.
+    Write(io::Error),
+    /// The body ended short of the Content-Length the server announced,
+    /// with what arrived kept in the `.part` file.
+    Truncated { expected: u64, received: u64 },
+    /// Stopped by a shutdown, with what arrived kept in the `.part` file.
+    Interrupted,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Http(e) => write!(f, "{}", e),
+            DownloadError::Write(e) => write!(f, "could not write the download: {}", e),
+            DownloadError::Truncated { expected, received } => {
+                write!(f, "the download was cut short: got {} of the {} bytes the server announced", received, expected)
+            },
+            DownloadError::Interrupted => write!(f, "interrupted"),
+        }
+    }
+}
+
+/// Delete the `.part` file of a failed download, unless `--keep-partial`.
+fn discard_part(ctx: &DownloadContext, part: &Path) {
+    if ctx.keep_partial {
+        info!("Keeping {}", part.display());
+        return
+    }
+    let _ = fs::remove_file(part);
+}
+
+/// Where to write the `.part` file for a download going to `targets`:
+/// `--tmp-dir`, else the first target that is writable.
+fn part_dir<'a>(ctx: &'a DownloadContext, targets: &[&'a Path]) -> &'a Path {
+    match &ctx.tmp_dir {
+        Some(tmp_dir) => tmp_dir,
+        None => targets.iter().find(|dir| !ctx.storage.is_blocked(dir)).or(targets.first()).copied()
+            .unwrap_or(&ctx.episode_dirs[0])
+    }
+}
+
+/// Download an enclosure into a `.part` file named `part_name`, applying its
+/// host profile's limits and retries. A `.part` file left by an interrupted
+/// attempt (or run) is resumed where the server supports range requests.
+async fn download(ctx: &DownloadContext, episode: &Episode, url: &str, part_name: &str, targets: &[&Path]) -> Result<Downloaded, DownloadError> {
+    let title = &episode.title;
+    let profile = ctx.host_limiter.profile_for(url);
+    let host = hosts::host_of(url);
+    let mut attempt = 0;
+
+    loop {
+        let part_dir = part_dir(ctx, targets);
+        let part_path = part_dir.join(part_name);
+        let started = Instant::now();
+        let result = {
+            let _slot = match (&ctx.fair_share, &ctx.feed_url) {
+                (Some(fair_share), Some(feed_url)) => Some(fair_share.acquire(feed_url).await),
+                _ => None
+            };
+            let _permit = ctx.host_limiter.acquire(url).await;
+            // Waiting for a slot may have outlasted the run.
+            if ctx.shutdown.is_requested() {
+                return Err(DownloadError::Interrupted)
+            }
+            let received = fs::metadata(&part_path).map_or(0, |metadata| metadata.len());
+            match received {
+                0 => info!("Downloading {}", title),
+                _ => info!("Resuming {} after {} bytes", title, received)
+            }
+            // `{year}/` in the name template files episodes in directories of their own.
+            match part_path.parent().map_or(Ok(()), fs::create_dir_all) {
+                Err(e) => Err(DownloadError::Write(e)),
+                Ok(()) => match send_from(ctx, url, received).await {
+                    Ok((resp, offset)) => write_body(ctx, episode, resp, &part_path, offset).await,
+                    Err(e) => Err(DownloadError::Http(e))
+                }
+            }
+        };
+        // What was received before a network error is kept for the retry.
+        if let Err(DownloadError::Write(_)) = result {
+            discard_part(ctx, &part_path);
+        }
+
+        let retrying = match &result {
+            Err(DownloadError::Http(e)) => attempt < profile.retries && hosts::should_retry(e),
+            Err(DownloadError::Truncated { .. }) => attempt < profile.retries,
+            _ => false
+        };
+        match &result {
+            Ok(downloaded) => ctx.host_stats.record_success(&host, (downloaded.len - downloaded.resumed_from) as usize, started.elapsed()),
+            Err(DownloadError::Http(_)) | Err(DownloadError::Truncated { .. }) => ctx.host_stats.record_failure(&host, retrying),
+            Err(DownloadError::Write(_)) | Err(DownloadError::Interrupted) => ()
+        }
+
+        match result {
+            Ok(downloaded) => return Ok(downloaded),
+            Err(e @ (DownloadError::Http(_) | DownloadError::Truncated { .. })) if retrying => {
+                let delay = Duration::from_millis(profile.retry_backoff_ms << attempt.min(16));
+                warn!("Download of {} failed, retrying in {:?}. Error: {}", title, delay, e);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            },
+            Err(DownloadError::Write(e)) if disk::is_storage_unavailable(&e) => {
+                if ctx.storage.block(part_dir, &e) {
+                    ctx.plugins.storage_unavailable(&part_dir.to_string_lossy(), &e.to_string()).await;
+                }
+                if ctx.wait_for_space {
+                    ctx.storage.wait_until_writable(part_dir, 0).await;
+                    continue
+                }
+                // Try another target, if one is still usable.
+                if ctx.tmp_dir.is_some() || targets.iter().all(|dir| ctx.storage.is_blocked(dir)) {
+                    return Err(DownloadError::Write(e))
+                }
+            },
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+/// Request `url` from byte `offset` on, for resuming a download. Returns
+/// the response and the offset its body actually starts at: 0 when the
+/// server ignored the range (or rejected it, in which case the download is
+/// requested again in full).
+async fn send_from(ctx: &DownloadContext, url: &str, offset: u64) -> Result<(reqwest::Response, u64), reqwest::Error> {
+    if offset > 0 {
+        let request = ctx.http.get(url).header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        let resp = ctx.http.send(request).await?;
+        match resp.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT if range_start(&resp) == Some(offset) => return Ok((resp, offset)),
+            reqwest::StatusCode::OK => {
+                info!("{} doesn't support resuming; downloading from the start", hosts::host_of(url));
+                return Ok((resp, 0))
+            },
+            status if status.is_success() || status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                debug!("Could not resume {} ({}); downloading from the start", url, status);
+            },
+            _ => return Ok((resp.error_for_status()?, 0))
+        }
+    }
+    let resp = ctx.http.send(ctx.http.get(url)).await?.error_for_status()?;
+    Ok((resp, 0))
+}
+
+/// First byte of a 206 response, from its `Content-Range: bytes N-M/T`.
+fn range_start(resp: &reqwest::Response) -> Option<u64> {
+    let range = resp.headers().get(reqwest::header::CONTENT_RANGE)?.to_str().ok()?;
+    range.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+/// Write a response body to `part_path` chunk by chunk as it arrives,
+/// hashing it on the way, so memory use stays flat however large the
+/// episode and the checksum costs no second pass over the data. With an
+/// `offset`, the body continues the first `offset` bytes already there.
+/// Progress is counted against `episode`.
+async fn write_body(ctx: &DownloadContext, episode: &Episode, mut resp: reqwest::Response, part_path: &Path, offset: u64) -> Result<Downloaded, DownloadError> {
+    let expected_len = resp.content_length().map(|len| offset + len);
+    let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let mut hasher = Sha256::new();
+    let file = match offset {
+        0 => File::options().write(true).create(true).truncate(true).open(part_path),
+        _ => {
+            let part = part_path.to_path_buf();
+            let prefix = tokio::task::spawn_blocking(move || hash_file(&part, offset)).await
+                .unwrap_or_else(|e| Err(io::Error::other(e)));
+            match prefix {
+                Ok(prefix) => hasher = prefix,
+                Err(e) => return Err(DownloadError::Write(e))
+            }
+            File::options().append(true).open(part_path)
+        }
+    }.map_err(DownloadError::Write)?;
+    if let Some(len) = expected_len {
+        disk::preallocate(&file, len).map_err(DownloadError::Write)?;
+    }
+    #[cfg(feature = "progress")]
+    if let Some(progress) = &ctx.progress {
+        progress.started(episode, expected_len, offset);
+    }
+
+    let mut writer = tokio::io::BufWriter::with_capacity(ctx.write_buffer.max(1), tokio::fs::File::from_std(file));
+    let mut len = offset;
+    let connection_rate = ctx.max_rate_per_download.map(RateLimit::new);
+    loop {
+        let chunk = tokio::select! {
+            chunk = resp.chunk() => chunk,
+            _ = ctx.shutdown.requested() => {
+                writer.flush().await.map_err(DownloadError::Write)?;
+                return Err(DownloadError::Interrupted)
+            }
+        };
+        let chunk = match chunk {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                // Keep what arrived so far for resuming.
+                let _ = writer.flush().await;
+                return Err(DownloadError::Http(e))
+            }
+        };
+        hasher.update(&chunk);
+        writer.write_all(&chunk).await.map_err(DownloadError::Write)?;
+        len += chunk.len() as u64;
+        if let Some(eta) = &ctx.eta {
+            eta.received(&episode.url, chunk.len() as u64);
+        }
+        #[cfg(feature = "progress")]
+        if let Some(progress) = &ctx.progress {
+            progress.received(&episode.url, chunk.len() as u64);
+        }
+        if ctx.max_rate.is_some() || connection_rate.is_some() {
+            let pace = async {
+                if let Some(limit) = &ctx.max_rate {
+                    limit.take(chunk.len()).await;
+                }
+                if let Some(limit) = &connection_rate {
+                    limit.take(chunk.len()).await;
+                }
+            };
+            tokio::select! {
+                () = pace => (),
+                _ = ctx.shutdown.requested() => {
+                    writer.flush().await.map_err(DownloadError::Write)?;
+                    return Err(DownloadError::Interrupted)
+                }
+            }
+        }
+    }
+    writer.flush().await.map_err(DownloadError::Write)?;
+    // Release space reserved past the end if the body was shorter than announced.
+    writer.get_ref().set_len(len).await.map_err(DownloadError::Write)?;
+    if let Some(expected) = expected_len.filter(|expected| len < *expected) {
+        return Err(DownloadError::Truncated { expected, received: len })
+    }
+
+    Ok(Downloaded {
+        path: part_path.to_path_buf(),
+        len,
+        resumed_from: offset,
+        expected_len,
+        content_type,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// The SHA-256 state after the first `len` bytes of the file at `path`.
+fn hash_file(path: &Path, len: u64) -> io::Result<Sha256> {
+    let mut hasher = Sha256::new();
+    let hashed = io::copy(&mut io::Read::take(File::open(path)?, len), &mut hasher)?;
+    if hashed < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} is shorter than expected", path.display())))
+    }
+    Ok(hasher)
+}
+
+/// Size and hex SHA-256 of the file at `path`.
+fn sha256_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let len = io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok((len, format!("{:x}", hasher.finalize())))
+}
+
+/// Storage stage: put a fetched episode into every target that needs it.
+pub async fn store(ctx: &DownloadContext, fetched: Fetched) -> EpisodeJob {
+    let Fetched { mut job, source, len, expected_len, claim: _claim } = fetched;
+    let source = match source {
+        Some(source) if job.state == EpisodeState::Verifying => source,
+        _ => return job
+    };
+
+    if len == 0 {
+        if let Source::Part(part) = &source {
+            discard_part(ctx, part);
+        }
+        job.advance(EpisodeState::Failed("empty response".to_string()));
+        return job
+    }
+
+    let (name_with_part_ext, name_with_true_ext) = ctx.naming.file_names(&job.episode);
+
+    // Tagged before any target gets a copy, so they all get the same file,
+    // and the sidecars and database describe it as stored.
+    #[cfg(feature = "id3")]
+    let (len, expected_len) = match &source {
+        Source::Part(part) if ctx.id3 && name_with_true_ext.ends_with(".mp3") => {
+            let len = tag_id3(ctx, &mut job, part, len).await;
+            (len, Some(len))
+        },
+        _ => (len, expected_len)
+    };
+
+    // Targets that need the episode, the one holding the download last so
+    // it is simply renamed into place after the others have their copies.
+    let mut targets: Vec<&PathBuf> = ctx.episode_dirs.iter().collect();
+    if let Source::Part(part) = &source {
+        targets.sort_by_key(|dir| part.starts_with(dir));
+    }
+
+    let mut written = None;
+    for (i, episodes_dir) in targets.iter().enumerate() {
+        let output_path_true = episodes_dir.join(&name_with_true_ext);
+        if let Some(existing) = ctx.naming.existing_file(episodes_dir, &job.episode).filter(|_| !ctx.replace_existing) {
+            debug!("Not writing {}; {} exists", &job.episode.title, existing.display());
+            continue
+        }
+
+        if ctx.storage.is_blocked(episodes_dir) && !ctx.wait_for_space {
+            continue
+        }
+
+        let (source_path, move_source) = match &source {
+            Source::Part(part) => (part.clone(), i + 1 == targets.len()),
+            Source::Copy(path) => (path.clone(), false)
+        };
+        info!("{} --> {}", &job.episode.title, output_path_true.display());
+        loop {
+            match place(ctx, source_path.clone(), move_source, expected_len, episodes_dir.join(&name_with_part_ext), output_path_true.clone()).await {
+                Ok(()) => {
+                    ctx.storage.unblock(episodes_dir);
+                    if let Err(e) = sidecar::write(&output_path_true, &job.episode, ctx.feed_url.as_deref(), len, job.sha256.as_deref(), &ctx.permissions) {
+                        error!("Failed to write metadata for {}. Error: {}", output_path_true.display(), e);
+                    }
+                    written.get_or_insert_with(|| output_path_true.clone());
+                },
+                Err(e) if disk::is_storage_unavailable(&e) => {
+                    if ctx.storage.block(episodes_dir, &e) {
+                        ctx.plugins.storage_unavailable(&episodes_dir.to_string_lossy(), &e.to_string()).await;
+                    }
+                    if ctx.wait_for_space {
+                        ctx.storage.wait_until_writable(episodes_dir, len).await;
+                        continue
+                    }
+                },
+                Err(e) => error!("Failed to write {} to {}. Error: {}", &job.episode.title, output_path_true.display(), e)
+            }
+            break
+        }
+    }
+    // Whatever of the download wasn't moved into place.
+    if let Source::Part(part) = &source {
+        if written.is_some() {
+            let _ = fs::remove_file(part);
+        }
+        else {
+            discard_part(ctx, part);
+        }
+    }
+
+    match written {
+        Some(output_path_true) => {
+            job.advance(EpisodeState::Stored);
+            if let Some(time_to_archive) = &ctx.time_to_archive {
+                time_to_archive.record(&name_with_true_ext, &job.episode.published);
+            }
+            if let Some(db) = &ctx.episodes_db {
+                if let Err(e) = db.record(&job.episode, &name_with_true_ext, len, job.sha256.as_deref()) {
+                    error!("{}: could not update the episode database. Error: {}", job.episode.title, e);
+                }
+            }
+            ctx.plugins.post_download(&job.episode, &output_path_true.to_string_lossy(), job.sha256.as_deref()).await;
+        },
+        None if ctx.storage.all_blocked(ctx.episode_dirs.iter()) => job.advance(EpisodeState::Failed("no writable output directory".to_string())),
+        None => job.advance(EpisodeState::Failed("could not write to any target".to_string()))
+    }
+
+    job
+}
+
+/// Write `job`'s ID3 tags into the downloaded `part`, of `len` bytes, and
+/// return its new length, updating the job's checksum to match.
+#[cfg(feature = "id3")]
+async fn tag_id3(ctx: &DownloadContext, job: &mut EpisodeJob, part: &Path, len: u64) -> u64 {
+    let cover = match (&ctx.embed_artwork, &job.episode.image) {
+        (Some(artwork), Some(url)) => artwork.get(&ctx.http, url).await,
+        _ => None
+    };
+    let (path, episode) = (part.to_path_buf(), job.episode.clone());
+    let tagged = tokio::task::spawn_blocking(move || {
+        // Even a failed write may have changed the file, so hash it either way.
+        let written = crate::tags::write_id3(&path, &episode, cover.as_deref());
+        (written, sha256_file(&path))
+    }).await;
+    let (written, hashed) = match tagged {
+        Ok(tagged) => tagged,
+        Err(e) => {
+            error!("{}: tagging failed. Error: {}", job.episode.title, e);
+            return len
+        }
+    };
+    if let Err(e) = written {
+        warn!("{}: could not write ID3 tags. Error: {}", job.episode.title, e);
+    }
+    match hashed {
+        Ok((len, sha256)) => {
+            job.sha256 = Some(sha256);
+            len
+        },
+        Err(e) => {
+            error!("{}: could not read back the tagged file. Error: {}", job.episode.title, e);
+            len
+        }
+    }
+}
+
+/// Put one target's copy of an episode in place off the async runtime:
+/// rename `source` to `output_path_true` if `move_source`, else copy it
+/// there through `output_path_tmp`, which is removed again on failure.
+async fn place(ctx: &DownloadContext, source: PathBuf, move_source: bool, expected_len: Option<u64>, output_path_tmp: PathBuf, output_path_true: PathBuf) -> io::Result<()> {
+    let write_buffer = ctx.write_buffer;
+    let permissions = ctx.permissions;
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    let io_uring = ctx.io_uring;
+
+    tokio::task::spawn_blocking(move || {
+            if let Some(dir) = output_path_true.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let result = match move_source {
+                true => disk::move_into_place(&source, &output_path_true),
+                false => {
+                    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+                    let copied = match io_uring {
+                        true => crate::uring::copy_episode_file(&source, expected_len, &output_path_tmp, write_buffer),
+                        false => copy_episode_file(&source, expected_len, &output_path_tmp, write_buffer),
+                    };
+                    #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+                    let copied = copy_episode_file(&source, expected_len, &output_path_tmp, write_buffer);
+
+                    let result = copied.and_then(|_| fs::rename(&output_path_tmp, &output_path_true));
+                    if result.is_err() {
+                        let _ = fs::remove_file(&output_path_tmp);
+                    }
+                    result
+                }
+            };
+            result?;
+
+            if let Err(e) = permissions.apply_file(&output_path_true) {
+                warn!("Failed to set permissions on {}. Error: {}", output_path_true.display(), e);
+            }
+            Ok(())
+        })
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+}
+
+/// Copy `source` to `output_path_tmp` through a `write_buffer`-sized
+/// buffer, preallocating `expected_len` bytes first when known.
+fn copy_episode_file(source: &Path, expected_len: Option<u64>, output_path_tmp: &Path, write_buffer: usize) -> io::Result<()> {
+    let file = File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path_tmp)?;
+
+    if let Some(len) = expected_len {
+        disk::preallocate(&file, len)?;
+    }
+
+    let mut file = BufWriter::with_capacity(write_buffer.max(1), file);
+    let len = io::copy(&mut File::open(source)?, &mut file)?;
+    file.flush()?;
+
+    // A source shorter than announced leaves preallocated zeroes; drop them.
+    file.get_ref().set_len(len)
+}
+
+/// `title` with characters that upset file systems or shells replaced, safe
+/// as a name on Windows, NTFS and exFAT too: no `<>:"/\|?*` or control
+/// characters, no trailing dots or spaces, and no reserved device name.
+pub fn safe_name(title: &str) -> String {
+    let name: String = title
+        .replace(" ", "_")
+        .replace(":", "-")
+        .replace("/", "-")
+        .replace("\\", "-")
+        .replace("|", "-")
+        .replace("\"", "")
+        .replace("\'", "")
+        .replace("*", "a")
+        .chars()
+        .filter(|c| !matches!(c, '<' | '>' | '?') && !c.is_control())
+        .collect();
+    not_reserved(name.trim_end_matches(['.', ' ']).to_string())
+}
+
+/// Names Windows keeps for devices, whatever the extension: `nul.mp3` is the
+/// null device too.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `name`, with `_` after its first part if that is a reserved device name.
+pub(crate) fn not_reserved(mut name: String) -> String {
+    let base = name.split('.').next().unwrap_or_default().len();
+    if RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(&name[..base])) {
+        name.insert(base, '_');
+    }
+    name
+}
+
+/// Extension for the episode's media: from the enclosure's `type`, else the
+/// enclosure URL's path, else `bin`.
+pub fn episode_extension(episode: &Episode) -> &'static str {
+    known_extension(episode).unwrap_or("bin")
+}
+
+fn known_extension(episode: &Episode) -> Option<&'static str> {
+    episode.enclosure_type.as_deref().and_then(extension_for_type)
+        .or_else(|| extension_from_url(&episode.url))
+}
+
+/// Media file extensions recognized in enclosure URLs.
+pub const MEDIA_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "m4b", "aac", "ogg", "oga", "opus", "flac", "wav", "mp4", "m4v", "mov", "webm", "mkv",
+];
+
+/// File extension for a media type, e.g. `m4a` for `audio/x-m4a`.
+pub fn extension_for_type(mime_type: &str) -> Option<&'static str> {
+    let essence = mime_type.split(';').next()?.trim().to_ascii_lowercase();
+    Some(match essence.as_str() {
+        "audio/mpeg" | "audio/mp3" | "audio/mpeg3" | "audio/x-mpeg" | "audio/x-mp3" => "mp3",
+        "audio/mp4" | "audio/m4a" | "audio/x-m4a" => "m4a",
+        "audio/x-m4b" => "m4b",
+        "audio/aac" | "audio/aacp" | "audio/x-aac" => "aac",
+        "audio/ogg" | "audio/vorbis" | "application/ogg" => "ogg",
+        "audio/opus" => "opus",
+        "audio/flac" | "audio/x-flac" => "flac",
+        "audio/wav" | "audio/wave" | "audio/x-wav" => "wav",
+        "audio/webm" | "video/webm" => "webm",
+        "video/mp4" => "mp4",
+        "video/x-m4v" => "m4v",
+        "video/quicktime" => "mov",
+        "video/x-matroska" => "mkv",
+        _ => return None
+    })
+}
+
+fn extension_from_url(url: &str) -> Option<&'static str> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let file = url.path_segments()?.next_back()?;
+    let (_, extension) = file.rsplit_once('.')?;
+    MEDIA_EXTENSIONS.iter().find(|known| known.eq_ignore_ascii_case(extension)).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_names() {
+        assert_eq!(safe_name("Ep 1: A/B \\ C|D"), "Ep_1-_A-B_-_C-D");
+        assert_eq!(safe_name("\"Quoted\" 'title'"), "Quoted_title");
+        assert_eq!(safe_name("What? <Really>*"), "What_Reallya");
+        assert_eq!(safe_name("tab\there\u{7}"), "tabhere");
+        assert_eq!(safe_name("Trailing..."), "Trailing");
+        assert_eq!(safe_name("Trailing. "), "Trailing._");
+        assert_eq!(safe_name("Café ☕"), "Café_☕");
+    }
+
+    #[test]
+    fn reserved_names_get_an_underscore() {
+        assert_eq!(safe_name("CON"), "CON_");
+        assert_eq!(safe_name("nul.mp3"), "nul_.mp3");
+        assert_eq!(safe_name("com1.tar.gz"), "com1_.tar.gz");
+        assert_eq!(safe_name("CONSOLE"), "CONSOLE");
+        assert_eq!(safe_name("COM10"), "COM10");
+    }
+}
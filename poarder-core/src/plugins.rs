@@ -0,0 +1,212 @@
+//! External plugins speaking a line-delimited JSON protocol over stdio.
+//!
+//! Each configured plugin is started once per run. For every hook it
+//! subscribes to, poarder writes one JSON object to the plugin's stdin:
+//!
+//! ```json
+//! {"schema_version": 1, "hook": "pre_download", "episode": {"url": "...", "title": "...", "published": "...", "raw": "..."}}
+//! ```
+//!
+//! (see `schemas/plugin-message.schema.json` for every field)
+//!
+//! and reads back exactly one JSON line. An empty object (`{}`) means
+//! "carry on". For `item_parsed` and `pre_download` the plugin may answer
+//! `{"action": "skip"}` to drop the episode, and for `item_parsed` it may
+//! also override `title` and `url`. Plugins that crash or answer garbage are
+//! logged and otherwise ignored.
+
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+use crate::schema;
+use crate::Episode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    FeedFetched,
+    ItemParsed,
+    PreDownload,
+    PostDownload,
+    RunComplete,
+    StorageUnavailable,
+}
+
+/// A `[[plugins]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PluginConfig {
+    pub name: String,
+    /// Program and arguments to run.
+    pub command: Vec<String>,
+    /// Hooks to deliver; all hooks when omitted.
+    #[serde(default)]
+    pub hooks: Option<Vec<Hook>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Reply {
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+impl Reply {
+    fn is_skip(&self) -> bool {
+        self.action.as_deref() == Some("skip")
+    }
+}
+
+struct Plugin {
+    config: PluginConfig,
+    child: Mutex<Child>,
+    io: Mutex<Option<(ChildStdin, BufReader<ChildStdout>)>>,
+}
+
+impl Plugin {
+    fn wants(&self, hook: Hook) -> bool {
+        match &self.config.hooks {
+            Some(hooks) => hooks.contains(&hook),
+            None => true
+        }
+    }
+
+    async fn call(&self, message: &Value) -> Reply {
+        let mut io = self.io.lock().await;
+        let (stdin, stdout) = match io.as_mut() {
+            Some(io) => io,
+            None => return Reply::default()
+        };
+
+        let result: Result<Reply, Box<dyn Error>> = async {
+            let mut line = serde_json::to_string(message)?;
+            line.push('\n');
+            stdin.write_all(line.as_bytes()).await?;
+            stdin.flush().await?;
+
+            let mut reply = String::new();
+            if stdout.read_line(&mut reply).await? == 0 {
+                return Err("plugin closed its stdout".into())
+            }
+            Ok(serde_json::from_str(&reply)?)
+        }.await;
+
+        match result {
+            Ok(reply) => reply,
+            Err(e) => {
+                error!("Plugin {} failed; disabling it. Error: {}", self.config.name, e);
+                *io = None;
+                Reply::default()
+            }
+        }
+    }
+}
+
+/// The set of running plugins for this invocation.
+#[derive(Default)]
+pub struct PluginHost {
+    plugins: Vec<Plugin>,
+}
+
+impl PluginHost {
+    pub fn start(configs: &[PluginConfig]) -> Result<PluginHost, Box<dyn Error>> {
+        let mut plugins = Vec::new();
+
+        for config in configs {
+            let (program, args) = match config.command.split_first() {
+                Some(split) => split,
+                None => return Err(format!("plugin {} has an empty command", config.name).into())
+            };
+
+            info!("Starting plugin {}", config.name);
+            let mut child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+
+            let stdin = child.stdin.take().ok_or("plugin stdin unavailable")?;
+            let stdout = child.stdout.take().ok_or("plugin stdout unavailable")?;
+
+            plugins.push(Plugin {
+                config: config.clone(),
+                child: Mutex::new(child),
+                io: Mutex::new(Some((stdin, BufReader::new(stdout)))),
+            });
+        }
+
+        Ok(PluginHost { plugins })
+    }
+
+    async fn dispatch(&self, hook: Hook, mut message: Value) -> Vec<Reply> {
+        message["schema_version"] = json!(schema::PLUGIN_MESSAGE.version);
+        message["hook"] = json!(hook);
+
+        let mut replies = Vec::new();
+        for plugin in self.plugins.iter().filter(|p| p.wants(hook)) {
+            debug!("Calling plugin {} for {:?}", plugin.config.name, hook);
+            replies.push(plugin.call(&message).await);
+        }
+        replies
+    }
+
+    pub async fn feed_fetched(&self, feed_url: &str, bytes: usize) {
+        self.dispatch(Hook::FeedFetched, json!({ "feed_url": feed_url, "bytes": bytes })).await;
+    }
+
+    /// Returns false if a plugin asked for the episode to be dropped.
+    pub async fn item_parsed(&self, episode: &mut Episode) -> bool {
+        for reply in self.dispatch(Hook::ItemParsed, json!({ "episode": &*episode })).await {
+            if reply.is_skip() {
+                return false
+            }
+            if let Some(title) = reply.title {
+                episode.title = title;
+            }
+            if let Some(url) = reply.url {
+                episode.url = url;
+            }
+        }
+        true
+    }
+
+    /// Returns false if a plugin vetoed the download.
+    pub async fn pre_download(&self, episode: &Episode) -> bool {
+        !self.dispatch(Hook::PreDownload, json!({ "episode": episode })).await
+            .iter()
+            .any(Reply::is_skip)
+    }
+
+    pub async fn post_download(&self, episode: &Episode, path: &str, sha256: Option<&str>) {
+        self.dispatch(Hook::PostDownload, json!({ "episode": episode, "path": path, "sha256": sha256 })).await;
+    }
+
+    /// `feed_url` is `None` for `poarder grab`.
+    pub async fn run_complete(&self, feed_url: Option<&str>, downloaded: usize) {
+        self.dispatch(Hook::RunComplete, json!({ "feed_url": feed_url, "downloaded": downloaded })).await;
+    }
+
+    pub async fn storage_unavailable(&self, path: &str, error: &str) {
+        self.dispatch(Hook::StorageUnavailable, json!({ "path": path, "error": error })).await;
+    }
+
+    /// Close every plugin's stdin and wait for it to exit.
+    pub async fn shutdown(&self) {
+        for plugin in &self.plugins {
+            plugin.io.lock().await.take();
+            if let Err(e) = plugin.child.lock().await.wait().await {
+                error!("Failed to wait for plugin {}. Error: {}", plugin.config.name, e);
+            }
+        }
+    }
+}
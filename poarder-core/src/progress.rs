@@ -0,0 +1,134 @@
+//! Progress bars on the terminal: one per download in flight, with its
+//! bytes, speed and time left, under a count of the run's episodes.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::Episode;
+
+pub struct Progress {
+    multi: MultiProgress,
+    /// Episodes of the run done with, of those planned.
+    overall: ProgressBar,
+    /// Enclosure URLs of the episodes planned and not yet done with.
+    planned: Mutex<HashSet<String>>,
+    /// The bar of each download in flight, by enclosure URL.
+    bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Progress").field("overall", &self.overall.position()).finish()
+    }
+}
+
+impl Progress {
+    /// Bars drawn on stdout, or `None` if it isn't a terminal, in which case
+    /// the log is all there is.
+    pub fn new() -> Option<Arc<Progress>> {
+        if !std::io::stdout().is_terminal() {
+            return None
+        }
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stdout());
+        let overall = multi.add(ProgressBar::new(0));
+        overall.set_style(ProgressStyle::with_template("{spinner} {pos}/{len} episodes, {elapsed} so far").unwrap());
+        overall.enable_steady_tick(Duration::from_millis(200));
+        Some(Arc::new(Progress {
+            multi,
+            overall,
+            planned: Mutex::new(HashSet::new()),
+            bars: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// Count `episodes` as still to download.
+    pub fn plan<'a>(&self, episodes: impl Iterator<Item = &'a Episode>) {
+        let mut planned = self.planned.lock().unwrap();
+        for episode in episodes {
+            if planned.insert(episode.url.clone()) {
+                self.overall.inc_length(1);
+            }
+        }
+    }
+
+    /// A download of `episode` started, expecting `len` bytes in all (if
+    /// the server said) of which `offset` are already there.
+    pub fn started(&self, episode: &Episode, len: Option<u64>, offset: u64) {
+        let bar = match len {
+            Some(len) => {
+                let bar = ProgressBar::new(len);
+                bar.set_style(ProgressStyle::with_template("  {wide_msg} {bytes:>10}/{total_bytes:<10} [{bar:25}] {binary_bytes_per_sec:>12} {eta:>4}").unwrap()
+                    .progress_chars("=> "));
+                bar
+            },
+            None => {
+                let bar = ProgressBar::new_spinner();
+                bar.set_style(ProgressStyle::with_template("  {wide_msg} {bytes:>10} {spinner} {binary_bytes_per_sec:>12}").unwrap());
+                bar
+            }
+        };
+        bar.set_message(crate::element_text(&episode.title));
+        bar.set_position(offset);
+        let bar = self.multi.add(bar);
+        if let Some(old) = self.bars.lock().unwrap().insert(episode.url.clone(), bar) {
+            // A retry starts over with a new bar.
+            self.multi.remove(&old);
+        }
+    }
+
+    /// `bytes` of the episode with enclosure `url` arrived.
+    pub fn received(&self, url: &str, bytes: u64) {
+        if let Some(bar) = self.bars.lock().unwrap().get(url) {
+            bar.inc(bytes);
+        }
+    }
+
+    /// The episode with enclosure `url` is done with, one way or another.
+    pub fn finished(&self, url: &str) {
+        if let Some(bar) = self.bars.lock().unwrap().remove(url) {
+            bar.finish_and_clear();
+            self.multi.remove(&bar);
+        }
+        if self.planned.lock().unwrap().remove(url) {
+            self.overall.inc(1);
+        }
+    }
+
+    /// Run `f`, e.g. printing a log line, with the bars out of its way.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.multi.suspend(f)
+    }
+
+    /// Take the bars off the terminal, at the end of the run.
+    pub fn finish(&self) {
+        for (_, bar) in self.bars.lock().unwrap().drain() {
+            bar.finish_and_clear();
+        }
+        self.overall.finish_and_clear();
+    }
+}
+
+/// A logger that prints through `inner` without tearing the bars.
+pub struct Logger<L> {
+    pub inner: L,
+    pub progress: Arc<Progress>,
+}
+
+impl<L: log::Log> log::Log for Logger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.inner.enabled(record.metadata()) {
+            self.progress.suspend(|| self.inner.log(record));
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
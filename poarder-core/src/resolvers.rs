@@ -0,0 +1,73 @@
+//! External resolvers (e.g. yt-dlp) that turn a web page, such as a YouTube
+//! video, into a direct media URL the pipeline can download.
+
+use log::{debug, info};
+use serde::Deserialize;
+use std::error::Error;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::hosts;
+
+/// A `[[resolvers]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ResolverConfig {
+    pub name: String,
+    /// Domains whose links go through this resolver, subdomains included.
+    pub domains: Vec<String>,
+    /// Program and arguments; `{url}` is replaced with the page URL. It must
+    /// print the media URL as the first line on stdout.
+    pub command: Vec<String>,
+}
+
+impl ResolverConfig {
+    fn matches(&self, host: &str) -> bool {
+        self.domains.iter().any(|domain| {
+            let domain = domain.to_lowercase();
+            host == domain || host.ends_with(&format!(".{}", domain))
+        })
+    }
+}
+
+pub struct Resolvers {
+    configs: Vec<ResolverConfig>,
+}
+
+impl Resolvers {
+    pub fn new(configs: &[ResolverConfig]) -> Resolvers {
+        Resolvers { configs: configs.to_vec() }
+    }
+
+    pub fn find(&self, url: &str) -> Option<&ResolverConfig> {
+        let host = hosts::host_of(url);
+        self.configs.iter().find(|config| config.matches(&host))
+    }
+
+    /// Run `resolver` for `url` and return the media URL it prints.
+    pub async fn resolve(&self, resolver: &ResolverConfig, url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let (program, args) = resolver.command.split_first()
+            .ok_or_else(|| format!("resolver {} has an empty command", resolver.name))?;
+        let args: Vec<String> = args.iter().map(|arg| arg.replace("{url}", url)).collect();
+
+        info!("Resolving {} with {}", url, resolver.name);
+        debug!("Running {} {:?}", program, args);
+        let output = Command::new(program)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .await
+            .map_err(|e| format!("could not run resolver {} ({}): {}", resolver.name, program, e))?;
+
+        if !output.status.success() {
+            return Err(format!("resolver {} failed with {}", resolver.name, output.status).into())
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| format!("resolver {} printed no URL for {}", resolver.name, url).into())
+    }
+}
@@ -0,0 +1,34 @@
+//! Versions of the JSON documents poarder hands to other programs, and
+//! their JSON Schema definitions, which `poarder schema` prints.
+//!
+//! Adding a field is backwards compatible and keeps the version. Renaming,
+//! removing or changing the meaning or type of a field bumps it, and the
+//! change is called out in the release notes.
+
+/// A JSON document with a stable, versioned shape.
+pub struct Schema {
+    pub name: &'static str,
+    pub version: u32,
+    pub document: &'static str,
+}
+
+/// `<name>.episode.json` sidecars.
+pub const EPISODE: Schema = Schema {
+    name: "episode",
+    version: 1,
+    document: include_str!("../../schemas/episode.schema.json"),
+};
+
+/// Messages sent to external plugins.
+pub const PLUGIN_MESSAGE: Schema = Schema {
+    name: "plugin-message",
+    version: 1,
+    document: include_str!("../../schemas/plugin-message.schema.json"),
+};
+
+pub const ALL: &[Schema] = &[EPISODE, PLUGIN_MESSAGE];
+
+/// The schema called `name`, if there is one.
+pub fn find(name: &str) -> Option<&'static Schema> {
+    ALL.iter().find(|schema| schema.name == name)
+}
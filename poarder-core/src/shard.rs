@@ -0,0 +1,109 @@
+//! Deterministic work partitioning (`--shard K/N`) with per-shard progress
+//! checkpoints, so several short-lived jobs (e.g. Kubernetes CronJob pods)
+//! can split a large backfill between them and each pick up where its
+//! previous run stopped.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One slice of the feed: shard `index` (1-based) of `count`.
+#[derive(Debug, Clone, Copy)]
+pub struct Shard {
+    index: u64,
+    count: u64,
+}
+
+impl Shard {
+    /// Parse `K/N`, e.g. `2/5`.
+    pub fn parse(text: &str) -> Result<Shard, String> {
+        let (index, count) = text.split_once('/').ok_or("expected K/N, e.g. 2/5")?;
+        let index: u64 = index.trim().parse().map_err(|e| format!("invalid shard index: {}", e))?;
+        let count: u64 = count.trim().parse().map_err(|e| format!("invalid shard count: {}", e))?;
+        if count == 0 || index == 0 || index > count {
+            return Err(format!("shard {} is not between 1 and {}", index, count))
+        }
+        Ok(Shard { index, count })
+    }
+
+    /// Whether the episode stored under `name` belongs to this shard. The
+    /// split depends only on the name, so every job agrees on it.
+    pub fn owns(&self, name: &str) -> bool {
+        let digest = Sha256::digest(name.as_bytes());
+        let mut prefix = [0u8; 8];
+        prefix.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(prefix) % self.count == self.index - 1
+    }
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CheckpointFile {
+    done: BTreeSet<String>,
+}
+
+/// Episodes a shard has already archived, kept in the archive's state
+/// directory and rewritten after every episode.
+pub struct Checkpoint {
+    path: PathBuf,
+    file: Mutex<CheckpointFile>,
+}
+
+impl Checkpoint {
+    pub fn open(state_dir: &Path, shard: &Shard) -> Result<Checkpoint, Box<dyn Error>> {
+        let path = state_dir.join(format!("shard-{}-of-{}.json", shard.index, shard.count));
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(_) => CheckpointFile::default()
+        };
+        Ok(Checkpoint { path, file: Mutex::new(file) })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.file.lock().unwrap().done.contains(name)
+    }
+
+    pub fn record(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let file = &mut *self.file.lock().unwrap();
+        if !file.done.insert(name.to_string()) {
+            return Ok(())
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string(file)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let shard = Shard::parse(" 2 / 5 ").unwrap();
+        assert_eq!((shard.index, shard.count), (2, 5));
+        assert_eq!(Shard::parse("5/5").unwrap().to_string(), "5/5");
+        for text in ["0/5", "6/5", "1/0", "2", "a/5", "1/b", "-1/5"] {
+            assert!(Shard::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn every_name_has_one_shard() {
+        let shards: Vec<Shard> = (1..=3).map(|index| Shard { index, count: 3 }).collect();
+        for name in ["a.mp3", "b.mp3", "Episode 12.m4a", ""] {
+            assert_eq!(shards.iter().filter(|shard| shard.owns(name)).count(), 1, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Stopping a run early, e.g. on Ctrl+C, without losing what it fetched:
+//! no new downloads start, the ones in flight stop at their next chunk with
+//! their `.part` files flushed for resuming, and whatever finished is still
+//! stored.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+impl Shutdown {
+    /// Ask every run sharing this to stop.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Wait until a stop is requested; returns at once if it already was.
+    pub async fn requested(&self) {
+        let notified = self.notify.notified();
+        tokio::pin!(notified);
+        // Registered before checking, so a request in between isn't missed.
+        notified.as_mut().enable();
+        if self.is_requested() {
+            return
+        }
+        notified.await
+    }
+}
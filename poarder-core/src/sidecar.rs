@@ -0,0 +1,113 @@
+//! `<episode>.episode.json` metadata sidecars, written next to every stored
+//! episode so other tools can read the archive without poarder's state.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::disk::Permissions;
+use crate::funding::{Funding, Value};
+use crate::schema;
+use crate::soundbites::Soundbite;
+use crate::Episode;
+
+const SUFFIX: &str = ".episode.json";
+
+#[derive(Debug, Serialize)]
+pub struct Enclosure<'a> {
+    pub url: &'a str,
+    #[serde(rename = "type")]
+    pub mime_type: Option<&'a str>,
+    /// Length announced in the feed, which is often wrong.
+    pub length: Option<u64>,
+}
+
+/// Contents of a sidecar, described by `schemas/episode.schema.json`.
+/// Absent values are `null`.
+#[derive(Debug, Serialize)]
+pub struct EpisodeSidecar<'a> {
+    pub schema_version: u32,
+    /// Name of the episode file this describes, in the same directory.
+    pub file: &'a str,
+    pub title: String,
+    pub guid: Option<&'a str>,
+    pub published: DateTime<FixedOffset>,
+    pub duration: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub link: Option<&'a str>,
+    pub chapters_url: Option<&'a str>,
+    /// `<podcast:funding>` links; empty when the feed has none.
+    pub funding: &'a [Funding],
+    /// `<podcast:value>` (Value4Value) payment details.
+    pub value: Option<&'a Value>,
+    /// `<podcast:soundbite>` clips, in seconds from the start.
+    pub soundbites: &'a [Soundbite],
+    /// The item's `podcast:` elements as written in the feed, including
+    /// ones poarder doesn't interpret.
+    pub podcast_tags: &'a [String],
+    /// The show's `podcast:` elements as written in the feed.
+    pub show_podcast_tags: &'a [String],
+    /// Feed the episode came from; `null` for `poarder grab`.
+    pub feed_url: Option<&'a str>,
+    pub enclosure: Enclosure<'a>,
+    /// Size of the stored file in bytes.
+    pub size: u64,
+    pub sha256: Option<&'a str>,
+    /// SHA-256 the feed published for the enclosure.
+    pub feed_sha256: Option<&'a str>,
+    /// Whether the file was checked against `feed_sha256`; a mismatch
+    /// fails the download, so this is `false` only when the feed has none.
+    pub verified: bool,
+    pub archived: DateTime<Utc>,
+}
+
+/// Path of the sidecar for the episode file at `episode_path`.
+pub fn path_for(episode_path: &Path) -> PathBuf {
+    let stem = episode_path.file_stem().unwrap_or_default().to_string_lossy();
+    episode_path.with_file_name(format!("{}{}", stem, SUFFIX))
+}
+
+/// For a sidecar's file name, the stem of the episode it belongs to.
+pub fn episode_stem(name: &str) -> Option<&str> {
+    name.strip_suffix(SUFFIX)
+}
+
+pub fn write(episode_path: &Path, episode: &Episode, feed_url: Option<&str>, size: u64, sha256: Option<&str>, permissions: &Permissions) -> Result<(), Box<dyn Error>> {
+    let file = episode_path.file_name().unwrap_or_default().to_string_lossy();
+    let sidecar = EpisodeSidecar {
+        schema_version: schema::EPISODE.version,
+        file: &file,
+        title: quick_xml::escape::unescape(&episode.title).map(|title| title.into_owned()).unwrap_or_else(|_| episode.title.clone()),
+        guid: episode.guid.as_deref(),
+        published: episode.published,
+        duration: episode.duration.as_deref(),
+        description: episode.description.as_deref(),
+        link: episode.link.as_deref(),
+        chapters_url: episode.chapters_url.as_deref(),
+        funding: &episode.funding,
+        value: episode.value.as_ref(),
+        soundbites: &episode.soundbites,
+        podcast_tags: &episode.podcast_tags,
+        show_podcast_tags: &episode.show_podcast_tags,
+        feed_url,
+        enclosure: Enclosure {
+            url: &episode.url,
+            mime_type: episode.enclosure_type.as_deref(),
+            length: episode.enclosure_length,
+        },
+        size,
+        sha256,
+        feed_sha256: episode.checksum.as_deref(),
+        verified: episode.checksum.is_some() && episode.checksum.as_deref() == sha256,
+        archived: Utc::now(),
+    };
+
+    let path = path_for(episode_path);
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(&sidecar)?)?;
+    fs::rename(&tmp_path, &path)?;
+    permissions.apply_file(&path)?;
+    Ok(())
+}
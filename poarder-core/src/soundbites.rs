@@ -0,0 +1,97 @@
+//! `<podcast:soundbite>` highlights, cut out of stored episodes with ffmpeg
+//! under `--soundbites` so they can be shared on their own.
+
+use log::{error, info};
+use quick_xml::events::BytesStart;
+use quick_xml::Reader;
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::disk::Permissions;
+use crate::pipeline::safe_name;
+use crate::Episode;
+
+/// A `<podcast:soundbite>`: a clip of the episode, in seconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Soundbite {
+    pub start: f64,
+    pub duration: f64,
+    pub title: Option<String>,
+}
+
+/// Read a `<podcast:soundbite>` whose start tag was just returned. Returns
+/// `None` when its times are missing or invalid.
+pub fn read(reader: &mut Reader<&[u8]>, element: &BytesStart) -> Result<Option<Soundbite>, Box<dyn Error>> {
+    let seconds = |name: &str| -> Option<f64> {
+        element.try_get_attribute(name).ok().flatten()?
+            .decode_and_unescape_value(reader).ok()?
+            .trim().parse().ok()
+            .filter(|seconds: &f64| seconds.is_finite() && *seconds >= 0.0)
+    };
+    let (start, duration) = (seconds("startTime"), seconds("duration"));
+    let title = crate::element_text(&reader.read_text(element.name())?);
+
+    Ok(match (start, duration) {
+        (Some(start), Some(duration)) if duration > 0.0 => Some(Soundbite {
+            start,
+            duration,
+            title: Some(title).filter(|title| !title.is_empty()),
+        }),
+        _ => None
+    })
+}
+
+/// Cut `episode`'s soundbites out of the file at `episode_path` into
+/// `<soundbites_dir>/<episode>/`. Clips already cut are left alone.
+pub async fn cut(ffmpeg: &str, episode: &Episode, episode_path: &Path, soundbites_dir: &Path, permissions: &Permissions) -> Result<usize, Box<dyn Error>> {
+    let stem = episode_path.file_stem().unwrap_or_default();
+    let dir = soundbites_dir.join(stem);
+    fs::create_dir_all(&dir)?;
+    permissions.apply_dir(soundbites_dir)?;
+    permissions.apply_dir(&dir)?;
+
+    let mut cut = 0;
+    for (i, soundbite) in episode.soundbites.iter().enumerate() {
+        let name = format!("{:02}-{}.mp3", i + 1, safe_name(soundbite.title.as_deref().unwrap_or("soundbite")));
+        let path = dir.join(&name);
+        if path.exists() {
+            continue
+        }
+
+        // Hidden until complete; ffmpeg picks the format from the extension.
+        let tmp_path = dir.join(format!(".{}", name));
+        let status = Command::new(ffmpeg)
+            .args(["-nostdin", "-loglevel", "error", "-y", "-ss"])
+            .arg(soundbite.start.to_string())
+            .arg("-t")
+            .arg(soundbite.duration.to_string())
+            .arg("-i")
+            .arg(episode_path)
+            .args(["-c", "copy"])
+            .arg(&tmp_path)
+            .stdin(Stdio::null())
+            .status()
+            .await
+            .map_err(|e| format!("could not run {}: {}", ffmpeg, e))?;
+        if !status.success() {
+            let _ = fs::remove_file(&tmp_path);
+            error!("{}: ffmpeg failed to cut soundbite {} ({})", episode.title, name, status);
+            continue
+        }
+
+        fs::rename(&tmp_path, &path)?;
+        if let Err(e) = permissions.apply_file(&path) {
+            error!("Failed to set permissions on {}. Error: {}", path.display(), e);
+        }
+        cut += 1;
+    }
+
+    if cut > 0 {
+        info!("{}: cut {} soundbites into {}", episode.title, cut, dir.display());
+    }
+    Ok(cut)
+}
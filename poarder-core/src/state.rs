@@ -0,0 +1,226 @@
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Shared state directory used to coordinate several poarder instances
+/// archiving into the same storage (e.g. over NFS).
+///
+/// Coordination is done with claim files created with `O_EXCL` semantics,
+/// which NFSv3+ honours, so no lock daemon or database server is needed.
+/// Downloads are claimed here; an archive's catalog and episode database
+/// are guarded by the lock in its own state directory (see [`lock`]).
+#[derive(Debug, Clone)]
+pub struct SharedState {
+    claims_dir: PathBuf,
+    owner: String,
+    claim_timeout: Duration,
+}
+
+/// A held claim on an episode, or a held [`lock`]. Its file is touched while
+/// held, so that it only goes stale once its instance stops, and removed
+/// when dropped.
+#[derive(Debug)]
+pub struct Claim {
+    path: PathBuf,
+    /// Dropping the sender stops the heartbeat thread.
+    heartbeat: Option<(mpsc::Sender<()>, JoinHandle<()>)>,
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if let Some((stop, thread)) = self.heartbeat.take() {
+            drop(stop);
+            let _ = thread.join();
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to release claim {}. Error: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Name of the lock file in an archive's state directory.
+pub const LOCK_FILE: &str = "state.lock";
+
+/// How long a lock goes without being touched before it is taken to be
+/// left behind by a crashed instance.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long to wait before trying a held lock again.
+const LOCK_RETRY: Duration = Duration::from_millis(50);
+
+/// Lock the catalog and episode database of the archive whose state is in
+/// `state_dir`, waiting for any other instance or task holding it. Every
+/// instance takes this lock, shared state or not, so that several can
+/// write into one archive, including over NFS where SQLite's own locks
+/// can't be relied on.
+pub fn lock(state_dir: &Path) -> io::Result<Claim> {
+    let path = state_dir.join(LOCK_FILE);
+    let owner = owner();
+    let mut waited = false;
+    loop {
+        if let Some(lock) = acquire(&path, &owner, LOCK_TIMEOUT, "the archive state")? {
+            return Ok(lock)
+        }
+        if !waited {
+            debug!("Waiting for {} held by {}", path.display(), read_owner(&path));
+            waited = true;
+        }
+        thread::sleep(LOCK_RETRY);
+    }
+}
+
+impl SharedState {
+    pub fn open(dir: &Path, claim_timeout: Duration) -> io::Result<SharedState> {
+        let claims_dir = dir.join("claims");
+        fs::create_dir_all(&claims_dir)?;
+
+        Ok(SharedState {
+            claims_dir,
+            owner: owner(),
+            claim_timeout,
+        })
+    }
+
+    /// Try to claim episode `name` of the feed at `feed_url` (or of no feed)
+    /// for this instance. Returns `Ok(None)` if another live instance
+    /// already holds it.
+    pub fn try_claim(&self, feed_url: Option<&str>, name: &str) -> io::Result<Option<Claim>> {
+        // Feeds get a directory each, so that shows with an episode of the
+        // same name don't block each other.
+        let feed_dir = self.claims_dir.join(feed_key(feed_url.unwrap_or_default()));
+        fs::create_dir_all(&feed_dir)?;
+        let path = feed_dir.join(format!("{}.claim", escape(name)));
+        acquire(&path, &self.owner, self.claim_timeout, name)
+    }
+}
+
+/// Create the claim file at `path` for `owner`, breaking it if it is older
+/// than `timeout`. Returns `Ok(None)` if it is held. `what` names what the
+/// claim is on, for the log.
+fn acquire(path: &Path, owner: &str, timeout: Duration, what: &str) -> io::Result<Option<Claim>> {
+    for _ in 0..2 {
+        match File::options().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                writeln!(file, "{}", owner)?;
+                let heartbeat = heartbeat(path, timeout);
+                return Ok(Some(Claim { path: path.to_path_buf(), heartbeat }))
+            },
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if !is_stale(path, timeout) {
+                    debug!("{} is claimed by {}", what, read_owner(path));
+                    return Ok(None)
+                }
+                if !break_claim(path, owner, timeout, what)? {
+                    return Ok(None)
+                }
+            },
+            Err(e) => return Err(e)
+        }
+    }
+
+    Ok(None)
+}
+
+/// Break the stale claim at `path`, returning whether it was. It is
+/// renamed out of the way rather than removed, so that if another instance
+/// broke it and claimed it afresh in the meantime, it's that claim that
+/// gets moved, and can be seen to be live and put back.
+fn break_claim(path: &Path, owner: &str, timeout: Duration, what: &str) -> io::Result<bool> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos());
+    let tombstone = path.with_extension(format!("stale.{}.{}", owner.replace(['/', ':'], "-"), nanos));
+    match fs::rename(path, &tombstone) {
+        Ok(()) => (),
+        // Another instance broke it first.
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e)
+    }
+
+    let holder = read_owner(&tombstone);
+    let stale = is_stale(&tombstone, timeout);
+    if !stale {
+        debug!("{} was claimed afresh by {}; putting the claim back", what, holder);
+        // Fails only if yet another instance has claimed it since, which
+        // holds it just as well.
+        let _ = fs::hard_link(&tombstone, path);
+    } else {
+        warn!("Breaking stale claim on {} held by {}", what, holder);
+    }
+    fs::remove_file(&tombstone)?;
+    Ok(stale)
+}
+
+/// Touch the claim at `path` every quarter of `timeout` while it is held.
+/// This runs on a thread of its own rather than a task, so that it keeps
+/// going while the runtime is busy, or blocked on the lock.
+fn heartbeat(path: &Path, timeout: Duration) -> Option<(mpsc::Sender<()>, JoinHandle<()>)> {
+    let interval = (timeout / 4).max(Duration::from_secs(1));
+    let claim = path.to_path_buf();
+    let (stop, stopped) = mpsc::channel::<()>();
+    let thread = thread::Builder::new().name("claim-heartbeat".to_string()).spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            let touched = File::options().write(true).open(&claim).and_then(|file| file.set_modified(SystemTime::now()));
+            if let Err(e) = touched {
+                warn!("Failed to refresh claim {}. Error: {}", claim.display(), e);
+            }
+        }
+    });
+    match thread {
+        Ok(thread) => Some((stop, thread)),
+        Err(e) => {
+            warn!("Could not start refreshing claim {}; it goes stale after {}s. Error: {}", path.display(), timeout.as_secs(), e);
+            None
+        }
+    }
+}
+
+fn is_stale(path: &Path, timeout: Duration) -> bool {
+    let modified = match fs::metadata(path).and_then(|m| m.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return false
+    };
+
+    match SystemTime::now().duration_since(modified) {
+        Ok(age) => age > timeout,
+        Err(_) => false
+    }
+}
+
+/// A feed's directory of claims, named for its URL.
+fn feed_key(feed_url: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(feed_url.as_bytes()));
+    digest[..16].to_string()
+}
+
+/// `name` as one file name: a name template can put episodes in
+/// directories, e.g. `{year}/{title}`.
+fn escape(name: &str) -> String {
+    name.replace('%', "%25").replace('/', "%2F")
+}
+
+fn read_owner(path: &Path) -> String {
+    fs::read_to_string(path)
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// This instance, as `host:pid`.
+fn owner() -> String {
+    format!("{}:{}", hostname(), std::process::id())
+}
+
+fn hostname() -> String {
+    if let Ok(name) = std::env::var("HOSTNAME") {
+        if !name.is_empty() {
+            return name
+        }
+    }
+
+    fs::read_to_string("/etc/hostname")
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
@@ -0,0 +1,233 @@
+//! Statistics accumulated across runs and stored as JSON in the archive's
+//! state directory: per-host download figures, used to tune host profiles,
+//! and how long new episodes took to be archived after publication.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostStats {
+    pub attempts: u64,
+    pub errors: u64,
+    pub retries: u64,
+    pub bytes: u64,
+    /// Wall-clock time spent on successful transfers.
+    pub seconds: f64,
+}
+
+impl HostStats {
+    pub fn error_rate(&self) -> f64 {
+        if self.attempts == 0 {
+            return 0.0
+        }
+        self.errors as f64 / self.attempts as f64
+    }
+
+    /// Average bytes per second over successful transfers.
+    pub fn average_speed(&self) -> f64 {
+        if self.seconds <= 0.0 {
+            return 0.0
+        }
+        self.bytes as f64 / self.seconds
+    }
+}
+
+pub struct HostStatsStore {
+    path: PathBuf,
+    hosts: Mutex<BTreeMap<String, HostStats>>,
+}
+
+impl HostStatsStore {
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join("hosts.json")
+    }
+
+    pub fn open(state_dir: &Path) -> Result<HostStatsStore, Box<dyn Error>> {
+        let path = Self::path_in(state_dir);
+        let hosts = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(Box::new(e))
+        };
+
+        Ok(HostStatsStore { path, hosts: Mutex::new(hosts) })
+    }
+
+    fn update(&self, host: &str, f: impl FnOnce(&mut HostStats)) {
+        f(self.hosts.lock().unwrap().entry(host.to_string()).or_default());
+    }
+
+    pub fn record_success(&self, host: &str, bytes: usize, elapsed: Duration) {
+        self.update(host, |stats| {
+            stats.attempts += 1;
+            stats.bytes += bytes as u64;
+            stats.seconds += elapsed.as_secs_f64();
+        });
+    }
+
+    pub fn record_failure(&self, host: &str, retrying: bool) {
+        self.update(host, |stats| {
+            stats.attempts += 1;
+            stats.errors += 1;
+            if retrying {
+                stats.retries += 1;
+            }
+        });
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(&*self.hosts.lock().unwrap())?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, text)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, HostStats> {
+        self.hosts.lock().unwrap().clone()
+    }
+}
+
+/// The `stats --hosts` table.
+pub struct HostsTable(BTreeMap<String, HostStats>);
+
+impl HostsTable {
+    pub fn new(store: &HostStatsStore) -> HostsTable {
+        HostsTable(store.snapshot())
+    }
+}
+
+impl fmt::Display for HostsTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "No host statistics recorded yet")
+        }
+
+        writeln!(f, "{:<40} {:>9} {:>7} {:>8} {:>12}", "HOST", "ATTEMPTS", "ERRORS", "RETRIES", "AVG SPEED")?;
+        for (host, stats) in &self.0 {
+            writeln!(f, "{:<40} {:>9} {:>6.1}% {:>8} {:>10.1}KB/s",
+                host, stats.attempts, stats.error_rate() * 100.0, stats.retries, stats.average_speed() / 1024.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeToArchive {
+    pub feed: String,
+    /// When this archive started tracking; older episodes are backfill and
+    /// would skew the figures.
+    pub since: DateTime<Utc>,
+    /// Seconds from publication to archive, by episode file name.
+    pub episodes: BTreeMap<String, f64>,
+}
+
+impl TimeToArchive {
+    /// The `pct` percentile (0-100) by nearest rank.
+    pub fn percentile(&self, pct: f64) -> Option<f64> {
+        let mut seconds: Vec<f64> = self.episodes.values().copied().collect();
+        if seconds.is_empty() {
+            return None
+        }
+        seconds.sort_by(f64::total_cmp);
+        let rank = ((pct / 100.0) * seconds.len() as f64).ceil() as usize;
+        Some(seconds[rank.clamp(1, seconds.len()) - 1])
+    }
+}
+
+pub struct TimeToArchiveStore {
+    path: PathBuf,
+    data: Mutex<TimeToArchive>,
+}
+
+impl TimeToArchiveStore {
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join("time-to-archive.json")
+    }
+
+    /// Open the store for `feed`, starting to track from now if it's new.
+    pub fn open(state_dir: &Path, feed: &str) -> Result<TimeToArchiveStore, Box<dyn Error>> {
+        let path = Self::path_in(state_dir);
+        let data = match fs::read_to_string(&path) {
+            Ok(text) => TimeToArchive { feed: feed.to_string(), ..serde_json::from_str(&text)? },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => TimeToArchive {
+                feed: feed.to_string(),
+                since: Utc::now(),
+                episodes: BTreeMap::new(),
+            },
+            Err(e) => return Err(Box::new(e))
+        };
+
+        Ok(TimeToArchiveStore { path, data: Mutex::new(data) })
+    }
+
+    /// Read the figures of an existing archive, if it has any.
+    pub fn load(state_dir: &Path) -> Result<Option<TimeToArchive>, Box<dyn Error>> {
+        match fs::read_to_string(Self::path_in(state_dir)) {
+            Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+
+    /// Note that the episode stored as `name` has just been archived.
+    pub fn record(&self, name: &str, published: &DateTime<FixedOffset>) {
+        let mut data = self.data.lock().unwrap();
+        if *published < data.since {
+            return
+        }
+        let seconds = (Utc::now() - published.with_timezone(&Utc)).num_milliseconds().max(0) as f64 / 1000.0;
+        data.episodes.insert(name.to_string(), seconds);
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let text = serde_json::to_string_pretty(&*self.data.lock().unwrap())?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, text)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    match seconds {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}h{:02}m", s / 3600, (s % 3600) / 60)
+    }
+}
+
+/// The `stats --freshness` table, of the archives with episodes recorded.
+pub struct FreshnessTable<'a>(Vec<&'a TimeToArchive>);
+
+impl FreshnessTable<'_> {
+    pub fn new(archives: &[Option<TimeToArchive>]) -> FreshnessTable<'_> {
+        FreshnessTable(archives.iter().flatten().filter(|a| !a.episodes.is_empty()).collect())
+    }
+}
+
+impl fmt::Display for FreshnessTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return writeln!(f, "No time-to-archive statistics recorded yet")
+        }
+
+        writeln!(f, "{:<60} {:>9} {:>9} {:>9}", "FEED", "EPISODES", "P50", "P95")?;
+        for archive in &self.0 {
+            writeln!(f, "{:<60} {:>9} {:>9} {:>9}",
+                archive.feed, archive.episodes.len(),
+                format_duration(archive.percentile(50.0).unwrap_or_default()),
+                format_duration(archive.percentile(95.0).unwrap_or_default()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+//! ID3v2 tags for archived MP3s, written from the feed rather than kept as
+//! served, which is often missing or wrong (ad-insertion CDNs are notorious).
+
+use chrono::{Datelike, Timelike};
+use id3::frame::{Picture, PictureType};
+use id3::{Tag, TagLike, Timestamp, Version};
+use log::warn;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::fixtures::Http;
+use crate::{element_text, Episode};
+
+/// Cover art to embed, fetched once per image URL in a run: most episodes
+/// share the show's.
+#[derive(Debug, Default)]
+pub struct Artwork {
+    pictures: tokio::sync::Mutex<HashMap<String, Option<Arc<Picture>>>>,
+}
+
+impl Artwork {
+    /// The picture at `url`, unless it can't be fetched or isn't a JPEG or
+    /// PNG, which is all players reliably show.
+    pub async fn get(&self, http: &Http, url: &str) -> Option<Arc<Picture>> {
+        // Held while fetching, so episodes waiting on the same image don't
+        // each fetch it.
+        let mut pictures = self.pictures.lock().await;
+        if let Some(picture) = pictures.get(url) {
+            return picture.clone()
+        }
+        let picture = match fetch_picture(http, url).await {
+            Ok(picture) => Some(Arc::new(picture)),
+            Err(e) => {
+                warn!("Not embedding artwork {}. Error: {}", url, e);
+                None
+            }
+        };
+        pictures.insert(url.to_string(), picture.clone());
+        picture
+    }
+}
+
+async fn fetch_picture(http: &Http, url: &str) -> Result<Picture, Box<dyn Error + Send + Sync>> {
+    let data = http.send(http.get(url)).await?.error_for_status()?.bytes().await?.to_vec();
+    let mime_type = match data.as_slice() {
+        [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        _ => return Err("not a JPEG or PNG image".into())
+    };
+    Ok(Picture { mime_type: mime_type.to_string(), picture_type: PictureType::CoverFront, description: String::new(), data })
+}
+
+/// Tag the MP3 at `path` with `episode`'s title, show, author, date,
+/// season and episode number, the genre "Podcast" and, if given, `cover` as
+/// its front cover. Other frames, such as artwork already embedded, are
+/// kept; an ID3v1 tag is removed.
+pub fn write_id3(path: &Path, episode: &Episode, cover: Option<&Picture>) -> id3::Result<()> {
+    // A tag too broken to read is replaced outright.
+    let mut tag = match id3::no_tag_ok(Tag::read_from_path(path)) {
+        Ok(tag) => tag.unwrap_or_default(),
+        Err(_) => {
+            Tag::remove_from_path(path)?;
+            Tag::new()
+        }
+    };
+    id3::v1::Tag::remove_from_path(path)?;
+
+    tag.set_title(element_text(&episode.title));
+    if let Some(show) = &episode.show {
+        tag.set_album(element_text(show));
+    }
+    if let Some(author) = &episode.author {
+        tag.set_artist(author.as_str());
+        tag.set_album_artist(author.as_str());
+    }
+    let published = episode.published;
+    let timestamp = Timestamp {
+        year: published.year(),
+        month: Some(published.month() as u8),
+        day: Some(published.day() as u8),
+        hour: Some(published.hour() as u8),
+        minute: Some(published.minute() as u8),
+        second: Some(published.second() as u8),
+    };
+    tag.set_date_released(timestamp);
+    tag.set_date_recorded(timestamp);
+    if let Some(number) = episode.number {
+        tag.set_track(number);
+    }
+    if let Some(season) = episode.season {
+        tag.set_disc(season);
+    }
+    tag.set_genre("Podcast");
+    if let Some(cover) = cover {
+        tag.remove_picture_by_type(PictureType::CoverFront);
+        tag.add_frame(cover.clone());
+    }
+    tag.write_to_path(path, Version::Id3v24)
+}
@@ -0,0 +1,53 @@
+//! Bandwidth limits, so archiving a large back catalog doesn't saturate a
+//! home connection or look like abuse to a CDN. A [`RateLimit`] is a token
+//! bucket: shared by every download it caps their total, and one per
+//! download caps each connection.
+
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug)]
+pub struct RateLimit {
+    bytes_per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that may be taken now; negative when downloads are owed time.
+    available: f64,
+    refilled: Instant,
+}
+
+impl RateLimit {
+    /// Up to `bytes_per_sec` on average, with bursts of a second's worth.
+    pub fn new(bytes_per_sec: u64) -> RateLimit {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        RateLimit {
+            bytes_per_sec,
+            bucket: Mutex::new(Bucket { available: bytes_per_sec, refilled: Instant::now() }),
+        }
+    }
+
+    /// Account for `bytes` just received, waiting as long as it takes for
+    /// them to fit the rate.
+    pub async fn take(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.refilled).as_secs_f64() * self.bytes_per_sec;
+            bucket.available = (bucket.available + refill).min(self.bytes_per_sec) - bytes as f64;
+            bucket.refilled = now;
+            // Each caller waits off its own debt, so downloads sharing a
+            // limit queue up behind each other rather than all at once.
+            match bucket.available < 0.0 {
+                true => Duration::from_secs_f64(-bucket.available / self.bytes_per_sec),
+                false => Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Optional io_uring write path (Linux, `io-uring` feature) for archiving to
+//! fast NVMe storage, where issuing one blocking `write` at a time leaves the
+//! drive idle. When an episode is copied to further targets, chunks are
+//! submitted concurrently and the kernel completes them in whatever order
+//! suits the device.
+
+use futures::{stream, StreamExt, TryStreamExt};
+use log::debug;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Writes kept in flight per file.
+const QUEUE_DEPTH: usize = 32;
+
+/// Whether the running kernel (and any seccomp policy) allows io_uring.
+pub fn available() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| io_uring::IoUring::new(2).is_ok())
+}
+
+/// io_uring counterpart of the pipeline's `copy_episode_file`: copy
+/// `source` to `output_path_tmp` in `write_buffer`-sized chunks, at most
+/// `QUEUE_DEPTH` of them in memory at once. Must be called from a thread
+/// without a tokio runtime, such as a `spawn_blocking` worker.
+pub fn copy_episode_file(source: &Path, expected_len: Option<u64>, output_path_tmp: &Path, write_buffer: usize) -> io::Result<()> {
+    tokio_uring::start(copy_file(source, expected_len, output_path_tmp, write_buffer.max(1)))
+}
+
+async fn copy_file(source: &Path, expected_len: Option<u64>, path: &Path, chunk: usize) -> io::Result<()> {
+    let len = std::fs::metadata(source)?.len();
+    let input = tokio_uring::fs::File::open(source).await?;
+    let file = tokio_uring::fs::File::create(path).await?;
+
+    if let Some(len) = expected_len.filter(|len| *len > 0) {
+        // Keep the size unchanged so a short source doesn't leave zeroes behind.
+        match file.fallocate(0, len, libc::FALLOC_FL_KEEP_SIZE).await {
+            Ok(()) => (),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS)) => {
+                debug!("fallocate not supported here; writing without preallocation");
+            },
+            Err(e) => return Err(e)
+        }
+    }
+
+    let result = stream::iter((0..len).step_by(chunk))
+        .map(|offset| {
+            let (input, file) = (&input, &file);
+            let size = (len - offset).min(chunk as u64) as usize;
+            async move {
+                let (read, buf) = input.read_exact_at(vec![0; size], offset).await;
+                read?;
+                file.write_all_at(buf, offset).await.0
+            }
+        })
+        .buffer_unordered(QUEUE_DEPTH)
+        .try_collect::<()>()
+        .await;
+
+    input.close().await?;
+    file.close().await?;
+    result
+}
@@ -0,0 +1,98 @@
+//! Missed-episode alerts. A show's cadence (see `cadence`) says when its
+//! next episode is due; a poll finding it overdue says so once, in the log
+//! and to the `[[notify]]` entries with `alerts = true`. A show silent for
+//! several intervals is reported as on a break rather than late, and a feed
+//! that keeps failing to fetch as the archiver's problem, not the show's.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+
+use crate::cadence::Cadence;
+use crate::notify::NotifyConfig;
+use crate::polling::PollState;
+
+/// Fetches in a row that must fail before a feed counts as failing, so a
+/// blip doesn't raise an alert.
+const FAILING_AFTER: u32 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Alert {
+    /// Fetching the feed has failed `attempts` times since it last worked.
+    Failing { since: Option<DateTime<Utc>>, attempts: u32, error: String },
+    /// The episode due at `due` hasn't appeared.
+    Late { due: DateTime<Utc>, cadence: Cadence },
+    /// No episode for so long that the show is on a break, or over.
+    OnBreak { cadence: Cadence },
+}
+
+impl Alert {
+    /// Tells this alert from the last one sent, so each goes out once.
+    fn key(&self) -> String {
+        match self {
+            Alert::Failing { .. } => "failing".to_string(),
+            Alert::Late { due, .. } => format!("late {}", due.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            Alert::OnBreak { cadence } => format!("break {}", cadence.last.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        }
+    }
+
+    pub fn message(&self, show: &str) -> String {
+        match self {
+            Alert::Failing { since: Some(since), attempts, error } => {
+                format!("{}: the feed has failed to fetch {} times since {}; the archiver isn't getting new episodes. Last error: {}", show, attempts, since.format("%Y-%m-%d %H:%M UTC"), error)
+            },
+            Alert::Failing { since: None, attempts, error } => {
+                format!("{}: the feed has failed to fetch {} times; the archiver isn't getting new episodes. Last error: {}", show, attempts, error)
+            },
+            Alert::Late { due, cadence } => {
+                format!("{}: no new episode since {}, though its {} cadence had one due on {}", show, cadence.last.format("%Y-%m-%d"), cadence.describe(), due.format("%Y-%m-%d"))
+            },
+            Alert::OnBreak { cadence } => {
+                format!("{}: no new episode since {}, well past its {} cadence; the show looks to be on a break", show, cadence.last.format("%Y-%m-%d"), cadence.describe())
+            },
+        }
+    }
+}
+
+/// What is wrong with the feed polled in `state`, whose episodes came out at
+/// `published`, as of `now`, if anything. A failing feed comes first: its
+/// episodes would look late whether or not the show published them.
+pub fn check(state: &PollState, published: impl IntoIterator<Item = DateTime<Utc>>, now: DateTime<Utc>) -> Option<Alert> {
+    if state.failed_polls >= FAILING_AFTER {
+        return Some(Alert::Failing {
+            since: state.last_polled,
+            attempts: state.failed_polls,
+            error: state.last_error.clone().unwrap_or_default(),
+        })
+    }
+    if state.complete {
+        return None
+    }
+    let cadence = Cadence::detect(published)?;
+    if cadence.on_break(now) {
+        return Some(Alert::OnBreak { cadence })
+    }
+    let due = cadence.overdue(now)?;
+    Some(Alert::Late { due, cadence })
+}
+
+/// Check the feed at `feed_url` and send its alert, naming the show
+/// `show()`, unless it already went out; `state` remembers which went out,
+/// to be saved by the caller.
+pub async fn raise(client: &reqwest::Client, notify: &[NotifyConfig], state: &mut PollState, show: impl FnOnce() -> String, feed_url: &str, published: impl IntoIterator<Item = DateTime<Utc>>) {
+    let alert = check(state, published, Utc::now());
+    let key = alert.as_ref().map(Alert::key);
+    if key == state.alerted {
+        return
+    }
+    let show = show();
+    match &alert {
+        Some(alert @ Alert::OnBreak { .. }) => info!("{}", alert.message(&show)),
+        Some(alert) => warn!("{}", alert.message(&show)),
+        None if state.alerted.as_deref() == Some("failing") => info!("{}: the feed is fetching again", show),
+        None => ()
+    }
+    if let Some(alert) = &alert {
+        crate::notify::alert(client, notify, feed_url, &alert.message(&show)).await;
+    }
+    state.alerted = key;
+}
@@ -0,0 +1,50 @@
+//! Items whose metadata looks wrong, flagged in the run summary rather than
+//! archived without a word: dates in the future or from before there were
+//! podcasts, which end up in file names like `32503680000-...`, and titles
+//! shared by different enclosures.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::collections::{HashMap, HashSet};
+
+use crate::Episode;
+
+/// Leeway for clocks and time zones before a date counts as in the future.
+const FUTURE_SLACK: Duration = Duration::days(1);
+
+/// What looks wrong with each of `episodes`, by enclosure URL, as of `now`.
+/// `known` are the titles and enclosures of episodes seen on earlier runs.
+pub fn find<'a>(episodes: impl IntoIterator<Item = &'a Episode> + Copy, known: impl IntoIterator<Item = (&'a str, &'a str)>, now: DateTime<Utc>) -> HashMap<String, Vec<String>> {
+    // Podcasts date from 2000, when RSS got enclosures; the show can't be older.
+    let earliest = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+    let mut enclosures: HashMap<String, HashSet<&str>> = HashMap::new();
+    let titles = known.into_iter().map(|(title, url)| (title.to_string(), url))
+        .chain(episodes.into_iter().map(|episode| (episode.title.clone(), episode.url.as_str())));
+    for (title, url) in titles {
+        enclosures.entry(normalize(&title)).or_default().insert(url);
+    }
+
+    let mut anomalies: HashMap<String, Vec<String>> = HashMap::new();
+    for episode in episodes {
+        let published = episode.published.with_timezone(&Utc);
+        let mut found = Vec::new();
+        if published > now + FUTURE_SLACK {
+            found.push(format!("published in the future, on {}", published.format("%Y-%m-%d")));
+        }
+        if published < earliest {
+            found.push(format!("published on {}, before there were podcasts", published.format("%Y-%m-%d")));
+        }
+        let others = enclosures.get(&normalize(&episode.title)).map_or(0, |urls| urls.len() - 1);
+        if others > 0 {
+            found.push(format!("the same title as {} other enclosure(s)", others));
+        }
+        if !found.is_empty() {
+            anomalies.entry(episode.url.clone()).or_default().extend(found);
+        }
+    }
+    anomalies
+}
+
+fn normalize(title: &str) -> String {
+    crate::element_text(title).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
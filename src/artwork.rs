@@ -0,0 +1,181 @@
+//! Show artwork, downloaded into the archive's `assets/` directory and
+//! normalised into the sizes and formats configured under `[[artwork]]`,
+//! since publishers ship anything from huge PNGs to tiny GIFs.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use log::{error, info};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+
+use crate::disk::Permissions;
+use crate::fixtures::Http;
+use crate::layout::FeedLayout;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtworkFormat {
+    Jpeg,
+    Png,
+}
+
+/// An `[[artwork]]` entry in the config file: one rendition to produce.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtworkConfig {
+    /// File name without extension, e.g. `cover`.
+    pub name: String,
+    /// Longest side in pixels.
+    pub size: u32,
+    #[serde(default = "default_format")]
+    pub format: ArtworkFormat,
+    /// JPEG quality, 1-100.
+    #[serde(default = "default_quality")]
+    pub quality: u8,
+}
+
+fn default_format() -> ArtworkFormat {
+    ArtworkFormat::Jpeg
+}
+
+fn default_quality() -> u8 {
+    90
+}
+
+impl ArtworkConfig {
+    fn file_name(&self) -> String {
+        match self.format {
+            ArtworkFormat::Jpeg => format!("{}.jpg", self.name),
+            ArtworkFormat::Png => format!("{}.png", self.name),
+        }
+    }
+}
+
+/// Renditions used when the config file doesn't list any: a 1400px cover,
+/// the size podcast directories ask for, plus a 300px thumbnail.
+fn default_renditions() -> Vec<ArtworkConfig> {
+    vec![
+        ArtworkConfig { name: "cover".to_string(), size: 1400, format: ArtworkFormat::Jpeg, quality: default_quality() },
+        ArtworkConfig { name: "thumb".to_string(), size: 300, format: ArtworkFormat::Jpeg, quality: default_quality() },
+    ]
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ArtworkState {
+    /// URL the current renditions were made from.
+    url: Option<String>,
+}
+
+/// The channel's artwork URL: `<itunes:image href>`, else `<image><url>`.
+pub fn channel_artwork_url(rss_xml: &str) -> Option<String> {
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    let mut image_url = None;
+    let mut in_image = false;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) | Ok(Event::Empty(element)) => match element.name().as_ref() {
+                b"item" => break,
+                b"itunes:image" => {
+                    let href = element.try_get_attribute("href").ok().flatten()?;
+                    return href.decode_and_unescape_value(&reader).ok().map(|href| href.to_string())
+                },
+                b"image" => in_image = true,
+                b"url" if in_image && image_url.is_none() => {
+                    image_url = reader.read_text(element.name()).ok().map(|url| url.trim().to_string());
+                },
+                _ => ()
+            },
+            Ok(Event::End(element)) if element.name().as_ref() == b"image" => in_image = false,
+            Ok(Event::Eof) | Err(_) => break,
+            _ => ()
+        }
+    }
+
+    image_url
+}
+
+fn render(image: &DynamicImage, rendition: &ArtworkConfig, path: &Path) -> Result<(), Box<dyn Error>> {
+    let resized = image.resize(rendition.size, rendition.size, FilterType::Lanczos3);
+    let tmp_path = path.with_extension("part");
+    let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+    match rendition.format {
+        ArtworkFormat::Jpeg => {
+            // JPEG has no alpha channel.
+            JpegEncoder::new_with_quality(&mut out, rendition.quality.clamp(1, 100))
+                .encode_image(&DynamicImage::ImageRgb8(resized.to_rgb8()))?
+        },
+        ArtworkFormat::Png => resized.write_to(&mut out, ImageFormat::Png)?,
+    }
+    drop(out);
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+/// Download the show's artwork and write every rendition into the assets
+/// directory of each layout. Does nothing when the artwork URL hasn't
+/// changed and the renditions already exist.
+pub async fn update(http: &Http, rss_xml: &str, renditions: &[ArtworkConfig], layouts: &[FeedLayout], permissions: &Permissions) -> Result<(), Box<dyn Error>> {
+    let url = match channel_artwork_url(rss_xml) {
+        Some(url) => url,
+        None => {
+            info!("Feed has no artwork");
+            return Ok(())
+        }
+    };
+
+    let defaults;
+    let renditions = if renditions.is_empty() {
+        defaults = default_renditions();
+        &defaults
+    }
+    else {
+        renditions
+    };
+
+    let state_path = layouts[0].state_dir().join("artwork.json");
+    let state: ArtworkState = match fs::read_to_string(&state_path) {
+        Ok(text) => serde_json::from_str(&text)?,
+        Err(_) => ArtworkState::default()
+    };
+    let targets: Vec<PathBuf> = layouts.iter()
+        .flat_map(|layout| renditions.iter().map(move |r| layout.assets_dir().join(r.file_name())))
+        .collect();
+    if state.url.as_deref() == Some(url.as_str()) && targets.iter().all(|path| path.exists()) {
+        return Ok(())
+    }
+
+    info!("Downloading artwork from {}", url);
+    let data = http.send(http.get(&url)).await?.error_for_status()?.bytes().await?;
+
+    let renditions = renditions.to_vec();
+    let layouts = layouts.to_vec();
+    let permissions = *permissions;
+    tokio::task::spawn_blocking(move || -> Result<(), Box<dyn Error + Send + Sync>> {
+        let image = image::ImageReader::new(Cursor::new(&data)).with_guessed_format()?.decode()?;
+        info!("Artwork is {}x{}", image.width(), image.height());
+        for layout in &layouts {
+            for rendition in &renditions {
+                let path = layout.assets_dir().join(rendition.file_name());
+                if let Err(e) = render(&image, rendition, &path) {
+                    error!("Failed to write {}. Error: {}", path.display(), e);
+                    continue
+                }
+                if let Err(e) = permissions.apply_file(&path) {
+                    error!("Failed to set permissions on {}. Error: {}", path.display(), e);
+                }
+            }
+        }
+        Ok(())
+    }).await?.map_err(|e| e.to_string())?;
+
+    fs::write(&state_path, serde_json::to_string_pretty(&ArtworkState { url: Some(url) })?)?;
+    Ok(())
+}
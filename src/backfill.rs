@@ -0,0 +1,127 @@
+//! `poarder backfill`: work through a large back catalog oldest first, in
+//! batches with a pause between them, picking up where it left off when
+//! interrupted. Regular runs stay light and download whatever is missing.
+
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::pipeline::{EpisodeJob, EpisodeState, RunSummary};
+use crate::{Downloader, Episode};
+
+/// Progress through one feed's back catalog, in `state/backfill.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BackfillState {
+    #[serde(skip)]
+    path: PathBuf,
+    feed: String,
+    /// Every episode published up to this time has been tried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    through: Option<DateTime<FixedOffset>>,
+    /// Episodes that failed; left to regular runs to retry.
+    #[serde(default)]
+    failed: Vec<String>,
+}
+
+impl BackfillState {
+    fn open(state_dir: &Path, feed: &str) -> Result<BackfillState, Box<dyn Error>> {
+        let path = state_dir.join("backfill.json");
+        let mut state: BackfillState = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BackfillState::default(),
+            Err(e) => return Err(Box::new(e))
+        };
+        if state.feed != feed {
+            // A different feed archived into the same place starts over.
+            state = BackfillState { feed: feed.to_string(), ..BackfillState::default() };
+        }
+        state.path = path;
+        Ok(state)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+pub struct BackfillOptions<'a> {
+    pub feed_url: &'a str,
+    pub state_dir: &'a Path,
+    pub batch_size: usize,
+    pub pause: Duration,
+}
+
+/// Download the episodes of `episodes` missing from the primary archive,
+/// oldest first, `batch_size` at a time.
+pub async fn run(downloader: &Downloader, mut episodes: Vec<Episode>, options: BackfillOptions<'_>) -> Result<RunSummary, Box<dyn Error>> {
+    let mut state = BackfillState::open(options.state_dir, options.feed_url)?;
+    let ctx = downloader.context();
+    let primary = ctx.episode_dirs[0].clone();
+
+    episodes.sort_by_key(|episode| episode.published);
+    let pending: Vec<Episode> = episodes.into_iter()
+        .filter(|episode| state.through.is_none_or(|through| episode.published > through))
+        .filter(|episode| ctx.naming.existing_file(&primary, episode).is_none())
+        .collect();
+    if let Some(eta) = &ctx.eta {
+        eta.plan(pending.iter());
+    }
+    match state.through {
+        Some(through) => info!("Resuming backfill after {}: {} episodes to go", through.format("%Y-%m-%d"), pending.len()),
+        None => info!("Starting backfill: {} episodes to go", pending.len())
+    }
+
+    let batches = pending.len().div_ceil(options.batch_size.max(1));
+    let mut pending = pending.into_iter().peekable();
+    let mut summary = RunSummary::default();
+    for batch_number in 1..=batches {
+        let batch: Vec<EpisodeJob> = pending.by_ref().take(options.batch_size.max(1))
+            .map(|episode| {
+                let mut job = EpisodeJob::new(episode);
+                job.advance(EpisodeState::Filtered);
+                job
+            })
+            .collect();
+        let through = batch.last().map(|job| job.episode.published);
+        info!("Backfill batch {} of {}: {} episodes", batch_number, batches, batch.len());
+
+        let finished = downloader.run(batch).await;
+        summary.count(&finished);
+        for job in finished.iter().filter(|job| matches!(job.state, EpisodeState::Failed(_))) {
+            let name = ctx.naming.file_name(&job.episode);
+            if !state.failed.contains(&name) {
+                state.failed.push(name);
+            }
+        }
+        // A stopped batch may have left episodes unstarted, so it is done
+        // again next time.
+        if ctx.shutdown.is_requested() {
+            state.save()?;
+            warn!("Backfill stopped: {} episodes stored; run it again to carry on", summary.stored);
+            return Ok(summary)
+        }
+        state.through = through;
+        state.save()?;
+
+        if pending.peek().is_some() && !options.pause.is_zero() {
+            info!("Pausing {:?} before the next batch", options.pause);
+            tokio::select! {
+                _ = tokio::time::sleep(options.pause) => (),
+                _ = ctx.shutdown.requested() => ()
+            }
+        }
+    }
+
+    if !state.failed.is_empty() {
+        warn!("{} episodes failed during the backfill; regular runs will retry them", state.failed.len());
+    }
+    info!("Backfill complete: {} episodes stored", summary.stored);
+    Ok(summary)
+}
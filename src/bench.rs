@@ -0,0 +1,43 @@
+//! `poarder bench parse <file>`: time the feed parser on a real feed, read
+//! through a memory map so file I/O stays out of the numbers.
+
+use memmap2::Mmap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::parse_feed;
+
+pub fn parse(path: &Path, iterations: usize) -> Result<(), Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+    // SAFETY: the map is only read, and only while this function runs; a
+    // feed modified underneath would at worst give odd numbers.
+    let map = unsafe { Mmap::map(&file)? };
+
+    let started = Instant::now();
+    let rss_xml = std::str::from_utf8(&map)?;
+    let utf8_check = started.elapsed();
+    let items = parse_feed(rss_xml, false)?.len();
+    println!("{}: {:.1} MiB, {} items, UTF-8 check {:?}", path.display(), mib(map.len()), items, utf8_check);
+
+    for (label, keep_raw) in [("parse", true), ("parse --low-memory", false)] {
+        let mut times = Vec::with_capacity(iterations);
+        for _ in 0..iterations.max(1) {
+            let started = Instant::now();
+            let episodes = parse_feed(rss_xml, keep_raw)?;
+            times.push(started.elapsed());
+            drop(episodes);
+        }
+        let min = *times.iter().min().unwrap();
+        let max = *times.iter().max().unwrap();
+        let mean = times.iter().sum::<Duration>() / times.len() as u32;
+        println!("{:<20} min {:>10.2?}  mean {:>10.2?}  max {:>10.2?}  {:>8.1} MiB/s",
+            label, min, mean, max, mib(map.len()) / mean.as_secs_f64());
+    }
+    Ok(())
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
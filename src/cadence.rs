@@ -0,0 +1,128 @@
+//! A show's publishing cadence, learnt from when its episodes came out, to
+//! say when the next ones are due.
+
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+
+/// Episodes looked at: recent ones say most about the current schedule.
+const HISTORY: usize = 25;
+
+/// Fewest episodes to tell a cadence from.
+const MIN_EPISODES: usize = 5;
+
+/// How regular a show must be to have a cadence: the share of gaps
+/// between episodes within a quarter of the typical one.
+const REGULAR_SHARE: f64 = 0.6;
+
+/// Without an episode for this many intervals, a show is taken to be on a
+/// break rather than late.
+const BREAK_AFTER: i32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cadence {
+    /// The typical gap between episodes.
+    pub interval: Duration,
+    /// For shows more often than weekly, the days they come out on, if not
+    /// every day of the week.
+    pub weekdays: Vec<Weekday>,
+    /// The latest episode.
+    pub last: DateTime<Utc>,
+}
+
+impl Cadence {
+    /// The cadence of a show whose episodes came out at `published`, unless
+    /// it has too few or too irregular ones.
+    pub fn detect(published: impl IntoIterator<Item = DateTime<Utc>>) -> Option<Cadence> {
+        let mut published: Vec<DateTime<Utc>> = published.into_iter().collect();
+        published.sort_unstable_by(|a, b| b.cmp(a));
+        published.dedup();
+        published.truncate(HISTORY);
+        if published.len() < MIN_EPISODES {
+            return None
+        }
+
+        let mut gaps: Vec<Duration> = published.windows(2).map(|pair| pair[0] - pair[1]).collect();
+        gaps.sort_unstable();
+        let interval = gaps[gaps.len() / 2];
+        // Several episodes at once is a back catalog being uploaded, not a schedule.
+        if interval < Duration::hours(12) {
+            return None
+        }
+        let (low, high) = (interval * 3 / 4, interval * 5 / 4);
+        let regular = gaps.iter().filter(|gap| (low..=high).contains(*gap)).count();
+        if (regular as f64) < gaps.len() as f64 * REGULAR_SHARE {
+            return None
+        }
+
+        let mut weekdays: Vec<Weekday> = Vec::new();
+        if interval < Duration::days(7) {
+            for day in published.iter().map(|date| date.weekday()) {
+                if !weekdays.contains(&day) {
+                    weekdays.push(day);
+                }
+            }
+            weekdays.sort_by_key(Weekday::num_days_from_monday);
+            if weekdays.len() == 7 {
+                weekdays.clear();
+            }
+        }
+        Some(Cadence { interval, weekdays, last: published[0] })
+    }
+
+    /// When the episode after `date` is due.
+    fn after(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+        if self.weekdays.is_empty() {
+            return date + self.interval
+        }
+        let mut next = date + Duration::days(1);
+        while !self.weekdays.contains(&next.weekday()) {
+            next += Duration::days(1);
+        }
+        next
+    }
+
+    /// Whether the show has gone long enough without an episode to be on a
+    /// break, or over, rather than just late.
+    pub fn on_break(&self, now: DateTime<Utc>) -> bool {
+        now - self.last > self.interval * BREAK_AFTER
+    }
+
+    /// When the episode after the latest was due, if by `now` it is later
+    /// than the quarter interval of slack a cadence allows and the show
+    /// isn't on a break.
+    pub fn overdue(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let due = self.after(self.last);
+        (!self.on_break(now) && now - due > self.interval / 4).then_some(due)
+    }
+
+    /// The next `count` episodes due after `now`, none if on a break.
+    pub fn upcoming(&self, now: DateTime<Utc>, count: usize) -> Vec<DateTime<Utc>> {
+        if self.on_break(now) {
+            return Vec::new()
+        }
+        let mut due = self.after(self.last);
+        while due <= now {
+            due = self.after(due);
+        }
+        let mut upcoming = vec![due];
+        while upcoming.len() < count {
+            due = self.after(due);
+            upcoming.push(due);
+        }
+        upcoming
+    }
+
+    /// E.g. `weekly`, `every 2 weeks`, `Mon, Wed, Fri`.
+    pub fn describe(&self) -> String {
+        if !self.weekdays.is_empty() {
+            return self.weekdays.iter().map(|day| day.to_string()).collect::<Vec<_>>().join(", ")
+        }
+        let days = (self.interval.num_hours() as f64 / 24.0).round() as i64;
+        match days {
+            1 => "daily".to_string(),
+            7 => "weekly".to_string(),
+            days if days % 7 == 0 => format!("every {} weeks", days / 7),
+            days if (28..=31).contains(&days) => "monthly".to_string(),
+            days => format!("every {} days", days)
+        }
+    }
+}
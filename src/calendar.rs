@@ -0,0 +1,188 @@
+//! `--calendar`: an iCalendar feed of each show, `feed/calendar.ics`, with
+//! an event for every archived episode when it was published and tentative
+//! ones for the episodes its cadence says are coming. `--serve` serves them
+//! too, so a calendar app can subscribe:
+//!
+//! - `GET /calendar.ics`: every show in the output directories
+//! - `GET /calendar/<show>.ics`: one show, by its directory's name
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cadence::Cadence;
+use crate::catalog::Catalog;
+use crate::layout::FeedLayout;
+
+/// File name of a show's calendar in its feed directory.
+pub const CALENDAR_FILE: &str = "calendar.ics";
+
+/// Expected episodes to put in the calendar.
+const UPCOMING: usize = 4;
+
+/// The calendar of `show`, archived from `feed_url` into `episodes_dir`.
+pub fn render(show: &str, feed_url: &str, catalog: &Catalog, episodes_dir: &Path) -> String {
+    let now = Utc::now();
+    let stamp = timestamp(now);
+    let mut events = Vec::new();
+    for (_, entry) in catalog.archived(episodes_dir) {
+        let key = entry.guid.as_deref().unwrap_or(&entry.url);
+        events.push(event(&[
+            ("UID", uid(feed_url, key)),
+            ("DTSTAMP", stamp.clone()),
+            ("DTSTART", timestamp(entry.published.with_timezone(&Utc))),
+            ("SUMMARY", text(&format!("{}: {}", show, crate::element_text(&entry.title)))),
+            ("URL", entry.url.clone()),
+        ]));
+    }
+
+    let cadence = Cadence::detect(catalog.entries().map(|(_, entry)| entry.published.with_timezone(&Utc)));
+    for due in cadence.iter().flat_map(|cadence| cadence.upcoming(now, UPCOMING)) {
+        let description = format!("Expected from its {} cadence", cadence.as_ref().map(Cadence::describe).unwrap_or_default());
+        events.push(event(&[
+            ("UID", uid(feed_url, &format!("expected {}", due.date_naive()))),
+            ("DTSTAMP", stamp.clone()),
+            ("DTSTART", timestamp(due)),
+            ("SUMMARY", text(&format!("{}: new episode expected", show))),
+            ("DESCRIPTION", text(&description)),
+            ("STATUS", "TENTATIVE".to_string()),
+            ("TRANSP", "TRANSPARENT".to_string()),
+        ]));
+    }
+    calendar(show, &events)
+}
+
+/// `events`, each a `BEGIN:VEVENT`..`END:VEVENT` block, as a calendar named `name`.
+fn calendar(name: &str, events: &[String]) -> String {
+    let mut ics = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//poarder//poarder//EN", "CALSCALE:GREGORIAN"] {
+        ics += line;
+        ics += "\r\n";
+    }
+    ics += &fold(&format!("X-WR-CALNAME:{}", text(name)));
+    for event in events {
+        ics += event;
+    }
+    ics += "END:VCALENDAR\r\n";
+    ics
+}
+
+fn event(properties: &[(&str, String)]) -> String {
+    let mut event = "BEGIN:VEVENT\r\n".to_string();
+    for (name, value) in properties {
+        event += &fold(&format!("{}:{}", name, value));
+    }
+    event += "END:VEVENT\r\n";
+    event
+}
+
+/// A UID for `key` of the feed at `feed_url` that stays the same across runs.
+fn uid(feed_url: &str, key: &str) -> String {
+    let hash = Sha256::new().chain_update(feed_url).chain_update([0]).chain_update(key).finalize();
+    format!("{}@poarder", &format!("{:x}", hash)[..32])
+}
+
+fn timestamp(when: DateTime<Utc>) -> String {
+    when.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// `value` escaped as iCalendar TEXT.
+fn text(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace("\r\n", "\\n").replace('\n', "\\n")
+}
+
+/// `line` folded into lines of at most 75 bytes, as iCalendar requires.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded += "\r\n ";
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded += "\r\n";
+    folded
+}
+
+/// The calendars in output directories `roots`, by show: an archive's own
+/// or those of the shows' directories in it.
+fn calendars(roots: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut calendars = Vec::new();
+    for root in roots {
+        let own = FeedLayout::new(root).feed_dir().join(CALENDAR_FILE);
+        if own.is_file() {
+            let name = root.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            calendars.push((name, own));
+            continue
+        }
+        let Ok(entries) = fs::read_dir(root) else {
+            continue
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = FeedLayout::new(&entry.path()).feed_dir().join(CALENDAR_FILE);
+            if path.is_file() {
+                calendars.push((entry.file_name().to_string_lossy().into_owned(), path));
+            }
+        }
+    }
+    calendars.sort();
+    calendars
+}
+
+/// The calendar `GET path` asks for, if there is one: one show's, or all
+/// of them merged.
+pub fn serve(roots: &[PathBuf], path: &str) -> Option<String> {
+    let path = path.split('?').next().unwrap_or(path);
+    let calendars = calendars(roots);
+    if path == "/calendar.ics" {
+        let events: Vec<String> = calendars.iter()
+            .filter_map(|(_, path)| fs::read_to_string(path).ok())
+            .flat_map(|ics| events_of(&ics))
+            .collect();
+        return Some(calendar("Podcasts", &events))
+    }
+    let name = percent_decode(path.strip_prefix("/calendar/")?.strip_suffix(".ics")?);
+    let (_, path) = calendars.iter().find(|(show, _)| *show == name)?;
+    fs::read_to_string(path).ok()
+}
+
+/// The `VEVENT` blocks of calendar `ics`.
+fn events_of(ics: &str) -> Vec<String> {
+    let mut events = Vec::new();
+    let mut rest = ics;
+    while let Some(start) = rest.find("BEGIN:VEVENT") {
+        let Some(end) = rest[start..].find("END:VEVENT\r\n") else {
+            break
+        };
+        let end = start + end + "END:VEVENT\r\n".len();
+        events.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    events
+}
+
+/// `%XX` escapes in a request path decoded, e.g. spaces in a show's name.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%').then(|| text.get(i + 1..i + 3)).flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
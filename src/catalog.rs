@@ -0,0 +1,318 @@
+//! Catalog of every episode seen in the upstream feed, kept so that
+//! episodes later delisted upstream can still be offered in the locally
+//! generated feed (`feed/archive.xml`) and listed by `poarder removed`.
+//! It is saved under the archive's state lock, merged with what other
+//! instances archiving into the same directory saved since it was read.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::naming::NameTemplate;
+use crate::state;
+use crate::Episode;
+
+/// Namespace of the elements poarder adds to the generated feed.
+const NAMESPACE: &str = "https://github.com/drew-buckley/poarder";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub title: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guid: Option<String>,
+    pub published: DateTime<FixedOffset>,
+    /// The item's XML as last seen upstream; empty with --low-memory.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub raw: String,
+    /// The item's `podcast:` elements, kept even without `raw` so the
+    /// generated feed doesn't lose them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub podcast_tags: Vec<String>,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    /// When the episode was first found missing from the feed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed: Option<DateTime<Utc>>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CatalogFile {
+    /// Entries by episode file name.
+    episodes: BTreeMap<String, CatalogEntry>,
+}
+
+pub struct Catalog {
+    path: PathBuf,
+    file: CatalogFile,
+    /// File names present in the feed on this run.
+    listed: HashSet<String>,
+    /// File names whose entries moved to another name on this run, so that
+    /// saving doesn't bring them back from the file.
+    moved: HashSet<String>,
+}
+
+impl Catalog {
+    pub fn open(state_dir: &Path) -> Result<Catalog, Box<dyn Error>> {
+        let path = state_dir.join("catalog.json");
+        let file = read(&path)?;
+        Ok(Catalog { path, file, listed: HashSet::new(), moved: HashSet::new() })
+    }
+
+    /// Note that `episode` is listed upstream right now. It is cataloged
+    /// under the name of its file in `episodes_dir`, or the one `naming`
+    /// will give it.
+    pub fn saw(&mut self, episode: &Episode, naming: &NameTemplate, episodes_dir: &Path) {
+        let current = naming.file_name(episode);
+        let name = naming.existing_file(episodes_dir, episode)
+            .and_then(|path| Some(path.strip_prefix(episodes_dir).ok()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| current.clone());
+        // The entry moves over when the episode's name changes: a new
+        // --name-template, or the `.mp3` every episode used to get.
+        if !self.file.episodes.contains_key(&name) {
+            let mut names = std::iter::once(current).chain(naming.earlier_names(episode));
+            if let Some((earlier, entry)) = names.find_map(|earlier| self.file.episodes.remove_entry(&earlier)) {
+                self.file.episodes.insert(name.clone(), entry);
+                self.moved.insert(earlier);
+            }
+        }
+        let now = Utc::now();
+        let first_seen = self.file.episodes.get(&name).map_or(now, |entry| entry.first_seen);
+        let raw = match (episode.raw.is_empty(), self.file.episodes.get(&name)) {
+            (true, Some(entry)) => entry.raw.clone(),
+            _ => episode.raw.clone()
+        };
+
+        self.file.episodes.insert(name.clone(), CatalogEntry {
+            title: episode.title.clone(),
+            url: episode.url.clone(),
+            guid: episode.guid.clone(),
+            published: episode.published,
+            raw,
+            podcast_tags: episode.podcast_tags.clone(),
+            first_seen,
+            last_seen: now,
+            removed: None,
+        });
+        self.listed.insert(name);
+    }
+
+    /// Note that the episode archived as `name` is listed upstream right now,
+    /// unchanged; returns its publication time.
+    pub fn saw_unchanged(&mut self, name: &str) -> Option<DateTime<FixedOffset>> {
+        let entry = self.file.episodes.get_mut(name)?;
+        entry.last_seen = Utc::now();
+        entry.removed = None;
+        self.listed.insert(name.to_string());
+        Some(entry.published)
+    }
+
+    /// File names of the episodes present in all of `episode_dirs`, by the
+    /// item XML they were last seen with.
+    pub fn archived_items(&self, episode_dirs: &[PathBuf]) -> HashMap<&str, &str> {
+        self.file.episodes.iter()
+            .filter(|(_, entry)| !entry.raw.is_empty())
+            .filter(|(name, _)| episode_dirs.iter().all(|dir| archived_file(dir, name).is_some()))
+            .map(|(name, entry)| (entry.raw.as_str(), name.as_str()))
+            .collect()
+    }
+
+    /// GUIDs of the episodes that are in `episodes_dir`.
+    pub fn archived_guids(&self, episodes_dir: &Path) -> HashSet<String> {
+        self.file.episodes.iter()
+            .filter(|(name, _)| archived_file(episodes_dir, name).is_some())
+            .filter_map(|(_, entry)| entry.guid.clone())
+            .collect()
+    }
+
+    /// Every episode in the catalog, by file name.
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &CatalogEntry)> {
+        self.file.episodes.iter()
+    }
+
+    /// Episodes in the catalog that are in `episodes_dir`, by file name.
+    pub fn archived<'a>(&'a self, episodes_dir: &'a Path) -> impl Iterator<Item = (&'a String, &'a CatalogEntry)> {
+        self.file.episodes.iter().filter(|(name, _)| archived_file(episodes_dir, name).is_some())
+    }
+
+    /// Episodes in the catalog that the feed no longer lists, by file name.
+    pub fn delisted(&self) -> impl Iterator<Item = (&String, &CatalogEntry)> {
+        self.file.episodes.iter().filter(|(name, _)| !self.listed.contains(*name))
+    }
+
+    /// Tombstone episodes that went missing on this run, returning their
+    /// file names.
+    pub fn mark_removed(&mut self) -> Vec<String> {
+        let now = Utc::now();
+        let mut newly_removed = Vec::new();
+        for (name, entry) in self.file.episodes.iter_mut() {
+            if !self.listed.contains(name) && entry.removed.is_none() {
+                entry.removed = Some(now);
+                newly_removed.push(name.clone());
+            }
+        }
+        newly_removed
+    }
+
+    /// Tombstoned episodes, most recently removed first.
+    pub fn removed(&self) -> Vec<(&String, &CatalogEntry)> {
+        let mut removed: Vec<_> = self.file.episodes.iter().filter(|(_, entry)| entry.removed.is_some()).collect();
+        removed.sort_by_key(|(_, entry)| std::cmp::Reverse(entry.removed));
+        removed
+    }
+
+    /// File the entry for `from` under `to`, after the episode was renamed.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(entry) = self.file.episodes.remove(from) {
+            self.file.episodes.insert(to.to_string(), entry);
+            self.moved.insert(from.to_string());
+        }
+    }
+
+    /// Write the catalog, keeping the entries other instances saved since
+    /// it was read, and theirs of an episode both have if seen later.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let _lock = state::lock(self.path.parent().unwrap_or(Path::new(".")))
+            .map_err(|e| format!("could not lock the archive state: {}", e))?;
+        let mut file = CatalogFile { episodes: self.file.episodes.clone() };
+        for (name, theirs) in read(&self.path)?.episodes {
+            if self.moved.contains(&name) {
+                continue
+            }
+            match file.episodes.get(&name) {
+                Some(ours) if ours.last_seen >= theirs.last_seen => (),
+                _ => {
+                    file.episodes.insert(name, theirs);
+                }
+            }
+        }
+
+        let text = serde_json::to_string_pretty(&file)?;
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, text)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The upstream feed plus every delisted episode that is still in
+    /// `episodes_dir`, flagged with `<poarder:archivedOnly>`. With
+    /// `mirror_url`, their enclosures point at the local copy under it, and
+    /// with `mirror_all` so do those of listed episodes that are archived;
+    /// nothing else in the feed is changed.
+    pub fn regenerate(&self, feed_xml: &str, episodes_dir: &Path, mirror_url: Option<&str>, mirror_all: bool) -> Option<String> {
+        let (root, tag, close) = match feed_xml.rfind("</channel>") {
+            Some(close) => ("<rss", "item", close),
+            None => ("<feed", "entry", feed_xml.rfind("</feed>")?)
+        };
+        let local_url = |name: &str| mirror_url.map(|base| format!("{}/episodes/{}", base.trim_end_matches('/'), name));
+
+        let mut items = String::new();
+        for (name, entry) in self.delisted() {
+            let Some(file) = archived_file(episodes_dir, name) else {
+                continue
+            };
+            let url = local_url(&file).unwrap_or_else(|| entry.url.clone());
+            let body = match (entry.raw.is_empty(), tag) {
+                (true, "item") => format!("<title>{}</title><pubDate>{}</pubDate><enclosure url=\"{}\"/>{}",
+                    escape(&entry.title), entry.published.to_rfc2822(), escape(&url), entry.podcast_tags.concat()),
+                (true, _) => format!("<title>{}</title><updated>{}</updated><link rel=\"enclosure\" href=\"{}\"/>{}",
+                    escape(&entry.title), entry.published.to_rfc3339(), escape(&url), entry.podcast_tags.concat()),
+                (false, _) => rewrite_enclosure(&entry.raw, &entry.url, &url)
+            };
+            items += &format!("<{tag}>{}<poarder:archivedOnly>true</poarder:archivedOnly></{tag}>\n", body);
+        }
+
+        // Listed items to rewrite in place, as (offset, length, new XML).
+        let mut rewrites = Vec::new();
+        if mirror_all {
+            for (name, entry) in self.file.episodes.iter().filter(|(name, _)| self.listed.contains(*name)) {
+                let url = match archived_file(episodes_dir, name).and_then(|file| local_url(&file)) {
+                    Some(url) if !entry.raw.is_empty() => url,
+                    _ => continue
+                };
+                if let Some(offset) = feed_xml[..close].find(&entry.raw) {
+                    rewrites.push((offset, entry.raw.len(), rewrite_enclosure(&entry.raw, &entry.url, &url)));
+                }
+            }
+            rewrites.sort_by_key(|(offset, _, _)| *offset);
+        }
+
+        let mut xml = String::with_capacity(feed_xml.len() + items.len() + 64);
+        let mut copied = 0;
+        for (offset, len, item) in rewrites {
+            if offset < copied {
+                continue
+            }
+            xml += &feed_xml[copied..offset];
+            xml += &item;
+            copied = offset + len;
+        }
+        xml += &feed_xml[copied..close];
+        xml += &items;
+        xml += &feed_xml[close..];
+
+        if let Some(start) = xml.find(root) {
+            xml.insert_str(start + root.len(), &format!(" xmlns:poarder=\"{}\"", NAMESPACE));
+        }
+        Some(xml)
+    }
+}
+
+fn read(path: &Path) -> Result<CatalogFile, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(CatalogFile::default()),
+        Err(e) => Err(Box::new(e))
+    }
+}
+
+/// `item_xml` with `old_url` replaced by `new_url` inside `<enclosure>` and
+/// `<link>` tags only.
+fn rewrite_enclosure(item_xml: &str, old_url: &str, new_url: &str) -> String {
+    let (old_url, new_url) = (escape(old_url), escape(new_url));
+    let mut rewritten = String::with_capacity(item_xml.len());
+    let mut rest = item_xml;
+    while let Some(found) = rest.find(old_url.as_ref()) {
+        let tag = &rest[rest[..found].rfind('<').unwrap_or(0)..found];
+        let in_enclosure = !tag.contains('>') && (tag.starts_with("<enclosure") || tag.starts_with("<link"));
+        rewritten += &rest[..found];
+        rewritten += if in_enclosure { new_url.as_ref() } else { old_url.as_ref() };
+        rest = &rest[found + old_url.len()..];
+    }
+    rewritten += rest;
+    rewritten
+}
+
+/// The file in `episodes_dir` for the catalog entry `name`: that name, or
+/// the `.mp3` one it was archived under before file names followed the
+/// media type.
+pub fn archived_file(episodes_dir: &Path, name: &str) -> Option<String> {
+    let legacy = Path::new(name).with_extension("mp3").to_string_lossy().into_owned();
+    [name.to_string(), legacy].into_iter().find(|file| episodes_dir.join(file).exists())
+}
+
+/// Print the `poarder removed` table for the archive rooted at `output_dir`.
+pub fn print_removed(output_dir: &Path, catalog: &Catalog) {
+    let removed = catalog.removed();
+    if removed.is_empty() {
+        println!("{}: no episodes removed upstream", output_dir.display());
+        return
+    }
+
+    let episodes_dir = crate::layout::FeedLayout::new(output_dir).episodes_dir();
+    let archived = removed.iter().filter(|(name, _)| archived_file(&episodes_dir, name).is_some()).count();
+    println!("{}: {} episodes removed upstream, {} of them archived", output_dir.display(), removed.len(), archived);
+    println!("{:<10} {:<10} {:<10} {:<8} TITLE", "REMOVED", "LAST SEEN", "PUBLISHED", "ARCHIVED");
+    for (name, entry) in removed {
+        println!("{:<10} {:<10} {:<10} {:<8} {}",
+            entry.removed.unwrap().format("%Y-%m-%d"),
+            entry.last_seen.format("%Y-%m-%d"),
+            entry.published.format("%Y-%m-%d"),
+            if archived_file(&episodes_dir, name).is_some() { "yes" } else { "no" },
+            entry.title);
+    }
+}
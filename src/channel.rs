@@ -0,0 +1,97 @@
+//! The show as a whole, as described by its feed's channel.
+
+use log::info;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::fixtures::Http;
+use crate::interop;
+use crate::layout::FeedLayout;
+
+/// What `--save-cover` names the show's cover in its episodes directory.
+pub const COVER_FILE: &str = "cover.jpg";
+
+/// What the metadata files say about a show.
+#[derive(Debug, Default)]
+pub struct Channel {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub image: Option<String>,
+}
+
+impl Channel {
+    /// The channel-level elements of an RSS feed; a JSON Feed's title and
+    /// author.
+    pub fn parse(rss_xml: &str) -> Channel {
+        let mut channel = Channel::default();
+        if poarder_core::FeedFormat::detect(None, rss_xml) == poarder_core::FeedFormat::Json {
+            channel.title = poarder_core::jsonfeed::title(rss_xml);
+            channel.author = poarder_core::jsonfeed::author(rss_xml);
+            return channel
+        }
+        let mut reader = Reader::from_str(rss_xml);
+        reader.trim_text(true);
+        let mut open: Vec<Vec<u8>> = Vec::new();
+        loop {
+            let (element, empty) = match reader.read_event() {
+                Ok(Event::Start(element)) => (element, false),
+                Ok(Event::Empty(element)) => (element, true),
+                Ok(Event::End(_)) => {
+                    open.pop();
+                    continue
+                },
+                Ok(Event::Eof) | Err(_) => return channel,
+                _ => continue
+            };
+            let in_channel = matches!(open.last().map(Vec::as_slice), Some(b"channel"));
+            let in_image = matches!(open.last().map(Vec::as_slice), Some(b"image"));
+            let name = element.name();
+            let field = match name.as_ref() {
+                b"item" => return channel,
+                b"itunes:image" if in_channel => {
+                    if let Some(href) = element.try_get_attribute("href").ok().flatten() {
+                        channel.image = href.decode_and_unescape_value(&reader).ok().map(|href| href.to_string());
+                    }
+                    None
+                },
+                b"title" if in_channel => Some(&mut channel.title),
+                b"itunes:author" if in_channel => Some(&mut channel.author),
+                b"description" | b"itunes:summary" if in_channel && channel.description.is_none() => Some(&mut channel.description),
+                b"language" if in_channel => Some(&mut channel.language),
+                b"url" if in_image && channel.image.is_none() => Some(&mut channel.image),
+                _ => None
+            };
+            match (field, empty) {
+                (Some(field), false) => match reader.read_text(name) {
+                    Ok(text) => *field = Some(crate::element_text(&text)),
+                    Err(_) => return channel
+                },
+                (None, false) => open.push(name.as_ref().to_vec()),
+                (_, true) => ()
+            }
+        }
+    }
+}
+
+/// The cover from `--artwork`, else the feed's image as published.
+pub async fn write_cover(http: &Http, layout: &FeedLayout, channel: &Channel, cover: &Path) -> Result<(), Box<dyn Error>> {
+    let rendition = layout.assets_dir().join("cover.jpg");
+    if rendition.exists() {
+        return interop::link_or_copy(&rendition, cover)
+    }
+    let Some(url) = &channel.image else {
+        return Ok(())
+    };
+    info!("Downloading cover from {}", url);
+    let data = http.send(http.get(url)).await?.error_for_status()?.bytes().await?;
+    let tmp_path = cover.with_extension("part");
+    fs::write(&tmp_path, &data)?;
+    fs::rename(tmp_path, cover)?;
+    Ok(())
+}
+
@@ -0,0 +1,239 @@
+//! `poarder config check`: validate the configuration without touching the
+//! network, and show the options a run would use and where each came from.
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::doctor::{find_program, Report};
+use crate::schedule::Schedule;
+use poarder_core::naming::NameTemplate;
+
+/// Where an option's effective value came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Default,
+    ConfigFile,
+    /// The feed's `[[feeds]]` entry.
+    Feed,
+    Env,
+    Flag,
+}
+
+/// An option as a run would see it.
+pub struct Setting {
+    pub key: &'static str,
+    pub value: Option<toml::Value>,
+    pub source: Source,
+}
+
+impl Setting {
+    fn origin(&self) -> String {
+        match self.source {
+            Source::Default => "default".to_string(),
+            Source::ConfigFile => "config file".to_string(),
+            Source::Feed => "[[feeds]] entry".to_string(),
+            Source::Env => format!("POARDER_{}", self.key.to_uppercase().replace('-', "_")),
+            Source::Flag => format!("--{}", self.key),
+        }
+    }
+
+    fn str(&self) -> Option<&str> {
+        self.value.as_ref().and_then(toml::Value::as_str)
+    }
+}
+
+/// A feed to archive and its options.
+pub struct FeedSettings {
+    pub url: String,
+    pub weight: u32,
+    pub settings: Vec<Setting>,
+}
+
+/// Check the config file at `config_path` and the effective `settings`,
+/// then print them. `feeds` is why there are none when it's an error.
+pub fn run(config_path: Option<&str>, settings: &[Setting], feeds: Result<Vec<FeedSettings>, String>) -> Result<(), Box<dyn Error>> {
+    let mut report = Report::default();
+
+    let config = match config_path {
+        Some(path) => match Config::load(Path::new(path)) {
+            Ok(config) => {
+                report.ok(&format!("Config file {} parses", path));
+                config
+            },
+            Err(e) => {
+                report.fail(&format!("Config file {} is invalid: {}", path, e), "correct the file; see README.md for the format");
+                Config::default()
+            }
+        },
+        None => {
+            report.ok("No config file; using defaults");
+            Config::default()
+        }
+    };
+
+    check_settings(&mut report, "", settings);
+    match &feeds {
+        Ok(feeds) => for feed in feeds {
+            check_url(&mut report, "Feed", &feed.url);
+            if feed.settings.iter().any(|setting| setting.source == Source::Feed) {
+                check_settings(&mut report, &format!("{}: ", feed.url), &feed.settings);
+            }
+        },
+        Err(e) => report.warn(e, "a run would have nothing to do"),
+    }
+    check_sections(&mut report, &config);
+
+    println!("\nEffective configuration:\n");
+    print_settings(settings.iter());
+    if let Ok(feeds) = &feeds {
+        for feed in feeds {
+            println!("\n[[feeds]]\nurl = {}\nweight = {}", toml::Value::from(feed.url.as_str()), feed.weight);
+            print_settings(feed.settings.iter().filter(|setting| setting.source == Source::Feed));
+        }
+    }
+
+    println!("\n{} problem(s), {} warning(s)", report.failures, report.warnings);
+    if report.failures > 0 {
+        return Err("the configuration has problems".into())
+    }
+    Ok(())
+}
+
+fn check_settings(report: &mut Report, prefix: &str, settings: &[Setting]) {
+    let get = |key: &str| settings.iter().find(|setting| setting.key == key);
+
+    if let Some(template) = get("name-template").and_then(Setting::str) {
+        let date_format = get("name-date-format").and_then(Setting::str);
+        match NameTemplate::new(template, date_format) {
+            Ok(_) => report.ok(&format!("{}Name template \"{}\" is valid", prefix, template)),
+            Err(e) => report.fail(&format!("{}{}", prefix, e), "see the placeholders under Archive layout in README.md"),
+        }
+    }
+
+    for key in ["include-regex", "exclude-regex"] {
+        if let Some(pattern) = get(key).and_then(Setting::str) {
+            match regex::Regex::new(pattern) {
+                Ok(_) => report.ok(&format!("{}{} \"{}\" is valid", prefix, key, pattern)),
+                Err(e) => report.fail(&format!("{}{} is invalid: {}", prefix, key, e), "see https://docs.rs/regex for the syntax"),
+            }
+        }
+    }
+
+    let dirs: Vec<&str> = match get("output-dir").and_then(|setting| setting.value.as_ref()) {
+        Some(toml::Value::Array(dirs)) => dirs.iter().filter_map(toml::Value::as_str).collect(),
+        Some(value) => value.as_str().into_iter().collect(),
+        None => Vec::new(),
+    };
+    for dir in dirs {
+        check_dir(report, &format!("{}Output directory", prefix), Path::new(dir));
+    }
+}
+
+fn check_dir(report: &mut Report, what: &str, dir: &Path) {
+    if dir.is_dir() {
+        report.ok(&format!("{} {} exists", what, dir.display()));
+    }
+    else if dir.exists() {
+        report.fail(&format!("{} {} is not a directory", what, dir.display()), "point it at a directory");
+    }
+    else if dir.parent().is_some_and(|parent| parent.as_os_str().is_empty() || parent.is_dir()) {
+        report.ok(&format!("{} {} will be created on the first run", what, dir.display()));
+    }
+    else {
+        report.warn(&format!("{} {} does not exist, nor does its parent", what, dir.display()),
+            "check the path; poarder will try to create all of it");
+    }
+}
+
+fn check_url(report: &mut Report, what: &str, url: &str) {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => report.ok(&format!("{} URL {} is valid", what, url)),
+        Ok(parsed) => report.fail(&format!("{} URL {} uses {}:, not http: or https:", what, url, parsed.scheme()), "use the feed's web address"),
+        Err(e) => report.fail(&format!("{} URL {} is invalid: {}", what, url, e), "correct the URL"),
+    }
+}
+
+/// The config's other tables: paths, commands, schedules and the feeds they name.
+fn check_sections(report: &mut Report, config: &Config) {
+    for dir in &config.tee {
+        check_dir(report, "Tee directory", Path::new(dir));
+    }
+
+    let commands = config.plugins.iter().map(|plugin| ("Plugin", &plugin.name, &plugin.command))
+        .chain(config.resolvers.iter().map(|resolver| ("Resolver", &resolver.name, &resolver.command)));
+    for (what, name, command) in commands {
+        match command.first() {
+            Some(program) if find_program(program) => report.ok(&format!("{} {} command {} found", what, name, program)),
+            Some(program) => report.fail(&format!("{} {} command {} not found", what, name, program),
+                "install it or use an absolute path in `command`"),
+            None => report.fail(&format!("{} {} has an empty command", what, name), "set `command` to the program to run"),
+        }
+    }
+    let auth_refresh_cmds = std::iter::once(&config.auth_refresh_cmd)
+        .chain(config.feeds.iter().filter_map(|feed| feed.auth_refresh_cmd.as_ref()));
+    for program in auth_refresh_cmds.filter_map(|command| command.first()) {
+        match find_program(program) {
+            true => report.ok(&format!("auth-refresh-cmd {} found", program)),
+            false => report.fail(&format!("auth-refresh-cmd {} not found", program), "install it or use an absolute path"),
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    for plugin in &config.wasm_plugins {
+        match Path::new(&plugin.path).is_file() {
+            true => report.ok(&format!("WASM module {} found", plugin.path)),
+            false => report.fail(&format!("WASM module {} not found", plugin.path), "correct `path`"),
+        }
+    }
+    #[cfg(not(feature = "wasm"))]
+    if !config.wasm_plugins.is_empty() {
+        report.fail("The config has wasm-plugins, but this poarder was built without WASM support", "rebuild with `--features wasm`");
+    }
+    #[cfg(not(feature = "artwork"))]
+    if !config.artwork.is_empty() {
+        report.fail("The config has artwork renditions, but this poarder was built without artwork support", "rebuild with `--features artwork`");
+    }
+
+    for notify in &config.notify {
+        let what = format!("Notifications to {:?}", notify.service);
+        match notify.problem() {
+            Some(problem) => report.fail(&format!("{}: {}", what, problem), "see Notifications in README.md"),
+            None => check_url(report, &what, &notify.url),
+        }
+    }
+
+    if let Some(digest) = &config.digest {
+        match digest.problem() {
+            Some(problem) => report.fail(&format!("Digest: {}", problem), "see Digests in README.md"),
+            None => report.ok(&format!("Digest every {} is valid", digest.every)),
+        }
+    }
+
+    for schedule in &config.schedules {
+        if let Err(e) = Schedule::from_config(schedule) {
+            report.fail(&format!("Schedule for {}: {}", schedule.feed.as_deref().unwrap_or("every feed"), e), "use HH:MM times and weekdays such as \"mon\"");
+        }
+    }
+    let named_feeds = config.schedules.iter().filter_map(|schedule| schedule.feed.as_deref())
+        .chain(config.scan.iter().filter_map(|scan| scan.feed.as_deref()));
+    for feed in named_feeds {
+        check_url(report, "Scheduled or scanned feed", feed);
+    }
+}
+
+fn print_settings<'a>(settings: impl Iterator<Item = &'a Setting>) {
+    let lines: Vec<(String, String)> = settings
+        .map(|setting| match &setting.value {
+            Some(value) => (format!("{} = {}", setting.key, value), setting.origin()),
+            None => (format!("# {} is unset", setting.key), String::new()),
+        })
+        .collect();
+    let width = lines.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+    for (line, origin) in lines {
+        match origin.as_str() {
+            "" => println!("{}", line),
+            origin => println!("{:width$}  # from {}", line, origin, width = width),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::digest::DigestConfig;
+use crate::hosts::HostProfile;
+use crate::notify::NotifyConfig;
+use crate::plugins::PluginConfig;
+use crate::resolvers::ResolverConfig;
+use crate::scan::ScanConfig;
+use crate::schedule::ScheduleConfig;
+
+/// Contents of the TOML configuration file passed with `--config`, or
+/// `config.toml` in [`config_dir`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Defaults for command line options.
+    #[serde(flatten)]
+    pub defaults: Options,
+    /// Feeds archived when none are given on the command line.
+    pub feeds: Vec<FeedConfig>,
+    pub plugins: Vec<PluginConfig>,
+    /// Extra archive roots that receive a copy of every episode.
+    pub tee: Vec<String>,
+    /// Host profile overrides keyed by domain.
+    pub hosts: HashMap<String, HostProfile>,
+    /// Commands that turn web pages (e.g. YouTube videos) into media URLs.
+    pub resolvers: Vec<ResolverConfig>,
+    /// Command printing fresh headers (`Name: value`) and URL parameters
+    /// (`?key=value`) when the feed's host answers 401 or 403, for feeds
+    /// without one of their own.
+    pub auth_refresh_cmd: Vec<String>,
+    /// Expected publication times, used by `--serve` to poll at the right moment.
+    pub schedules: Vec<ScheduleConfig>,
+    /// Feeds that stop reading at already archived items, and how.
+    pub scan: Vec<ScanConfig>,
+    /// Chat rooms told about new episodes.
+    pub notify: Vec<NotifyConfig>,
+    /// A periodic newsletter of new episodes.
+    pub digest: Option<DigestConfig>,
+    #[cfg(feature = "artwork")]
+    pub artwork: Vec<crate::artwork::ArtworkConfig>,
+    /// Kept so configs asking for artwork renditions are rejected rather than ignored.
+    #[cfg(not(feature = "artwork"))]
+    pub artwork: Vec<toml::Value>,
+    #[cfg(feature = "wasm")]
+    pub wasm_plugins: Vec<crate::wasm::WasmPluginConfig>,
+    /// Kept so configs using WASM plugins are rejected rather than ignored.
+    #[cfg(not(feature = "wasm"))]
+    pub wasm_plugins: Vec<toml::Value>,
+}
+
+/// Command line options that can be set in the config file, for every feed
+/// or for one. Flags and `POARDER_*` variables take precedence.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Options {
+    pub output_dir: Option<String>,
+    pub task_count: Option<usize>,
+    pub max_downloads: Option<usize>,
+    pub name_template: Option<String>,
+    pub name_date_format: Option<String>,
+    pub replace_existing: Option<bool>,
+    pub include_regex: Option<String>,
+    pub exclude_regex: Option<String>,
+    pub match_descriptions: Option<bool>,
+}
+
+impl Options {
+    /// Whether the option named `key` (as on the command line) is set.
+    pub fn is_set(&self, key: &str) -> bool {
+        match key {
+            "output-dir" => self.output_dir.is_some(),
+            "task-count" => self.task_count.is_some(),
+            "max-downloads" => self.max_downloads.is_some(),
+            "name-template" => self.name_template.is_some(),
+            "name-date-format" => self.name_date_format.is_some(),
+            "replace-existing" => self.replace_existing.is_some(),
+            "include-regex" => self.include_regex.is_some(),
+            "exclude-regex" => self.exclude_regex.is_some(),
+            "match-descriptions" => self.match_descriptions.is_some(),
+            _ => false
+        }
+    }
+}
+
+/// A `[[feeds]]` entry.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FeedConfig {
+    pub url: String,
+    /// Share of the run's download slots, as with `weight=` in a feeds file.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// This feed's `auth-refresh-cmd`, instead of the global one.
+    #[serde(default)]
+    pub auth_refresh_cmd: Option<Vec<String>>,
+    /// Overrides for this feed; its own `output-dir` holds the archive
+    /// itself rather than a directory per show.
+    #[serde(flatten)]
+    pub options: Options,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+/// The config file used without `--config`, if there is one.
+pub fn default_path() -> Option<PathBuf> {
+    let path = config_dir().ok()?.join("config.toml");
+    path.exists().then_some(path)
+}
+
+/// poarder's directory under `$XDG_CONFIG_HOME`, else `~/.config`.
+pub fn config_dir() -> Result<PathBuf, Box<dyn Error>> {
+    let base = match (std::env::var_os("XDG_CONFIG_HOME"), std::env::var_os("HOME"), std::env::var_os("APPDATA")) {
+        (Some(dir), _, _) if !dir.is_empty() => PathBuf::from(dir),
+        (_, Some(home), _) => PathBuf::from(home).join(".config"),
+        (_, _, Some(app_data)) => PathBuf::from(app_data),
+        _ => return Err("cannot find a config directory; set XDG_CONFIG_HOME".into())
+    };
+    Ok(base.join("poarder"))
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    /// The `auth-refresh-cmd` for the feed at `feed_url`: its own, else the
+    /// global one.
+    pub fn auth_refresh_cmd(&self, feed_url: &str) -> &[String] {
+        self.feeds.iter()
+            .find(|feed| feed.url == feed_url)
+            .and_then(|feed| feed.auth_refresh_cmd.as_deref())
+            .unwrap_or(&self.auth_refresh_cmd)
+    }
+}
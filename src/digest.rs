@@ -0,0 +1,267 @@
+//! `[digest]`: a periodic HTML newsletter of the episodes archived since the
+//! last one, with an excerpt of each one's show notes, written to a file or
+//! mailed. Episodes are queued in `digest.json` in the output directory as
+//! they are stored, and the digest goes out at the end of the first run
+//! after it is due.
+
+use chrono::{DateTime, Local, Utc};
+use log::info;
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::Episode;
+
+/// The `[digest]` table of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DigestConfig {
+    /// How often a digest goes out, e.g. `1w` or `1d`.
+    #[serde(default = "default_every")]
+    pub every: String,
+    /// File to write each digest to; `{date}` is replaced by its date, to
+    /// keep them all.
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default = "default_title")]
+    pub title: String,
+    /// Characters of show notes to quote for each episode; 0 for none.
+    #[serde(default = "default_excerpt")]
+    pub excerpt: usize,
+    #[cfg(feature = "smtp")]
+    #[serde(default)]
+    pub smtp: Option<crate::smtp::SmtpConfig>,
+    /// Kept so configs mailing the digest are rejected rather than ignored.
+    #[cfg(not(feature = "smtp"))]
+    #[serde(default)]
+    pub smtp: Option<toml::Value>,
+}
+
+fn default_every() -> String {
+    "1w".to_string()
+}
+
+fn default_title() -> String {
+    "New podcast episodes".to_string()
+}
+
+fn default_excerpt() -> usize {
+    300
+}
+
+impl DigestConfig {
+    pub fn interval(&self) -> Result<Duration, String> {
+        crate::parse_age(&self.every)
+    }
+
+    /// Why this digest can't be used, if it can't.
+    pub fn problem(&self) -> Option<String> {
+        if let Err(e) = self.interval() {
+            return Some(format!("`every`: {}", e))
+        }
+        #[cfg(not(feature = "smtp"))]
+        if self.smtp.is_some() {
+            return Some("`smtp` is set, but this poarder was built without the `smtp` feature".to_string())
+        }
+        #[cfg(feature = "smtp")]
+        if let Some(problem) = self.smtp.as_ref().and_then(crate::smtp::SmtpConfig::problem) {
+            return Some(format!("`smtp`: {}", problem))
+        }
+        if self.output.is_none() && self.smtp.is_none() {
+            return Some("set `output`, `smtp` or both".to_string())
+        }
+        None
+    }
+}
+
+/// An episode waiting for the next digest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub show: String,
+    pub title: String,
+    pub published: DateTime<Utc>,
+    pub archived: DateTime<Utc>,
+    /// The episode's web page, if the feed gives one.
+    pub link: Option<String>,
+    /// The archived file if the archive is served, else the enclosure.
+    pub url: String,
+    #[serde(default)]
+    pub excerpt: String,
+}
+
+impl Entry {
+    pub fn new(episode: &Episode, local_url: Option<String>, excerpt: usize) -> Entry {
+        Entry {
+            show: episode.show.as_deref().map(crate::element_text).unwrap_or_default(),
+            title: crate::element_text(&episode.title),
+            published: episode.published.with_timezone(&Utc),
+            archived: Utc::now(),
+            link: episode.link.clone(),
+            url: local_url.unwrap_or_else(|| episode.url.clone()),
+            excerpt: episode.description.as_deref().filter(|_| excerpt > 0).map(|notes| excerpt_of(notes, excerpt)).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DigestFile {
+    last_sent: Option<DateTime<Utc>>,
+    pending: Vec<Entry>,
+}
+
+/// The digest of a run: what it stored, to queue for the next digest.
+#[derive(Debug)]
+pub struct Digest {
+    config: DigestConfig,
+    path: PathBuf,
+    new: Mutex<Vec<Entry>>,
+}
+
+impl Digest {
+    /// The digest queued in output directory `root`.
+    pub fn new(config: DigestConfig, root: &Path) -> Digest {
+        Digest { config, path: root.join("digest.json"), new: Mutex::new(Vec::new()) }
+    }
+
+    pub fn excerpt(&self) -> usize {
+        self.config.excerpt
+    }
+
+    pub fn add(&self, entries: impl IntoIterator<Item = Entry>) {
+        self.new.lock().unwrap().extend(entries);
+    }
+
+    /// Queue what this run stored, and send the digest if it is due, or
+    /// anyway with `force`.
+    pub async fn finish(&self, force: bool) -> Result<(), Box<dyn Error>> {
+        let mut file: DigestFile = match fs::read_to_string(&self.path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DigestFile::default(),
+            Err(e) => return Err(Box::new(e))
+        };
+        file.pending.append(&mut self.new.lock().unwrap());
+
+        let interval = chrono::Duration::from_std(self.config.interval()?)?;
+        let now = Utc::now();
+        // The first period starts with the first episode queued.
+        let since = file.last_sent.or_else(|| file.pending.iter().map(|entry| entry.archived).min());
+        let due = force || since.is_some_and(|since| now - since >= interval);
+        let sent = match due && !file.pending.is_empty() {
+            true => {
+                file.pending.sort_by_key(|entry| entry.published);
+                let html = render(&self.config.title, file.last_sent, &file.pending);
+                self.send(&html, file.pending.len()).await
+            },
+            false => Ok(())
+        };
+        // A quiet period counts as sent too, so the next one isn't cut
+        // short by its first episode. What couldn't be sent stays queued.
+        if due && sent.is_ok() {
+            file.pending.clear();
+            file.last_sent = Some(now);
+        }
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&file)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        sent
+    }
+
+    async fn send(&self, html: &str, episodes: usize) -> Result<(), Box<dyn Error>> {
+        if let Some(output) = &self.config.output {
+            let path = PathBuf::from(output.replace("{date}", &Local::now().format("%Y-%m-%d").to_string()));
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, html)?;
+            fs::rename(tmp_path, &path)?;
+            info!("Digest of {} episode(s) --> {}", episodes, path.display());
+        }
+        #[cfg(feature = "smtp")]
+        if let Some(smtp) = &self.config.smtp {
+            crate::smtp::send(smtp, &self.config.title, html).await
+                .map_err(|e| format!("could not mail the digest: {}", e))?;
+            info!("Mailed digest of {} episode(s) to {}", episodes, smtp.to.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// The first `len` characters of the text of `notes`, which may be HTML,
+/// cut at a word.
+fn excerpt_of(notes: &str, len: usize) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in notes.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            },
+            c if !in_tag => text.push(c),
+            _ => ()
+        }
+    }
+    let text = crate::element_text(&text).split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() <= len {
+        return text
+    }
+    let cut: String = text.chars().take(len).collect();
+    match cut.rfind(' ') {
+        Some(end) => format!("{}…", &cut[..end]),
+        None => format!("{}…", cut)
+    }
+}
+
+/// The digest as an HTML page, episodes grouped by show.
+fn render(title: &str, since: Option<DateTime<Utc>>, entries: &[Entry]) -> String {
+    let mut shows: Vec<&str> = entries.iter().map(|entry| entry.show.as_str()).collect();
+    shows.sort_unstable();
+    shows.dedup();
+
+    let mut html = String::new();
+    html += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+    html += &format!("<title>{}</title>\n</head>\n<body style=\"font-family: sans-serif; max-width: 40em\">\n", escape(title));
+    html += &format!("<h1>{}</h1>\n", escape(title));
+    let period = match since {
+        Some(since) => format!("since {}", since.with_timezone(&Local).format("%A, %B %-d")),
+        None => "so far".to_string()
+    };
+    html += &format!("<p>{} new episode(s) archived {}.</p>\n", entries.len(), period);
+    for show in shows {
+        html += &format!("<h2>{}</h2>\n", escape(if show.is_empty() { "Other episodes" } else { show }));
+        for entry in entries.iter().filter(|entry| entry.show == show) {
+            html += &format!("<h3><a href=\"{}\">{}</a></h3>\n", escape(&entry.url), escape(&entry.title));
+            html += &format!("<p><small>{}", entry.published.with_timezone(&Local).format("%Y-%m-%d"));
+            if let Some(link) = &entry.link {
+                html += &format!(" · <a href=\"{}\">Show notes</a>", escape(link));
+            }
+            html += "</small></p>\n";
+            if !entry.excerpt.is_empty() {
+                html += &format!("<p>{}</p>\n", escape(&entry.excerpt));
+            }
+        }
+    }
+    html += "<p><small>Archived by poarder.</small></p>\n</body>\n</html>\n";
+    html
+}
+
+/// `poarder digest`: send the digest queued in `root` now.
+pub async fn run(config: Option<DigestConfig>, root: &Path) -> Result<(), Box<dyn Error>> {
+    let Some(config) = config else {
+        return Err("no [digest] in the config file".into())
+    };
+    if let Some(problem) = config.problem() {
+        return Err(format!("[digest]: {}", problem).into())
+    }
+    Digest::new(config, root).finish(true).await
+}
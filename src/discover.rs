@@ -0,0 +1,99 @@
+//! Turn whatever URL a user has for a show into its RSS feed URL.
+
+use log::info;
+use serde::Deserialize;
+use std::error::Error;
+
+#[derive(Debug, Deserialize)]
+struct ItunesLookup {
+    results: Vec<ItunesResult>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ItunesResult {
+    feed_url: Option<String>,
+}
+
+/// Resolve `url` to a feed URL. Accepts feed URLs as-is, Apple Podcasts show
+/// pages (via the iTunes lookup API) and web pages that advertise their feed
+/// with `<link rel="alternate" type="application/rss+xml">`.
+pub async fn resolve_feed_url(client: &reqwest::Client, url: &str) -> Result<String, Box<dyn Error>> {
+    let parsed = reqwest::Url::parse(url)?;
+
+    if parsed.host_str() == Some("podcasts.apple.com") {
+        let id = parsed.path_segments()
+            .and_then(|mut segments| segments.find_map(|s| s.strip_prefix("id").map(str::to_string)))
+            .ok_or("Apple Podcasts URL has no show id")?;
+
+        let lookup: ItunesLookup = serde_json::from_str(&client
+            .get(format!("https://itunes.apple.com/lookup?id={}&entity=podcast", id))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?)?;
+
+        let feed_url = lookup.results.into_iter()
+            .find_map(|r| r.feed_url)
+            .ok_or("Apple Podcasts has no public feed for this show")?;
+        info!("Resolved {} to {}", url, feed_url);
+        return Ok(feed_url)
+    }
+
+    let body = client.get(url).send().await?.error_for_status()?.text().await?;
+    if looks_like_feed(&body) {
+        return Ok(url.to_string())
+    }
+
+    match find_feed_link(&body) {
+        Some(href) => {
+            let feed_url = parsed.join(&href)?.to_string();
+            info!("Resolved {} to {}", url, feed_url);
+            Ok(feed_url)
+        },
+        None => Err(format!("{} is neither a feed nor a page linking to one", url).into())
+    }
+}
+
+pub fn looks_like_feed(body: &str) -> bool {
+    let head: String = body.chars().take(1024).collect();
+    head.contains("<rss") || head.contains("<feed") || head.contains("jsonfeed.org/version/")
+}
+
+/// Find the `href` of the first `<link>` tag advertising an RSS feed (or,
+/// failing that, a JSON Feed).
+pub fn find_feed_link(html: &str) -> Option<String> {
+    find_link(html, "application/rss+xml").or_else(|| find_link(html, "application/feed+json"))
+}
+
+fn find_link(html: &str, mime_type: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut rest = lower.as_str();
+    let mut offset = 0;
+
+    while let Some(start) = rest.find("<link") {
+        let end = rest[start..].find('>')? + start;
+        let tag = &rest[start..end];
+        if tag.contains(mime_type) {
+            // Take the href from the original text to keep its case.
+            let original = &html[offset + start..offset + end];
+            return attribute(original, "href")
+        }
+        offset += end;
+        rest = &rest[end..];
+    }
+
+    None
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let pos = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[pos..];
+    let (quote, value) = match value.chars().next()? {
+        q @ ('"' | '\'') => (q, &value[1..]),
+        _ => (' ', value)
+    };
+    Some(value.split(quote).next()?.replace("&amp;", "&"))
+}
@@ -0,0 +1,175 @@
+//! `poarder doctor`: check the environment and suggest fixes.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::discover;
+use crate::layout::FeedLayout;
+use crate::migrations;
+use crate::stats::HostStatsStore;
+
+/// Warn when less than this much space is free in the archive.
+const LOW_SPACE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct Report {
+    pub failures: usize,
+    pub warnings: usize,
+}
+
+impl Report {
+    pub fn ok(&mut self, what: &str) {
+        println!("[ ok ] {}", what);
+    }
+
+    pub fn warn(&mut self, what: &str, fix: &str) {
+        self.warnings += 1;
+        println!("[warn] {}\n       fix: {}", what, fix);
+    }
+
+    pub fn fail(&mut self, what: &str, fix: &str) {
+        self.failures += 1;
+        println!("[FAIL] {}\n       fix: {}", what, fix);
+    }
+}
+
+pub async fn run(client: &reqwest::Client, config_path: Option<&str>, output_dirs: &[String], feeds: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut report = Report::default();
+
+    let config = check_config(&mut report, config_path);
+    for dir in output_dirs.iter().chain(config.tee.iter()) {
+        check_output_dir(&mut report, Path::new(dir));
+    }
+    check_plugins(&mut report, &config);
+    for feed in feeds {
+        check_feed(&mut report, client, feed).await;
+    }
+
+    println!("\n{} problem(s), {} warning(s)", report.failures, report.warnings);
+    if report.failures > 0 {
+        return Err("doctor found problems".into())
+    }
+    Ok(())
+}
+
+fn check_config(report: &mut Report, config_path: Option<&str>) -> Config {
+    let path = match config_path {
+        Some(path) => path,
+        None => {
+            report.ok("No config file given; using defaults");
+            return Config::default()
+        }
+    };
+
+    match Config::load(Path::new(path)) {
+        Ok(config) => {
+            report.ok(&format!("Config file {} is valid", path));
+            config
+        },
+        Err(e) => {
+            report.fail(&format!("Config file {} is invalid: {}", path, e), "correct the file; see README.md for the format");
+            Config::default()
+        }
+    }
+}
+
+fn check_output_dir(report: &mut Report, root: &Path) {
+    if !root.exists() {
+        report.warn(&format!("Output directory {} does not exist yet", root.display()),
+            "it will be created on the first run; make sure its parent is writable");
+        return
+    }
+
+    let probe = root.join(".poarder-doctor");
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            report.ok(&format!("Output directory {} is writable", root.display()));
+        },
+        Err(e) => report.fail(&format!("Output directory {} is not writable: {}", root.display(), e),
+            "fix its ownership/permissions for the user running poarder"),
+    }
+
+    match fs2::available_space(root) {
+        Ok(free) if free < LOW_SPACE_BYTES => report.warn(
+            &format!("Only {} MiB free in {}", free / 1024 / 1024, root.display()),
+            "free up space or point --output-dir at a bigger disk"),
+        Ok(free) => report.ok(&format!("{} GiB free in {}", free / 1024 / 1024 / 1024, root.display())),
+        Err(e) => report.warn(&format!("Could not determine free space in {}: {}", root.display(), e),
+            "check the filesystem is mounted"),
+    }
+
+    let layout = FeedLayout::new(root);
+    match migrations::read_version(&layout) {
+        Ok(version) if version > migrations::CURRENT_VERSION => report.fail(
+            &format!("Archive state in {} is version {}, newer than this poarder supports", root.display(), version),
+            "upgrade poarder"),
+        Ok(version) if version < migrations::CURRENT_VERSION => report.ok(
+            &format!("Archive state in {} is version {} and will be upgraded on the next run", root.display(), version)),
+        Ok(_) => report.ok(&format!("Archive state in {} is current", root.display())),
+        Err(e) => report.fail(&format!("Archive state version in {} is unreadable: {}", root.display(), e),
+            "restore state/VERSION from a backup, or delete it to re-run migrations"),
+    }
+
+    match HostStatsStore::open(&layout.state_dir()) {
+        Ok(_) => report.ok(&format!("Host statistics in {} are readable", root.display())),
+        Err(e) => report.warn(&format!("Host statistics in {} are corrupt: {}", root.display(), e),
+            &format!("delete {}", HostStatsStore::path_in(&layout.state_dir()).display())),
+    }
+}
+
+fn check_plugins(report: &mut Report, config: &Config) {
+    for plugin in &config.plugins {
+        let program = match plugin.command.first() {
+            Some(program) => program,
+            None => {
+                report.fail(&format!("Plugin {} has an empty command", plugin.name), "set `command` to the program to run");
+                continue
+            }
+        };
+
+        if find_program(program) {
+            report.ok(&format!("Plugin {} command {} found", plugin.name, program));
+        }
+        else {
+            report.fail(&format!("Plugin {} command {} not found", plugin.name, program),
+                "install it or use an absolute path in `command`");
+        }
+    }
+}
+
+pub fn find_program(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file()
+    }
+
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+async fn check_feed(report: &mut Report, client: &reqwest::Client, url: &str) {
+    let result = client.get(url)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .and_then(|r| r.error_for_status());
+
+    let body = match result {
+        Ok(resp) => resp.text().await,
+        Err(e) => {
+            report.fail(&format!("Feed {} is unreachable: {}", url, e), "check the URL and your network/proxy settings");
+            return
+        }
+    };
+
+    match body {
+        Ok(body) if discover::looks_like_feed(&body) => report.ok(&format!("Feed {} is reachable", url)),
+        Ok(_) => report.fail(&format!("{} does not look like an RSS feed", url),
+            "use the show's feed URL; `poarder init` can find it from a website or Apple Podcasts link"),
+        Err(e) => report.fail(&format!("Feed {} could not be read: {}", url, e), "retry later; the host may be having trouble"),
+    }
+}
@@ -0,0 +1,43 @@
+//! Feed items that failed to parse, kept in `failed-items/` so a bug report
+//! can carry the exact item rather than a description of it. Each item gets
+//! two files named after a hash of its text, so one that fails on every run
+//! is kept once: the item as it is in the feed (`.xml`, or `.json` for a
+//! JSON Feed), and a `.txt` with the error and where in the feed it was.
+
+use chrono::{SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+
+use poarder_core::{FeedFormat, MalformedItem};
+
+use crate::layout::FeedLayout;
+
+/// Write `items` of the feed at `feed_url`, in `format`, to `layout`'s
+/// `failed-items/`. Returns the number not kept from an earlier run.
+pub fn save(layout: &FeedLayout, feed_url: &str, format: FeedFormat, items: &[MalformedItem]) -> io::Result<usize> {
+    if items.is_empty() {
+        return Ok(0)
+    }
+    let dir = layout.failed_items_dir();
+    fs::create_dir_all(&dir)?;
+
+    let ext = match format {
+        FeedFormat::Rss => "xml",
+        FeedFormat::Json => "json",
+    };
+    let mut saved = 0;
+    for item in items {
+        let hash = format!("{:x}", Sha256::digest(item.raw.as_bytes()));
+        let raw_path = dir.join(format!("{}.{}", &hash[..16], ext));
+        if raw_path.exists() {
+            continue
+        }
+        let report = format!("Feed: {}\nItem: {}\nError: {}\nPosition: byte {} of the feed\nSeen: {}\n",
+            feed_url, item.label, item.reason, item.offset, Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+        fs::write(dir.join(format!("{}.txt", &hash[..16])), report)?;
+        fs::write(raw_path, &item.raw)?;
+        saved += 1;
+    }
+    Ok(saved)
+}
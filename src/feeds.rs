@@ -0,0 +1,278 @@
+//! Several feeds in one invocation: `--rss-url` given more than once,
+//! `--feeds-file`, or an OPML export from a podcast app with `--opml`. Each
+//! feed is archived in its own directory under the output directory, named
+//! by `--show-dir-template` after the channel title and author (inside the
+//! OPML folders it was listed under) when the feed is first seen and
+//! remembered in `feeds.json`, so a show renamed upstream keeps its archive.
+
+use log::info;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::channel::Channel;
+use crate::config::Options;
+use crate::fixtures::Http;
+use crate::limits::FeedLimits;
+use crate::pipeline::safe_name;
+
+/// A feed to archive.
+#[derive(Debug, Clone)]
+pub struct FeedSource {
+    pub url: String,
+    /// OPML folders the feed was listed under, outermost first.
+    pub folders: Vec<String>,
+    /// Share of the run's download slots relative to other feeds.
+    pub weight: u32,
+    /// Settings from the feed's `[[feeds]]` entry in the config file.
+    pub options: Options,
+}
+
+impl FeedSource {
+    pub fn new(url: &str) -> FeedSource {
+        FeedSource { url: url.to_string(), folders: Vec::new(), weight: 1, options: Options::default() }
+    }
+}
+
+/// Feeds from a file with one URL per line, optionally followed by
+/// `weight=<n>`; blank lines and lines starting with `#` are skipped.
+pub fn read_list(path: &Path) -> Result<Vec<FeedSource>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let mut sources = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let mut words = line.split_whitespace();
+        let mut source = FeedSource::new(words.next().unwrap_or_default());
+        for option in words {
+            source.weight = match option.strip_prefix("weight=").map(str::parse) {
+                Some(Ok(weight)) if weight > 0 => weight,
+                _ => return Err(format!("{}: expected weight=<n> after {}, got {}", path.display(), source.url, option).into())
+            };
+        }
+        sources.push(source);
+    }
+    Ok(sources)
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct FeedIndexFile {
+    /// Directory name by feed URL.
+    feeds: BTreeMap<String, String>,
+}
+
+/// Feeds in an OPML subscription export, with the folders (outlines without
+/// a feed URL) they are nested in.
+pub fn read_opml(path: &Path) -> Result<Vec<FeedSource>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    parse_opml(&text).map_err(|e| format!("{} is not valid OPML: {}", path.display(), e).into())
+}
+
+fn parse_opml(text: &str) -> Result<Vec<FeedSource>, quick_xml::Error> {
+    let mut reader = Reader::from_str(text);
+    reader.trim_text(true);
+
+    let mut sources = Vec::new();
+    // For each open <outline>, the folder it starts, if it is one.
+    let mut open: Vec<Option<String>> = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) if element.name().as_ref() == b"outline" => {
+                let folder = match outline_attribute(&reader, &element, b"xmlUrl") {
+                    Some(url) => {
+                        sources.push(FeedSource { folders: open.iter().flatten().cloned().collect(), ..FeedSource::new(&url) });
+                        None
+                    },
+                    None => outline_attribute(&reader, &element, b"text")
+                        .or_else(|| outline_attribute(&reader, &element, b"title"))
+                        .map(|name| safe_name(&name))
+                        .filter(|name| !name.is_empty() && name != "." && name != "..")
+                };
+                open.push(folder);
+            },
+            Ok(Event::Empty(element)) if element.name().as_ref() == b"outline" => {
+                if let Some(url) = outline_attribute(&reader, &element, b"xmlUrl") {
+                    sources.push(FeedSource { folders: open.iter().flatten().cloned().collect(), ..FeedSource::new(&url) });
+                }
+            },
+            Ok(Event::End(element)) if element.name().as_ref() == b"outline" => {
+                open.pop();
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(e),
+            _ => ()
+        }
+    }
+    Ok(sources)
+}
+
+fn outline_attribute(reader: &Reader<&[u8]>, element: &BytesStart, name: &[u8]) -> Option<String> {
+    let value = element.try_get_attribute(name).ok().flatten()?;
+    let value = value.decode_and_unescape_value(reader).ok()?.trim().to_string();
+    Some(value).filter(|value| !value.is_empty())
+}
+
+/// Show directories named after the show's title alone.
+pub const DEFAULT_SHOW_DIR_TEMPLATE: &str = "{show}";
+
+/// Check that `template` names a directory for each show: `{show}`, perhaps
+/// with `{author}`, and nested only below the output directory.
+pub fn check_show_dir_template(template: &str) -> Result<(), Box<dyn Error>> {
+    if !template.contains("{show}") {
+        return Err(format!("show directory template \"{}\" has no {{show}}", template).into())
+    }
+    let rest = template.replace("{show}", "").replace("{author}", "");
+    if rest.contains(['{', '}', '\\']) {
+        return Err(format!("show directory template \"{}\" takes only {{show}} and {{author}}", template).into())
+    }
+    if template.split('/').any(|level| level.is_empty() || level == "." || level == "..") {
+        return Err(format!("show directory template \"{}\" has an empty, . or .. directory", template).into())
+    }
+    Ok(())
+}
+
+/// Which directory under an output directory holds which feed.
+pub struct FeedIndex {
+    path: PathBuf,
+    file: FeedIndexFile,
+}
+
+impl FeedIndex {
+    pub fn open(root: &Path) -> Result<FeedIndex, Box<dyn Error>> {
+        let path = root.join("feeds.json");
+        let file = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => FeedIndexFile::default(),
+            Err(e) => return Err(Box::new(e))
+        };
+        Ok(FeedIndex { path, file })
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&self.file)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The directory of the feed at `url`, relative to the output
+    /// directory, if it has been given one.
+    pub fn dir(&self, url: &str) -> Option<&str> {
+        self.file.feeds.get(url).map(String::as_str)
+    }
+
+    /// Each feed's URL and directory, relative to the output directory.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.file.feeds.iter().map(|(url, dir)| (url.as_str(), dir.as_str()))
+    }
+
+    /// The directory for `feed`, relative to the output directory, fetching
+    /// the feed for its title and author if it hasn't been seen before, to
+    /// name it by `template`.
+    pub async fn dir_for(&mut self, http: &Http, feed: &FeedSource, template: &str) -> Result<String, Box<dyn Error>> {
+        let url = feed.url.as_str();
+        if let Some(name) = self.file.feeds.get(url) {
+            return Ok(name.clone())
+        }
+
+        let resp = http.send(http.get(url)).await?.error_for_status()?;
+        let rss_xml = FeedLimits::default().read(resp).await?;
+        let show = match channel_title(&rss_xml).map(|title| safe_name(&title)) {
+            Some(name) if !name.is_empty() && name != "." && name != ".." => name,
+            _ => reqwest::Url::parse(url).ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| "feed".to_string())
+        };
+        let author = match Channel::parse(&rss_xml).author.map(|author| safe_name(&author)) {
+            Some(name) if !name.is_empty() && name != "." && name != ".." => name,
+            _ => "Unknown_author".to_string()
+        };
+        let base = template.split("{author}").map(|part| part.replace("{show}", &show)).collect::<Vec<_>>().join(&author);
+        let base = feed.folders.iter().chain(std::iter::once(&base)).cloned().collect::<Vec<_>>().join("/");
+        // Two shows with the same title still get a directory each.
+        let mut name = base.clone();
+        let mut n = 2;
+        while self.file.feeds.values().any(|taken| *taken == name) {
+            name = format!("{}-{}", base, n);
+            n += 1;
+        }
+
+        info!("{} --> {}/", url, name);
+        self.file.feeds.insert(url.to_string(), name.clone());
+        Ok(name)
+    }
+}
+
+/// The channel's (or Atom feed's) `<title>`, or a JSON Feed's `title`.
+fn channel_title(rss_xml: &str) -> Option<String> {
+    if poarder_core::FeedFormat::detect(None, rss_xml) == poarder_core::FeedFormat::Json {
+        return poarder_core::jsonfeed::title(rss_xml)
+    }
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    let mut open = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => match element.name().as_ref() {
+                b"item" | b"entry" => return None,
+                b"title" if matches!(open.last().map(Vec::as_slice), Some(b"channel" | b"feed")) => {
+                    return reader.read_text(element.name()).ok().map(|text| crate::element_text(&text))
+                },
+                name => open.push(name.to_vec())
+            },
+            Ok(Event::End(_)) => {
+                open.pop();
+            },
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => ()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opml_folders() {
+        let opml = r#"<?xml version="1.0"?>
+            <opml version="2.0">
+              <head><title>Subscriptions</title></head>
+              <body>
+                <outline text="Top" xmlUrl="https://example.com/top.xml"/>
+                <outline text="News &amp; Politics">
+                  <outline title="Daily">
+                    <outline text="One" xmlUrl=" https://example.com/one.xml?a=1&amp;b=2 "/>
+                  </outline>
+                  <outline text="Two" xmlUrl="https://example.com/two.xml"></outline>
+                </outline>
+                <outline text="..">
+                  <outline text="Three" xmlUrl="https://example.com/three.xml"/>
+                </outline>
+                <outline text="No feed"/>
+              </body>
+            </opml>"#;
+        let sources: Vec<(String, Vec<String>)> = parse_opml(opml).unwrap().into_iter()
+            .map(|source| (source.url, source.folders))
+            .collect();
+        assert_eq!(sources, [
+            ("https://example.com/top.xml".to_string(), vec![]),
+            ("https://example.com/one.xml?a=1&b=2".to_string(), vec!["News_&_Politics".to_string(), "Daily".to_string()]),
+            ("https://example.com/two.xml".to_string(), vec!["News_&_Politics".to_string()]),
+            ("https://example.com/three.xml".to_string(), vec![]),
+        ]);
+    }
+
+    #[test]
+    fn invalid_opml() {
+        assert!(parse_opml("<opml><body><outline text=\"a></body></opml>").is_err());
+        assert!(parse_opml("<opml><body></outline></body></opml>").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! `poarder fsck`: find files in an archive's episode directory that the
+//! saved feed doesn't account for (leftover `.part` files, episodes whose
+//! title has since changed, manual copies) and optionally adopt, relink or
+//! delete them.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::db;
+use crate::init::prompt;
+use crate::layout::FeedLayout;
+use crate::live::LiveState;
+use crate::naming::NameTemplate;
+use crate::sidecar;
+
+/// Files the user chose to keep, so they aren't reported again.
+const ADOPTED_FILE: &str = "adopted.json";
+
+enum Orphan {
+    Partial(PathBuf),
+    Unknown { path: PathBuf, relink: Option<String> },
+}
+
+pub fn run(output_dirs: &[String], naming: &NameTemplate, fix: bool) -> Result<(), Box<dyn Error>> {
+    let mut total = 0;
+    for root in output_dirs {
+        total += check_archive(&FeedLayout::new(Path::new(root)), naming, fix)?;
+    }
+
+    if total > 0 && !fix {
+        println!("Run `poarder fsck --fix` to adopt, relink or delete them.");
+    }
+    Ok(())
+}
+
+fn check_archive(layout: &FeedLayout, naming: &NameTemplate, fix: bool) -> Result<usize, Box<dyn Error>> {
+    let feed_path = layout.feed_dir().join("rss.xml");
+    let feed = fs::read_to_string(&feed_path)
+        .map_err(|e| format!("could not read {} ({}); run poarder against this archive first", feed_path.display(), e))?;
+
+    let episodes = crate::parse_feed(&feed, false)?;
+    let expected: HashSet<String> = episodes.iter()
+        .map(|episode| naming.file_name(episode))
+        .collect();
+    // Also accounted for: episodes under names they got before (another
+    // --name-template, or `.mp3` regardless of media type), and whatever the
+    // episode database says was archived (e.g. named after the server's
+    // Content-Type), and the show's cover from --save-cover.
+    let mut known: HashSet<String> = episodes.iter().flat_map(|episode| naming.earlier_names(episode)).collect();
+    known.extend(db::EpisodeDb::open(&layout.state_dir())?.files()?);
+    known.insert(crate::channel::COVER_FILE.to_string());
+
+    let adopted_path = layout.state_dir().join(ADOPTED_FILE);
+    let mut adopted: Vec<String> = match fs::read_to_string(&adopted_path) {
+        Ok(text) => serde_json::from_str(&text)?,
+        Err(_) => Vec::new()
+    };
+
+    // Live recordings aren't in the feed until the published episode replaces them.
+    let live = LiveState::open(&layout.state_dir())?;
+    let live_files: HashSet<&str> = live.files().collect();
+
+    let episodes_dir = layout.episodes_dir();
+    let names = layout.episode_files()?;
+
+    let present: HashSet<&String> = names.iter().collect();
+    // A sidecar is accounted for as long as its episode file is.
+    let has_episode = |name: &str| sidecar::episode_stem(name).is_some_and(|stem| {
+        names.iter().any(|other| other != name && other.strip_prefix(stem).is_some_and(|ext| ext.starts_with('.')))
+    });
+    let orphans: Vec<Orphan> = names.iter()
+        .filter(|name| !expected.contains(*name) && !known.contains(*name) && !adopted.contains(name) && !live_files.contains(name.as_str()) && !has_episode(name))
+        .map(|name| {
+            let path = episodes_dir.join(name);
+            if name.ends_with(".part") {
+                return Orphan::Partial(path)
+            }
+
+            // Same publish timestamp as an episode that's missing on disk:
+            // most likely the title changed after it was downloaded.
+            let timestamp = name.split('-').next().unwrap_or_default();
+            let relink = expected.iter()
+                .filter(|candidate| !present.contains(candidate))
+                .find(|candidate| candidate.split('-').next() == Some(timestamp))
+                .cloned();
+            Orphan::Unknown { path, relink }
+        })
+        .collect();
+
+    println!("{}: {} orphaned file(s)", layout.root().display(), orphans.len());
+    for orphan in &orphans {
+        match orphan {
+            Orphan::Partial(path) => println!("  partial  {}", path.display()),
+            Orphan::Unknown { path, relink: Some(name) } => println!("  unknown  {} (looks like {})", path.display(), name),
+            Orphan::Unknown { path, relink: None } => println!("  unknown  {}", path.display()),
+        }
+    }
+
+    if fix {
+        for orphan in &orphans {
+            fix_orphan(orphan, &episodes_dir, &mut adopted)?;
+        }
+        fs::write(&adopted_path, serde_json::to_string_pretty(&adopted)?)?;
+    }
+
+    Ok(orphans.len())
+}
+
+fn fix_orphan(orphan: &Orphan, episodes_dir: &Path, adopted: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
+    let (path, relink) = match orphan {
+        Orphan::Partial(path) => (path, None),
+        Orphan::Unknown { path, relink } => (path, relink.as_deref()),
+    };
+    let name = path.strip_prefix(episodes_dir).unwrap_or(path).to_string_lossy().to_string();
+
+    let question = match (orphan, relink) {
+        (Orphan::Partial(_), _) => format!("{}: [d]elete or [s]kip", name),
+        (_, Some(target)) => format!("{}: [a]dopt, [r]elink to {}, [d]elete or [s]kip", name, target),
+        (_, None) => format!("{}: [a]dopt, [d]elete or [s]kip", name),
+    };
+
+    match (prompt(&question, Some("s"))?.to_lowercase().as_str(), relink) {
+        ("a", _) if !matches!(orphan, Orphan::Partial(_)) => adopted.push(name),
+        ("r", Some(target)) => {
+            let target = episodes_dir.join(target);
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::rename(path, target)?
+        },
+        ("d", _) => fs::remove_file(path)?,
+        _ => ()
+    }
+    Ok(())
+}
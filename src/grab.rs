@@ -0,0 +1,111 @@
+//! `poarder grab`: archive direct media URLs that aren't part of any feed,
+//! e.g. a one-off episode shared as a link.
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED};
+use std::path::Path;
+
+use crate::fixtures::Http;
+use crate::Episode;
+
+/// Decode `%XX` escapes, leaving malformed ones as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// File name from a `Content-Disposition` header, preferring the RFC 5987
+/// `filename*` form.
+fn disposition_filename(header: &str) -> Option<String> {
+    let mut plain = None;
+    for param in header.split(';').map(str::trim) {
+        if let Some(value) = param.strip_prefix("filename*=") {
+            // charset'language'percent-encoded-name
+            let encoded = value.splitn(3, '\'').nth(2).unwrap_or(value);
+            return Some(percent_decode(encoded.trim_matches('"')))
+        }
+        if let Some(value) = param.strip_prefix("filename=") {
+            plain = Some(value.trim_matches('"').to_string());
+        }
+    }
+    plain
+}
+
+/// Last non-empty path segment of `url`.
+pub fn url_filename(url: &str) -> Option<String> {
+    let parsed = reqwest::Url::parse(url).ok()?;
+    let segment = parsed.path_segments()?.rev().find(|segment| !segment.is_empty())?;
+    Some(percent_decode(segment))
+}
+
+/// An episode for `url`, titled after the server's suggested file name (or
+/// the URL's) and dated by `Last-Modified` (or now).
+pub async fn episode_for(http: &Http, url: &str) -> Episode {
+    let headers = match http.send(http.head(url)).await.and_then(|resp| resp.error_for_status()) {
+        Ok(resp) => Some(resp.headers().clone()),
+        Err(e) => {
+            warn!("HEAD {} failed; naming it from the URL. Error: {}", url, e);
+            None
+        }
+    };
+    let header = |name| headers.as_ref()
+        .and_then(|headers| headers.get(name))
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let filename = header(CONTENT_DISPOSITION).as_deref().and_then(disposition_filename)
+        .or_else(|| url_filename(url));
+    let title = filename.as_deref()
+        .and_then(|name| Path::new(name).file_stem())
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|title| !title.is_empty())
+        .unwrap_or_else(|| "download".to_string());
+
+    let published = header(LAST_MODIFIED)
+        .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+        .unwrap_or_else(|| Utc::now().fixed_offset());
+    info!("{} -> \"{}\" ({})", url, title, published.to_rfc3339());
+
+    Episode {
+        url: url.to_string(),
+        title,
+        datetime: published.naive_local(),
+        published,
+        guid: None,
+        link: None,
+        description: None,
+        duration: None,
+        chapters_url: None,
+        season: None,
+        number: None,
+        show: None,
+        author: None,
+        image: None,
+        enclosure_type: header(CONTENT_TYPE),
+        enclosure_length: header(CONTENT_LENGTH).and_then(|length| length.parse().ok()),
+        funding: Vec::new(),
+        value: None,
+        soundbites: Vec::new(),
+        checksum: None,
+        podcast_tags: Vec::new(),
+        show_podcast_tags: Vec::new(),
+        needs_resolver: false,
+        raw: String::new(),
+    }
+}
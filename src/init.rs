@@ -0,0 +1,198 @@
+//! `poarder init`: interactive first-run setup.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::discover;
+
+const CONFIG_TEMPLATE: &str = r#"# poarder configuration, generated by `poarder init`.
+
+# Defaults for command line options; flags and POARDER_* variables override
+# them. Each [[feeds]] entry below can override them for its feed too.
+# output-dir = "/srv/podcasts"
+# task-count = 4
+# max-downloads = 4
+# name-template = "{date}-{title}.{ext}"
+# name-date-format = "%Y-%m-%d"
+# replace-existing = false
+
+# Extra archive roots that receive a copy of every episode.
+# tee = ["/mnt/nas/podcasts"]
+
+# Per-host download limits. Built-in profiles exist for archive.org, Libsyn
+# and Anchor; entries here override them.
+# [hosts."archive.org"]
+# max-concurrency = 1
+# min-interval-ms = 2000
+# retries = 5
+# retry-backoff-ms = 10000
+
+# Expected publication times; with --serve, poarder warms up the connection
+# just before and polls often until the new episode appears.
+# [[schedules]]
+# feed = "https://example.com/feed.xml"
+# weekdays = ["mon", "thu"]
+# time = "06:00"
+# window-minutes = 30
+# poll-seconds = 30
+
+# External plugins speaking JSON over stdio.
+# [[plugins]]
+# name = "example"
+# command = ["/usr/local/bin/poarder-plugin"]
+# hooks = ["item_parsed", "post_download"]
+"#;
+
+struct Feed {
+    url: String,
+    dir: PathBuf,
+}
+
+pub fn prompt(question: &str, default: Option<&str>) -> io::Result<String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+
+    Ok(match (answer.is_empty(), default) {
+        (true, Some(default)) => default.to_string(),
+        _ => answer.to_string(),
+    })
+}
+
+fn confirm(question: &str, default: bool) -> io::Result<bool> {
+    let answer = prompt(question, Some(if default { "Y/n" } else { "y/N" }))?;
+    Ok(match answer.to_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    })
+}
+
+/// Directory name for a show, from the last meaningful piece of its URL.
+fn default_show_name(url: &str) -> String {
+    let parsed = match reqwest::Url::parse(url) {
+        Ok(parsed) => parsed,
+        Err(_) => return "show".to_string()
+    };
+
+    parsed.path_segments()
+        .and_then(|segments| segments.rev().find(|s| !s.is_empty() && !s.contains('.')).map(str::to_string))
+        .or_else(|| parsed.host_str().map(str::to_string))
+        .unwrap_or_else(|| "show".to_string())
+}
+
+/// Quote an argument for a systemd `ExecStart=` line.
+fn systemd_quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%"))
+}
+
+pub async fn run(client: &reqwest::Client) -> Result<(), Box<dyn Error>> {
+    println!("Welcome to poarder! Let's set up your archive.\n");
+
+    let output_root = std::path::absolute(prompt("Where should podcasts be archived", Some("./podcasts"))?)?;
+
+    let mut feeds = Vec::new();
+    loop {
+        let url = prompt("Podcast URL (feed, Apple Podcasts or website; empty to finish)", None)?;
+        if url.is_empty() {
+            break
+        }
+
+        let feed_url = match discover::resolve_feed_url(client, &url).await {
+            Ok(feed_url) => feed_url,
+            Err(e) => {
+                println!("Could not find a feed for {}: {}", url, e);
+                continue
+            }
+        };
+        println!("Found feed {}", feed_url);
+
+        let name = prompt("Directory name for this show", Some(&default_show_name(&feed_url)))?;
+        feeds.push(Feed { url: feed_url, dir: output_root.join(name) });
+    }
+
+    if feeds.is_empty() {
+        return Err("no feeds given; nothing to set up".into())
+    }
+
+    // Found without --config there.
+    let default_path = crate::config::config_dir()?.join("config.toml");
+    let config_path = PathBuf::from(prompt("Where should the config file go", Some(&default_path.to_string_lossy()))?);
+    if config_path.exists() && !confirm(&format!("{} exists; overwrite it?", config_path.display()), false)? {
+        return Err("not overwriting existing config".into())
+    }
+    if let Some(parent) = config_path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut config = CONFIG_TEMPLATE.to_string();
+    for feed in &feeds {
+        config += &format!("\n[[feeds]]\nurl = {}\noutput-dir = {}\n",
+            toml::Value::from(feed.url.as_str()), toml::Value::from(feed.dir.to_string_lossy().as_ref()));
+    }
+    fs::write(&config_path, config)?;
+    println!("Wrote {}", config_path.display());
+
+    if confirm("Generate a systemd service and timer to run poarder on a schedule?", true)? {
+        let schedule = prompt("How often (systemd OnCalendar expression)", Some("hourly"))?;
+        let unit_dir = PathBuf::from(prompt("Where should the unit files go", Some("."))?);
+        write_systemd_units(&unit_dir, &config_path, &feeds, &schedule)?;
+    }
+    else {
+        println!("\nRun these to archive your feeds:");
+        for feed in &feeds {
+            println!("  poarder --config {} -r {} -o {}", config_path.display(), feed.url, feed.dir.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn write_systemd_units(unit_dir: &Path, config_path: &Path, feeds: &[Feed], schedule: &str) -> Result<(), Box<dyn Error>> {
+    let exe = std::env::current_exe()?;
+    let config_path = fs::canonicalize(config_path)?;
+
+    let mut service = String::from("[Unit]\n\
+        Description=Archive podcasts with poarder\n\
+        Wants=network-online.target\n\
+        After=network-online.target\n\
+        \n\
+        [Service]\n\
+        Type=oneshot\n");
+    for feed in feeds {
+        service += &format!("ExecStart={} --syslog --config {} --rss-url {} --output-dir {}\n",
+            systemd_quote(&exe.to_string_lossy()),
+            systemd_quote(&config_path.to_string_lossy()),
+            systemd_quote(&feed.url),
+            systemd_quote(&feed.dir.to_string_lossy()));
+    }
+
+    let timer = format!("[Unit]\n\
+        Description=Run poarder on a schedule\n\
+        \n\
+        [Timer]\n\
+        OnCalendar={}\n\
+        Persistent=true\n\
+        \n\
+        [Install]\n\
+        WantedBy=timers.target\n", schedule);
+
+    fs::create_dir_all(unit_dir)?;
+    let service_path = unit_dir.join("poarder.service");
+    let timer_path = unit_dir.join("poarder.timer");
+    fs::write(&service_path, service)?;
+    fs::write(&timer_path, timer)?;
+
+    println!("Wrote {} and {}", service_path.display(), timer_path.display());
+    println!("Install them with:");
+    println!("  cp {} {} ~/.config/systemd/user/", service_path.display(), timer_path.display());
+    println!("  systemctl --user daemon-reload && systemctl --user enable --now poarder.timer");
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! `poarder import` and `poarder export`: move episodes between a poarder
+//! archive and the download folders of other podcast tools, so switching
+//! in either direction doesn't mean downloading everything again.
+
+use chrono::NaiveDate;
+use log::{debug, info, warn};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::db::EpisodeDb;
+use crate::disk::{self, Permissions};
+use crate::grab::url_filename;
+use crate::layout::FeedLayout;
+use crate::naming::NameTemplate;
+use crate::pipeline::MEDIA_EXTENSIONS;
+use crate::rename::{archived, file_name_with_ext};
+use crate::{sidecar, Episode};
+
+/// How another tool lays out a show's downloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ForeignLayout {
+    /// podcast-archiver: `<show>/<YYYY-MM-DD> - <title>.<ext>`.
+    PodcastArchiver,
+    /// gPodder: `<show>/<file name from the enclosure URL>`.
+    Gpodder,
+    /// Podgrab: `<show>/<title>.<ext>`.
+    Podgrab,
+}
+
+impl ForeignLayout {
+    fn file_name(&self, episode: &Episode, ext: &str) -> String {
+        let title = unescape(&episode.title);
+        match self {
+            ForeignLayout::PodcastArchiver => portable(&format!("{} - {}.{}", episode.datetime.format("%Y-%m-%d"), title, ext)),
+            ForeignLayout::Gpodder => url_filename(&episode.url).map(|name| portable(&name))
+                .unwrap_or_else(|| portable(&format!("{}.{}", title, ext))),
+            ForeignLayout::Podgrab => portable(&format!("{}.{}", title, ext)),
+        }
+    }
+}
+
+/// Copy (or with `link`, hard-link) the archived episodes of `layout` into
+/// `to/<show>/` as `foreign` would have named them.
+pub fn export(layout: &FeedLayout, naming: &NameTemplate, foreign: ForeignLayout, to: &Path, link: bool) -> Result<(), Box<dyn Error>> {
+    let episodes = archived(layout, naming)?;
+    let show = episodes.iter().find_map(|(episode, _)| episode.show.as_deref()).map(unescape)
+        .or_else(|| Some(layout.root().file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "Podcast".to_string());
+    let show_dir = to.join(portable(&show));
+    fs::create_dir_all(&show_dir)?;
+
+    let (exported, existing) = mirror(layout, &episodes, foreign, &show_dir, link)?;
+    println!("Exported {} episode(s) to {}; {} were there already", exported, show_dir.display(), existing);
+    Ok(())
+}
+
+/// Copy (or with `link`, hard-link) the files of `episodes`, archived at
+/// `layout`, into `show_dir` as `foreign` would have named them. Returns how
+/// many were added and how many were there already.
+pub fn mirror(layout: &FeedLayout, episodes: &[(Episode, String)], foreign: ForeignLayout, show_dir: &Path, link: bool) -> Result<(usize, usize), Box<dyn Error>> {
+    let (mut added, mut existing) = (0, 0);
+    for (episode, file) in episodes {
+        let ext = file.rsplit_once('.').map_or("", |(_, ext)| ext);
+        let target = show_dir.join(foreign.file_name(episode, ext));
+        if target.exists() {
+            existing += 1;
+            continue
+        }
+        let source = layout.episodes_dir().join(file);
+        match link {
+            true => link_or_copy(&source, &target)?,
+            false => fs::copy(&source, &target).map(|_| ())?
+        }
+        added += 1;
+    }
+    Ok((added, existing))
+}
+
+/// Hard-link `from` to `to`, or copy it where that isn't possible, e.g.
+/// across filesystems.
+pub fn link_or_copy(from: &Path, to: &Path) -> Result<(), Box<dyn Error>> {
+    if let Err(e) = fs::hard_link(from, to) {
+        debug!("Could not link {} to {}; copying it. Error: {}", from.display(), to.display(), e);
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// What `poarder import` brings in, and how.
+pub struct ImportOptions<'a> {
+    /// A show's folder in another tool's downloads.
+    pub from: &'a Path,
+    pub feed_url: &'a str,
+    /// Copy the files rather than move them.
+    pub copy: bool,
+    /// Only print what would be imported.
+    pub dry_run: bool,
+}
+
+/// Bring the files in `options.from` that are episodes of `episodes` into
+/// the archive at `layout`, recording them as archived.
+pub fn import(layout: &FeedLayout, naming: &NameTemplate, permissions: &Permissions, episodes: &[Episode], options: ImportOptions) -> Result<(), Box<dyn Error>> {
+    let entries = fs::read_dir(options.from).map_err(|e| format!("could not read {}: {}", options.from.display(), e))?;
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.rsplit_once('.').is_some_and(|(_, ext)| MEDIA_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())))
+        .collect();
+    names.sort();
+
+    let by_url_name: HashMap<String, &Episode> = episodes.iter()
+        .filter_map(|episode| Some((url_filename(&episode.url)?, episode)))
+        .collect();
+    let mut by_title: HashMap<String, Vec<&Episode>> = HashMap::new();
+    for episode in episodes {
+        by_title.entry(title_key(&unescape(&episode.title))).or_default().push(episode);
+    }
+
+    layout.create()?;
+    let episodes_dir = layout.episodes_dir();
+    let db = EpisodeDb::open(&layout.state_dir())?;
+    let (mut imported, mut unmatched) = (0, Vec::new());
+    for name in &names {
+        let Some(episode) = by_url_name.get(name).copied().or_else(|| by_name(&by_title, name)) else {
+            unmatched.push(name);
+            continue
+        };
+        let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+        let file = file_name_with_ext(naming, episode, ext);
+        let target = episodes_dir.join(&file);
+        if target.exists() || db.get(episode)?.is_some_and(|record| episodes_dir.join(record.file).exists()) {
+            info!("{}: already archived", name);
+            continue
+        }
+
+        println!("{}  -> {}", name, target.display());
+        if options.dry_run {
+            imported += 1;
+            continue
+        }
+        let source = options.from.join(name);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match options.copy {
+            true => fs::copy(&source, &target).map(|_| ())?,
+            false => disk::move_into_place(&source, &target)?
+        }
+        permissions.apply_file(&target)?;
+        let size = fs::metadata(&target)?.len();
+        db.record(episode, &file, size, None)?;
+        if let Err(e) = sidecar::write(&target, episode, Some(options.feed_url), size, None, permissions) {
+            warn!("{}: could not write sidecar. Error: {}", file, e);
+        }
+        imported += 1;
+    }
+
+    for name in &unmatched {
+        warn!("{}: no episode of the feed matches it; left where it is", name);
+    }
+    match options.dry_run {
+        true => println!("{} file(s) would be imported, {} unmatched", imported, unmatched.len()),
+        false => println!("Imported {} file(s), {} unmatched", imported, unmatched.len())
+    }
+    Ok(())
+}
+
+/// The episode a file named after its title is of. The title may follow a
+/// `YYYY-MM-DD` or `YYYYMMDD` date, which then picks between episodes of the
+/// same title, or an episode number.
+fn by_name<'a>(by_title: &HashMap<String, Vec<&'a Episode>>, name: &str) -> Option<&'a Episode> {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let date_prefix = [("%Y-%m-%d", 10), ("%Y%m%d", 8)].into_iter()
+        .find_map(|(format, len)| Some((NaiveDate::parse_from_str(stem.get(..len)?, format).ok()?, &stem[len..])));
+    let number_prefix = stem.find(|c: char| !c.is_ascii_digit()).filter(|len| *len > 0).map(|len| &stem[len..]);
+
+    let candidates = [Some((None, stem)), date_prefix.map(|(date, rest)| (Some(date), rest)), number_prefix.map(|rest| (None, rest))];
+    for (date, title) in candidates.into_iter().flatten() {
+        let Some(episodes) = by_title.get(&title_key(title)) else {
+            continue
+        };
+        let dated: Vec<&&Episode> = episodes.iter().filter(|episode| date.is_none_or(|date| episode.datetime.date() == date)).collect();
+        match dated.as_slice() {
+            [episode] => return Some(episode),
+            [] => (),
+            _ => {
+                warn!("{}: matches {} episodes titled alike; skipping it", name, dated.len());
+                return None
+            }
+        }
+    }
+    None
+}
+
+/// `title` reduced to lower-case letters and digits, which survives the
+/// different ways tools make titles safe for file names.
+fn title_key(title: &str) -> String {
+    title.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+fn unescape(text: &str) -> String {
+    quick_xml::escape::unescape(text).map_or_else(|_| text.to_string(), |text| text.into_owned())
+}
+
+/// `name` with characters that aren't allowed in file names on common
+/// systems replaced.
+pub fn portable(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c
+        })
+        .collect();
+    name.trim_matches([' ', '.']).to_string()
+}
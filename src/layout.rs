@@ -0,0 +1,159 @@
+use log::info;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Standard on-disk layout of a single feed's archive:
+///
+/// ```text
+/// <root>/
+///   episodes/   downloaded media (and in-flight .part files)
+///   assets/     artwork and other per-show files
+///   notes/      show notes and per-episode text
+///   feed/       snapshots of the upstream feed (rss.xml)
+///   state/      poarder bookkeeping for this feed
+/// ```
+#[derive(Debug, Clone)]
+pub struct FeedLayout {
+    root: PathBuf,
+}
+
+impl FeedLayout {
+    pub fn new(root: &Path) -> FeedLayout {
+        FeedLayout { root: root.to_path_buf() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn episodes_dir(&self) -> PathBuf {
+        self.root.join("episodes")
+    }
+
+    pub fn assets_dir(&self) -> PathBuf {
+        self.root.join("assets")
+    }
+
+    pub fn notes_dir(&self) -> PathBuf {
+        self.root.join("notes")
+    }
+
+    pub fn feed_dir(&self) -> PathBuf {
+        self.root.join("feed")
+    }
+
+    /// Clips cut with `--soundbites`, one directory per episode. Only
+    /// created when used.
+    pub fn soundbites_dir(&self) -> PathBuf {
+        self.root.join("soundbites")
+    }
+
+    /// Feed items that failed to parse, kept for bug reports. Only created
+    /// when used.
+    pub fn failed_items_dir(&self) -> PathBuf {
+        self.root.join("failed-items")
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.root.join("state")
+    }
+
+    /// The root followed by every layout directory.
+    pub fn dirs(&self) -> [PathBuf; 6] {
+        [self.root.clone(), self.episodes_dir(), self.assets_dir(), self.notes_dir(), self.feed_dir(), self.state_dir()]
+    }
+
+    /// Create any missing layout directories.
+    pub fn create(&self) -> io::Result<()> {
+        for dir in self.dirs() {
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// The files in the episodes directory, by their names relative to it,
+    /// including those a `{year}/` name template files in a directory per
+    /// year.
+    pub fn episode_files(&self) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.episodes_dir())? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+            if entry.file_type()?.is_file() {
+                names.push(name);
+                continue
+            }
+            if !entry.file_type()?.is_dir() {
+                continue
+            }
+            for inner in fs::read_dir(entry.path())? {
+                let inner = inner?;
+                if let (true, Ok(inner_name)) = (inner.file_type()?.is_file(), inner.file_name().into_string()) {
+                    names.push(format!("{}/{}", name, inner_name));
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Move files left by the old flat layout (everything dumped into the
+    /// output directory) into their place in the structured layout.
+    /// Returns the number of files moved.
+    pub fn migrate_flat(&self) -> io::Result<usize> {
+        let mut moved = 0;
+
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue
+            }
+
+            let name = entry.file_name();
+            let name = match name.to_str() {
+                Some(name) => name,
+                None => continue
+            };
+
+            let target_dir = if name == "rss.xml" {
+                self.feed_dir()
+            }
+            else if is_flat_episode_name(name) {
+                self.episodes_dir()
+            }
+            else {
+                continue
+            };
+
+            let target = target_dir.join(name);
+            if target.exists() {
+                continue
+            }
+
+            info!("Migrating {} --> {}", entry.path().display(), target.display());
+            fs::rename(entry.path(), target)?;
+            moved += 1;
+        }
+
+        Ok(moved)
+    }
+}
+
+/// Matches the `<timestamp>-<title>.<ext>` names written by the flat layout.
+fn is_flat_episode_name(name: &str) -> bool {
+    // Pre-1970 dates produce a negative timestamp.
+    let unsigned = name.strip_prefix('-').unwrap_or(name);
+    let (timestamp, rest) = match unsigned.split_once('-') {
+        Some(split) => split,
+        None => return false
+    };
+
+    !timestamp.is_empty()
+        && timestamp.chars().all(|c| c.is_ascii_digit())
+        && (rest.ends_with(".mp3") || rest.ends_with(".part"))
+}
@@ -0,0 +1,182 @@
+//! `--library`: keep a media server's podcast library in step with the
+//! archive. Each show gets a folder in `--library-dir` holding its episodes,
+//! hard-linked in as `<YYYY-MM-DD> - <title>.<ext>`, its cover and the
+//! metadata file the server reads, and the server can be asked to rescan
+//! after a run that stored something: Plex just the show's folder, as soon
+//! as it has new episodes, the others the whole library at the end of the
+//! run.
+
+use log::{info, warn};
+use quick_xml::escape::escape;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::channel::{self, Channel};
+use crate::disk::Permissions;
+use crate::fixtures::Http;
+use crate::interop::{self, ForeignLayout};
+use crate::layout::FeedLayout;
+use crate::naming::NameTemplate;
+
+/// A media server with podcast (or audio) libraries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Server {
+    /// Audiobookshelf podcast library: `cover.jpg` and `metadata.json`.
+    Audiobookshelf,
+    /// Jellyfin music library, one album per show: `folder.jpg` and `album.nfo`.
+    Jellyfin,
+    /// Plex music library, as `<artist>/<album>/` with the show as both:
+    /// `artist.jpg` and `cover.jpg`. Plex reads the rest from the files.
+    Plex,
+}
+
+impl Server {
+    fn cover_name(&self) -> &'static str {
+        match self {
+            Server::Audiobookshelf => "cover.jpg",
+            Server::Jellyfin => "folder.jpg",
+            Server::Plex => "cover.jpg",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Library {
+    pub server: Server,
+    pub dir: PathBuf,
+    /// Base URL of the server's API, to trigger scans.
+    pub url: Option<String>,
+    pub token: Option<String>,
+    /// Audiobookshelf library or Plex library section to scan.
+    pub library_id: Option<String>,
+}
+
+impl Library {
+    /// Bring the show archived at `layout` into the library: link in new
+    /// episodes, and write its cover and metadata. Returns the show's folder
+    /// if episodes were added to it.
+    pub async fn update(&self, http: &Http, layout: &FeedLayout, naming: &NameTemplate, rss_xml: &str, permissions: &Permissions) -> Result<Option<PathBuf>, Box<dyn Error>> {
+        let channel = Channel::parse(rss_xml);
+        let show = channel.title.clone()
+            .or_else(|| Some(layout.root().file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "Podcast".to_string());
+        let show_dir = match self.server {
+            Server::Plex => self.dir.join(interop::portable(&show)).join(interop::portable(&show)),
+            _ => self.dir.join(interop::portable(&show)),
+        };
+        fs::create_dir_all(&show_dir)?;
+
+        let episodes = crate::rename::archived(layout, naming)?;
+        let (linked, _) = interop::mirror(layout, &episodes, ForeignLayout::PodcastArchiver, &show_dir, true)?;
+        if linked > 0 {
+            info!("Added {} episode(s) to {}", linked, show_dir.display());
+        }
+
+        let cover = show_dir.join(self.server.cover_name());
+        if !cover.exists() {
+            if let Err(e) = channel::write_cover(http, layout, &channel, &cover).await {
+                warn!("Could not add cover to {}. Error: {}", show_dir.display(), e);
+            }
+        }
+        let mut written = vec![show_dir.clone(), cover.clone()];
+        let metadata = match self.server {
+            Server::Audiobookshelf => Some(("metadata.json", audiobookshelf_metadata(&channel, &show)?)),
+            Server::Jellyfin => Some(("album.nfo", jellyfin_nfo(&channel, &show))),
+            Server::Plex => None,
+        };
+        if let Some((name, metadata)) = metadata {
+            let path = show_dir.join(name);
+            let tmp_path = path.with_extension("tmp");
+            fs::write(&tmp_path, metadata)?;
+            fs::rename(tmp_path, &path)?;
+            written.push(path);
+        }
+        // Plex shows the artist's picture separately from the album's.
+        if let (Server::Plex, Some(artist_dir)) = (self.server, show_dir.parent()) {
+            let artist_cover = artist_dir.join("artist.jpg");
+            if cover.exists() && !artist_cover.exists() {
+                interop::link_or_copy(&cover, &artist_cover)?;
+            }
+            written.extend([artist_dir.to_path_buf(), artist_cover]);
+        }
+
+        for path in &written {
+            let applied = match path.is_dir() {
+                true => permissions.apply_dir(path),
+                false if path.exists() => permissions.apply_file(path),
+                false => Ok(())
+            };
+            if let Err(e) = applied {
+                warn!("Failed to set permissions on {}. Error: {}", path.display(), e);
+            }
+        }
+        Ok((linked > 0).then_some(show_dir))
+    }
+
+    /// Ask the server to scan the library for the new episodes. Plex was
+    /// asked to scan each show's folder by `scan_show` already.
+    pub async fn scan(&self, client: &reqwest::Client) -> Result<(), Box<dyn Error>> {
+        let Some(url) = &self.url else {
+            return Ok(())
+        };
+        let url = url.trim_end_matches('/');
+        let token = self.token.as_deref().unwrap_or_default();
+        let request = match (self.server, &self.library_id) {
+            (Server::Audiobookshelf, Some(id)) => client.post(format!("{}/api/libraries/{}/scan", url, id)).bearer_auth(token),
+            (Server::Audiobookshelf, None) => return Err("Audiobookshelf needs --library-id to scan".into()),
+            (Server::Jellyfin, _) => client.post(format!("{}/Library/Refresh", url))
+                .header(reqwest::header::AUTHORIZATION, format!("MediaBrowser Token=\"{}\"", token)),
+            (Server::Plex, _) => return Ok(()),
+        };
+        request.send().await?.error_for_status()?;
+        info!("Asked {} to scan the library", url);
+        Ok(())
+    }
+
+    /// Ask Plex to scan just `show_dir`, which has new episodes. Plex must
+    /// see the library at the same path as poarder does.
+    pub async fn scan_show(&self, client: &reqwest::Client, show_dir: &Path) -> Result<(), Box<dyn Error>> {
+        let (Server::Plex, Some(url)) = (self.server, &self.url) else {
+            return Ok(())
+        };
+        let Some(section) = &self.library_id else {
+            return Err("Plex needs --library-id, the library section, to scan".into())
+        };
+        let url = url.trim_end_matches('/');
+        let show_dir = std::path::absolute(show_dir)?;
+        client.get(format!("{}/library/sections/{}/refresh", url, section))
+            .query(&[("path", show_dir.to_string_lossy())])
+            .header("X-Plex-Token", self.token.as_deref().unwrap_or_default())
+            .send().await?.error_for_status()?;
+        info!("Asked {} to scan {}", url, show_dir.display());
+        Ok(())
+    }
+}
+
+fn audiobookshelf_metadata(channel: &Channel, show: &str) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&serde_json::json!({
+        "title": show,
+        "author": channel.author,
+        "description": channel.description,
+        "language": channel.language,
+        "imageURL": channel.image,
+        "genres": ["Podcast"],
+        "tags": [],
+        "explicit": false,
+        "type": "episodic",
+    }))
+}
+
+fn jellyfin_nfo(channel: &Channel, show: &str) -> String {
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"utf-8\" standalone=\"yes\"?>\n<album>\n");
+    nfo.push_str(&format!("  <title>{}</title>\n", escape(show)));
+    if let Some(author) = &channel.author {
+        nfo.push_str(&format!("  <albumartist>{}</albumartist>\n  <artist>{}</artist>\n", escape(author), escape(author)));
+    }
+    if let Some(description) = &channel.description {
+        nfo.push_str(&format!("  <plot>{}</plot>\n", escape(description)));
+    }
+    nfo.push_str("  <genre>Podcast</genre>\n</album>\n");
+    nfo
+}
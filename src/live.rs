@@ -0,0 +1,238 @@
+//! `podcast:liveItem` support: with `--record-live`, live streams are
+//! recorded with ffmpeg while they run and kept as an episode until the
+//! publisher's own recording of the same show appears in the feed.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use log::{error, info, warn};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::disk::Permissions;
+use crate::naming::NameTemplate;
+use crate::pipeline::{EpisodeJob, EpisodeState};
+use crate::{sidecar, Episode};
+
+/// Longest recording made when the feed doesn't say when a stream ends.
+const MAX_RECORDING: Duration = Duration::from_secs(6 * 3600);
+
+/// How early a pending stream is picked up, to catch its first minute.
+const LEAD: chrono::Duration = chrono::Duration::minutes(1);
+
+#[derive(Debug, Clone)]
+pub struct LiveItem {
+    pub status: String,
+    pub start: DateTime<FixedOffset>,
+    pub end: Option<DateTime<FixedOffset>>,
+    pub episode: Episode,
+}
+
+impl LiveItem {
+    /// Guid when the feed gives one, else the title; the publisher's later
+    /// recording is matched on this.
+    fn key(&self) -> String {
+        self.episode.guid.clone().unwrap_or_else(|| self.episode.title.clone())
+    }
+
+    fn is_on_air(&self, now: DateTime<Utc>) -> bool {
+        self.status != "ended" && self.start - LEAD <= now && self.end.is_none_or(|end| end > now)
+    }
+}
+
+/// Every `<podcast:liveItem>` in the feed that has a start time and a stream.
+pub fn parse(rss_xml: &str) -> Vec<LiveItem> {
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    let mut items = Vec::new();
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) if element.name().as_ref() == b"podcast:liveItem" => {
+                let attr = |name: &str| -> Option<String> {
+                    element.try_get_attribute(name).ok().flatten()?
+                        .decode_and_unescape_value(&reader).ok().map(|value| value.to_string())
+                };
+                let status = attr("status").unwrap_or_default();
+                let start = attr("start").and_then(|start| DateTime::parse_from_rfc3339(&start).ok());
+                let end = attr("end").and_then(|end| DateTime::parse_from_rfc3339(&end).ok());
+                let inner = match reader.read_text(element.name()) {
+                    Ok(inner) => inner,
+                    Err(e) => {
+                        error!("Could not read liveItem. Error: {}", e);
+                        continue
+                    }
+                };
+                let start = match start {
+                    Some(start) => start,
+                    None => {
+                        warn!("Ignoring liveItem without a start time");
+                        continue
+                    }
+                };
+
+                // A liveItem is an item without a pubDate; date it by its start.
+                match crate::parse_item(&format!("<pubDate>{}</pubDate>{}", start.to_rfc2822(), inner), false) {
+                    Ok(episode) => items.push(LiveItem { status, start, end, episode }),
+                    Err(e) => error!("Could not parse liveItem. Error: {}", e)
+                }
+            },
+            Ok(Event::Eof) | Err(_) => break,
+            _ => ()
+        }
+    }
+
+    crate::funding::inherit_show(rss_xml, items.iter_mut().map(|item| &mut item.episode));
+    crate::passthrough::inherit_show(rss_xml, items.iter_mut().map(|item| &mut item.episode));
+    items
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Recording {
+    file: String,
+    start: DateTime<FixedOffset>,
+}
+
+/// Live recordings kept in the archive, in `state/live.json`.
+#[derive(Default, Serialize, Deserialize)]
+pub struct LiveState {
+    #[serde(skip)]
+    path: PathBuf,
+    recordings: BTreeMap<String, Recording>,
+}
+
+impl LiveState {
+    pub fn path_in(state_dir: &Path) -> PathBuf {
+        state_dir.join("live.json")
+    }
+
+    pub fn open(state_dir: &Path) -> Result<LiveState, Box<dyn Error>> {
+        let path = Self::path_in(state_dir);
+        let mut state: LiveState = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LiveState::default(),
+            Err(e) => return Err(Box::new(e))
+        };
+        state.path = path;
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// File names of live recordings, which the feed itself won't list.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.recordings.values().map(|recording| recording.file.as_str())
+    }
+
+    /// Streams on air now that haven't been recorded yet, and the start of
+    /// the next pending one.
+    pub fn due(&self, items: &[LiveItem], now: DateTime<Utc>) -> (Vec<LiveItem>, Option<DateTime<Utc>>) {
+        let on_air = items.iter()
+            .filter(|item| item.is_on_air(now) && !self.recordings.contains_key(&item.key()))
+            .cloned()
+            .collect();
+        let next = items.iter()
+            .filter(|item| item.status != "ended" && item.start - LEAD > now)
+            .map(|item| (item.start - LEAD).with_timezone(&Utc))
+            .min();
+        (on_air, next)
+    }
+
+    pub fn add(&mut self, item: &LiveItem, file: &str) {
+        self.recordings.insert(item.key(), Recording { file: file.to_string(), start: item.start });
+    }
+
+    /// Delete live recordings whose show has now been stored from the
+    /// publisher's own recording.
+    pub fn replace_finished(&mut self, stored: &[EpisodeJob], episode_dirs: &[PathBuf]) {
+        for job in stored.iter().filter(|job| job.state == EpisodeState::Stored) {
+            let key = job.episode.guid.clone().unwrap_or_else(|| job.episode.title.clone());
+            let recording = match self.recordings.remove(&key) {
+                Some(recording) => recording,
+                None => continue
+            };
+            info!("{}: replacing the live recording with the published episode", job.episode.title);
+            for dir in episode_dirs {
+                let path = dir.join(&recording.file);
+                for path in [sidecar::path_for(&path), path] {
+                    if let Err(e) = fs::remove_file(&path) {
+                        if e.kind() != std::io::ErrorKind::NotFound {
+                            error!("Failed to remove {}. Error: {}", path.display(), e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Record `item`'s stream with ffmpeg into the primary archive, copy it to
+/// the mirrors, and return its file name.
+pub async fn record(ffmpeg: &str, item: &LiveItem, feed_url: &str, episode_dirs: &[PathBuf], naming: &NameTemplate, permissions: &Permissions) -> Result<String, Box<dyn Error>> {
+    // Named apart from regular episodes so the published recording never
+    // collides with it.
+    let (part_name, _) = naming.file_names(&item.episode);
+    let name = format!("{}.live.mp3", naming.stem(&item.episode));
+    let duration = match item.end {
+        Some(end) => Duration::from_secs((end.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64).min(MAX_RECORDING),
+        None => MAX_RECORDING
+    };
+
+    let part_path = episode_dirs[0].join(&part_name);
+    if let Some(dir) = part_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    info!("{}: recording live stream {} for up to {:?}", item.episode.title, item.episode.url, duration);
+    let status = Command::new(ffmpeg)
+        .args(["-nostdin", "-loglevel", "error", "-y", "-i", &item.episode.url, "-t"])
+        .arg(duration.as_secs().to_string())
+        .args(["-vn", "-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"])
+        .arg(&part_path)
+        .stdin(Stdio::null())
+        .status()
+        .await
+        .map_err(|e| format!("could not run {}: {}", ffmpeg, e))?;
+
+    // ffmpeg exits non-zero when the stream drops, which still leaves a
+    // usable recording of everything up to that point.
+    let size = fs::metadata(&part_path).map(|meta| meta.len()).unwrap_or(0);
+    if size == 0 {
+        let _ = fs::remove_file(&part_path);
+        return Err(format!("ffmpeg recorded nothing ({})", status).into())
+    }
+    if !status.success() {
+        warn!("{}: ffmpeg stopped early ({}); keeping what was recorded", item.episode.title, status);
+    }
+
+    for (i, dir) in episode_dirs.iter().enumerate() {
+        let path = dir.join(&name);
+        let result = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| match i {
+            0 => fs::rename(&part_path, &path),
+            _ => fs::copy(episode_dirs[0].join(&name), &path).map(|_| ())
+        });
+        if let Err(e) = result {
+            error!("Failed to store {}. Error: {}", path.display(), e);
+            continue
+        }
+        if let Err(e) = permissions.apply_file(&path) {
+            error!("Failed to set permissions on {}. Error: {}", path.display(), e);
+        }
+        if let Err(e) = sidecar::write(&path, &item.episode, Some(feed_url), size, None, permissions) {
+            error!("Failed to write metadata for {}. Error: {}", path.display(), e);
+        }
+    }
+
+    info!("{}: live recording stored as {}", item.episode.title, name);
+    Ok(name)
+}
@@ -1,295 +1,2256 @@
-use futures::{stream, StreamExt};
-use clap::{Parser, error};
-use log::{debug, error, info, log_enabled, warn};
-use std::fs::{File, self};
+mod alerts;
+mod anomalies;
+#[cfg(feature = "artwork")]
+mod artwork;
+mod backfill;
+mod bench;
+mod cadence;
+mod calendar;
+mod catalog;
+mod channel;
+mod check;
+mod config;
+mod digest;
+mod discover;
+mod doctor;
+mod failed_items;
+mod feeds;
+mod fsck;
+mod grab;
+mod init;
+mod interop;
+mod layout;
+mod library;
+mod live;
+mod migrations;
+mod notify;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod polling;
+mod rename;
+mod report;
+mod scan;
+mod schedule;
+mod serve;
+#[cfg(feature = "smtp")]
+mod smtp;
+mod status;
+mod subscriptions;
+mod template;
+mod titles;
+mod verify;
+mod window;
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+mod sandbox;
+#[cfg(feature = "self-update")]
+mod update;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueSource};
+use futures::StreamExt;
+use log::{debug, error, info, warn};
+use std::fs::File;
 use std::io::Write;
-use std::collections::LinkedList;
-use std::path::Path;
-use bytes::Bytes;
-use std::{error::Error, fmt};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, FixedOffset};
-use chrono::format::ParseError;
-use quick_xml::events::Event;
-use quick_xml::reader::Reader;
-
-
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use poarder_core::{db, disk, eta, fair, fixtures, funding, hosts, limits, naming, passthrough, pipeline, plugins, resolvers, schema, shard, shutdown, sidecar, soundbites, state, stats, throttle};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use poarder_core::uring;
+use poarder_core::{element_text, parse_feed, parse_feed_with, parse_item, Downloader, Episode, FeedFormat, ItemStep};
+
+
+#[cfg(feature = "rustls")]
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (TLS: rustls)");
+#[cfg(all(feature = "native-tls", not(feature = "rustls")))]
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (TLS: native-tls)");
+#[cfg(not(any(feature = "native-tls", feature = "rustls")))]
+const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (TLS: none)");
+
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version = VERSION, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
-    /// URL to podcast RSS feed.
-    #[clap(short, long)]
-    rss_url: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// URL to podcast RSS feed. Repeat to archive several feeds at once, each in a directory named after the show.
+    #[clap(short, long, multiple_occurrences = true, env = "POARDER_RSS_URL")]
+    rss_url: Vec<String>,
+
+    /// File listing feed URLs to archive, one per line, like repeating --rss-url.
+    #[clap(long, env = "POARDER_FEEDS_FILE")]
+    feeds_file: Option<String>,
+
+    /// OPML subscription export (gPodder, AntennaPod, Apple Podcasts, ...) whose feeds to archive, keeping its folders.
+    #[clap(long, env = "POARDER_OPML")]
+    opml: Option<String>,
+
+    /// Directories for each show when archiving several, from {show} and {author}, e.g. {author}/{show}.
+    #[clap(long, default_value = feeds::DEFAULT_SHOW_DIR_TEMPLATE, env = "POARDER_SHOW_DIR_TEMPLATE")]
+    show_dir_template: String,
+
+    /// Directory under each output directory for the feed being archived, when there are several.
+    #[clap(skip)]
+    show_dir: Option<String>,
+
+    /// Download slots shared by the feeds being archived, when there are several.
+    #[clap(skip)]
+    fair_share: Option<Arc<fair::FairShare>>,
+
+    /// The --max-rate shared by every download of the run.
+    #[clap(skip)]
+    rate_limit: Option<Arc<throttle::RateLimit>>,
+
+    /// What `/status` shows, with --serve.
+    #[clap(skip)]
+    status: Option<Arc<status::Status>>,
+
+    /// What the run has left to download, for estimating when it will be done.
+    #[clap(skip)]
+    eta: Option<Arc<eta::Eta>>,
+
+    /// Connection to the --mqtt broker.
+    #[cfg(feature = "mqtt")]
+    #[clap(skip)]
+    mqtt: Option<Arc<mqtt::Mqtt>>,
+
+    /// Episodes stored this run, for the `[digest]`.
+    #[clap(skip)]
+    digest: Option<Arc<digest::Digest>>,
 
-    #[clap(long, action)]
+    /// Set on SIGINT/SIGTERM, to stop downloading without losing work.
+    #[clap(skip)]
+    shutdown: Arc<shutdown::Shutdown>,
+
+    /// Progress bars, when archiving with stdout on a terminal.
+    #[cfg(feature = "progress")]
+    #[clap(skip)]
+    progress: Option<Arc<poarder_core::progress::Progress>>,
+
+    /// Options given as flags or environment variables, which the config file doesn't override.
+    #[clap(skip)]
+    given: Vec<String>,
+
+    /// Those of `given` that came from environment variables.
+    #[clap(skip)]
+    from_env: Vec<String>,
+
+    #[clap(long, action, env = "POARDER_REPLACE_EXISTING")]
     replace_existing: bool,
 
+    /// Episode file names, from {date}, {year}, {title}, {episode}, {season}, {guid}, {show} and {ext}; may start with {year}/.
+    #[clap(long, default_value = naming::DEFAULT_TEMPLATE, env = "POARDER_NAME_TEMPLATE")]
+    name_template: String,
+
+    /// strftime format of {date} in --name-template, e.g. %Y-%m-%d; a Unix timestamp by default.
+    #[clap(long, env = "POARDER_NAME_DATE_FORMAT")]
+    name_date_format: Option<String>,
+
+    /// Longest episode file name in bytes; longer titles are shortened.
+    #[clap(long, default_value = "255", env = "POARDER_MAX_NAME_LENGTH")]
+    max_name_length: usize,
+
     /// Number of tokio tasks to use while performing downloads.
-    #[clap(short, long, default_value = "4")]
+    #[clap(short, long, default_value = "4", env = "POARDER_TASK_COUNT")]
     task_count: usize,
 
-    /// Directory to save 
-    #[clap(short, long, default_value = ".")]
-    output_dir: String,
+    /// Downloads at once across all feeds when archiving several, handed out to the feeds in turn. Defaults to --task-count.
+    #[clap(long, env = "POARDER_MAX_DOWNLOADS")]
+    max_downloads: Option<usize>,
+
+    /// Cap on the combined download rate of the whole run, e.g. 2MiB/s or 500K.
+    #[clap(long, value_parser = parse_rate, env = "POARDER_MAX_RATE")]
+    max_rate: Option<u64>,
+
+    /// Cap on the rate of each download on its own, e.g. 512KiB/s.
+    #[clap(long, value_parser = parse_rate, env = "POARDER_MAX_RATE_PER_DOWNLOAD")]
+    max_rate_per_download: Option<u64>,
+
+    /// Async runtime: multi-thread uses a worker per core, current-thread runs everything on one thread (e.g. a single-core VPS).
+    #[clap(long, value_enum, default_value = "multi-thread", env = "POARDER_RUNTIME")]
+    runtime: Runtime,
+
+    /// Root directory of the feed's archive. Repeat to mirror episodes into several archives in one pass.
+    #[clap(short, long, default_value = ".", global = true, multiple_occurrences = true, env = "POARDER_OUTPUT_DIR")]
+    output_dir: Vec<String>,
 
     /// Use syslog.
-   #[clap(long, action)]
-   syslog: bool
+   #[clap(long, action, env = "POARDER_SYSLOG")]
+   syslog: bool,
+
+    /// Don't show progress bars for downloads, even on a terminal.
+    #[cfg(feature = "progress")]
+    #[clap(long, action, env = "POARDER_NO_PROGRESS")]
+    no_progress: bool,
+
+    /// Shared state directory for coordinating multiple instances writing to the same storage.
+    #[clap(long, env = "POARDER_SHARED_STATE")]
+    shared_state: Option<String>,
+
+    /// Seconds after which another instance's claim on an episode is considered stale.
+    #[clap(long, default_value = "3600", env = "POARDER_CLAIM_TIMEOUT")]
+    claim_timeout: u64,
+
+    /// Number of downloaded episodes allowed to wait for the disk writer before downloads pause.
+    #[clap(long, default_value = "2", env = "POARDER_WRITE_QUEUE")]
+    write_queue: usize,
+
+    /// Size of the buffer used when writing episodes to disk, e.g. 1M or 512K.
+    #[clap(long, default_value = "1M", value_parser = parse_size, env = "POARDER_WRITE_BUFFER")]
+    write_buffer: usize,
+
+    /// Write episodes with io_uring; can be faster on NVMe storage.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    #[clap(long, action, env = "POARDER_IO_URING")]
+    io_uring: bool,
+
+    /// Tag downloaded MP3s with the episode's title, show, author, date and number from the feed.
+    #[cfg(feature = "id3")]
+    #[clap(long, action, env = "POARDER_ID3")]
+    id3: bool,
+
+    /// Embed the episode's artwork, else the show's, in the ID3 tags; needs --id3.
+    #[cfg(feature = "id3")]
+    #[clap(long, action, requires = "id3", env = "POARDER_EMBED_ARTWORK")]
+    embed_artwork: bool,
+
+    /// Save the show's artwork as cover.jpg next to its episodes, for media servers and file browsers.
+    #[clap(long, action, env = "POARDER_SAVE_COVER")]
+    save_cover: bool,
+
+    /// Directory for partially downloaded files, e.g. fast local scratch space; defaults to the archive itself.
+    #[clap(long, env = "POARDER_TMP_DIR")]
+    tmp_dir: Option<String>,
+
+    /// When an output directory fills up or goes read-only, pause until it is writable again instead of giving up on it.
+    #[clap(long, action, env = "POARDER_WAIT_FOR_SPACE")]
+    wait_for_space: bool,
+
+    /// Stop at the first failed episode or feed and exit with an error, rather than carrying on with the rest.
+    #[clap(long, action, env = "POARDER_FAIL_FAST")]
+    fail_fast: bool,
+
+    /// Exit with an error if at least this many episodes failed to download; 0 never does.
+    #[clap(long, default_value = "1", value_name = "COUNT", env = "POARDER_FAIL_ON_ERROR")]
+    fail_on_error: usize,
+
+    /// Fail a feed with any malformed item, rather than leaving the item out and reporting it.
+    #[clap(long, action, env = "POARDER_STRICT")]
+    strict: bool,
+
+    /// Keep the .part files of failed downloads, even ones that can't be resumed, and never clean them up.
+    #[clap(long, action, env = "POARDER_KEEP_PARTIAL")]
+    keep_partial: bool,
+
+    /// Fail downloads whose size differs from the length the feed gives the enclosure.
+    #[clap(long, action, env = "POARDER_CHECK_ENCLOSURE_LENGTH")]
+    check_enclosure_length: bool,
+
+    /// Delete .part files nothing has written to for this long (e.g. 12h, 7d) when a run starts.
+    #[clap(long, default_value = "7d", value_parser = parse_age, conflicts_with = "keep-partial", env = "POARDER_CLEAN_PARTIAL_OLDER_THAN")]
+    clean_partial_older_than: Duration,
+
+    /// How often to log the estimated time left for the run's downloads (e.g. 30s, 5m); 0s turns it off.
+    #[clap(long, default_value = "1m", value_parser = parse_age, env = "POARDER_ETA_INTERVAL")]
+    eta_interval: Duration,
+
+    /// Permission mode for created files, e.g. 0644.
+    #[clap(long, value_parser = disk::parse_mode, env = "POARDER_FILE_MODE")]
+    file_mode: Option<u32>,
+
+    /// Permission mode for created directories, e.g. 0755.
+    #[clap(long, value_parser = disk::parse_mode, env = "POARDER_DIR_MODE")]
+    dir_mode: Option<u32>,
+
+    /// Owner for created files and directories, as user, user:group or :group.
+    #[clap(long, value_parser = disk::Owner::parse, env = "POARDER_CHOWN")]
+    chown: Option<disk::Owner>,
+
+    /// Restrict filesystem access to the output, state and scratch directories (Linux, Landlock).
+    #[cfg(all(feature = "sandbox", target_os = "linux"))]
+    #[clap(long, action, env = "POARDER_SANDBOX")]
+    sandbox: bool,
+
+    /// Extra path --sandbox leaves readable, e.g. a plugin's interpreter or data; may be repeated.
+    #[cfg(all(feature = "sandbox", target_os = "linux"))]
+    #[clap(long, multiple_occurrences = true, env = "POARDER_SANDBOX_READ")]
+    sandbox_read: Vec<String>,
+
+    /// Download the show's artwork into assets/ as resized renditions (see [[artwork]] in the config).
+    #[cfg(feature = "artwork")]
+    #[clap(long, action, env = "POARDER_ARTWORK")]
+    artwork: bool,
+
+    /// Record podcast:liveItem streams with ffmpeg while they are on air, until the published episode replaces them.
+    #[clap(long, action, env = "POARDER_RECORD_LIVE")]
+    record_live: bool,
+
+    /// Cut podcast:soundbite clips out of stored episodes into soundbites/ with ffmpeg.
+    #[clap(long, env = "POARDER_SOUNDBITES")]
+    soundbites: bool,
+
+    /// ffmpeg binary used for --record-live and --soundbites.
+    #[clap(long, default_value = "ffmpeg", env = "POARDER_FFMPEG")]
+    ffmpeg: String,
+
+    /// Minimise memory use for small devices: one download at a time, no feed copies, no raw item XML.
+    #[clap(long, action, env = "POARDER_LOW_MEMORY")]
+    low_memory: bool,
+
+    /// Record every HTTP response into this directory for later replay.
+    #[clap(long, conflicts_with = "replay-http", env = "POARDER_RECORD_HTTP")]
+    record_http: Option<String>,
+
+    /// Serve HTTP responses from a directory written by --record-http instead of the network.
+    #[clap(long, env = "POARDER_REPLAY_HTTP")]
+    replay_http: Option<String>,
+
+    /// Run once and exit; what a run does without --serve or --daemon, kept for container jobs that spell it out.
+    #[clap(long, action, conflicts_with_all = &["serve", "daemon"], env = "POARDER_ONESHOT")]
+    oneshot: bool,
+
+    /// Keep archiving every --interval, serving /healthz and /readyz on --listen.
+    #[clap(long, action, env = "POARDER_SERVE")]
+    serve: bool,
+
+    /// Keep archiving every --interval, like --serve without the health endpoints; replaces a cron job.
+    #[clap(long, action, conflicts_with = "serve", env = "POARDER_DAEMON")]
+    daemon: bool,
+
+    /// Time between runs with --serve or --daemon, e.g. 30m; a bare number is seconds.
+    #[clap(long, default_value = "3600", value_parser = parse_interval, env = "POARDER_INTERVAL")]
+    interval: Duration,
+
+    /// Address for the --serve health endpoints.
+    #[clap(long, default_value = "0.0.0.0:8080", env = "POARDER_LISTEN")]
+    listen: String,
+
+    /// Only handle shard K of N (e.g. 2/5), so N jobs can split a backfill; progress is checkpointed per shard.
+    #[clap(long, value_parser = shard::Shard::parse, env = "POARDER_SHARD")]
+    shard: Option<shard::Shard>,
+
+    /// Base URL the archive is served from; delisted episodes in feed/archive.xml then link to the local copies.
+    #[clap(long, env = "POARDER_MIRROR_URL")]
+    mirror_url: Option<String>,
+
+    /// Also point archived episodes still in the feed at --mirror-url in feed/archive.xml, leaving their XML otherwise untouched.
+    #[clap(long, action, requires = "mirror-url", conflicts_with = "low-memory", env = "POARDER_MIRROR_FEED")]
+    mirror_feed: bool,
+
+    /// Write an iCalendar feed of each show's episodes, and of when the next are expected, to feed/calendar.ics; --serve serves them.
+    #[clap(long, action, env = "POARDER_CALENDAR")]
+    calendar: bool,
+
+    /// Feed syntax; auto goes by the Content-Type, then the document itself.
+    #[clap(long, value_enum, default_value = "auto", env = "POARDER_FORMAT")]
+    format: Format,
+
+    /// Largest feed to download, e.g. 64M; a bigger one is refused rather than read.
+    #[clap(long, default_value = "64M", value_parser = parse_size, env = "POARDER_MAX_FEED_SIZE")]
+    max_feed_size: usize,
+
+    /// Most items a feed may have before it is refused.
+    #[clap(long, default_value = "100000", env = "POARDER_MAX_FEED_ITEMS")]
+    max_feed_items: usize,
+
+    /// Deepest a feed's elements may be nested before it is refused.
+    #[clap(long, default_value = "64", env = "POARDER_MAX_FEED_DEPTH")]
+    max_feed_depth: usize,
+
+    /// Read every item of the feed this run, even where the config asks for incremental scans.
+    #[clap(long, action, env = "POARDER_FULL_SCAN")]
+    full_scan: bool,
+
+    /// Only archive episodes published since this date (YYYY-MM-DD or RFC 3339) or within this age, e.g. 30d.
+    #[clap(long, value_parser = window::parse_since, env = "POARDER_SINCE")]
+    since: Option<window::Bound>,
+
+    /// Only archive episodes published up to the end of this date (YYYY-MM-DD or RFC 3339) or older than this age.
+    #[clap(long, value_parser = window::parse_until, env = "POARDER_UNTIL")]
+    until: Option<window::Bound>,
+
+    /// Only archive the N newest episodes, of those within --since and --until.
+    #[clap(long, value_name = "N", env = "POARDER_LATEST")]
+    latest: Option<usize>,
+
+    /// Only archive episodes whose title matches this regular expression; (?i) ignores case.
+    #[clap(long, value_name = "REGEX", value_parser = titles::parse_regex, env = "POARDER_INCLUDE_REGEX")]
+    include_regex: Option<String>,
+
+    /// Skip episodes whose title matches this regular expression, e.g. "(?i)rebroadcast|trailer".
+    #[clap(long, value_name = "REGEX", value_parser = titles::parse_regex, env = "POARDER_EXCLUDE_REGEX")]
+    exclude_regex: Option<String>,
+
+    /// Match --include-regex and --exclude-regex against episode descriptions as well as titles.
+    #[clap(long, action, env = "POARDER_MATCH_DESCRIPTIONS")]
+    match_descriptions: bool,
+
+    /// Also file each show in a media server's library in --library-dir, as that server expects.
+    #[clap(long, value_enum, requires = "library-dir", env = "POARDER_LIBRARY")]
+    library: Option<library::Server>,
+
+    /// Library folder the media server watches; each show gets a folder in it.
+    #[clap(long, env = "POARDER_LIBRARY_DIR")]
+    library_dir: Option<String>,
+
+    /// Base URL of the media server, to have it scan the library after runs that stored episodes.
+    #[clap(long, requires = "library", env = "POARDER_LIBRARY_URL")]
+    library_url: Option<String>,
+
+    /// API token for --library-url.
+    #[clap(long, env = "POARDER_LIBRARY_TOKEN", hide_env_values = true)]
+    library_token: Option<String>,
+
+    /// ID of the Audiobookshelf library, or Plex library section, to scan.
+    #[clap(long, env = "POARDER_LIBRARY_ID")]
+    library_id: Option<String>,
+
+    /// Publish new episodes and run status to this MQTT broker (mqtt://[user:password@]host[:port]), with Home Assistant discovery.
+    #[cfg(feature = "mqtt")]
+    #[clap(long = "mqtt", value_name = "URL", env = "POARDER_MQTT", hide_env_values = true)]
+    mqtt_url: Option<String>,
+
+    /// Prefix of the MQTT topics poarder publishes to.
+    #[cfg(feature = "mqtt")]
+    #[clap(long, default_value = "poarder", env = "POARDER_MQTT_TOPIC")]
+    mqtt_topic: String,
+
+    /// Home Assistant's MQTT discovery prefix.
+    #[cfg(feature = "mqtt")]
+    #[clap(long, default_value = "homeassistant", env = "POARDER_MQTT_DISCOVERY_PREFIX")]
+    mqtt_discovery_prefix: String,
+
+    /// Path to a TOML configuration file [default: config.toml in $XDG_CONFIG_HOME/poarder, if it exists].
+    #[clap(long, global = true, env = "POARDER_CONFIG")]
+    config: Option<String>,
+
+    /// Contents of `--config`, loaded once in `main`.
+    #[clap(skip)]
+    config_file: Arc<config::Config>,
+}
+
+/// Options that can also be set in the config file (see `config::Options`).
+const CONFIG_OPTIONS: &[&str] = &["output-dir", "task-count", "max-downloads", "name-template", "name-date-format", "replace-existing",
+    "include-regex", "exclude-regex", "match-descriptions"];
+
+impl Args {
+    fn feed_limits(&self) -> limits::FeedLimits {
+        limits::FeedLimits {
+            max_size: self.max_feed_size,
+            max_items: self.max_feed_items,
+            max_depth: self.max_feed_depth,
+        }
+    }
+
+    fn window(&self) -> window::Window {
+        window::Window { since: self.since, until: self.until, latest: self.latest }
+    }
+
+    /// The title filter, whose patterns may come from the config file.
+    fn title_filter(&self) -> Result<titles::TitleFilter, String> {
+        titles::TitleFilter::new(self.include_regex.as_deref(), self.exclude_regex.as_deref(), self.match_descriptions)
+            .map_err(|e| format!("invalid include-regex or exclude-regex in the config file: {}", e))
+    }
+
+    fn library(&self) -> Option<library::Library> {
+        Some(library::Library {
+            server: self.library?,
+            dir: PathBuf::from(self.library_dir.as_ref()?),
+            url: self.library_url.clone(),
+            token: self.library_token.clone(),
+            library_id: self.library_id.clone(),
+        })
+    }
+
+    /// The options that can be set in the config file, for `config check`:
+    /// their values and whether a flag, a variable, the feed's entry in the
+    /// config (`feed`), its top level (`defaults`) or nothing set them.
+    fn settings(&self, defaults: &config::Options, feed: Option<&config::Options>) -> Vec<check::Setting> {
+        CONFIG_OPTIONS.iter()
+            .map(|&key| {
+                let source = match () {
+                    _ if self.from_env.iter().any(|id| id == key) => check::Source::Env,
+                    _ if self.given.iter().any(|id| id == key) => check::Source::Flag,
+                    _ if feed.is_some_and(|feed| feed.is_set(key)) => check::Source::Feed,
+                    _ if defaults.is_set(key) => check::Source::ConfigFile,
+                    _ => check::Source::Default
+                };
+                let value = match key {
+                    "output-dir" => match self.output_dir.as_slice() {
+                        [dir] => Some(toml::Value::from(dir.as_str())),
+                        dirs => Some(toml::Value::from(dirs.to_vec()))
+                    },
+                    "task-count" => Some(toml::Value::from(self.task_count as i64)),
+                    "max-downloads" => self.max_downloads.map(|max| toml::Value::from(max as i64)),
+                    "name-template" => Some(toml::Value::from(self.name_template.as_str())),
+                    "name-date-format" => self.name_date_format.as_deref().map(toml::Value::from),
+                    "replace-existing" => Some(toml::Value::from(self.replace_existing)),
+                    "include-regex" => self.include_regex.as_deref().map(toml::Value::from),
+                    "exclude-regex" => self.exclude_regex.as_deref().map(toml::Value::from),
+                    "match-descriptions" => Some(toml::Value::from(self.match_descriptions)),
+                    _ => None
+                };
+                check::Setting { key, value, source }
+            })
+            .collect()
+    }
+
+    /// Take `options` from the config file, except where a flag or variable was given.
+    fn apply_options(&mut self, options: &config::Options) {
+        let given = self.given.clone();
+        let unset = |id: &str| !given.iter().any(|given| given == id);
+        if let (Some(dir), true) = (&options.output_dir, unset("output-dir")) {
+            self.output_dir = vec![dir.clone()];
+        }
+        if let (Some(task_count), true) = (options.task_count, unset("task-count")) {
+            self.task_count = task_count;
+        }
+        if let (Some(max_downloads), true) = (options.max_downloads, unset("max-downloads")) {
+            self.max_downloads = Some(max_downloads);
+        }
+        if let (Some(name_template), true) = (&options.name_template, unset("name-template")) {
+            self.name_template = name_template.clone();
+        }
+        if let (Some(name_date_format), true) = (&options.name_date_format, unset("name-date-format")) {
+            self.name_date_format = Some(name_date_format.clone());
+        }
+        if let (Some(replace_existing), true) = (options.replace_existing, unset("replace-existing")) {
+            self.replace_existing = replace_existing;
+        }
+        if let (Some(include_regex), true) = (&options.include_regex, unset("include-regex")) {
+            self.include_regex = Some(include_regex.clone());
+        }
+        if let (Some(exclude_regex), true) = (&options.exclude_regex, unset("exclude-regex")) {
+            self.exclude_regex = Some(exclude_regex.clone());
+        }
+        if let (Some(match_descriptions), true) = (options.match_descriptions, unset("match-descriptions")) {
+            self.match_descriptions = match_descriptions;
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum FeedAction {
+    /// Stop polling the feed.
+    Disable {
+        /// The feed's URL, or the name of its show directory.
+        feed: String,
+        /// Resume polling after this date (YYYY-MM-DD or RFC 3339) instead of staying disabled.
+        #[clap(long, value_parser = polling::parse_until)]
+        snooze_until: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    /// Resume polling the feed.
+    Enable {
+        /// The feed's URL, or the name of its show directory.
+        feed: String,
+    },
+    /// Show whether the feed, or without one every feed, is polled.
+    Status {
+        /// The feed's URL, or the name of its show directory.
+        feed: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum ConfigAction {
+    /// Check templates, paths, URLs and commands, then print the merged configuration and where each value came from.
+    Check,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum TemplateAction {
+    /// Print what the feed's first episodes would be named under a template, next to their names under --name-template.
+    Test {
+        /// Feed URL, or a saved feed file.
+        #[clap(long)]
+        feed: String,
+
+        /// Template to try, e.g. "{show} - {episode} - {title}.{ext}".
+        #[clap(long)]
+        template: String,
+
+        /// strftime format of {date} in the template; --name-date-format by default.
+        #[clap(long)]
+        date_format: Option<String>,
+
+        /// Number of episodes to show.
+        #[clap(long, default_value = "10")]
+        count: usize,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum BenchTarget {
+    /// Time parsing a feed file, e.g. a saved feed/rss.xml.
+    Parse {
+        file: String,
+
+        /// Times to parse the feed in each mode.
+        #[clap(long, default_value = "10")]
+        iterations: usize,
+    },
 }
 
-#[derive(Debug, Clone)]
-struct Episode {
-    url: String,
-    title: String,
-    datetime: NaiveDateTime,
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Interactively create a config file and optional systemd units.
+    Init,
+
+    /// Validate the configuration without network access and print the options a run would use.
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Check the configuration, output directories and feeds for problems.
+    Doctor {
+        /// Feed URL to check; may be repeated.
+        #[clap(short, long)]
+        rss_url: Vec<String>,
+    },
+
+    /// Bundle an anonymised copy of a feed plus environment details for a bug report.
+    Report {
+        /// URL of the problematic feed.
+        feed_url: String,
+
+        /// Number of items to keep from the feed.
+        #[clap(long, default_value = "5")]
+        items: usize,
+
+        /// Also include the first 64 KiB of each kept episode.
+        #[clap(long, action)]
+        sample_media: bool,
+
+        /// Directory to write the report into.
+        #[clap(long, default_value = "poarder-report")]
+        out: String,
+    },
+
+    /// Download direct media URLs into the archive, without a feed.
+    Grab {
+        /// Media URLs to download.
+        #[clap(required = true)]
+        urls: Vec<String>,
+    },
+
+    /// Download a feed's whole back catalog oldest first, in batches that resume where they left off.
+    Backfill {
+        /// URL of the feed.
+        feed: String,
+
+        /// Episodes per batch; progress is saved after each one.
+        #[clap(long, default_value = "50")]
+        batch_size: usize,
+
+        /// Seconds to wait between batches.
+        #[clap(long, default_value = "300")]
+        pause: u64,
+
+        /// Downloads at a time.
+        #[clap(long, default_value = "1")]
+        tasks: usize,
+    },
+
+    /// Subscribe to feeds, to be archived by `poarder update`.
+    Add {
+        /// Feed URLs; Apple Podcasts links and show websites work too.
+        #[clap(required = true)]
+        urls: Vec<String>,
+    },
+
+    /// Unsubscribe from feeds, keeping what was archived.
+    Remove {
+        /// Feed URLs as listed in the subscriptions file.
+        #[clap(required = true)]
+        urls: Vec<String>,
+    },
+
+    /// Archive every subscribed feed, each in its own directory.
+    Update,
+
+    /// List episodes that have disappeared from the upstream feed.
+    Removed,
+
+    /// Stop or resume polling a feed archived in --output-dir, keeping its config and history.
+    Feed {
+        #[clap(subcommand)]
+        action: FeedAction,
+    },
+
+    /// Bring a show's episodes downloaded by another podcast tool into the archive in --output-dir.
+    Import {
+        /// The show's folder in the other tool's downloads, e.g. ~/gPodder/Downloads/<show>.
+        from: String,
+
+        /// URL of the show's feed, to match the files against.
+        #[clap(long)]
+        feed: String,
+
+        /// Copy the files instead of moving them.
+        #[clap(long, action)]
+        copy: bool,
+
+        /// Only print what would be imported.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    /// Copy the archive in --output-dir into the layout of another podcast tool.
+    Export {
+        /// Tool whose layout to use.
+        #[clap(long, value_enum)]
+        layout: interop::ForeignLayout,
+
+        /// Directory to export into; the show gets a folder in it.
+        to: String,
+
+        /// Hard-link the files instead of copying them, where both are on one filesystem.
+        #[clap(long, action)]
+        link: bool,
+    },
+
+    /// Rename the archived episodes of each --output-dir to --name-template, keeping an undo journal.
+    Migrate {
+        /// Put back the names from before the latest migrate instead.
+        #[clap(long, action)]
+        undo: bool,
+
+        /// Only print what would be renamed.
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    /// Try out a --name-template on a feed before renaming an archive to it.
+    Template {
+        #[clap(subcommand)]
+        action: TemplateAction,
+    },
+
+    /// List the versioned JSON schemas, or print one, e.g. `poarder schema episode`.
+    Schema {
+        name: Option<String>,
+    },
+
+    /// Measure poarder's performance on local data.
+    Bench {
+        #[clap(subcommand)]
+        target: BenchTarget,
+    },
+
+    /// Report files in the archive that the saved feed doesn't account for.
+    Fsck {
+        /// Ask whether to adopt, relink or delete each orphaned file.
+        #[clap(long, action)]
+        fix: bool,
+    },
 
-    raw: String,
+    /// Hash the archived episodes again and report any missing or no longer matching their SHA-256.
+    Verify,
+
+    /// Send the [digest] of episodes archived since the last one now, rather than when it is due.
+    Digest,
+
+    /// Show statistics gathered across previous runs.
+    Stats {
+        /// Only show per-host error rates, speeds and retry counts.
+        #[clap(long, action)]
+        hosts: bool,
+
+        /// Only show time from publication to archive (p50/p95).
+        #[clap(long, action)]
+        freshness: bool,
+    },
+
+    /// Replace this binary with the latest signed GitHub release.
+    #[cfg(feature = "self-update")]
+    SelfUpdate {
+        /// Only report whether an update is available.
+        #[clap(long, action)]
+        check: bool,
+
+        /// Reinstall even if already on the latest version.
+        #[clap(long, action)]
+        force: bool,
+
+        /// With --force, install the latest release even if it is older than this binary.
+        #[clap(long, action, requires = "force")]
+        allow_downgrade: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Format {
+    Auto,
+    /// RSS or Atom.
+    Rss,
+    /// JSON Feed.
+    Json,
 }
 
-#[derive(Debug)]
-struct RssFormatError {
-    text: String
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Runtime {
+    CurrentThread,
+    MultiThread,
 }
 
-impl Error for RssFormatError {}
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    args.given = CONFIG_OPTIONS.iter()
+        .filter(|id| matches!(matches.value_source(id), Some(ValueSource::CommandLine | ValueSource::EnvVariable)))
+        .map(|id| id.to_string())
+        .collect();
+    args.from_env = CONFIG_OPTIONS.iter()
+        .filter(|id| matches!(matches.value_source(id), Some(ValueSource::EnvVariable)))
+        .map(|id| id.to_string())
+        .collect();
+    if args.config.is_none() {
+        args.config = config::default_path().map(|path| path.to_string_lossy().into_owned());
+    }
+    if let Some(path) = &args.config {
+        match config::Config::load(Path::new(path)) {
+            Ok(config) => {
+                args.apply_options(&config.defaults);
+                args.config_file = Arc::new(config);
+            },
+            // Theirs to report.
+            Err(_) if matches!(args.command, Some(Command::Config { .. } | Command::Doctor { .. })) => (),
+            Err(e) => return Err(format!("{}: {}", path, e).into())
+        }
+    }
 
-impl fmt::Display for RssFormatError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Oh no, something bad went down")
+    args.rate_limit = args.max_rate.map(|rate| Arc::new(throttle::RateLimit::new(rate)));
+
+    #[cfg(feature = "progress")]
+    if !args.no_progress && !args.syslog && matches!(args.command, None | Some(Command::Update)) {
+        args.progress = poarder_core::progress::Progress::new();
+    }
+    init_logging(&args);
+
+    // Landlock only restricts threads created afterwards, so this has to
+    // happen before the runtime spawns its workers.
+    #[cfg(all(feature = "sandbox", target_os = "linux"))]
+    if args.sandbox && matches!(args.command, None | Some(Command::Update)) {
+        let (writable, readable) = sandbox_paths(&args)?;
+        sandbox::apply(&writable, &readable)?;
     }
+
+    let mut runtime = match args.runtime {
+        Runtime::CurrentThread => tokio::runtime::Builder::new_current_thread(),
+        Runtime::MultiThread => tokio::runtime::Builder::new_multi_thread()
+    };
+    runtime.enable_all()
+        .build()?
+        .block_on(run(args))
 }
 
+async fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "mqtt")]
+    let mut args = args;
+    #[cfg(feature = "mqtt")]
+    if let (Some(url), None | Some(Command::Update)) = (&args.mqtt_url, &args.command) {
+        args.mqtt = Some(mqtt::Mqtt::connect(url, &args.mqtt_topic, &args.mqtt_discovery_prefix)?);
+    }
+    #[cfg(feature = "mqtt")]
+    let mqtt = args.mqtt.clone();
+    #[cfg(feature = "progress")]
+    let progress = args.progress.clone();
+    if matches!(args.command, None | Some(Command::Update) | Some(Command::Grab { .. }) | Some(Command::Backfill { .. })) {
+        tokio::spawn(serve::stop_on_signal(args.shutdown.clone()));
+    }
+
+    let result = run_command(args).await;
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = mqtt {
+        mqtt.finish().await;
+    }
+    #[cfg(feature = "progress")]
+    if let Some(progress) = progress {
+        progress.finish();
+    }
+    result
+}
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+async fn run_command(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.command {
+        Some(Command::Digest) => {
+            digest::run(args.config_file.digest.clone(), Path::new(&args.output_dir[0])).await
+        },
+        Some(Command::Stats { hosts, freshness }) => {
+            let archives = archives(&args)?;
+            let both = !hosts && !freshness;
+            if *hosts || both {
+                for archive in &archives {
+                    if archives.len() > 1 {
+                        println!("{}:", archive[0]);
+                    }
+                    let layout = layout::FeedLayout::new(Path::new(&archive[0]));
+                    let host_stats = stats::HostStatsStore::open(&layout.state_dir())?;
+                    print!("{}", stats::HostsTable::new(&host_stats));
+                }
+            }
+            if *freshness || both {
+                if both {
+                    println!();
+                }
+                let mut stores = Vec::new();
+                for dir in archives.iter().flatten() {
+                    let layout = layout::FeedLayout::new(Path::new(dir));
+                    stores.push(stats::TimeToArchiveStore::load(&layout.state_dir())?);
+                }
+                print!("{}", stats::FreshnessTable::new(&stores));
+            }
+            Ok(())
+        },
+        Some(Command::Config { action: ConfigAction::Check }) => {
+            let config = args.config_file.clone();
+            let feeds = feed_sources(&args).map_err(|e| e.to_string()).map(|feeds| feeds.into_iter()
+                .map(|feed| {
+                    let mut feed_args = args.clone();
+                    feed_args.apply_options(&feed.options);
+                    check::FeedSettings {
+                        url: feed.url,
+                        weight: feed.weight,
+                        settings: feed_args.settings(&config.defaults, Some(&feed.options)),
+                    }
+                })
+                .collect());
+            check::run(args.config.as_deref(), &args.settings(&config.defaults, None), feeds)
+        },
+        Some(Command::Doctor { rss_url }) => {
+            doctor::run(&http_client(false)?, args.config.as_deref(), &args.output_dir, rss_url).await
+        },
+        Some(Command::Report { feed_url, items, sample_media, out }) => {
+            let http = fixtures::Http::new(http_client(false)?, fixtures::HttpMode::Live);
+            info!("Downloading {}", feed_url);
+            let resp = http.send(http.get(feed_url)).await?.error_for_status()?;
+            let (feed, _) = read_feed(&args, resp).await?;
+            report::run(&http, &feed, report::ReportOptions {
+                items: *items,
+                sample_media: *sample_media,
+                out_dir: Path::new(out),
+                config_path: args.config.as_deref(),
+            }).await
+        },
+        Some(Command::Grab { urls }) => {
+            let session = open_session(&args, None, http_client(args.low_memory)?)?;
+            let mut jobs = Vec::new();
+            for url in urls {
+                let mut job = pipeline::EpisodeJob::new(grab::episode_for(&session.client, url).await);
+                if !session.ctx.plugins.item_parsed(&mut job.episode).await {
+                    info!("{}: dropped by plugin", url);
+                    continue
+                }
+                job.advance(pipeline::EpisodeState::Filtered);
+                jobs.push(job);
+            }
 
-    init_logging(args.syslog);
+            let mut summary = pipeline::RunSummary::default();
+            summary.count(&run_jobs(&args, session.ctx, jobs).await);
+            report(&args, &summary)
+        },
+        Some(Command::Backfill { feed, batch_size, pause, tasks }) => {
+            let mut session = open_session(&args, Some(feed), http_client(args.low_memory)?)?;
+            let eta = eta::Eta::new(feed);
+            session.ctx.eta = Some(eta.clone());
+            let _eta_log = log_eta(eta, args.eta_interval);
+            #[cfg(feature = "wasm")]
+            let mut wasm_host = wasm::WasmHost::load(&session.config.wasm_plugins)?;
+
+            info!("Downloading RSS feed");
+            let resp = session.client.send(session.client.get(feed)).await?.error_for_status()?;
+            let (rss_xml, format) = read_feed(&args, resp).await?;
+            let mut episodes = Vec::new();
+            for episode in parse_feed_with(&rss_xml, format, false, |_| ItemStep::Parse, |_| false, |_| ())? {
+                let mut episode = episode;
+                #[cfg(feature = "wasm")]
+                if !wasm_host.apply(feed, &mut episode) {
+                    continue
+                }
+                if session.ctx.plugins.item_parsed(&mut episode).await {
+                    episodes.push(episode);
+                }
+            }
+
+            let episodes = args.title_filter()?.retain(episodes);
+            let episodes = args.window().retain(episodes, chrono::Utc::now());
+
+            let state_dir = session.layouts[0].state_dir();
+            let downloader = Downloader::new(session.ctx).concurrency(*tasks, 1);
+            let summary = backfill::run(&downloader, episodes, backfill::BackfillOptions {
+                feed_url: feed,
+                state_dir: &state_dir,
+                batch_size: *batch_size,
+                pause: Duration::from_secs(*pause),
+            }).await?;
+            downloader.finish(summary.stored).await;
+            report(&args, &summary)
+        },
+        Some(Command::Add { urls }) => subscriptions::add(&http_client(false)?, urls).await,
+        Some(Command::Remove { urls }) => subscriptions::remove(urls),
+        Some(Command::Update) => {
+            let path = subscriptions::path()?;
+            if !path.exists() || feeds::read_list(&path)?.is_empty() {
+                return Err("no subscriptions yet; add one with `poarder add <url>`".into())
+            }
+            let args = Args { command: None, feeds_file: Some(path.to_string_lossy().into_owned()), ..args.clone() };
+            let feeds = feed_sources(&args)?;
+            let client = http_client(args.low_memory)?;
+            let summary = archive_feeds(args.clone(), feeds, client).await?;
+            report(&args, &summary)
+        },
+        Some(Command::Template { action: TemplateAction::Test { feed, template, date_format, count } }) => {
+            let date_format = date_format.as_deref().or(args.name_date_format.as_deref());
+            let proposed = naming::NameTemplate::new(template, date_format)?.max_length(args.max_name_length);
+            template::run(&http_client(false)?, feed, &name_template(&args)?, &proposed, *count).await
+        },
+        Some(Command::Schema { name: None }) => {
+            for schema in schema::ALL {
+                println!("{:<16} version {}", schema.name, schema.version);
+            }
+            Ok(())
+        },
+        Some(Command::Schema { name: Some(name) }) => match schema::find(name) {
+            Some(schema) => {
+                print!("{}", schema.document);
+                Ok(())
+            },
+            None => Err(format!("no schema named {}; run `poarder schema` to list them", name).into())
+        },
+        Some(Command::Removed) => {
+            for dir in archives(&args)?.iter().flatten() {
+                let layout = layout::FeedLayout::new(Path::new(dir));
+                catalog::print_removed(Path::new(dir), &catalog::Catalog::open(&layout.state_dir())?);
+            }
+            Ok(())
+        },
+        Some(Command::Feed { action }) => match action {
+            FeedAction::Disable { feed, snooze_until } => polling::run(&[feed_archive(&args, feed)?], Some(true), *snooze_until),
+            FeedAction::Enable { feed } => polling::run(&[feed_archive(&args, feed)?], Some(false), None),
+            FeedAction::Status { feed: Some(feed) } => polling::run(&[feed_archive(&args, feed)?], None, None),
+            FeedAction::Status { feed: None } => {
+                let primaries: Vec<String> = archives(&args)?.into_iter().map(|mut archive| archive.swap_remove(0)).collect();
+                polling::run(&primaries, None, None)
+            },
+        },
+        Some(Command::Import { from, feed, copy, dry_run }) => {
+            let resp = http_client(false)?.get(feed).send().await?.error_for_status()?;
+            let (document, format) = read_feed(&args, resp).await?;
+            let episodes: Vec<Episode> = parse_feed_with(&document, format, false, |_| ItemStep::Parse, |_| false, |_| ())?.into_iter().collect();
+            let permissions = disk::Permissions { file_mode: args.file_mode, dir_mode: args.dir_mode, owner: args.chown };
+            // Into the feed's show directory, if a run of several feeds made it one.
+            let root = match (&args.show_dir, feeds::FeedIndex::open(Path::new(&args.output_dir[0]))?.dir(feed)) {
+                (None, Some(show_dir)) => Path::new(&args.output_dir[0]).join(show_dir),
+                _ => archive_root(&args, &args.output_dir[0])
+            };
+            interop::import(&layout::FeedLayout::new(&root), &name_template(&args)?, &permissions, &episodes, interop::ImportOptions {
+                from: Path::new(from),
+                feed_url: feed,
+                copy: *copy,
+                dry_run: *dry_run,
+            })
+        },
+        Some(Command::Export { layout, to, link }) => {
+            for dir in archives(&args)?.iter().flatten() {
+                interop::export(&layout::FeedLayout::new(Path::new(dir)), &name_template(&args)?, *layout, Path::new(to), *link)?;
+            }
+            Ok(())
+        },
+        Some(Command::Migrate { undo: true, dry_run }) => rename::undo(&archives(&args)?.concat(), *dry_run),
+        Some(Command::Migrate { undo: false, dry_run }) => rename::run(&archives(&args)?.concat(), &name_template(&args)?, &args.name_template, *dry_run),
+        Some(Command::Fsck { fix }) => fsck::run(&archives(&args)?.concat(), &name_template(&args)?, *fix),
+        Some(Command::Verify) => verify::run(&archives(&args)?),
+        Some(Command::Bench { target: BenchTarget::Parse { file, iterations } }) => bench::parse(Path::new(file), *iterations),
+        Some(Command::Init) => init::run(&http_client(false)?).await,
+        #[cfg(feature = "self-update")]
+        Some(Command::SelfUpdate { check, force, allow_downgrade }) => {
+            update::run(&http_client(false)?, *check, *force, *allow_downgrade).await
+        },
+        None if args.serve || args.daemon => {
+            let feeds = feed_sources(&args)?;
+            let rss_urls: Vec<String> = feeds.iter().map(|feed| feed.url.clone()).collect();
+            let config = args.config_file.clone();
+            let mut schedules = Vec::new();
+            for rss_url in &rss_urls {
+                schedules.extend(schedule::for_feed(&config.schedules, rss_url)?);
+            }
+
+            // One client for every run, so connections warmed before a
+            // scheduled publication are still open when the feed is polled.
+            let client = http_client(args.low_memory)?;
+            let listen = args.serve.then_some(args.listen.as_str());
+            let calendars: Vec<PathBuf> = match args.calendar {
+                true => args.output_dir.iter().map(PathBuf::from).collect(),
+                false => Vec::new()
+            };
+            let single = args.feeds_file.is_none() && args.opml.is_none() && feeds.len() == 1;
+            let status = args.serve.then(|| status::Status::new(&feeds, &args.output_dir[0], single));
+            let args = Args { status: status.clone(), ..args.clone() };
+            serve::run(listen, args.interval, &schedules, serve::Pages { calendars, status }, &args.shutdown,
+                || archive_feeds(args.clone(), feeds.clone(), client.clone()),
+                || warm_up(&client, &rss_urls)).await
+        },
+        None => {
+            let feeds = feed_sources(&args)?;
+            let client = http_client(args.low_memory)?;
+            let summary = archive_feeds(args.clone(), feeds, client).await?;
+            report(&args, &summary)
+        }
+    }
+}
+
+/// Log how the run went, listing the episodes that failed, and fail it if it
+/// was stopped early or at least `--fail-on-error` of them did.
+fn report(args: &Args, summary: &pipeline::RunSummary) -> Result<(), Box<dyn std::error::Error>> {
+    for (title, reason) in &summary.failures {
+        warn!("Failed: {}: {}", title, reason);
+    }
+    for (title, anomaly) in &summary.anomalies {
+        warn!("Suspicious: {}: {}", title, anomaly);
+    }
+    for (item, reason) in &summary.malformed {
+        warn!("Malformed: {}: {}", item, reason);
+    }
+    if args.shutdown.is_requested() {
+        warn!("Stopped early: {}", summary);
+        return Err("interrupted".into())
+    }
+    info!("Run complete: {}", summary);
+    match args.fail_on_error {
+        threshold if threshold > 0 && summary.failed >= threshold => Err(format!("{} episode(s) failed", summary.failed).into()),
+        _ => Ok(())
+    }
+}
+
+/// Directories `--sandbox` leaves writable and readable for an archive run.
+/// Writable ones are created up front, since Landlock rules need existing paths.
+#[cfg(all(feature = "sandbox", target_os = "linux"))]
+fn sandbox_paths(args: &Args) -> Result<(Vec<PathBuf>, Vec<PathBuf>), Box<dyn std::error::Error>> {
+    let config = args.config_file.clone();
+
+    let writable: Vec<PathBuf> = args.output_dir.iter()
+        .chain(config.tee.iter())
+        .chain(config.feeds.iter().filter_map(|feed| feed.options.output_dir.as_ref()))
+        .chain(args.tmp_dir.iter())
+        .chain(args.shared_state.iter())
+        .chain(args.record_http.iter())
+        .map(PathBuf::from)
+        .chain(config.digest.iter().filter_map(|digest| digest.output.as_deref())
+            .filter_map(|output| Path::new(output).parent().map(Path::to_path_buf)))
+        .collect();
+    for dir in &writable {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let mut readable: Vec<PathBuf> = args.config.iter()
+        .chain(args.feeds_file.iter())
+        .chain(args.opml.iter())
+        .chain(args.replay_http.iter())
+        .chain(args.sandbox_read.iter())
+        .map(PathBuf::from)
+        .collect();
+    // Plugins may be scripts outside the system paths, or programs on PATH.
+    let commands = config.plugins.iter().map(|p| &p.command)
+        .chain(config.resolvers.iter().map(|r| &r.command))
+        .chain(std::iter::once(&config.auth_refresh_cmd))
+        .chain(config.feeds.iter().filter_map(|feed| feed.auth_refresh_cmd.as_ref()));
+    for command in commands {
+        readable.extend(command.iter().map(PathBuf::from).filter(|p| p.is_absolute()));
+    }
+    #[cfg(feature = "wasm")]
+    readable.extend(config.wasm_plugins.iter().map(|plugin| PathBuf::from(&plugin.path)));
+    if matches!(args.command, Some(Command::Update)) {
+        readable.push(subscriptions::path()?);
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        readable.extend(std::env::split_paths(&path).filter(|p| p.is_absolute()));
+    }
+
+    Ok((writable, readable))
+}
+
+/// Open a connection to each feed's host ahead of time, so DNS and the TLS
+/// handshake are done by the time the feed is fetched.
+async fn warm_up(client: &reqwest::Client, rss_urls: &[String]) {
+    for rss_url in rss_urls {
+        let started = std::time::Instant::now();
+        match client.head(rss_url).send().await {
+            Ok(_) => info!("Warmed up connection for {} in {:?}", rss_url, started.elapsed()),
+            Err(e) => log::warn!("Failed to warm up connection for {}. Error: {}", rss_url, e)
+        }
+    }
+}
+
+/// Feeds to archive, from `--rss-url`, `--feeds-file` and `--opml`.
+fn feed_sources(args: &Args) -> Result<Vec<feeds::FeedSource>, Box<dyn std::error::Error>> {
+    let mut sources: Vec<feeds::FeedSource> = args.rss_url.iter().map(|url| feeds::FeedSource::new(url)).collect();
+    if let Some(path) = &args.feeds_file {
+        sources.extend(feeds::read_list(Path::new(path))?);
+    }
+    if let Some(path) = &args.opml {
+        sources.extend(feeds::read_opml(Path::new(path))?);
+    }
+
+    let config = args.config_file.clone();
+    if sources.is_empty() {
+        sources.extend(config.feeds.iter().map(|feed| feeds::FeedSource::new(&feed.url)));
+    }
+    for source in sources.iter_mut() {
+        if let Some(feed) = config.feeds.iter().find(|feed| feed.url == source.url) {
+            source.options = feed.options.clone();
+            if source.weight == 1 {
+                source.weight = feed.weight;
+            }
+        }
+    }
+
+    // A feed listed twice would be archived twice at once into one place.
+    let mut seen = std::collections::HashSet::new();
+    sources.retain(|source| seen.insert(source.url.clone()));
+    if sources.is_empty() {
+        return Err("no feeds to archive; pass --rss-url, list some in --feeds-file or --opml, or add [[feeds]] to the config file".into())
+    }
+    Ok(sources)
+}
+
+/// Where the archive in output directory `dir` is, which with several feeds
+/// is the feed's own directory inside it.
+fn archive_root(args: &Args, dir: &str) -> PathBuf {
+    match &args.show_dir {
+        Some(show_dir) => Path::new(dir).join(show_dir),
+        None => PathBuf::from(dir)
+    }
+}
+
+/// The archives that subcommands working on what is already archived look
+/// at, each as its directory in every output directory, primary first: the
+/// one at `--show-dir`, else the output directory if a feed was archived
+/// straight into it, each show directory a run of several feeds made in
+/// it, and those of `[[feeds]]` with an `output-dir` of their own.
+fn archives(args: &Args) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+    let in_each = |dir: &str| -> Vec<String> {
+        args.output_dir.iter().map(|root| Path::new(root).join(dir).to_string_lossy().into_owned()).collect()
+    };
+    if let Some(show_dir) = &args.show_dir {
+        return Ok(vec![in_each(show_dir)])
+    }
+
+    let mut archives = Vec::new();
+    if layout::FeedLayout::new(Path::new(&args.output_dir[0])).state_dir().exists() {
+        archives.push(args.output_dir.clone());
+    }
+    let index = feeds::FeedIndex::open(Path::new(&args.output_dir[0]))?;
+    archives.extend(index.entries().map(|(_, dir)| in_each(dir)));
+    let config = args.config_file.clone();
+    for dir in config.feeds.iter().filter_map(|feed| feed.options.output_dir.as_ref()) {
+        if !archives.iter().any(|archive| archive[0] == *dir) {
+            archives.push(vec![dir.clone()]);
+        }
+    }
+
+    // Nothing archived yet: say so about the output directory itself.
+    if archives.is_empty() {
+        archives.push(args.output_dir.clone());
+    }
+    Ok(archives)
+}
+
+/// The primary directory of the archive of `feed`, given as its URL or the
+/// name of its show directory, out of those [`archives`] finds.
+fn feed_archive(args: &Args, feed: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let root = Path::new(&args.output_dir[0]);
+    let config = args.config_file.clone();
+    let index = feeds::FeedIndex::open(root)?;
+    let indexed = index.entries().map(|(url, dir)| (Some(url), root.join(dir)));
+    let configured = config.feeds.iter()
+        .filter_map(|entry| Some((Some(entry.url.as_str()), PathBuf::from(entry.options.output_dir.as_ref()?))));
+    let own = layout::FeedLayout::new(root).state_dir().exists().then(|| (None, root.to_path_buf()));
+    let candidates: Vec<(Option<&str>, PathBuf)> = indexed.chain(configured).chain(own).collect();
+
+    let named = |dir: &Path| dir.strip_prefix(root).is_ok_and(|show_dir| show_dir == Path::new(feed))
+        || dir.file_name().is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(feed));
+    match candidates.iter().find(|(url, dir)| *url == Some(feed) || named(dir)) {
+        Some((_, dir)) => Ok(dir.to_string_lossy().into_owned()),
+        None if candidates.is_empty() => Err(format!("{} holds no archive; check --output-dir", root.display()).into()),
+        None => {
+            let names: Vec<String> = candidates.iter().map(|(url, dir)| url.map_or_else(|| dir.display().to_string(), str::to_string)).collect();
+            Err(format!("no archived feed matches {}; the archived feeds are {}", feed, names.join(", ")).into())
+        }
+    }
+}
+
+/// Archive directories, storage checks and shared services for one run,
+/// before any episodes are known.
+struct Session {
+    #[cfg_attr(not(any(feature = "wasm", feature = "artwork")), allow(dead_code))]
+    config: Arc<config::Config>,
+    layouts: Vec<layout::FeedLayout>,
+    permissions: disk::Permissions,
+    client: Arc<fixtures::Http>,
+    ctx: pipeline::DownloadContext,
+}
+
+/// Prepare the archive for a run. `feed_url` is the feed being archived,
+/// or `None` for episodes that don't come from a feed.
+fn open_session(args: &Args, feed_url: Option<&str>, http_client: reqwest::Client) -> Result<Session, Box<dyn std::error::Error>> {
+    let shared_state = match &args.shared_state {
+        Some(dir) => Some(state::SharedState::open(Path::new(dir), Duration::from_secs(args.claim_timeout))?),
+        None => None
+    };
+
+    let config = args.config_file.clone();
+    let plugins = Arc::new(plugins::PluginHost::start(&config.plugins)?);
+    let host_limiter = Arc::new(hosts::HostLimiter::new(&config.hosts));
+    #[cfg(not(feature = "wasm"))]
+    if !config.wasm_plugins.is_empty() {
+        return Err("wasm-plugins are configured but poarder was built without the `wasm` feature".into())
+    }
+    #[cfg(not(feature = "artwork"))]
+    if !config.artwork.is_empty() {
+        return Err("artwork renditions are configured but poarder was built without the `artwork` feature".into())
+    }
+
+    let permissions = disk::Permissions {
+        file_mode: args.file_mode,
+        dir_mode: args.dir_mode,
+        owner: args.chown,
+    };
+
+    // The first target is the primary archive and holds the state; the rest are mirrors.
+    let mut layouts = Vec::new();
+    for dir in args.output_dir.iter().chain(config.tee.iter()) {
+        let layout = layout::FeedLayout::new(&archive_root(args, dir));
+        layout.create()?;
+        for dir in layout.dirs() {
+            permissions.apply_dir(&dir)
+                .map_err(|e| format!("could not set permissions on {}: {}", dir.display(), e))?;
+        }
+        migrations::run(&layout)?;
+        layouts.push(layout);
+    }
+    let layout = layouts[0].clone();
+    let episode_dirs: Vec<PathBuf> = layouts.iter().map(|l| l.episodes_dir()).collect();
+    let host_stats = Arc::new(stats::HostStatsStore::open(&layout.state_dir())?);
+    let time_to_archive = match feed_url {
+        Some(feed_url) => Some(Arc::new(stats::TimeToArchiveStore::open(&layout.state_dir(), feed_url)?)),
+        None => None
+    };
+
+    // Catch a full or read-only disk before downloading anything.
+    let storage = disk::StorageGate::default();
+    for dir in &episode_dirs {
+        if let Err(e) = disk::probe(dir, 0) {
+            if !disk::is_storage_unavailable(&e) {
+                return Err(e.into())
+            }
+            storage.block(dir, &e);
+        }
+    }
+    if !args.wait_for_space && storage.all_blocked(episode_dirs.iter()) {
+        return Err("no output directory is writable".into())
+    }
+
+    let tmp_dir = args.tmp_dir.as_ref().map(PathBuf::from);
+    if let Some(tmp_dir) = &tmp_dir {
+        std::fs::create_dir_all(tmp_dir)?;
+    }
+
+    // Downloads abandoned long ago won't be resumed.
+    if !args.keep_partial {
+        for dir in episode_dirs.iter().chain(&tmp_dir) {
+            if let Err(e) = disk::remove_stale_parts(dir, args.clean_partial_older_than) {
+                warn!("Could not clean up partial downloads in {}. Error: {}", dir.display(), e);
+            }
+        }
+    }
+
+    let mut client = fixtures::Http::new(http_client, http_mode(args));
+    if let Some(feed_url) = feed_url {
+        client = client.with_auth(config.auth_refresh_cmd(feed_url), feed_url);
+    }
+    let client = Arc::new(client);
+
+    let checkpoint = match &args.shard {
+        Some(shard) => Some(shard::Checkpoint::open(&layout.state_dir(), shard)?),
+        None => None
+    };
+
+    let ctx = pipeline::DownloadContext {
+        http: client.clone(),
+        feed_url: feed_url.map(str::to_string),
+        episode_dirs,
+        tmp_dir,
+        storage,
+        wait_for_space: args.wait_for_space,
+        fail_fast: args.fail_fast,
+        keep_partial: args.keep_partial,
+        check_enclosure_length: args.check_enclosure_length,
+        shutdown: args.shutdown.clone(),
+        permissions,
+        checkpoint,
+        replace_existing: args.replace_existing,
+        write_buffer: args.write_buffer,
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        io_uring: args.io_uring && uring_available(),
+        #[cfg(feature = "id3")]
+        id3: args.id3,
+        #[cfg(feature = "id3")]
+        embed_artwork: args.embed_artwork.then(|| Arc::new(poarder_core::tags::Artwork::default())),
+        shared_state,
+        plugins,
+        resolvers: resolvers::Resolvers::new(&config.resolvers),
+        host_limiter,
+        fair_share: args.fair_share.clone(),
+        max_rate: args.rate_limit.clone(),
+        max_rate_per_download: args.max_rate_per_download,
+        eta: args.eta.clone(),
+        #[cfg(feature = "progress")]
+        progress: args.progress.clone(),
+        host_stats,
+        time_to_archive,
+        episodes_db: Some(Arc::new(db::EpisodeDb::open(&layout.state_dir())?)),
+        naming: name_template(args)?,
+    };
+
+    Ok(Session { config, layouts, permissions, client, ctx })
+}
+
+fn name_template(args: &Args) -> Result<naming::NameTemplate, Box<dyn std::error::Error>> {
+    Ok(naming::NameTemplate::new(&args.name_template, args.name_date_format.as_deref())?.max_length(args.max_name_length))
+}
+
+fn http_mode(args: &Args) -> fixtures::HttpMode {
+    match (&args.record_http, &args.replay_http) {
+        (Some(dir), _) => fixtures::HttpMode::Record(PathBuf::from(dir)),
+        (_, Some(dir)) => fixtures::HttpMode::Replay(PathBuf::from(dir)),
+        _ => fixtures::HttpMode::Live
+    }
+}
+
+/// Push `jobs` through the pipeline, then save statistics and shut plugins down.
+async fn run_jobs(args: &Args, ctx: pipeline::DownloadContext, jobs: Vec<pipeline::EpisodeJob>) -> Vec<pipeline::EpisodeJob> {
+    let downloader = if args.low_memory {
+        Downloader::new(ctx).concurrency(1, 1)
+    }
+    else {
+        Downloader::new(ctx).concurrency(args.task_count, args.write_queue)
+    };
+    let finished = downloader.run(jobs).await;
+
+    let stored = finished.iter().filter(|job| job.state == pipeline::EpisodeState::Stored).count();
+    downloader.finish(stored).await;
+    finished
+}
+
+/// Log how long `eta`'s downloads look set to take every `interval`, until
+/// the returned set is dropped.
+fn log_eta(eta: Arc<eta::Eta>, interval: Duration) -> tokio::task::JoinSet<()> {
+    let mut log = tokio::task::JoinSet::new();
+    if interval.is_zero() {
+        return log
+    }
+    log.spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            for report in eta.reports() {
+                info!("ETA {}", report);
+            }
+        }
+    });
+    log
+}
+
+/// Archive `feeds`, then have the media server of `--library` pick up what
+/// is new, and queue it for the `[digest]`.
+async fn archive_feeds(args: Args, feeds: Vec<feeds::FeedSource>, http_client: reqwest::Client) -> Result<pipeline::RunSummary, Box<dyn std::error::Error>> {
+    let library = args.library();
+    let digest = match args.config_file.digest.clone() {
+        Some(digest) => match digest.problem() {
+            Some(problem) => return Err(format!("[digest]: {}", problem).into()),
+            None => Some(Arc::new(digest::Digest::new(digest, Path::new(&args.output_dir[0]))))
+        },
+        None => None
+    };
+    let args = Args { digest: digest.clone(), ..args };
+    #[cfg(feature = "mqtt")]
+    let mqtt = args.mqtt.clone();
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &mqtt {
+        mqtt.run_started().await;
+    }
+
+    let summary = archive_each(args, feeds, http_client.clone()).await;
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &mqtt {
+        mqtt.run_finished(summary.as_ref().ok()).await;
+    }
+    if let Some(digest) = digest {
+        if let Err(e) = digest.finish(false).await {
+            error!("Failed to update the digest. Error: {}", e);
+        }
+    }
+    let summary = summary?;
+    if let (Some(library), true) = (library, summary.stored > 0) {
+        if let Err(e) = library.scan(&http_client).await {
+            error!("Failed to start a library scan. Error: {}", e);
+        }
+    }
+    Ok(summary)
+}
+
+/// Archive `feeds`: a single `--rss-url` straight into the output
+/// directories, otherwise concurrently, each into its own directory inside
+/// them. With `--fail-fast`, the first failed episode or feed ends the run.
+async fn archive_each(args: Args, feeds: Vec<feeds::FeedSource>, http_client: reqwest::Client) -> Result<pipeline::RunSummary, Box<dyn std::error::Error>> {
+    if let ([feed], None, None) = (feeds.as_slice(), &args.feeds_file, &args.opml) {
+        let eta = eta::Eta::new(&feed.url);
+        if let Some(status) = &args.status {
+            status.run_started(eta.clone());
+        }
+        let _eta_log = log_eta(eta.clone(), args.eta_interval);
+        let mut feed_args = Args { eta: Some(eta), ..args.clone() };
+        feed_args.apply_options(&feed.options);
+        let summary = archive(feed_args, feed.url.clone(), http_client).await?;
+        if args.fail_fast && summary.failed > 0 {
+            return Err(format!("{} episode(s) failed", summary.failed).into())
+        }
+        return Ok(summary)
+    }
+
+    feeds::check_show_dir_template(&args.show_dir_template)?;
+    let mut index = feeds::FeedIndex::open(Path::new(&args.output_dir[0]))?;
+    let http = fixtures::Http::new(http_client.clone(), http_mode(&args));
+    let mut failed_feeds = 0;
+    // Feeds take turns at the download slots, so a long backlog in one
+    // doesn't hold up the others.
+    let weights = feeds.iter().map(|feed| (feed.url.clone(), feed.weight)).collect();
+    let fair_share = fair::FairShare::new(args.max_downloads.unwrap_or(args.task_count), weights);
+    let eta = eta::Eta::new("all feeds");
+    if let Some(status) = &args.status {
+        status.run_started(eta.clone());
+    }
+    let _eta_log = log_eta(eta.clone(), args.eta_interval);
+    let mut runs = futures::stream::FuturesUnordered::new();
+    for feed in feeds.iter() {
+        let mut feed_args = Args { fair_share: Some(fair_share.clone()), ..args.clone() };
+        feed_args.apply_options(&feed.options);
+        // Feeds without an output directory of their own in the config get
+        // one named after the show.
+        if feed_args.output_dir == args.output_dir {
+            let show_dir = match index.dir_for(&http, feed, &args.show_dir_template).await {
+                Ok(show_dir) => show_dir,
+                Err(e) if args.fail_fast => {
+                    index.save()?;
+                    return Err(format!("{}: could not fetch the feed: {}", feed.url, e).into())
+                },
+                Err(e) => {
+                    error!("{}: could not fetch the feed. Error: {}", feed.url, e);
+                    failed_feeds += 1;
+                    continue
+                }
+            };
+            feed_args.show_dir = Some(show_dir);
+        }
+        feed_args.eta = Some(eta.child(feed_args.show_dir.as_deref().unwrap_or(&feed.url)));
+        let rss_url = feed.url.clone();
+        let http_client = http_client.clone();
+        runs.push(async move { (rss_url.clone(), archive(feed_args, rss_url, http_client).await) });
+    }
+    index.save()?;
+
+    let mut summary = pipeline::RunSummary::default();
+    // Dropping `runs` on the way out cancels the feeds still going.
+    while let Some((rss_url, result)) = runs.next().await {
+        match result {
+            Ok(feed_summary) if args.fail_fast && feed_summary.failed > 0 => {
+                return Err(format!("{}: {} episode(s) failed", rss_url, feed_summary.failed).into())
+            },
+            Ok(feed_summary) => summary.add(feed_summary),
+            Err(e) if args.fail_fast => return Err(format!("{}: run failed: {}", rss_url, e).into()),
+            Err(e) => {
+                error!("{}: run failed. Error: {}", rss_url, e);
+                failed_feeds += 1;
+            }
+        }
+    }
+    if failed_feeds > 0 {
+        return Err(format!("{} of {} feeds failed", failed_feeds, feeds.len()).into())
+    }
+    Ok(summary)
+}
+
+/// Write the show's cover into each archive's episodes directory, unless
+/// it is already there.
+async fn save_cover(client: &fixtures::Http, rss_xml: &str, layouts: &[layout::FeedLayout], permissions: &disk::Permissions) {
+    let channel = channel::Channel::parse(rss_xml);
+    for layout in layouts {
+        let cover = layout.episodes_dir().join(channel::COVER_FILE);
+        if cover.exists() {
+            continue
+        }
+        match channel::write_cover(client, layout, &channel, &cover).await {
+            Ok(()) if cover.exists() => if let Err(e) = permissions.apply_file(&cover) {
+                warn!("Failed to set permissions on {}. Error: {}", cover.display(), e);
+            },
+            Ok(()) => (),
+            Err(e) => error!("Failed to save cover. Error: {}", e)
+        }
+    }
+}
+
+/// Archive one feed.
+async fn archive(args: Args, rss_url: String, http_client: reqwest::Client) -> Result<pipeline::RunSummary, Box<dyn std::error::Error>> {
+    let primary = layout::FeedLayout::new(&archive_root(&args, &args.output_dir[0]));
+    let mut poll_state = polling::PollState::open(&primary.state_dir())?;
+    let config = args.config_file.clone();
+    // Between runs of a long-running poarder, go by the feed's own idea of
+    // how often to check, unless the config has a publication schedule for
+    // it or a full scan was asked for.
+    let feed_hints = (args.serve || args.daemon) && !args.full_scan && !config.schedules.iter().any(|schedule| schedule.feed.as_deref().is_none_or(|feed| feed == rss_url));
+    if let Some(reason) = poll_state.paused(chrono::Utc::now(), feed_hints) {
+        info!("Not polling {}: {}", rss_url, reason);
+        return Ok(pipeline::RunSummary::default())
+    }
+
+    let session = open_session(&args, Some(&rss_url), http_client.clone())?;
+    let Session { layouts, permissions, client, ctx, .. } = session;
+    let layout = layouts[0].clone();
+    let plugins = ctx.plugins.clone();
+    let episodes_db = ctx.episodes_db.clone();
+    #[cfg(feature = "wasm")]
+    let mut wasm_host = wasm::WasmHost::load(&session.config.wasm_plugins)?;
 
     info!("Downloading RSS feed");
-    let rss_xml = reqwest::get(args.rss_url)
-        .await?
-        .text()
-        .await?;
-
-    let rss_xml_clone = rss_xml.clone();
-    let output_path = Path::new(&args.output_dir.clone()).join("rss.xml");
-    tokio::spawn(async move {
+    let mut request = client.get(&rss_url);
+    // Runs asked to redo work, to look for live streams or to pick other
+    // episodes than last time need the feed even when it hasn't changed.
+    let window = args.window();
+    let title_filter = args.title_filter()?;
+    let selection = (window.is_set() || title_filter.is_set()).then(|| format!("{:?} {:?}", window, title_filter));
+    if !args.full_scan && !args.replace_existing && !args.record_live && poll_state.selection == selection {
+        request = polling::conditional(&poll_state, request);
+    }
+    let resp = match client.send(request).await.and_then(reqwest::Response::error_for_status) {
+        Ok(resp) => resp,
+        Err(e) => return Err(feed_failed(&args, &config.notify, &http_client, &mut poll_state, &layout, &rss_url, e.into()).await)
+    };
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        info!("Feed unchanged since the last run");
+        poll_state.fetched(chrono::Utc::now());
+        // An unchanged feed is just when an episode may be missing.
+        match catalog::Catalog::open(&layout.state_dir()) {
+            Ok(catalog) => raise_alerts(&config.notify, &http_client, &mut poll_state, &rss_url, || show_name(&args, &layout, &rss_url, None), &catalog).await,
+            Err(e) => error!("Failed to open episode catalog. Error: {}", e)
+        }
+        if let Err(e) = poll_state.save() {
+            error!("Failed to save polling state. Error: {}", e);
+        }
+        return Ok(pipeline::RunSummary::default())
+    }
+    let validators = polling::validators(resp.headers());
+    let (rss_xml, format) = match read_feed(&args, resp).await {
+        Ok(feed) => feed,
+        Err(e) => return Err(feed_failed(&args, &config.notify, &http_client, &mut poll_state, &layout, &rss_url, e).await)
+    };
+    plugins.feed_fetched(&rss_url, rss_xml.len()).await;
+
+    // Work overlapping the downloads. It is waited for before returning, and
+    // aborted if the run ends early, so nothing is cut off mid-write at exit.
+    let mut background = tokio::task::JoinSet::new();
+    let output_paths: Vec<PathBuf> = layouts.iter().map(|l| l.feed_dir().join("rss.xml")).collect();
+    if args.low_memory || args.runtime == Runtime::CurrentThread {
+        // Write straight from the fetched buffer rather than cloning it for a
+        // background task, which a single thread couldn't overlap anyway.
+        write_rss_snapshots(&rss_xml, &output_paths, &permissions);
+    }
+    else {
+        let rss_xml_clone = rss_xml.clone();
+        background.spawn_blocking(move || {
+            write_rss_snapshots(&rss_xml_clone, &output_paths, &permissions);
+        });
+    }
+
+    let mut live_state = None;
+    let mut live_recordings = tokio::task::JoinSet::new();
+    let mut next_live = None;
+    if args.record_live {
+        let state = live::LiveState::open(&layout.state_dir())?;
+        let (due, next) = state.due(&live::parse(&rss_xml), chrono::Utc::now());
+        next_live = next;
+        if !due.is_empty() {
+            let ffmpeg = args.ffmpeg.clone();
+            let feed_url = rss_url.clone();
+            let episode_dirs = ctx.episode_dirs.clone();
+            let naming = ctx.naming.clone();
+            live_recordings.spawn(async move {
+                let mut recorded = Vec::new();
+                for item in due {
+                    match live::record(&ffmpeg, &item, &feed_url, &episode_dirs, &naming, &permissions).await {
+                        Ok(name) => recorded.push((item, name)),
+                        Err(e) => error!("{}: could not record live stream. Error: {}", item.episode.title, e)
+                    }
+                }
+                recorded
+            });
+        }
+        live_state = Some(state);
+    }
+
+    let mut catalog = catalog::Catalog::open(&layout.state_dir())?;
+    let mut other_shards = 0;
+    let mut checkpointed = 0;
+
+    let now = chrono::Utc::now();
+    let stop_after = match args.full_scan {
+        true => None,
+        false => scan::for_feed(&session.config.scan, &rss_url).stop_after(poll_state.last_full_scan, now)
+    };
+    let episodes_dir = layout.episodes_dir();
+    let archived_guids = match stop_after {
+        Some(_) => catalog.archived_guids(&episodes_dir),
+        None => Default::default()
+    };
+    // Items already archived and unchanged since need no parsing; this
+    // relies on the catalog's copy of each item's XML. Soundbites are cut
+    // from parsed episodes, so catching up on them needs every item.
+    // --latest goes by every episode, so it needs them all parsed too.
+    let unchanged = match args.low_memory || args.replace_existing || args.full_scan || args.soundbites || args.latest.is_some() {
+        true => Default::default(),
+        false => catalog.archived_items(&ctx.episode_dirs)
+    };
+    let mut skipped = Vec::new();
+    let consecutive_archived = std::cell::Cell::new(0);
+    let cut_short = std::cell::Cell::new(false);
+    let count_archived = |archived: bool| {
+        consecutive_archived.set(if archived { consecutive_archived.get() + 1 } else { 0 });
+        cut_short.set(stop_after.is_some_and(|n| consecutive_archived.get() >= n));
+        cut_short.get()
+    };
+    let mut malformed = Vec::new();
+    let parsed = parse_feed_with(&rss_xml, format, !args.low_memory, |raw| {
+        let Some(name) = unchanged.get(raw) else {
+            return ItemStep::Parse
+        };
+        skipped.push(name.to_string());
+        match count_archived(true) {
+            true => ItemStep::Stop,
+            false => ItemStep::Skip
+        }
+    }, |episode| {
+        if stop_after.is_none() {
+            return false
+        }
+        let archived = match &episode.guid {
+            Some(guid) if archived_guids.contains(guid) => true,
+            _ => ctx.naming.existing_file(&episodes_dir, episode).is_some()
+        };
+        count_archived(archived)
+    }, |item| malformed.push(item)).map_err(|e| format!("could not parse the feed: {}", e))?;
+    match failed_items::save(&layout, &rss_url, format, &malformed) {
+        Ok(0) => (),
+        Ok(saved) => info!("Saved {} malformed item(s) to {} for bug reports", saved, layout.failed_items_dir().display()),
+        Err(e) => warn!("Could not save the malformed items. Error: {}", e)
+    }
+    let malformed: Vec<(String, String)> = malformed.into_iter().map(|item| (item.label, item.reason)).collect();
+    if args.strict && !malformed.is_empty() {
+        let items: Vec<String> = malformed.iter().map(|(item, reason)| format!("{}: {}", item, reason)).collect();
+        return Err(format!("{} malformed item(s) in the feed, and --strict is set: {}", malformed.len(), items.join("; ")).into())
+    }
+    let cut_short = cut_short.get();
+    if cut_short {
+        info!("Stopped reading the feed after {} archived episodes in a row", consecutive_archived.get());
+    }
+    if !skipped.is_empty() {
+        debug!("{} archived episodes unchanged since the last run", skipped.len());
+    }
+    let anomalies = anomalies::find(&parsed, catalog.entries().map(|(_, entry)| (entry.title.as_str(), entry.url.as_str())), now);
+
+    let mut latest_episode = None;
+    for name in &skipped {
+        let published = catalog.saw_unchanged(name);
+        latest_episode = latest_episode.max(published.map(|published| published.with_timezone(&chrono::Utc)));
+    }
+
+    // Before the window, so --latest counts only episodes the filter keeps.
+    let (parsed, unwanted): (Vec<Episode>, Vec<Episode>) = parsed.into_iter()
+        .partition(|episode| title_filter.exclusion(episode).is_none());
+    if !unwanted.is_empty() {
+        info!("{} episodes excluded by --include-regex or --exclude-regex", unwanted.len());
+    }
+    for episode in &unwanted {
+        latest_episode = latest_episode.max(Some(episode.published.with_timezone(&chrono::Utc)));
+        debug!("{}: {}", episode.title, title_filter.exclusion(episode).unwrap_or_default());
+        catalog.saw(episode, &ctx.naming, &episodes_dir);
+    }
+
+    let published: Vec<_> = parsed.iter().map(|episode| episode.published).collect();
+    let exclusions = window.exclusions(&published, now);
+    let excluded = exclusions.iter().filter(|exclusion| exclusion.is_some()).count();
+    if excluded > 0 {
+        info!("{} episodes outside --since, --until or --latest", excluded);
+    }
+
+    let mut jobs = Vec::new();
+    let mut outcomes = Vec::new();
+    for (episode, exclusion) in parsed.into_iter().zip(exclusions) {
+        latest_episode = latest_episode.max(Some(episode.published.with_timezone(&chrono::Utc)));
+        if let Some(reason) = exclusion {
+            debug!("{}: {}", episode.title, reason);
+            catalog.saw(&episode, &ctx.naming, &episodes_dir);
+            continue
+        }
+        let mut job = pipeline::EpisodeJob::new(episode);
+
+        #[cfg(feature = "wasm")]
+        if !wasm_host.apply(&rss_url, &mut job.episode) {
+            catalog.saw(&job.episode, &ctx.naming, &episodes_dir);
+            job.advance(pipeline::EpisodeState::Skipped("dropped by WASM filter".to_string()));
+            outcomes.push(job);
+            continue
+        }
+
+        if !plugins.item_parsed(&mut job.episode).await {
+            catalog.saw(&job.episode, &ctx.naming, &episodes_dir);
+            job.advance(pipeline::EpisodeState::Skipped("dropped by plugin".to_string()));
+            outcomes.push(job);
+            continue
+        }
+
+        catalog.saw(&job.episode, &ctx.naming, &episodes_dir);
+
+        if let (Some(shard), Some(checkpoint)) = (&args.shard, &ctx.checkpoint) {
+            let name = ctx.naming.file_name(&job.episode);
+            if !shard.owns(&name) {
+                other_shards += 1;
+                continue
+            }
+            if checkpoint.contains(&name) {
+                checkpointed += 1;
+                continue
+            }
+        }
+
+        job.advance(pipeline::EpisodeState::Filtered);
+        jobs.push(job);
+    }
+
+    poll_state.polled(&rss_xml, latest_episode, now);
+    if !cut_short {
+        poll_state.last_full_scan = Some(now);
+    }
+    raise_alerts(&config.notify, &http_client, &mut poll_state, &rss_url, || show_name(&args, &layout, &rss_url, Some(&rss_xml)), &catalog).await;
+    if let Err(e) = poll_state.save() {
+        error!("Failed to save polling state. Error: {}", e);
+    }
+
+    if let Some(shard) = &args.shard {
+        info!("Shard {}: {} episodes already done, {} to check, {} belong to other shards",
+            shard, checkpointed, jobs.len(), other_shards);
+    }
+
+    if let Some(eta) = &ctx.eta {
+        eta.plan(jobs.iter().map(|job| &job.episode).filter(|episode| !pipeline::is_archived(&ctx, episode)));
+    }
+    #[cfg(feature = "progress")]
+    if let Some(progress) = &ctx.progress {
+        progress.plan(jobs.iter().map(|job| &job.episode).filter(|episode| !pipeline::is_archived(&ctx, episode)));
+    }
+    #[cfg(feature = "mqtt")]
+    let queued = jobs.iter().filter(|job| !pipeline::is_archived(&ctx, &job.episode)).count();
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &args.mqtt {
+        mqtt.queue(queued, 0).await;
+    }
+
+    let episode_dirs = ctx.episode_dirs.clone();
+    let naming = ctx.naming.clone();
+    outcomes.extend(run_jobs(&args, ctx, jobs).await);
+
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &args.mqtt {
+        mqtt.queue(0, queued).await;
+        // Oldest first, so the latest episode ends up the newest.
+        let mut new: Vec<&Episode> = outcomes.iter().filter(|job| job.state == pipeline::EpisodeState::Stored).map(|job| &job.episode).collect();
+        new.sort_by_key(|episode| episode.published);
+        for episode in new {
+            mqtt.new_episode(episode, &rss_url).await;
+        }
+    }
+
+    // Where the archive serves an episode, if it does.
+    let local_url = |episode: &Episode| args.mirror_url.as_deref().zip(naming.existing_file(&layout.episodes_dir(), episode))
+        .and_then(|(base, path)| Some(format!("{}/episodes/{}", base.trim_end_matches('/'), path.strip_prefix(layout.episodes_dir()).ok()?.to_string_lossy())));
+    if !config.notify.is_empty() {
+        let mut new: Vec<notify::NewEpisode> = outcomes.iter()
+            .filter(|job| job.state == pipeline::EpisodeState::Stored)
+            .map(|job| notify::NewEpisode { episode: &job.episode, local_url: local_url(&job.episode) })
+            .collect();
+        new.sort_by_key(|new| new.episode.published);
+        notify::send(&http_client, &config.notify, &rss_url, &new).await;
+    }
+    if let Some(digest) = &args.digest {
+        digest.add(outcomes.iter()
+            .filter(|job| job.state == pipeline::EpisodeState::Stored)
+            .map(|job| digest::Entry::new(&job.episode, local_url(&job.episode), digest.excerpt())));
+    }
+
+    if args.soundbites {
+        // Every archived episode, so enabling this catches up on older ones.
+        for job in outcomes.iter().filter(|job| !job.episode.soundbites.is_empty()) {
+            for layout in &layouts {
+                let Some(episode_path) = naming.existing_file(&layout.episodes_dir(), &job.episode) else {
+                    continue
+                };
+                if let Err(e) = soundbites::cut(&args.ffmpeg, &job.episode, &episode_path, &layout.soundbites_dir(), &permissions).await {
+                    error!("{}: could not cut soundbites. Error: {}", job.episode.title, e);
+                }
+            }
+        }
+    }
+
+    // Items past where an incremental scan stopped aren't gone from the feed.
+    if !cut_short {
+        for name in catalog.mark_removed() {
+            warn!("{} is no longer in the feed", name);
+        }
+    }
+    #[cfg(feature = "artwork")]
+    if args.artwork {
+        if let Err(e) = artwork::update(&client, &rss_xml, &session.config.artwork, &layouts, &permissions).await {
+            error!("Failed to update artwork. Error: {}", e);
+        }
+    }
+
+    if let Err(e) = catalog.save() {
+        error!("Failed to save episode catalog. Error: {}", e);
+    }
+    if let Some(db) = &episodes_db {
+        for layout in &layouts {
+            if let Err(e) = verify::write_manifest(layout, db, &permissions) {
+                error!("Failed to write {}. Error: {}", layout.state_dir().join(verify::MANIFEST_FILE).display(), e);
+            }
+        }
+    }
+    if args.calendar {
+        let show = channel::Channel::parse(&rss_xml).title.unwrap_or_else(|| rss_url.clone());
+        for layout in &layouts {
+            let ics = calendar::render(&show, &rss_url, &catalog, &layout.episodes_dir());
+            let path = layout.feed_dir().join(calendar::CALENDAR_FILE);
+            let tmp_path = path.with_extension("tmp");
+            let written = std::fs::write(&tmp_path, ics)
+                .and_then(|()| std::fs::rename(&tmp_path, &path))
+                .and_then(|()| permissions.apply_file(&path));
+            if let Err(e) = written {
+                error!("Failed to write {}. Error: {}", path.display(), e);
+            }
+        }
+    }
+    // The archive feed keeps the original's syntax, which only works for XML.
+    for layout in layouts.iter().filter(|_| !cut_short && format == FeedFormat::Rss) {
+        match catalog.regenerate(&rss_xml, &layout.episodes_dir(), args.mirror_url.as_deref(), args.mirror_feed) {
+            Some(xml) => write_rss_snapshots(&xml, &[layout.feed_dir().join("archive.xml")], &permissions),
+            None => warn!("Feed has no </channel> or </feed>; not writing {}", layout.feed_dir().join("archive.xml").display())
+        }
+    }
+
+    if let Some(mut state) = live_state {
+        while let Some(recorded) = live_recordings.join_next().await {
+            for (item, name) in recorded? {
+                state.add(&item, &name);
+            }
+        }
+        state.replace_finished(&outcomes, &episode_dirs);
+        if let Err(e) = state.save() {
+            error!("Failed to save live recordings. Error: {}", e);
+        }
+    }
+    while let Some(result) = background.join_next().await {
+        if let Err(e) = result {
+            error!("Background task failed. Error: {}", e);
+        }
+    }
+    if args.save_cover {
+        save_cover(&client, &rss_xml, &layouts, &permissions).await;
+    }
+    // After the snapshots, which the library's episode list is read from.
+    if let Some(library) = args.library() {
+        match library.update(&client, &layout, &naming, &rss_xml, &permissions).await {
+            Ok(Some(show_dir)) => if let Err(e) = library.scan_show(&http_client, &show_dir).await {
+                error!("Failed to start a library scan. Error: {}", e);
+            },
+            Ok(None) => (),
+            Err(e) => error!("Failed to update the {:?} library. Error: {}", library.server, e)
+        }
+    }
+
+    let mut summary = pipeline::RunSummary { skipped: skipped.len(), malformed, next_live, ..Default::default() };
+    summary.count(&outcomes);
+    // Only what this run went for, so each is flagged once rather than every run.
+    for job in outcomes.iter().filter(|job| matches!(job.state, pipeline::EpisodeState::Stored | pipeline::EpisodeState::Failed(_))) {
+        if let Some(found) = anomalies.get(&job.episode.url) {
+            summary.anomalies.push((element_text(&job.episode.title), found.join("; ")));
+        }
+    }
+    // Only a feed archived without failures, and without being stopped
+    // early, may be skipped next time it is unchanged; otherwise the failed
+    // or unstarted episodes wouldn't be retried.
+    (poll_state.etag, poll_state.last_modified) = match summary.failed == 0 && !args.shutdown.is_requested() {
+        true => validators,
+        false => (None, None)
+    };
+    poll_state.selection = selection;
+    if let Err(e) = poll_state.save() {
+        error!("Failed to save polling state. Error: {}", e);
+    }
+
+    Ok(summary)
+}
+
+/// Record that fetching the feed at `rss_url` failed with `e`, alerting
+/// once it keeps failing, and return the error.
+async fn feed_failed(args: &Args, notify: &[notify::NotifyConfig], client: &reqwest::Client, poll_state: &mut polling::PollState, layout: &layout::FeedLayout, rss_url: &str, e: Box<dyn std::error::Error>) -> Box<dyn std::error::Error> {
+    poll_state.fetch_failed(&e.to_string());
+    match catalog::Catalog::open(&layout.state_dir()) {
+        Ok(catalog) => raise_alerts(notify, client, poll_state, rss_url, || show_name(args, layout, rss_url, None), &catalog).await,
+        Err(e) => error!("Failed to open episode catalog. Error: {}", e)
+    }
+    if let Err(e) = poll_state.save() {
+        error!("Failed to save polling state. Error: {}", e);
+    }
+    e
+}
+
+/// Send the missed-episode alert for the feed at `rss_url` of show
+/// `show()`, if one is due; see `alerts`.
+async fn raise_alerts(notify: &[notify::NotifyConfig], client: &reqwest::Client, poll_state: &mut polling::PollState, rss_url: &str, show: impl FnOnce() -> String, catalog: &catalog::Catalog) {
+    let published = catalog.entries().map(|(_, entry)| entry.published.with_timezone(&chrono::Utc));
+    alerts::raise(client, notify, poll_state, show, rss_url, published).await;
+}
+
+/// The name of the show archived in `layout` from `rss_url`: from `rss_xml`
+/// if this run fetched it, else from the last snapshot.
+fn show_name(args: &Args, layout: &layout::FeedLayout, rss_url: &str, rss_xml: Option<&str>) -> String {
+    rss_xml.map(str::to_string)
+        .or_else(|| std::fs::read_to_string(layout.feed_dir().join("rss.xml")).ok())
+        .and_then(|rss_xml| channel::Channel::parse(&rss_xml).title)
+        .or_else(|| args.show_dir.clone())
+        .unwrap_or_else(|| rss_url.to_string())
+}
+
+fn http_client(low_memory: bool) -> Result<reqwest::Client, reqwest::Error> {
+    let mut builder = reqwest::Client::builder();
+
+    #[cfg(feature = "rustls")]
+    {
+        builder = builder.use_rustls_tls();
+    }
+
+    if low_memory {
+        builder = builder.pool_max_idle_per_host(1);
+    }
+
+    builder.build()
+}
+
+/// The body of a feed response and the format to parse it as, checked
+/// against the feed limits and for being a feed at all.
+async fn read_feed(args: &Args, resp: reqwest::Response) -> Result<(String, FeedFormat), Box<dyn std::error::Error>> {
+    let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let url = resp.url().clone();
+    let limits = args.feed_limits();
+    let body = limits.read(resp).await?;
+    match limits::check_content(content_type.as_deref(), &body) {
+        Ok(()) => (),
+        Err(limits::ContentError::Html) => {
+            let hint = match discover::find_feed_link(&body).and_then(|href| url.join(&href).ok()) {
+                Some(feed_url) => format!("did you mean its feed link, {}?", feed_url),
+                None => "did you mean the feed link? The page doesn't link to one; look for an RSS link on the show's site".to_string()
+            };
+            return Err(format!("{} returned HTML, not a feed; {}", url, hint).into())
+        },
+        Err(e) => return Err(e.into())
+    }
+    let format = match args.format {
+        Format::Auto => FeedFormat::detect(content_type.as_deref(), &body),
+        Format::Rss => FeedFormat::Rss,
+        Format::Json => FeedFormat::Json,
+    };
+    limits.check(&body, format)?;
+    Ok((body, format))
+}
+
+fn write_rss_snapshots(rss_xml: &str, output_paths: &[PathBuf], permissions: &disk::Permissions) {
+    for output_path in output_paths {
         info!("RSS --> {}", &output_path.to_str().unwrap());
         let rss_file = File::options()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(output_path);
         let mut rss_file = match rss_file {
             Ok(file) => file,
             Err(e) => {
                 error!("Got I/O error: {}", e);
-                return
+                continue
             }
         };
 
-        if let Err(e) = rss_file.write(rss_xml_clone.as_bytes()) {
-            error!("Failed to write RSS XML")
+        if let Err(e) = rss_file.write_all(rss_xml.as_bytes()) {
+            error!("Failed to write RSS XML. Error: {}", e)
         }
-    });
+        if let Err(e) = permissions.apply_file(output_path) {
+            error!("Failed to set permissions on {}. Error: {}", output_path.display(), e)
+        }
+    }
+}
 
-    let episodes = parse_rss(&rss_xml).unwrap();
-
-    info!("Downloading {} episodes with {} tasks", episodes.len(), args.task_count);
-    let client = reqwest::Client::new();
-    let bodies = stream::iter(episodes)
-        .map(|episode| {
-            let client = client.clone();
-            let episode_clone = episode.clone();
-            let output_dir_clone = args.output_dir.clone();
-            tokio::spawn(async move {
-                let (_, name_with_true_ext) = episode_to_filename(&episode);
-                let output_path_true = Path::new(&output_dir_clone).join(name_with_true_ext.clone());
-                if (&args.replace_existing).clone() || !output_path_true.exists() {
-                    info!("Downloading {}", &episode_clone.title);
-                    let resp = client.get(episode.url).send().await?;
-                    let data = match resp.bytes().await {
-                        Ok(data) => data,
-                        Err(e) => return Err(e)
-                    };
-                    return Ok((episode_clone, data))
-                }
-                
-                info!("Skipping {}; {}/{} exists", &episode.title, &output_dir_clone, &name_with_true_ext);
-                let empty: Bytes = Bytes::new();
-                Ok((episode_clone, empty))
-            })
-        })
-        .buffer_unordered(args.task_count);
-
-    bodies
-        .for_each(|b| async {
-            match b {
-                Ok(Ok(b)) => {
-                    let episode = b.0;
-                    let data = b.1;
-                    debug!("Got {} bytes", data.len());
-                    
-                    if data.len() == 0 {
-                        return
-                    }
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+fn uring_available() -> bool {
+    let available = uring::available();
+    if !available {
+        log::warn!("io_uring is not available on this system; using regular file writes");
+    }
+    available
+}
 
-                    let (name_with_part_ext, name_with_true_ext) = episode_to_filename(&episode);
-
-                    let output_path_tmp = Path::new(&args.output_dir).join(name_with_part_ext);
-                    let output_path_true = Path::new(&args.output_dir).join(name_with_true_ext.clone());
-
-                    if (&args.replace_existing).clone() || !output_path_true.exists() {
-                        info!("{} --> {}/{}", &episode.title, &args.output_dir, &name_with_true_ext);
-                        let file = File::options()
-                            .write(true)
-                            .create(true)
-                            .open(&output_path_tmp);
-                        let mut file = match file {
-                            Ok(file) => file,
-                            Err(e) => {
-                                error!("Got I/O error: {}", e);
-                                return
-                            }
-                        };
-
-                        if let Err(e) = file.write(&data) {
-                            error!("Failed to write to {}. Error: {}", &output_path_tmp.to_str().unwrap(), e);
-                        }
-
-                        if let Err(e) = fs::rename(&output_path_tmp, output_path_true) {
-                            error!("Failed to move to {}. Error: {}", &output_path_tmp.to_str().unwrap(), e);
-                        }
-                    }
-                    else {
-                        info!("Skipping {}; {}/{} exists", &episode.title, &args.output_dir, &name_with_true_ext);
-                    }
-                },
-                Ok(Err(e)) => error!("Got a reqwest::Error: {}", e),
-                Err(e) => error!("Got a tokio::JoinError: {}", e),
-            }
-        })
-        .await;
+/// Parse a rate in bytes per second, a size as for [`parse_size`] that may
+/// be spelled `KiB`, `MB` and so on and end in `/s`, e.g. `2MiB/s`.
+fn parse_rate(text: &str) -> Result<u64, String> {
+    let size = text.trim();
+    let size = strip_suffix_ignore_case(size, "/s").unwrap_or(size);
+    let size = strip_suffix_ignore_case(size, "ib").or_else(|| strip_suffix_ignore_case(size, "b")).unwrap_or(size);
+    parse_size(size).map(|rate| rate as u64).map_err(|e| format!("{} (e.g. 2MiB/s)", e))
+}
 
-    Ok(())
+fn strip_suffix_ignore_case<'a>(text: &'a str, suffix: &str) -> Option<&'a str> {
+    let at = text.len().checked_sub(suffix.len())?;
+    (text.is_char_boundary(at) && text[at..].eq_ignore_ascii_case(suffix)).then(|| &text[..at])
 }
 
-fn episode_to_filename(episode: &Episode) -> (String, String) {
-    let name = episode.title
-        .replace(" ", "_")
-        .replace(":", "-")
-        .replace("/", "-")
-        .replace("\"", "")
-        .replace("\'", "")
-        .replace("*", "a");
+/// Parse a byte count with an optional K, M or G suffix (powers of 1024).
+fn parse_size(text: &str) -> Result<usize, String> {
+    let text = text.trim();
+    let (digits, multiplier) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&text[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&text[..i], 1 << 30),
+        _ => (text, 1)
+    };
 
-    let name_with_part_ext = episode.datetime.timestamp().to_string() + "-" + &name.clone() + ".part";
-    let name_with_true_ext = episode.datetime.timestamp().to_string() + "-" + &name.clone() + ".mp3";
+    match digits.parse::<usize>() {
+        Ok(0) => Err("size must be greater than zero".to_string()),
+        Ok(n) => n.checked_mul(multiplier).ok_or_else(|| format!("{} is too large", text)),
+        Err(e) => Err(format!("invalid size {:?}: {}", text, e))
+    }
+}
+
+/// A duration like `90s`, `30m`, `12h`, `7d` or `2w`.
+fn parse_age(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let (digits, unit) = match text.char_indices().last() {
+        Some((i, 's')) => (&text[..i], 1),
+        Some((i, 'm')) => (&text[..i], 60),
+        Some((i, 'h')) => (&text[..i], 60 * 60),
+        Some((i, 'd')) => (&text[..i], 24 * 60 * 60),
+        Some((i, 'w')) => (&text[..i], 7 * 24 * 60 * 60),
+        _ => return Err(format!("expected a number and a unit (s, m, h, d or w), got {:?}", text))
+    };
+
+    match digits.parse::<u64>() {
+        Ok(n) => n.checked_mul(unit).map(Duration::from_secs).ok_or_else(|| format!("{} is too long", text)),
+        Err(e) => Err(format!("invalid duration {:?}: {}", text, e))
+    }
+}
+
+/// Shortest `--interval`, so a typo can't have poarder poll a feed back to back.
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
 
-    (name_with_part_ext, name_with_true_ext)
+/// Parse `--interval`: seconds, or a number and a unit as with [`parse_age`].
+fn parse_interval(text: &str) -> Result<Duration, String> {
+    let interval = match text.trim().parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => parse_age(text)?
+    };
+    match interval < MIN_INTERVAL {
+        true => Err(format!("interval must be at least {}s", MIN_INTERVAL.as_secs())),
+        false => Ok(interval)
+    }
 }
 
-fn init_logging(use_syslog: bool) {
+fn init_logging(args: &Args) {
     let mut log_builder = env_logger::Builder::from_env(
         env_logger::Env::default().default_filter_or("info"));
 
-    if use_syslog {
+    if args.syslog {
         log_builder.format(|buffer, record| {
             writeln!(buffer, "<{}>{}", record.level() as u8 + 2 , record.args())
         });
     }
+    #[cfg(feature = "progress")]
+    if let Some(progress) = &args.progress {
+        let logger = log_builder.build();
+        log::set_max_level(logger.filter());
+        let logger = poarder_core::progress::Logger { inner: logger, progress: progress.clone() };
+        log::set_boxed_logger(Box::new(logger)).expect("logging is initialized once");
+        return
+    }
     log_builder.init();
 }
 
-fn parse_rss(rss_xml: &str) -> Result<LinkedList<Episode>, Box<dyn Error>> {
-    let mut reader = Reader::from_str(rss_xml);
-    reader.trim_text(true);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut list_of_events = LinkedList::new();
-
-    loop {
-        match reader.read_event() {
-            Ok(Event::Start(e)) if e.name().as_ref() == b"item" => {
-                let txt = reader
-                    .read_text(e.name())
-                    .expect("Cannot decode text value");
-                if let Ok(episode) = parse_item(txt.as_ref()) {
-                    list_of_events.push_back(episode);
-                }
-                else {
-                    error!("Could not parse episode");
-                }
-                
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => {
-                error!("Error at position {}: {:?}", reader.buffer_position(), e);
-                return Err(Box::new(e))
-            },
-            _ => ()
+    #[test]
+    fn rate_suffixes_in_any_case() {
+        for text in ["2M", "2MiB/s", "2mib/s", "2MB/s", "2mb/s", "2m/S", " 2MiB "] {
+            assert_eq!(parse_rate(text), Ok(2 << 20), "{}", text);
         }
+        assert_eq!(parse_rate("512b/s"), Ok(512));
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("0/s").is_err());
     }
 
-    Ok(list_of_events)
-}
-
-fn parse_item(item_xml: &str) -> Result<Episode, Box<dyn Error>> {
-    let mut reader = Reader::from_str(item_xml);
-    let mut title: Option<String> = None;
-    let mut datetime: Option<NaiveDateTime> = None;
-    let mut url: Option<String> = None;
-
-    reader.expand_empty_elements(true);
-    
-    loop {
-        match reader.read_event() {
-            Ok(Event::Start(element)) => {
-                // info!("At element, {}", std::str::from_utf8(element.name().as_ref()).unwrap());
-                if element.name().as_ref() == b"title" {
-                    let txt = match reader.read_text(element.name()) {
-                        Ok(txt) => txt,
-                        Err(e) => return Err(Box::new(e))
-                    };
-
-                    title = Some(txt.to_string());
-                }
-                else if element.name().as_ref() == b"pubDate" {
-                    let txt = match reader.read_text(element.name()) {
-                        Ok(txt) => txt,
-                        Err(e) => return Err(Box::new(e))
-                    };
-
-                    datetime = match parse_date_time(txt.as_ref()) {
-                        Ok(datetime) => Some(datetime.naive_local()),
-                        Err(e) => return Err(Box::new(e))
-                    }
-                }
-                else if element.name().as_ref() == b"enclosure" {
-                    for attr_result in element.attributes() {
-                        let attr = attr_result?;
-                        match attr.key.as_ref() {
-                            b"url" => url = Some(attr.decode_and_unescape_value(&reader)?.to_string()),
-                            _ => (),
-                        }
-                    }
-                }
-            }
-            Ok(Event::Eof) => break,
-            Err(e) => {
-                error!("Error at position {}: {:?}", reader.buffer_position(), e);
-                return Err(Box::new(e))
-            },
-            _ => ()
-        }
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("10"), Ok(10));
+        assert_eq!(parse_size("4k"), Ok(4 << 10));
+        assert_eq!(parse_size("64M"), Ok(64 << 20));
+        assert_eq!(parse_size("1g"), Ok(1 << 30));
+        assert!(parse_size("0").is_err());
+        assert!(parse_size("").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1K").is_err());
+        assert!(parse_size(&format!("{}G", usize::MAX)).is_err());
     }
 
-    if url.is_none() || title.is_none() || datetime.is_none() {
-        error!("Missing episode properties");
-        return Err(Box::new(RssFormatError{ text: item_xml.to_string() }))
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_age("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_age("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_age("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_age("1w"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert!(parse_age("10").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("1y").is_err());
+        assert!(parse_age(&format!("{}w", u64::MAX)).is_err());
     }
 
-    Ok(Episode{url: url.unwrap().clone(), title: title.unwrap().clone(), datetime: datetime.unwrap().clone(), raw: item_xml.to_string()})
-}
-
-fn parse_date_time(datetime_str: &str) -> Result<DateTime<FixedOffset>, ParseError> {
-    DateTime::parse_from_rfc2822(&datetime_str)
+    #[test]
+    fn intervals_default_to_seconds() {
+        assert_eq!(parse_interval("3600"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_interval("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_interval("10"), Ok(MIN_INTERVAL));
+        assert!(parse_interval("soon").is_err());
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("0s").is_err());
+        assert!(parse_interval("9").is_err());
+    }
 }
@@ -0,0 +1,120 @@
+use crate::catalog::{self, Catalog};
+use crate::db::EpisodeDb;
+use crate::layout::FeedLayout;
+use crate::sidecar;
+use log::info;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Version of the on-disk archive/state format written by this build.
+pub const CURRENT_VERSION: u32 = 2;
+
+type Migration = fn(&FeedLayout) -> Result<(), Box<dyn Error>>;
+
+/// Migrations indexed by the version they upgrade *from*; entry `n` moves an
+/// archive from version `n` to `n + 1`. Append new entries and bump
+/// `CURRENT_VERSION` whenever the format changes.
+///
+/// Changes that older archives are read as they are don't need one: fields
+/// added to `catalog.json`, `polling.json` and the sidecars are optional,
+/// and catalog entries follow an episode to its new name (`.mp3` for every
+/// episode before files were named after their media type, or an earlier
+/// `--name-template`) the next time it is seen. `poarder migrate` renames
+/// the files themselves, on request.
+const MIGRATIONS: [Migration; CURRENT_VERSION as usize] = [
+    migrate_flat_layout,
+    fill_episode_db,
+];
+
+#[derive(Debug)]
+pub struct StateVersionError {
+    found: u32,
+}
+
+impl Error for StateVersionError {}
+
+impl fmt::Display for StateVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Archive state is version {} but this poarder only understands up to version {}; please upgrade",
+            self.found, CURRENT_VERSION)
+    }
+}
+
+/// Bring the archive at `layout` up to `CURRENT_VERSION`, recording each
+/// completed step so an interrupted upgrade resumes where it left off.
+pub fn run(layout: &FeedLayout) -> Result<(), Box<dyn Error>> {
+    let mut version = read_version(layout)?;
+
+    if version > CURRENT_VERSION {
+        return Err(Box::new(StateVersionError { found: version }))
+    }
+
+    while version < CURRENT_VERSION {
+        info!("Migrating archive state from version {} to {}", version, version + 1);
+        MIGRATIONS[version as usize](layout)?;
+        version += 1;
+        write_version(layout, version)?;
+    }
+
+    Ok(())
+}
+
+fn version_path(layout: &FeedLayout) -> PathBuf {
+    layout.state_dir().join("VERSION")
+}
+
+/// Archives without a version file predate versioning and are version 0.
+pub fn read_version(layout: &FeedLayout) -> Result<u32, Box<dyn Error>> {
+    match fs::read_to_string(version_path(layout)) {
+        Ok(text) => Ok(text.trim().parse()?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(Box::new(e))
+    }
+}
+
+fn write_version(layout: &FeedLayout, version: u32) -> Result<(), Box<dyn Error>> {
+    let path = version_path(layout);
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, format!("{}\n", version))?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+/// 0 -> 1: move everything out of the original flat output directory.
+fn migrate_flat_layout(layout: &FeedLayout) -> Result<(), Box<dyn Error>> {
+    let moved = layout.migrate_flat()?;
+    if moved > 0 {
+        info!("Migrated {} files from the flat layout in {}", moved, layout.root().display());
+    }
+    Ok(())
+}
+
+/// 1 -> 2: record the episodes already archived in `state/episodes.db`,
+/// which decides what is archived from then on, with the checksums their
+/// sidecars have.
+fn fill_episode_db(layout: &FeedLayout) -> Result<(), Box<dyn Error>> {
+    let state_dir = layout.state_dir();
+    let catalog = Catalog::open(&state_dir)?;
+    let db = EpisodeDb::open(&state_dir)?;
+    let episodes_dir = layout.episodes_dir();
+    let mut recorded = 0;
+    for (name, entry) in catalog.entries() {
+        let Some(file) = catalog::archived_file(&episodes_dir, name) else {
+            continue
+        };
+        let path = episodes_dir.join(&file);
+        let size = fs::metadata(&path)?.len();
+        let sha256 = fs::read_to_string(sidecar::path_for(&path)).ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok())
+            .and_then(|sidecar| Some(sidecar.get("sha256")?.as_str()?.to_string()));
+        if db.adopt(entry.guid.as_deref().unwrap_or(&entry.url), &entry.url, &file, size, sha256.as_deref())? {
+            recorded += 1;
+        }
+    }
+    if recorded > 0 {
+        info!("Recorded {} archived episode(s) in the episode database in {}", recorded, state_dir.display());
+    }
+    Ok(())
+}
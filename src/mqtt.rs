@@ -0,0 +1,211 @@
+//! `--mqtt`: publish what poarder is doing to an MQTT broker: each new
+//! episode, how many episodes are waiting to download and how the last run
+//! went. Home Assistant discovery messages make these show up there as a
+//! "poarder" device, with sensors and a new-episode event for automations.
+
+use chrono::{SecondsFormat, Utc};
+use log::{info, warn};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde_json::json;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::pipeline::RunSummary;
+use crate::Episode;
+
+/// A connection to the broker of `--mqtt`, kept for the whole process.
+pub struct Mqtt {
+    client: AsyncClient,
+    /// Prefix of poarder's own topics.
+    topic: String,
+    /// Episodes waiting to download, across every feed being archived.
+    queue: Mutex<usize>,
+    event_loop: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl std::fmt::Debug for Mqtt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Mqtt").field("topic", &self.topic).finish()
+    }
+}
+
+impl Mqtt {
+    /// Connect to the broker at `url` (`mqtt://[user:password@]host[:port]`)
+    /// in the background, announcing the entities under `discovery_prefix`
+    /// each time the connection is made.
+    pub fn connect(url: &str, topic: &str, discovery_prefix: &str) -> Result<Arc<Mqtt>, Box<dyn Error>> {
+        let url = reqwest::Url::parse(url).map_err(|e| format!("invalid --mqtt URL {}: {}", url, e))?;
+        if url.scheme() != "mqtt" {
+            return Err(format!("--mqtt takes an mqtt:// URL, got {}", url).into())
+        }
+        let Some(host) = url.host_str() else {
+            return Err(format!("--mqtt URL {} has no host", url).into())
+        };
+        let topic = topic.trim_end_matches('/').to_string();
+        let node = node_id(&topic);
+        let mut options = MqttOptions::new(format!("{}-{}", node, std::process::id()), host, url.port().unwrap_or(1883));
+        options.set_keep_alive(Duration::from_secs(30));
+        if !url.username().is_empty() {
+            options.set_credentials(url.username(), url.password().unwrap_or_default());
+        }
+
+        let (client, mut event_loop) = AsyncClient::new(options, 64);
+        let discovery = discovery(&topic, &node, discovery_prefix.trim_end_matches('/'));
+        let announcer = client.clone();
+        let host = host.to_string();
+        let event_loop = tokio::spawn(async move {
+            let mut failures = 0;
+            loop {
+                match event_loop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to MQTT broker {}", host);
+                        failures = 0;
+                        for (topic, payload) in &discovery {
+                            if let Err(e) = announcer.try_publish(topic, QoS::AtLeastOnce, true, payload.to_string()) {
+                                warn!("Failed to announce {} to Home Assistant. Error: {}", topic, e);
+                            }
+                        }
+                    },
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => return,
+                    Ok(_) => (),
+                    Err(e) => {
+                        // Only the first of a row of failures; the client keeps retrying.
+                        if failures == 0 {
+                            warn!("No connection to MQTT broker {}; retrying. Error: {}", host, e);
+                        }
+                        failures += 1;
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(Arc::new(Mqtt { client, topic, queue: Mutex::new(0), event_loop: Mutex::new(Some(event_loop)) }))
+    }
+
+    async fn publish(&self, subtopic: &str, retain: bool, payload: String) {
+        let topic = format!("{}/{}", self.topic, subtopic);
+        if let Err(e) = self.client.publish(&topic, QoS::AtLeastOnce, retain, payload).await {
+            warn!("Failed to publish to MQTT topic {}. Error: {}", topic, e);
+        }
+    }
+
+    pub async fn run_started(&self) {
+        self.publish("status", true, "running".to_string()).await;
+    }
+
+    /// Report how a run went; `summary` is `None` when it ended in an error.
+    pub async fn run_finished(&self, summary: Option<&RunSummary>) {
+        let status = match summary {
+            Some(summary) if summary.failed == 0 => "ok",
+            _ => "failed"
+        };
+        self.publish("status", true, status.to_string()).await;
+        let last_run = json!({
+            "finished": Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            "status": status,
+            "stored": summary.map(|summary| summary.stored),
+            "skipped": summary.map(|summary| summary.skipped),
+            "failed": summary.map(|summary| summary.failed),
+            "bytes": summary.map(|summary| summary.bytes),
+        });
+        self.publish("last_run", true, last_run.to_string()).await;
+    }
+
+    /// Add `added` episodes to the download queue, and take `done` off it.
+    pub async fn queue(&self, added: usize, done: usize) {
+        let queue = {
+            let mut queue = self.queue.lock().unwrap();
+            *queue = (*queue + added).saturating_sub(done);
+            *queue
+        };
+        self.publish("queue", true, queue.to_string()).await;
+    }
+
+    /// Announce a newly archived episode of `feed_url`.
+    pub async fn new_episode(&self, episode: &Episode, feed_url: &str) {
+        let payload = json!({
+            "event_type": "new_episode",
+            "title": crate::element_text(&episode.title),
+            "show": episode.show.as_deref().map(crate::element_text),
+            "published": episode.published.to_rfc3339(),
+            "feed": feed_url,
+            "url": episode.url,
+        }).to_string();
+        self.publish("episode", false, payload.clone()).await;
+        self.publish("last_episode", true, payload).await;
+    }
+
+    /// Send what is still queued and disconnect, giving up after a few
+    /// seconds if the broker can't be reached.
+    pub async fn finish(&self) {
+        let Some(event_loop) = self.event_loop.lock().unwrap().take() else {
+            return
+        };
+        if self.client.disconnect().await.is_err() {
+            return
+        }
+        let abort = event_loop.abort_handle();
+        if tokio::time::timeout(Duration::from_secs(5), event_loop).await.is_err() {
+            warn!("Gave up sending to the MQTT broker");
+            abort.abort();
+        }
+    }
+}
+
+/// `topic` as the ID Home Assistant knows the device by.
+fn node_id(topic: &str) -> String {
+    topic.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+/// Home Assistant discovery messages, by topic, for the entities fed by the
+/// topics under `topic`.
+fn discovery(topic: &str, node: &str, prefix: &str) -> Vec<(String, serde_json::Value)> {
+    let device = json!({
+        "identifiers": [node],
+        "name": "poarder",
+        "model": "Podcast archiver",
+        "sw_version": env!("CARGO_PKG_VERSION"),
+    });
+    let entity = |component: &str, object: &str, mut config: serde_json::Value| {
+        config["unique_id"] = json!(format!("{}_{}", node, object));
+        config["object_id"] = json!(format!("{}_{}", node, object));
+        config["device"] = device.clone();
+        (format!("{}/{}/{}/{}/config", prefix, component, node, object), config)
+    };
+    vec![
+        entity("sensor", "status", json!({
+            "name": "Status",
+            "state_topic": format!("{}/status", topic),
+            "icon": "mdi:podcast",
+        })),
+        entity("sensor", "last_run", json!({
+            "name": "Last run",
+            "state_topic": format!("{}/last_run", topic),
+            "value_template": "{{ value_json.finished }}",
+            "json_attributes_topic": format!("{}/last_run", topic),
+            "device_class": "timestamp",
+        })),
+        entity("sensor", "queue", json!({
+            "name": "Queued episodes",
+            "state_topic": format!("{}/queue", topic),
+            "unit_of_measurement": "episodes",
+            "state_class": "measurement",
+            "icon": "mdi:download",
+        })),
+        entity("sensor", "last_episode", json!({
+            "name": "Latest episode",
+            "state_topic": format!("{}/last_episode", topic),
+            "value_template": "{{ value_json.title }}",
+            "json_attributes_topic": format!("{}/last_episode", topic),
+            "icon": "mdi:podcast",
+        })),
+        entity("event", "new_episode", json!({
+            "name": "New episode",
+            "state_topic": format!("{}/episode", topic),
+            "event_types": ["new_episode"],
+        })),
+    ]
+}
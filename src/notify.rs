@@ -0,0 +1,197 @@
+//! `[[notify]]`: post a message to a Discord or Slack webhook, or a Matrix
+//! room, for each new episode, and with `alerts` for missed episodes too.
+
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::json;
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Episode;
+
+/// Where a `[[notify]]` entry posts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Service {
+    Discord,
+    Slack,
+    Matrix,
+}
+
+/// A `[[notify]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct NotifyConfig {
+    pub service: Service,
+    /// Webhook URL; for Matrix, the homeserver's base URL.
+    pub url: String,
+    /// Matrix room ID, e.g. `!abcdef:example.org`.
+    #[serde(default)]
+    pub room: Option<String>,
+    /// Matrix access token.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Feed URLs to notify about; every feed when empty.
+    #[serde(default)]
+    pub feeds: Vec<String>,
+    /// The message, with placeholders (see [`PLACEHOLDERS`]).
+    #[serde(default = "default_template")]
+    pub template: String,
+    /// Also post missed-episode and failing-feed alerts; see `alerts`.
+    #[serde(default)]
+    pub alerts: bool,
+}
+
+fn default_template() -> String {
+    "New episode of {show}: {title}\n{url}".to_string()
+}
+
+pub const PLACEHOLDERS: &[&str] = &["show", "title", "date", "duration", "link", "url"];
+
+impl NotifyConfig {
+    /// Why this entry can't be used, if it can't.
+    pub fn problem(&self) -> Option<String> {
+        if self.service == Service::Matrix && (self.room.is_none() || self.token.is_none()) {
+            return Some("Matrix needs `room` and `token`".to_string())
+        }
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                break
+            };
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Some(format!("unknown placeholder {{{}}} in template", name))
+            }
+            rest = &rest[start + end + 1..];
+        }
+        None
+    }
+}
+
+/// An episode stored this run, and where the archive serves it, if it does.
+pub struct NewEpisode<'a> {
+    pub episode: &'a Episode,
+    pub local_url: Option<String>,
+}
+
+/// Post `episodes`, new in `feed_url`, to every `[[notify]]` entry for it,
+/// one message per episode.
+pub async fn send(client: &reqwest::Client, configs: &[NotifyConfig], feed_url: &str, episodes: &[NewEpisode<'_>]) {
+    let configs = configs.iter().filter(|config| config.feeds.is_empty() || config.feeds.iter().any(|feed| feed == feed_url));
+    for config in configs {
+        let mut sent = 0;
+        for new in episodes {
+            let message = render(&config.template, new);
+            match post(client, config, &message).await {
+                Ok(()) => sent += 1,
+                Err(e) => error!("{}: could not notify {:?}. Error: {}", new.episode.title, config.service, e)
+            }
+        }
+        if sent > 0 {
+            info!("Notified {:?} of {} new episode(s)", config.service, sent);
+        }
+    }
+}
+
+/// Post `message`, an alert about `feed_url`, to every `[[notify]]` entry
+/// for it with `alerts` on.
+pub async fn alert(client: &reqwest::Client, configs: &[NotifyConfig], feed_url: &str, message: &str) {
+    let configs = configs.iter()
+        .filter(|config| config.alerts)
+        .filter(|config| config.feeds.is_empty() || config.feeds.iter().any(|feed| feed == feed_url));
+    for config in configs {
+        if let Err(e) = post(client, config, message).await {
+            error!("Could not send an alert to {:?}. Error: {}", config.service, e);
+        }
+    }
+}
+
+fn render(template: &str, new: &NewEpisode) -> String {
+    let episode = new.episode;
+    let value = |name: &str| match name {
+        "show" => episode.show.as_deref().map(crate::element_text).unwrap_or_default(),
+        "title" => crate::element_text(&episode.title),
+        "date" => episode.published.format("%Y-%m-%d").to_string(),
+        "duration" => episode.duration.as_deref().and_then(duration).unwrap_or_default(),
+        "link" => episode.link.clone().unwrap_or_default(),
+        "url" => new.local_url.clone().unwrap_or_else(|| episode.url.clone()),
+        _ => String::new()
+    };
+    // One pass over the template, so braces in a title stay as they are.
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break
+        };
+        let name = &rest[start + 1..start + end];
+        message.push_str(&rest[..start]);
+        match PLACEHOLDERS.contains(&name) {
+            true => message.push_str(&value(name)),
+            false => message.push_str(&rest[start..=start + end])
+        }
+        rest = &rest[start + end + 1..];
+    }
+    message.push_str(rest);
+    message
+}
+
+/// `<itunes:duration>`, `HH:MM:SS`, `MM:SS` or seconds, as e.g. `1h 5m`.
+fn duration(raw: &str) -> Option<String> {
+    let seconds = raw.trim().split(':')
+        .try_fold(0u64, |total, part| Some(total * 60 + part.trim().parse::<f64>().ok()? as u64))?;
+    match seconds.div_ceil(60) {
+        0 => None,
+        minutes if minutes < 60 => Some(format!("{}m", minutes)),
+        minutes => Some(format!("{}h {}m", minutes / 60, minutes % 60))
+    }
+}
+
+async fn post(client: &reqwest::Client, config: &NotifyConfig, message: &str) -> Result<(), Box<dyn Error>> {
+    let (request, body) = match config.service {
+        // Discord refuses messages over 2000 characters.
+        Service::Discord => (client.post(&config.url), json!({ "content": message.chars().take(2000).collect::<String>() })),
+        Service::Slack => (client.post(&config.url), json!({ "text": slack_escape(message) })),
+        Service::Matrix => {
+            // Matrix deduplicates by transaction ID, which must be unique per message.
+            static SENT: AtomicU64 = AtomicU64::new(0);
+            let txn = format!("poarder-{}-{}", chrono::Utc::now().timestamp_millis(), SENT.fetch_add(1, Ordering::Relaxed));
+            let mut url = reqwest::Url::parse(&config.url)?;
+            url.path_segments_mut().map_err(|_| "Matrix url can't be a base URL")?
+                .pop_if_empty()
+                .extend(["_matrix", "client", "v3", "rooms", config.room.as_deref().unwrap_or_default(), "send", "m.room.message", &txn]);
+            let request = client.put(url).bearer_auth(config.token.as_deref().unwrap_or_default());
+            (request, json!({ "msgtype": "m.text", "body": message }))
+        }
+    };
+    request.header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body.to_string())
+        .send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// `text` with the characters Slack treats as markup escaped.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration("3723").as_deref(), Some("1h 3m"));
+        assert_eq!(duration("01:02:03").as_deref(), Some("1h 3m"));
+        assert_eq!(duration("45:00").as_deref(), Some("45m"));
+        assert_eq!(duration(" 59 ").as_deref(), Some("1m"));
+        assert_eq!(duration("3600").as_deref(), Some("1h 0m"));
+        assert_eq!(duration("90.5").as_deref(), Some("2m"));
+        assert_eq!(duration("0"), None);
+        assert_eq!(duration("00:00:00"), None);
+        assert_eq!(duration(""), None);
+        assert_eq!(duration("1:xx"), None);
+        assert_eq!(duration("an hour"), None);
+    }
+}
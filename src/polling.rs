@@ -0,0 +1,281 @@
+//! Whether a feed is polled at all: `poarder feed disable` turns it off,
+//! optionally only until a date, without touching its config or history.
+//! Shows marked `<itunes:complete>` or long silent are polled less often,
+//! and long-running modes honour the feed's `<ttl>`, `<skipHours>` and
+//! `<skipDays>`.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, SecondsFormat, Timelike, Utc, Weekday};
+use log::info;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::alerts;
+use crate::catalog::Catalog;
+use crate::layout::FeedLayout;
+
+/// Polling state of one archive, in `state/polling.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PollState {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    pub disabled: bool,
+    /// With `disabled`, polling resumes on its own after this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed_until: Option<DateTime<Utc>>,
+    /// The feed says `<itunes:complete>yes</itunes:complete>`.
+    #[serde(default)]
+    pub complete: bool,
+    /// Publication time of the newest episode seen.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latest_episode: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_polled: Option<DateTime<Utc>>,
+    /// Last time every item of the feed was read; see `scan`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_full_scan: Option<DateTime<Utc>>,
+    /// The feed's `<ttl>`: minutes it may be cached before checking again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_minutes: Option<i64>,
+    /// The feed's `<skipHours>`: hours (UTC, 0-23) not to poll in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_hours: Vec<u32>,
+    /// The feed's `<skipDays>`, e.g. `Saturday`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skip_days: Vec<String>,
+    /// `ETag` of the feed as of the last run that archived all of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` of the feed as of the last run that archived all of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// `--since`, `--until` and `--latest` of that run, if any; with others
+    /// the validators don't apply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<String>,
+    /// Fetches of the feed that failed since the last one that worked.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub failed_polls: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// The missed-episode alert last sent, so it isn't sent again; see `alerts`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alerted: Option<String>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Channel-level elements that say how often to poll.
+#[derive(Default)]
+struct Hints {
+    complete: bool,
+    ttl_minutes: Option<i64>,
+    skip_hours: Vec<u32>,
+    skip_days: Vec<String>,
+}
+
+/// How often a complete show is still checked, in case it is revived.
+const COMPLETE_INTERVAL: Duration = Duration::days(7);
+
+/// Without a new episode for this long a show is considered dormant...
+const DORMANT_AFTER: Duration = Duration::days(180);
+
+/// ...and checked this often.
+const DORMANT_INTERVAL: Duration = Duration::days(1);
+
+impl PollState {
+    pub fn open(state_dir: &Path) -> Result<PollState, Box<dyn Error>> {
+        let path = state_dir.join("polling.json");
+        let mut state: PollState = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => PollState::default(),
+            Err(e) => return Err(Box::new(e))
+        };
+        state.path = path;
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Why the feed shouldn't be polled at `now`, if it shouldn't. With
+    /// `feed_hints`, for polling between the runs of a long-running
+    /// poarder, that includes a complete or dormant show being checked less
+    /// often and the feed's `<ttl>`, `<skipHours>` and `<skipDays>`.
+    pub fn paused(&self, now: DateTime<Utc>, feed_hints: bool) -> Option<String> {
+        match (self.disabled, self.snoozed_until) {
+            (true, None) => return Some("disabled".to_string()),
+            (true, Some(until)) if until > now => return Some(format!("snoozed until {}", until.to_rfc3339_opts(SecondsFormat::Secs, true))),
+            _ => ()
+        }
+        if !feed_hints {
+            return None
+        }
+
+        if let Some((reason, interval)) = self.slowed(now) {
+            match self.last_polled.map(|last_polled| last_polled + interval) {
+                Some(next) if next > now => return Some(format!("{}; next check after {}", reason, next.to_rfc3339_opts(SecondsFormat::Secs, true))),
+                _ => ()
+            }
+        }
+        self.skipped(now)
+    }
+
+    /// Why the feed's own hints rule out polling at `now`, if they do.
+    fn skipped(&self, now: DateTime<Utc>) -> Option<String> {
+        if let (Some(ttl), Some(last_polled)) = (self.ttl_minutes, self.last_polled) {
+            let next = last_polled + Duration::minutes(ttl);
+            if next > now {
+                return Some(format!("the feed's <ttl> is {} minutes; next check after {}", ttl, next.to_rfc3339_opts(SecondsFormat::Secs, true)))
+            }
+        }
+        if self.skip_hours.contains(&now.hour()) {
+            return Some(format!("the feed's <skipHours> include {}:00 UTC", now.hour()))
+        }
+        if self.skip_days.iter().any(|day| day.parse::<Weekday>().is_ok_and(|day| day == now.weekday())) {
+            return Some(format!("the feed's <skipDays> include {:?}", now.weekday()))
+        }
+        None
+    }
+
+    /// Why, and to what interval, polling is slowed down.
+    fn slowed(&self, now: DateTime<Utc>) -> Option<(String, Duration)> {
+        if self.complete {
+            return Some(("marked complete by the publisher".to_string(), COMPLETE_INTERVAL))
+        }
+        match self.latest_episode {
+            Some(latest) if now - latest > DORMANT_AFTER => {
+                Some((format!("no new episode since {}", latest.format("%Y-%m-%d")), DORMANT_INTERVAL))
+            },
+            _ => None
+        }
+    }
+
+    /// Record a successful poll of `rss_xml`, whose newest episode was
+    /// published at `latest_episode`.
+    pub fn polled(&mut self, rss_xml: &str, latest_episode: Option<DateTime<Utc>>, now: DateTime<Utc>) {
+        let was_complete = self.complete;
+        let hints = channel_hints(rss_xml);
+        self.complete = hints.complete;
+        self.ttl_minutes = hints.ttl_minutes.filter(|ttl| *ttl > 0);
+        self.skip_hours = hints.skip_hours;
+        self.skip_days = hints.skip_days;
+        if self.complete && !was_complete {
+            info!("The feed is marked complete; checking it every {} days from now on", COMPLETE_INTERVAL.num_days());
+        }
+        self.latest_episode = latest_episode.max(self.latest_episode);
+        self.fetched(now);
+    }
+
+    /// Record that the feed was fetched, changed or not.
+    pub fn fetched(&mut self, now: DateTime<Utc>) {
+        self.last_polled = Some(now);
+        self.failed_polls = 0;
+        self.last_error = None;
+    }
+
+    /// Record that fetching the feed failed with `error`.
+    pub fn fetch_failed(&mut self, error: &str) {
+        self.failed_polls += 1;
+        self.last_error = Some(error.to_string());
+    }
+}
+
+/// Make `request` conditional on the feed having changed since the validators
+/// were stored, so an unchanged feed costs a `304 Not Modified`.
+pub fn conditional(state: &PollState, mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    if let Some(etag) = &state.etag {
+        request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &state.last_modified {
+        request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+    }
+    request
+}
+
+/// The `ETag` and `Last-Modified` of a feed response.
+pub fn validators(headers: &reqwest::header::HeaderMap) -> (Option<String>, Option<String>) {
+    let header = |name| headers.get(name).and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok()).map(str::to_string);
+    (header(reqwest::header::ETAG), header(reqwest::header::LAST_MODIFIED))
+}
+
+/// The channel's `<itunes:complete>yes</itunes:complete>`, `<ttl>`,
+/// `<skipHours>` and `<skipDays>`.
+fn channel_hints(rss_xml: &str) -> Hints {
+    let mut hints = Hints::default();
+    let mut reader = Reader::from_str(rss_xml);
+    reader.trim_text(true);
+    loop {
+        let element = match reader.read_event() {
+            Ok(Event::Start(element)) => element,
+            Ok(Event::Eof) | Err(_) => return hints,
+            _ => continue
+        };
+        let text = match element.name().as_ref() {
+            b"item" | b"entry" => return hints,
+            b"itunes:complete" | b"ttl" | b"hour" | b"day" => match reader.read_text(element.name()) {
+                Ok(text) => crate::element_text(&text),
+                Err(_) => return hints
+            },
+            _ => continue
+        };
+        match element.name().as_ref() {
+            b"itunes:complete" => hints.complete = text.eq_ignore_ascii_case("yes"),
+            b"ttl" => hints.ttl_minutes = text.trim().parse().ok(),
+            b"hour" => hints.skip_hours.extend(text.trim().parse::<u32>().ok().map(|hour| hour % 24)),
+            _ => hints.skip_days.push(text.trim().to_string())
+        }
+    }
+}
+
+/// Parse `--snooze-until`: RFC 3339, or a date meaning midnight UTC.
+pub fn parse_until(text: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(datetime.with_timezone(&Utc))
+    }
+    match NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+        Err(_) => Err(format!("expected YYYY-MM-DD or an RFC 3339 time, got {}", text))
+    }
+}
+
+/// `poarder feed disable|enable|status` for the archives in `output_dirs`.
+pub fn run(output_dirs: &[String], disable: Option<bool>, snooze_until: Option<DateTime<Utc>>) -> Result<(), Box<dyn Error>> {
+    let now = Utc::now();
+    for dir in output_dirs {
+        let mut state = PollState::open(&FeedLayout::new(Path::new(dir)).state_dir())?;
+        if let Some(disable) = disable {
+            state.disabled = disable;
+            state.snoozed_until = snooze_until.filter(|_| disable);
+            if !disable {
+                // Check a complete or dormant show on the next run too.
+                state.last_polled = None;
+            }
+            state.save()?;
+        }
+        match (state.paused(now, false), state.slowed(now)) {
+            (Some(reason), _) => println!("{}: {}", dir, reason),
+            (None, Some((reason, interval))) => println!("{}: {}; --serve and --daemon check it every {} day(s)", dir, reason, interval.num_days()),
+            (None, None) => println!("{}: enabled", dir)
+        }
+        let catalog = Catalog::open(&FeedLayout::new(Path::new(dir)).state_dir())?;
+        let published = catalog.entries().map(|(_, entry)| entry.published.with_timezone(&Utc));
+        if let Some(alert) = alerts::check(&state, published, now) {
+            println!("{}", alert.message(dir));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,252 @@
+//! `poarder migrate`: rename an archive's episodes to the current
+//! `--name-template`. The renames are written to an undo journal in
+//! `state/renames.json` before any file moves, so `poarder migrate --undo`
+//! can restore the old names after a bad template, or an interrupted run.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::Catalog;
+use crate::db::EpisodeDb;
+use crate::layout::FeedLayout;
+use crate::naming::NameTemplate;
+use crate::pipeline::episode_extension;
+use crate::Episode;
+
+/// A file or directory moved, relative to the archive root.
+#[derive(Debug, Serialize, Deserialize)]
+struct Rename {
+    from: String,
+    to: String,
+    /// The episode's own file, as named in the catalog and database.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    episode: bool,
+}
+
+/// The renames of one `poarder migrate`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Batch {
+    template: String,
+    started: DateTime<Utc>,
+    renames: Vec<Rename>,
+}
+
+/// Undo journal of an archive, newest batch last.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    batches: Vec<Batch>,
+}
+
+impl Journal {
+    fn path(layout: &FeedLayout) -> PathBuf {
+        layout.state_dir().join("renames.json")
+    }
+
+    fn open(layout: &FeedLayout) -> Result<Journal, Box<dyn Error>> {
+        match fs::read_to_string(Journal::path(layout)) {
+            Ok(text) => Ok(serde_json::from_str(&text)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Journal::default()),
+            Err(e) => Err(Box::new(e))
+        }
+    }
+
+    fn save(&self, layout: &FeedLayout) -> Result<(), Box<dyn Error>> {
+        let path = Journal::path(layout);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// Rename the episodes of the archives in `output_dirs` to `naming`, whose
+/// source is `template`. With `dry_run`, only print what would be renamed.
+pub fn run(output_dirs: &[String], naming: &NameTemplate, template: &str, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    for dir in output_dirs {
+        let layout = FeedLayout::new(Path::new(dir));
+        let renames = plan(&layout, naming)?;
+        for rename in &renames {
+            println!("{}  -> {}", rename.from, rename.to);
+        }
+        if renames.is_empty() {
+            println!("{}: every episode already follows \"{}\"", dir, template);
+            continue
+        }
+        if dry_run {
+            println!("{}: {} file(s) would be renamed", dir, renames.len());
+            continue
+        }
+
+        // Journal first: whatever happens below can be undone.
+        let mut journal = Journal::open(&layout)?;
+        journal.batches.push(Batch { template: template.to_string(), started: Utc::now(), renames });
+        journal.save(&layout)?;
+
+        let batch = journal.batches.last().unwrap();
+        for rename in &batch.renames {
+            if let Err(e) = move_file(&layout.root().join(&rename.from), &layout.root().join(&rename.to)) {
+                return Err(format!("could not rename {} to {} ({}); `poarder migrate --undo -o {}` restores the files renamed so far",
+                    rename.from, rename.to, e, dir).into())
+            }
+        }
+        update_records(&layout, batch.renames.iter().filter(|rename| rename.episode).map(|rename| (rename.from.as_str(), rename.to.as_str())))?;
+        println!("{}: renamed {} file(s); `poarder migrate --undo` reverts this", dir, batch.renames.len());
+    }
+    Ok(())
+}
+
+/// Revert the latest `poarder migrate` of each archive in `output_dirs`.
+pub fn undo(output_dirs: &[String], dry_run: bool) -> Result<(), Box<dyn Error>> {
+    for dir in output_dirs {
+        let layout = FeedLayout::new(Path::new(dir));
+        let mut journal = Journal::open(&layout)?;
+        let Some(batch) = journal.batches.pop() else {
+            println!("{}: nothing to undo", dir);
+            continue
+        };
+
+        let mut restored = 0;
+        for rename in batch.renames.iter().rev() {
+            let (from, to) = (layout.root().join(&rename.from), layout.root().join(&rename.to));
+            match (to.exists(), from.exists()) {
+                (true, false) => {
+                    println!("{}  -> {}", rename.to, rename.from);
+                    if !dry_run {
+                        move_file(&to, &from)?;
+                    }
+                    restored += 1;
+                },
+                // Never renamed, e.g. the migration was interrupted.
+                (false, true) => (),
+                _ => warn!("Not restoring {}: {} and {} both or neither exist", rename.from, to.display(), from.display())
+            }
+        }
+        if dry_run {
+            println!("{}: {} file(s) would get their names from before \"{}\" back", dir, restored, batch.template);
+            continue
+        }
+
+        update_records(&layout, batch.renames.iter().rev().filter(|rename| rename.episode).map(|rename| (rename.to.as_str(), rename.from.as_str())))?;
+        journal.save(&layout)?;
+        println!("{}: restored {} file(s) renamed for \"{}\" on {}; set --name-template back to match",
+            dir, restored, batch.template, batch.started.to_rfc3339_opts(SecondsFormat::Secs, true));
+    }
+    Ok(())
+}
+
+/// The archived episodes of `layout`'s saved feed and their files in its
+/// episode directory: by the episode database, or else by the names
+/// `naming` finds them under.
+pub fn archived(layout: &FeedLayout, naming: &NameTemplate) -> Result<Vec<(Episode, String)>, Box<dyn Error>> {
+    let feed_path = layout.feed_dir().join("rss.xml");
+    let feed = fs::read_to_string(&feed_path)
+        .map_err(|e| format!("could not read {} ({}); run poarder against this archive first", feed_path.display(), e))?;
+    let db = EpisodeDb::open(&layout.state_dir())?;
+    let episodes_dir = layout.episodes_dir();
+
+    let mut archived = Vec::new();
+    for episode in crate::parse_feed(&feed, false)? {
+        let recorded = db.get(&episode)?.map(|record| record.file).filter(|file| episodes_dir.join(file).is_file());
+        let file = recorded.or_else(|| {
+            let path = naming.existing_file(&episodes_dir, &episode)?;
+            Some(path.strip_prefix(&episodes_dir).ok()?.to_string_lossy().into_owned())
+        });
+        if let Some(file) = file {
+            archived.push((episode, file));
+        }
+    }
+    Ok(archived)
+}
+
+/// The renames that bring `layout`'s archived episodes in line with
+/// `naming`, along with their sidecars and soundbites.
+fn plan(layout: &FeedLayout, naming: &NameTemplate) -> Result<Vec<Rename>, Box<dyn Error>> {
+    let archived = archived(layout, naming)?;
+    let names = layout.episode_files()?;
+    let episode_files: HashSet<&str> = archived.iter().map(|(_, file)| file.as_str()).collect();
+
+    let episodes_path = |name: &str| format!("episodes/{}", name);
+    let mut renames = Vec::new();
+    for (episode, file) in &archived {
+        let new_file = file_name_with_ext(naming, episode, file.rsplit_once('.').map_or("", |(_, ext)| ext));
+        if new_file == *file {
+            continue
+        }
+        // Sidecars are named after the episode file up to its last dot.
+        let old_stem = file.rsplit_once('.').map_or(file.as_str(), |(stem, _)| stem);
+        let new_stem = new_file.rsplit_once('.').map_or(new_file.as_str(), |(stem, _)| stem);
+        renames.push(Rename { from: episodes_path(file), to: episodes_path(&new_file), episode: true });
+
+        let companions = names.iter()
+            .filter(|name| !episode_files.contains(name.as_str()))
+            .filter_map(|name| Some((name, name.strip_prefix(old_stem)?.strip_prefix('.')?)));
+        for (name, rest) in companions {
+            renames.push(Rename { from: episodes_path(name), to: episodes_path(&format!("{}.{}", new_stem, rest)), episode: false });
+        }
+        // Soundbites go by the file's name, whatever year directory it is in.
+        let (old_stem, new_stem) = (file_name(old_stem), file_name(new_stem));
+        if old_stem != new_stem && layout.soundbites_dir().join(old_stem).is_dir() {
+            renames.push(Rename { from: format!("soundbites/{}", old_stem), to: format!("soundbites/{}", new_stem), episode: false });
+        }
+    }
+
+    // Refuse rather than overwrite: a template that merges episodes, or a
+    // name already taken, would lose files.
+    let mut targets = HashSet::new();
+    let clashes: Vec<&str> = renames.iter()
+        .filter(|rename| !targets.insert(rename.to.as_str()) || layout.root().join(&rename.to).exists())
+        .map(|rename| rename.to.as_str())
+        .collect();
+    if !clashes.is_empty() {
+        return Err(format!("{}: the new names clash with each other or existing files: {}; `poarder template test` shows the names",
+            layout.root().display(), clashes.join(", ")).into())
+    }
+    Ok(renames)
+}
+
+/// The episode's name under `naming`, but with the extension `ext` its
+/// file was stored with, which may come from the server's Content-Type
+/// rather than the feed.
+pub fn file_name_with_ext(naming: &NameTemplate, episode: &Episode, ext: &str) -> String {
+    match ext == episode_extension(episode) {
+        true => naming.file_name(episode),
+        false => format!("{}.{}", naming.stem(episode), ext)
+    }
+}
+
+/// Point the catalog and episode database at the episodes' new file names.
+fn update_records<'a>(layout: &FeedLayout, renames: impl Iterator<Item = (&'a str, &'a str)>) -> Result<(), Box<dyn Error>> {
+    let mut catalog = Catalog::open(&layout.state_dir())?;
+    let db = EpisodeDb::open(&layout.state_dir())?;
+    for (from, to) in renames {
+        let (from, to) = (episode_name(from), episode_name(to));
+        catalog.rename(from, to);
+        db.rename(from, to)?;
+    }
+    catalog.save()?;
+    crate::verify::write_manifest(layout, &db, &Default::default())?;
+    info!("Updated the catalog and episode database");
+    Ok(())
+}
+
+/// `path` renamed to `to`, into a year directory that may not exist yet.
+fn move_file(path: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::rename(path, to)
+}
+
+/// The name the catalog and database know an `episodes/...` file by.
+fn episode_name(path: &str) -> &str {
+    path.strip_prefix("episodes/").unwrap_or(path)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
@@ -0,0 +1,299 @@
+//! `poarder report`: bundle an anonymised, minimal reproduction of a feed
+//! that poarder has trouble with, for attaching to bug reports. URLs lose
+//! their credentials and tokens, email addresses and the feed's owner are
+//! redacted, and so are the config's secrets.
+
+use log::warn;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::fixtures::Http;
+
+/// Bytes of each enclosure kept with `--sample-media`.
+const MEDIA_SAMPLE_BYTES: usize = 64 * 1024;
+
+pub struct ReportOptions<'a> {
+    pub items: usize,
+    pub sample_media: bool,
+    pub out_dir: &'a Path,
+    pub config_path: Option<&'a str>,
+}
+
+/// Write the report on `feed`, as downloaded within the feed limits.
+pub async fn run(http: &Http, feed: &str, options: ReportOptions<'_>) -> Result<(), Box<dyn Error>> {
+    let feed = truncate_items(feed, options.items);
+    let enclosures = enclosure_urls(&feed);
+    let anonymised = redact_emails(&anonymise_urls(&redact_contacts(&feed)));
+
+    fs::create_dir_all(options.out_dir)?;
+    fs::write(options.out_dir.join("feed.xml"), &anonymised)?;
+
+    if options.sample_media {
+        for (i, url) in enclosures.iter().enumerate() {
+            let sample_path = options.out_dir.join(format!("enclosure-{}.sample", i));
+            let request = http.get(url).header(reqwest::header::RANGE, format!("bytes=0-{}", MEDIA_SAMPLE_BYTES - 1));
+            match http.send(request).await.and_then(|r| r.error_for_status()) {
+                Ok(resp) => {
+                    let mut data = resp.bytes().await?.to_vec();
+                    data.truncate(MEDIA_SAMPLE_BYTES);
+                    fs::write(sample_path, data)?;
+                },
+                Err(e) => warn!("Could not sample enclosure {}. Error: {}", i, e),
+            }
+        }
+    }
+
+    let parse_summary = match crate::parse_feed(&anonymised, false) {
+        Ok(episodes) => format!("parsed {} of {} items", episodes.len(), count_items(&anonymised)),
+        Err(e) => format!("parse failed: {}", e),
+    };
+
+    let config = match options.config_path {
+        Some(path) => match fs::read_to_string(path).map(|text| text.parse::<toml::Value>()) {
+            Ok(Ok(mut config)) => {
+                redact_config(&mut config);
+                redact_emails(&anonymise_urls(&toml::to_string_pretty(&config)?))
+            },
+            // Left out rather than risk secrets in it.
+            Ok(Err(e)) => format!("<unparseable: {}>", e),
+            Err(e) => format!("<unreadable: {}>", e),
+        },
+        None => "<none>".to_string(),
+    };
+
+    let info = format!("poarder {}\nos: {} {}\nfeatures: {}\nresult: {}\n\nconfig:\n{}\n",
+        crate::VERSION,
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        enabled_features().join(", "),
+        parse_summary,
+        config);
+    fs::write(options.out_dir.join("info.txt"), info)?;
+
+    println!("Wrote report to {}. Please review it for anything private before attaching it to an issue.", options.out_dir.display());
+    Ok(())
+}
+
+/// Every feature of `Cargo.toml` but `default` and `full`, and whether this
+/// build has it.
+const FEATURES: &[(&str, bool)] = &[
+    ("native-tls", cfg!(feature = "native-tls")),
+    ("rustls", cfg!(feature = "rustls")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("self-update", cfg!(feature = "self-update")),
+    ("io-uring", cfg!(feature = "io-uring")),
+    ("sandbox", cfg!(feature = "sandbox")),
+    ("artwork", cfg!(feature = "artwork")),
+    ("mqtt", cfg!(feature = "mqtt")),
+    ("id3", cfg!(feature = "id3")),
+    ("progress", cfg!(feature = "progress")),
+    ("smtp", cfg!(feature = "smtp")),
+];
+
+fn enabled_features() -> Vec<&'static str> {
+    FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect()
+}
+
+fn count_items(feed: &str) -> usize {
+    feed.matches("<item>").count() + feed.matches("<item ").count()
+}
+
+/// Keep the channel header and the first `keep` items.
+fn truncate_items(feed: &str, keep: usize) -> String {
+    // Nothing kept: cut where the first item starts.
+    let first_item = [feed.find("<item>"), feed.find("<item ")].into_iter().flatten().min();
+    let mut end_of_kept = first_item.filter(|_| keep == 0);
+    let mut search_from = 0;
+    for _ in 0..keep {
+        match feed[search_from..].find("</item>") {
+            Some(pos) => {
+                search_from += pos + "</item>".len();
+                end_of_kept = Some(search_from);
+            },
+            None => return feed.to_string()
+        }
+    }
+
+    let (cut, last_item_end) = match (end_of_kept, feed.rfind("</item>")) {
+        (Some(cut), Some(last)) if last + "</item>".len() > cut => (cut, last + "</item>".len()),
+        _ => return feed.to_string()
+    };
+
+    format!("{}{}", &feed[..cut], &feed[last_item_end..])
+}
+
+fn enclosure_urls(feed: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = feed;
+    while let Some(start) = rest.find("<enclosure") {
+        let tag_end = rest[start..].find('>').map(|p| start + p).unwrap_or(rest.len());
+        let tag = &rest[start..tag_end];
+        if let Some(pos) = tag.find("url=") {
+            let value = &tag[pos + 4..];
+            if let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') {
+                if let Some(url) = value[1..].split(quote).next() {
+                    urls.push(url.replace("&amp;", "&"));
+                }
+            }
+        }
+        rest = &rest[tag_end..];
+    }
+    urls
+}
+
+/// Elements naming the people behind a feed, and what they become.
+const CONTACT_ELEMENTS: [(&str, &str); 6] = [
+    ("itunes:owner", "<itunes:name>REDACTED</itunes:name><itunes:email>redacted@example.invalid</itunes:email>"),
+    ("googleplay:owner", "redacted@example.invalid"),
+    ("itunes:email", "redacted@example.invalid"),
+    ("googleplay:email", "redacted@example.invalid"),
+    ("managingEditor", "redacted@example.invalid (REDACTED)"),
+    ("webMaster", "redacted@example.invalid (REDACTED)"),
+];
+
+/// Replace the contents of the feed's contact elements.
+fn redact_contacts(feed: &str) -> String {
+    let mut feed = feed.to_string();
+    for (name, replacement) in CONTACT_ELEMENTS {
+        let (open, close) = (format!("<{}", name), format!("</{}>", name));
+        let mut from = 0;
+        while let Some(start) = feed[from..].find(&open).map(|pos| from + pos) {
+            let after_name = start + open.len();
+            let Some(tag_end) = feed[after_name..].find('>').map(|pos| after_name + pos + 1) else { break };
+            // Another element with this one's name as a prefix, or empty.
+            if !feed[after_name..].starts_with(['>', ' ', '\t', '\r', '\n']) || feed[..tag_end].ends_with("/>") {
+                from = tag_end;
+                continue
+            }
+            let Some(end) = feed[tag_end..].find(&close).map(|pos| tag_end + pos) else { break };
+            feed.replace_range(tag_end..end, replacement);
+            from = tag_end + replacement.len() + close.len();
+        }
+    }
+    feed
+}
+
+/// Replace anything that looks like an email address.
+fn redact_emails(text: &str) -> String {
+    // Addresses are taken to be ASCII, so these land on character boundaries.
+    let is_address_byte = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'%' | b'+' | b'-');
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find('@') {
+        let local_start = rest[..at].bytes().rposition(|b| !is_address_byte(b)).map_or(0, |pos| pos + 1);
+        let domain_end = rest[at + 1..].bytes().position(|b| !is_address_byte(b)).map_or(rest.len(), |pos| at + 1 + pos);
+        let domain = rest[at + 1..domain_end].trim_end_matches('.');
+        if local_start < at && domain.contains('.') {
+            out.push_str(&rest[..local_start]);
+            out.push_str("redacted@example.invalid");
+            rest = &rest[at + 1 + domain.len()..];
+        } else {
+            out.push_str(&rest[..=at]);
+            rest = &rest[at + 1..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Redact the config's secrets: tokens, passwords and user names, and the
+/// arguments of commands, which can carry them too. The programs are kept.
+fn redact_config(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let secret = ["token", "password", "username", "secret", "key"].iter().any(|word| key.contains(word));
+                match (value, secret) {
+                    (value @ toml::Value::String(_), true) => *value = toml::Value::from("REDACTED"),
+                    (toml::Value::Array(command), false) if key == "command" || key.ends_with("-cmd") => {
+                        for arg in command.iter_mut().skip(1) {
+                            *arg = toml::Value::from("REDACTED");
+                        }
+                    },
+                    (value, _) => redact_config(value),
+                }
+            }
+        },
+        toml::Value::Array(values) => values.iter_mut().for_each(redact_config),
+        _ => ()
+    }
+}
+
+/// Rewrite every http(s) URL to drop credentials, query strings and
+/// fragments, and mask path segments that look like access tokens.
+fn anonymise_urls(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = [rest.find("http://"), rest.find("https://")].into_iter().flatten().min() {
+        out.push_str(&rest[..start]);
+
+        let end = rest[start..]
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | ']'))
+            .map(|p| start + p)
+            .unwrap_or(rest.len());
+        out.push_str(&anonymise_url(&rest[start..end]));
+        rest = &rest[end..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn anonymise_url(url: &str) -> String {
+    let mut parsed = match reqwest::Url::parse(&url.replace("&amp;", "&")) {
+        Ok(parsed) => parsed,
+        Err(_) => return "https://redacted.invalid/".to_string()
+    };
+
+    let _ = parsed.set_username("");
+    let _ = parsed.set_password(None);
+    parsed.set_query(None);
+    parsed.set_fragment(None);
+
+    let segments: Vec<String> = parsed.path_segments()
+        .map(|segments| segments.map(redact_segment).collect())
+        .unwrap_or_default();
+    if !segments.is_empty() {
+        parsed.set_path(&segments.join("/"));
+    }
+
+    parsed.to_string()
+}
+
+fn redact_segment(segment: &str) -> String {
+    let stem = segment.split('.').next().unwrap_or(segment);
+    if looks_like_token(stem) {
+        format!("REDACTED{}", &segment[stem.len()..])
+    }
+    else {
+        segment.to_string()
+    }
+}
+
+/// Long runs of mixed letters and digits are almost always tokens or IDs.
+fn looks_like_token(stem: &str) -> bool {
+    stem.len() >= 20
+        && stem.chars().any(|c| c.is_ascii_digit())
+        && stem.chars().any(|c| c.is_ascii_alphabetic())
+        && stem.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_feature_is_reported() {
+        let manifest: toml::Value = include_str!("../Cargo.toml").parse().unwrap();
+        let mut declared: Vec<&str> = manifest["features"].as_table().unwrap().keys()
+            .map(String::as_str)
+            .filter(|name| !matches!(*name, "default" | "full"))
+            .collect();
+        let mut reported: Vec<&str> = FEATURES.iter().map(|(name, _)| *name).collect();
+        declared.sort();
+        reported.sort();
+        assert_eq!(reported, declared);
+    }
+}
@@ -0,0 +1,40 @@
+//! Optional Landlock self-restriction (Linux, `sandbox` feature).
+//!
+//! With `--sandbox`, poarder gives up filesystem access to everything except
+//! its output, state and scratch directories (read-write) and the system and
+//! configuration paths it needs to run (read-only). Network access is left
+//! alone. Landlock only covers threads created after the restriction, so it
+//! must be applied before the async runtime starts.
+
+use landlock::{
+    path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI,
+};
+use log::{info, warn};
+use std::error::Error;
+use std::path::PathBuf;
+
+/// System locations needed for TLS roots, DNS, shared libraries and running
+/// plugin interpreters.
+const SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/opt", "/nix", "/proc/self"];
+
+/// Device files opened for reading and writing.
+const DEVICE_PATHS: &[&str] = &["/dev/null", "/dev/urandom", "/dev/random"];
+
+pub fn apply(writable: &[PathBuf], readable: &[PathBuf]) -> Result<(), Box<dyn Error>> {
+    let abi = ABI::V2;
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(SYSTEM_PATHS, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(readable, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(DEVICE_PATHS, AccessFs::from_all(abi)))?
+        .add_rules(path_beneath_rules(writable, AccessFs::from_all(abi)))?
+        .restrict_self()?;
+
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => info!("Sandbox enabled; writable: {:?}", writable),
+        RulesetStatus::PartiallyEnforced => warn!("Sandbox only partially enforced; this kernel's Landlock support is limited"),
+        RulesetStatus::NotEnforced => warn!("Sandbox not enforced; this kernel doesn't support Landlock"),
+    }
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! How much of a feed a run reads. A full scan goes through every item,
+//! which is what notices delisted episodes. An incremental scan stops after
+//! a run of items that are already archived, which is all frequent polling
+//! needs; it still falls back to a full scan every so often.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScanMode {
+    #[default]
+    Full,
+    Incremental,
+}
+
+/// A `[[scan]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScanConfig {
+    /// Feed URL the entry applies to; every feed when omitted.
+    #[serde(default)]
+    pub feed: Option<String>,
+    #[serde(default)]
+    pub mode: ScanMode,
+    /// Consecutive already archived items after which an incremental scan stops.
+    #[serde(default = "default_stop_after")]
+    pub stop_after: usize,
+    /// Hours after which an incremental feed gets a full scan again; 0 for never.
+    #[serde(default = "default_full_every_hours")]
+    pub full_every_hours: i64,
+}
+
+fn default_stop_after() -> usize { 10 }
+fn default_full_every_hours() -> i64 { 24 }
+
+impl Default for ScanConfig {
+    fn default() -> ScanConfig {
+        ScanConfig {
+            feed: None,
+            mode: ScanMode::Full,
+            stop_after: default_stop_after(),
+            full_every_hours: default_full_every_hours(),
+        }
+    }
+}
+
+impl ScanConfig {
+    /// After how many consecutive archived items to stop at `now`, or
+    /// `None` for a full scan.
+    pub fn stop_after(&self, last_full_scan: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<usize> {
+        if self.mode == ScanMode::Full {
+            return None
+        }
+        match last_full_scan {
+            Some(last) if self.full_every_hours <= 0 || now - last < Duration::hours(self.full_every_hours) => {
+                Some(self.stop_after.max(1))
+            },
+            _ => None
+        }
+    }
+}
+
+/// The entry from the config that applies to `feed_url`: one naming the
+/// feed, else one for every feed, else a full scan.
+pub fn for_feed(configs: &[ScanConfig], feed_url: &str) -> ScanConfig {
+    configs.iter().find(|config| config.feed.as_deref() == Some(feed_url))
+        .or_else(|| configs.iter().find(|config| config.feed.is_none()))
+        .cloned()
+        .unwrap_or_default()
+}
@@ -0,0 +1,100 @@
+//! Publisher schedules for `--serve`: shows that publish at a known weekday
+//! and time get their connections warmed up just before, and are then polled
+//! frequently for a short window so new episodes are archived quickly.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Weekday};
+use serde::Deserialize;
+
+/// A `[[schedules]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ScheduleConfig {
+    /// Feed URL the schedule applies to; every feed when omitted.
+    #[serde(default)]
+    pub feed: Option<String>,
+    /// Publication days, e.g. `["mon", "thu"]`; every day when empty.
+    #[serde(default)]
+    pub weekdays: Vec<String>,
+    /// Expected publication time, local time, as `HH:MM`.
+    pub time: String,
+    /// Seconds before publication to warm up DNS and TLS.
+    #[serde(default = "default_lead_seconds")]
+    pub lead_seconds: i64,
+    /// Minutes after publication to keep polling.
+    #[serde(default = "default_window_minutes")]
+    pub window_minutes: i64,
+    /// Seconds between polls within the window.
+    #[serde(default = "default_poll_seconds")]
+    pub poll_seconds: u64,
+}
+
+fn default_lead_seconds() -> i64 { 60 }
+fn default_window_minutes() -> i64 { 30 }
+fn default_poll_seconds() -> u64 { 30 }
+
+/// An upcoming publication window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub publish: DateTime<Local>,
+    pub warm_at: DateTime<Local>,
+    pub until: DateTime<Local>,
+    pub poll: std::time::Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    weekdays: Vec<Weekday>,
+    time: NaiveTime,
+    lead: Duration,
+    window: Duration,
+    poll: std::time::Duration,
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig) -> Result<Schedule, String> {
+        let time = NaiveTime::parse_from_str(&config.time, "%H:%M")
+            .map_err(|e| format!("invalid schedule time {:?}: {}", config.time, e))?;
+        let weekdays = config.weekdays.iter()
+            .map(|day| day.parse::<Weekday>().map_err(|_| format!("invalid weekday {:?}", day)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Schedule {
+            weekdays,
+            time,
+            lead: Duration::seconds(config.lead_seconds.max(0)),
+            window: Duration::minutes(config.window_minutes.max(0)),
+            poll: std::time::Duration::from_secs(config.poll_seconds.max(1)),
+        })
+    }
+
+    /// The earliest window whose publication is after `after` and which
+    /// hasn't closed by `now`.
+    fn next_window(&self, now: DateTime<Local>, after: Option<DateTime<Local>>) -> Option<Window> {
+        (0..=7)
+            .filter_map(|days| {
+                let date = now.date_naive() + Duration::days(days);
+                if !self.weekdays.is_empty() && !self.weekdays.contains(&date.weekday()) {
+                    return None
+                }
+                Local.from_local_datetime(&date.and_time(self.time)).earliest()
+            })
+            .filter(|publish| *publish + self.window > now && after.is_none_or(|after| *publish > after))
+            .map(|publish| Window { publish, warm_at: publish - self.lead, until: publish + self.window, poll: self.poll })
+            .next()
+    }
+}
+
+/// The next window among `schedules`, skipping publications up to and including `after`.
+pub fn next_window(schedules: &[Schedule], now: DateTime<Local>, after: Option<DateTime<Local>>) -> Option<Window> {
+    schedules.iter()
+        .filter_map(|schedule| schedule.next_window(now, after))
+        .min_by_key(|window| window.warm_at)
+}
+
+/// Schedules from the config that apply to `feed_url`.
+pub fn for_feed(configs: &[ScheduleConfig], feed_url: &str) -> Result<Vec<Schedule>, String> {
+    configs.iter()
+        .filter(|config| config.feed.as_deref().is_none_or(|feed| feed == feed_url))
+        .map(Schedule::from_config)
+        .collect()
+}
@@ -0,0 +1,290 @@
+//! Container-friendly operation: `--serve` keeps archiving on an interval
+//! and answers orchestrator health checks, `--daemon` does the same without
+//! them, and all of these and `--oneshot` shut down cleanly on SIGTERM/SIGINT:
+//! no new downloads start, the ones in flight keep their `.part` files for
+//! resuming, and what already arrived is stored.
+//!
+//! When the config has publisher schedules for the feed, both also
+//! warm up the connection just before each expected publication and then
+//! poll every few seconds until the episode appears or the window closes.
+//!
+//! - `GET /healthz`: 200 while the process is running (liveness)
+//! - `GET /readyz`: 200 once a run has completed, 503 before (readiness)
+//! - `GET /calendar.ics` and `GET /calendar/<show>.ics` with `--calendar`;
+//!   see `calendar`
+//! - `GET /status`: an HTML overview of the feeds, queue, recent failures
+//!   and disk space; see `status`
+
+use chrono::Local;
+use log::{debug, error, info, warn};
+use std::error::Error;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::calendar;
+use crate::pipeline::RunSummary;
+use crate::shutdown::Shutdown;
+use crate::schedule::{self, Schedule};
+use crate::status::Status;
+
+/// Largest request head we bother reading.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// How long a client has to send its request head.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct Health {
+    ready: AtomicBool,
+    pages: Pages,
+}
+
+/// What is served besides the health checks.
+#[derive(Default)]
+pub struct Pages {
+    /// Output directories whose calendars are served.
+    pub calendars: Vec<PathBuf>,
+    pub status: Option<Arc<Status>>,
+}
+
+/// Resolves when the process is asked to stop (SIGTERM, or SIGINT/Ctrl+C).
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = terminate.recv() => (),
+                _ = tokio::signal::ctrl_c() => (),
+            },
+            Err(e) => {
+                warn!("Cannot listen for SIGTERM. Error: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+
+    info!("Received shutdown signal");
+}
+
+/// Stop the runs sharing `shutdown` on the first shutdown signal, letting
+/// the downloads in flight save what they have; quit at once on a second.
+pub async fn stop_on_signal(shutdown: Arc<Shutdown>) {
+    shutdown_signal().await;
+    info!("Stopping after the downloads in flight; press Ctrl+C again to quit now");
+    shutdown.request();
+    shutdown_signal().await;
+    warn!("Quitting without waiting for the downloads in flight");
+    std::process::exit(130);
+}
+
+/// Call `archive` every `interval` until `shutdown` is requested, serving
+/// health endpoints on `listen` (if any) meanwhile, and `pages`. Around
+/// each publication in
+/// `schedules`, call `warm` first and then `archive` at the schedule's poll
+/// rate until something new is stored.
+pub async fn run<F, Fut, W, WFut>(listen: Option<&str>, interval: Duration, schedules: &[Schedule], pages: Pages, shutdown: &Shutdown, mut archive: F, mut warm: W) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<RunSummary, Box<dyn Error>>>,
+    W: FnMut() -> WFut,
+    WFut: Future<Output = ()>,
+{
+    let health = Arc::new(Health { pages, ..Health::default() });
+    if let Some(listen) = listen {
+        let listener = TcpListener::bind(listen).await?;
+        info!("Serving health checks on {}", listener.local_addr()?);
+        tokio::spawn(serve_health(listener, health.clone()));
+    }
+
+    let mut handled = None;
+    let mut run_first = true;
+    let mut next_live = None;
+    loop {
+        if run_first {
+            match run_once(&mut archive, &health, shutdown).await {
+                Some(summary) => next_live = summary.next_live,
+                None => return Ok(())
+            }
+        }
+        run_first = true;
+
+        let now = Local::now();
+        let next_regular = now + chrono::Duration::from_std(interval).unwrap_or(chrono::Duration::MAX);
+        let window = match schedule::next_window(schedules, now, handled) {
+            Some(window) if window.warm_at < next_regular => window,
+            _ => {
+                let wait = match next_live {
+                    Some(start) if start < next_regular => {
+                        info!("Next run at {} for a live stream", start.with_timezone(&Local));
+                        until(start.with_timezone(&Local))
+                    },
+                    _ => {
+                        info!("Next run in {:?}", interval);
+                        interval
+                    }
+                };
+                if !pause(shutdown, wait).await {
+                    return Ok(())
+                }
+                continue
+            }
+        };
+
+        info!("Next episode expected at {}; polling every {:?} until {}", window.publish, window.poll, window.until);
+        if !pause(shutdown, until(window.warm_at)).await {
+            return Ok(())
+        }
+        warm().await;
+        if !pause(shutdown, until(window.publish)).await {
+            return Ok(())
+        }
+
+        loop {
+            let summary = run_once(&mut archive, &health, shutdown).await;
+            if let Some(summary) = &summary {
+                next_live = summary.next_live;
+            }
+            match summary {
+                None => return Ok(()),
+                Some(summary) if summary.stored > 0 => break,
+                Some(_) if Local::now() + chrono::Duration::from_std(window.poll).unwrap_or_default() > window.until => {
+                    info!("Nothing new by {}; back to the regular interval", window.until);
+                    break
+                },
+                Some(_) => if !pause(shutdown, window.poll).await {
+                    return Ok(())
+                }
+            }
+        }
+        handled = Some(window.publish);
+        run_first = false;
+    }
+}
+
+/// Run `archive` once, logging the outcome. Returns `None` if `shutdown`
+/// was requested meanwhile, once the run has stopped.
+async fn run_once<F, Fut>(archive: &mut F, health: &Health, shutdown: &Shutdown) -> Option<RunSummary>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<RunSummary, Box<dyn Error>>>,
+{
+    let result = archive().await;
+    if shutdown.is_requested() {
+        match result {
+            Ok(summary) => warn!("Stopped early: {}", summary),
+            Err(e) => warn!("Stopped early. Error: {}", e)
+        }
+        return None
+    }
+
+    if let Ok(summary) = &result {
+        for (title, anomaly) in &summary.anomalies {
+            warn!("Suspicious: {}: {}", title, anomaly);
+        }
+        for (item, reason) in &summary.malformed {
+            warn!("Malformed: {}: {}", item, reason);
+        }
+    }
+    if let Some(status) = &health.pages.status {
+        status.run_finished(result.as_ref().map_err(|e| e.to_string()));
+    }
+    let summary = match result {
+        Ok(summary) if summary.failed == 0 => {
+            info!("Run complete: {}", summary);
+            summary
+        },
+        Ok(summary) => {
+            for (title, reason) in &summary.failures {
+                warn!("Failed: {}: {}", title, reason);
+            }
+            warn!("Run complete: {}", summary);
+            summary
+        },
+        Err(e) => {
+            error!("Run failed. Error: {}", e);
+            RunSummary::default()
+        }
+    };
+    health.ready.store(true, Ordering::Relaxed);
+    Some(summary)
+}
+
+/// Sleep for `duration`. Returns false if `shutdown` was requested first.
+async fn pause(shutdown: &Shutdown, duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = shutdown.requested() => false,
+    }
+}
+
+fn until(when: chrono::DateTime<Local>) -> Duration {
+    (when - Local::now()).to_std().unwrap_or_default()
+}
+
+async fn serve_health(listener: TcpListener, health: Arc<Health>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let health = health.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &health).await {
+                        debug!("Health check connection failed. Error: {}", e);
+                    }
+                });
+            },
+            Err(e) => error!("Failed to accept health check connection. Error: {}", e),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, health: &Health) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    let read_head = async {
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    // A client that never finishes its request doesn't get to hold the connection.
+    tokio::time::timeout(REQUEST_TIMEOUT, read_head).await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request not received in time"))??;
+
+    let request = String::from_utf8_lossy(&request);
+    let target = request.split_whitespace().nth(1).unwrap_or("/");
+    // Probes may add a query string, e.g. `/healthz?probe=1`.
+    let path = target.split(['?', '#']).next().unwrap_or(target);
+    let calendar = match health.pages.calendars.is_empty() {
+        true => None,
+        false => calendar::serve(&health.pages.calendars, path)
+    };
+    let (status, content_type, body) = match (path, calendar) {
+        (_, Some(ics)) => ("200 OK", "text/calendar; charset=utf-8", ics),
+        ("/healthz", _) => ("200 OK", "text/plain", "ok\n".to_string()),
+        ("/readyz", _) if health.ready.load(Ordering::Relaxed) => ("200 OK", "text/plain", "ready\n".to_string()),
+        ("/readyz", _) => ("503 Service Unavailable", "text/plain", "not ready\n".to_string()),
+        ("/status", _) => match &health.pages.status {
+            Some(status) => ("200 OK", "text/html; charset=utf-8", status.render()),
+            None => ("404 Not Found", "text/plain", "not found\n".to_string())
+        },
+        _ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+    };
+
+    let response = format!("HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,80 @@
+//! Mail over SMTP, for `[digest.smtp]`.
+
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+use std::error::Error;
+
+/// How the connection to the mail server is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Security {
+    /// STARTTLS, usually on port 587.
+    #[default]
+    Starttls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+    /// None at all, e.g. for a relay on localhost.
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SmtpConfig {
+    pub server: String,
+    /// Defaults to the usual port for `security`.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: Security,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Sender, e.g. `poarder <poarder@example.org>`.
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl SmtpConfig {
+    /// Why mail can't be sent with this, if it can't.
+    pub fn problem(&self) -> Option<String> {
+        if self.to.is_empty() {
+            return Some("`to` is empty".to_string())
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            if let Err(e) = address.parse::<Mailbox>() {
+                return Some(format!("invalid address {:?}: {}", address, e))
+            }
+        }
+        None
+    }
+}
+
+/// Mail `html` to everyone in `config.to`.
+pub async fn send(config: &SmtpConfig, subject: &str, html: &str) -> Result<(), Box<dyn Error>> {
+    let mut builder = Message::builder()
+        .from(config.from.parse()?)
+        .subject(subject)
+        .header(ContentType::TEXT_HTML);
+    for to in &config.to {
+        builder = builder.to(to.parse()?);
+    }
+    let message = builder.body(html.to_string())?;
+
+    let mut transport = match config.security {
+        Security::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.server)?,
+        Security::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.server)?,
+        Security::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.server)
+    };
+    if let Some(port) = config.port {
+        transport = transport.port(port);
+    }
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(message).await?;
+    Ok(())
+}
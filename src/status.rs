@@ -0,0 +1,184 @@
+//! The `/status` page of `--serve`: a one-glance view of the archive for
+//! whoever hosts it. Each feed with its last successful fetch and whether
+//! it is failing, what the run in progress still has to download, the
+//! episodes that failed lately and how full the disks are.
+
+use chrono::{DateTime, Local, Utc};
+use quick_xml::escape::escape;
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::db::EpisodeDb;
+use crate::eta::{self, Eta};
+use crate::feeds::{FeedIndex, FeedSource};
+use crate::layout::FeedLayout;
+use crate::pipeline::RunSummary;
+use crate::polling::PollState;
+
+/// Failed episodes kept for the page, newest first.
+const RECENT_FAILURES: usize = 20;
+
+/// How a run went: its summary, or why it failed.
+type Outcome = Result<RunSummary, String>;
+
+pub struct Status {
+    /// Each feed's URL and its archive, where that doesn't depend on the
+    /// feed index.
+    feeds: Vec<(String, Option<PathBuf>)>,
+    /// The output directory that feeds get a directory each in.
+    output_dir: PathBuf,
+    /// What the run in progress, or the last one, has left to download.
+    run: Mutex<Option<Arc<Eta>>>,
+    /// When the last run finished, and how it went.
+    last_run: Mutex<Option<(DateTime<Utc>, Outcome)>>,
+    /// When, which and why.
+    failures: Mutex<VecDeque<(DateTime<Utc>, String, String)>>,
+}
+
+impl fmt::Debug for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Status").field("feeds", &self.feeds).field("output_dir", &self.output_dir).finish()
+    }
+}
+
+impl Status {
+    /// Status of archiving `feeds` into `output_dir`; `single` when the
+    /// one feed is archived straight into it.
+    pub fn new(feeds: &[FeedSource], output_dir: &str, single: bool) -> Arc<Status> {
+        let feeds = feeds.iter()
+            .map(|feed| {
+                let root = match (&feed.options.output_dir, single) {
+                    (Some(dir), _) => Some(PathBuf::from(dir)),
+                    (None, true) => Some(PathBuf::from(output_dir)),
+                    (None, false) => None
+                };
+                (feed.url.clone(), root)
+            })
+            .collect();
+        Arc::new(Status {
+            feeds,
+            output_dir: PathBuf::from(output_dir),
+            run: Mutex::new(None),
+            last_run: Mutex::new(None),
+            failures: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    pub fn run_started(&self, eta: Arc<Eta>) {
+        *self.run.lock().unwrap() = Some(eta);
+    }
+
+    /// Note how a run went: its summary, or why it failed.
+    pub fn run_finished(&self, result: Result<&RunSummary, String>) {
+        let now = Utc::now();
+        if let Ok(summary) = result {
+            let mut failures = self.failures.lock().unwrap();
+            for (title, reason) in &summary.failures {
+                failures.push_front((now, title.clone(), reason.clone()));
+            }
+            failures.truncate(RECENT_FAILURES);
+        }
+        *self.last_run.lock().unwrap() = Some((now, result.cloned()));
+    }
+
+    /// The page, from the archives as they are now.
+    pub fn render(&self) -> String {
+        let index = FeedIndex::open(&self.output_dir).ok();
+        let roots: Vec<(&str, Option<PathBuf>)> = self.feeds.iter()
+            .map(|(url, root)| {
+                let root = root.clone().or_else(|| Some(self.output_dir.join(index.as_ref()?.dir(url)?)));
+                (url.as_str(), root)
+            })
+            .collect();
+
+        let mut html = String::new();
+        html += "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n";
+        html += "<title>poarder status</title>\n</head>\n<body style=\"font-family: sans-serif\">\n";
+        html += "<h1>poarder status</h1>\n";
+        html += &match &*self.last_run.lock().unwrap() {
+            Some((at, Ok(summary))) => format!("<p>Last run finished {}: {}.</p>\n", time(*at), escape(&summary.to_string())),
+            Some((at, Err(e))) => format!("<p>Last run failed {}: {}.</p>\n", time(*at), escape(e)),
+            None => "<p>The first run hasn't finished yet.</p>\n".to_string()
+        };
+
+        html += "<h2>Feeds</h2>\n<table>\n<tr><th>Feed</th><th>Last fetched</th><th>State</th><th>Archived</th><th>Latest episode</th></tr>\n";
+        for (url, root) in &roots {
+            html += &feed_row(url, root.as_deref());
+        }
+        html += "</table>\n";
+
+        html += "<h2>Queue</h2>\n";
+        let reports = self.run.lock().unwrap().as_ref().map(|eta| eta.reports()).unwrap_or_default();
+        match reports.is_empty() {
+            true => html += "<p>Nothing waiting to download.</p>\n",
+            false => {
+                html += "<ul>\n";
+                for report in reports {
+                    html += &format!("<li>{}</li>\n", escape(&report));
+                }
+                html += "</ul>\n";
+            }
+        }
+
+        html += "<h2>Recent failures</h2>\n";
+        let failures = self.failures.lock().unwrap();
+        match failures.is_empty() {
+            true => html += "<p>None.</p>\n",
+            false => {
+                html += "<table>\n<tr><th>When</th><th>Episode</th><th>Error</th></tr>\n";
+                for (at, title, reason) in failures.iter() {
+                    html += &format!("<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n", time(*at), escape(title), escape(reason));
+                }
+                html += "</table>\n";
+            }
+        }
+
+        html += "<h2>Disk</h2>\n<table>\n<tr><th>Directory</th><th>Free</th><th>Size</th></tr>\n";
+        let mut dirs: Vec<&Path> = vec![&self.output_dir];
+        for root in roots.iter().filter_map(|(_, root)| root.as_deref()) {
+            if !root.starts_with(&self.output_dir) && !dirs.contains(&root) {
+                dirs.push(root);
+            }
+        }
+        for dir in dirs {
+            html += &match (fs2::available_space(dir), fs2::total_space(dir)) {
+                (Ok(free), Ok(total)) if total > 0 => format!("<tr><td>{}</td><td>{} ({}%)</td><td>{}</td></tr>\n",
+                    escape(&dir.display().to_string()), eta::bytes(free), free * 100 / total, eta::bytes(total)),
+                _ => format!("<tr><td>{}</td><td colspan=\"2\">unavailable</td></tr>\n", escape(&dir.display().to_string()))
+            };
+        }
+        html += "</table>\n</body>\n</html>\n";
+        html
+    }
+}
+
+/// A feed's row of the table; its archive is at `root`, if it has one yet.
+fn feed_row(url: &str, root: Option<&Path>) -> String {
+    let Some(root) = root else {
+        return format!("<tr><td>{}</td><td colspan=\"4\">not fetched yet</td></tr>\n", escape(url))
+    };
+    let layout = FeedLayout::new(root);
+    let poll = PollState::open(&layout.state_dir()).unwrap_or_default();
+    let state = match (&poll.last_error, poll.paused(Utc::now(), true)) {
+        (_, Some(reason)) => reason,
+        (Some(error), None) => format!("failing ({} times): {}", poll.failed_polls, error),
+        (None, None) => "ok".to_string()
+    };
+    let archived = match layout.state_dir().join("episodes.db").exists() {
+        true => EpisodeDb::open(&layout.state_dir()).and_then(|db| db.records()).unwrap_or_default(),
+        false => Vec::new()
+    };
+    let size: u64 = archived.iter().map(|record| record.size).sum();
+    format!("<tr><td><a href=\"{}\">{}</a></td><td>{}</td><td>{}</td><td>{} episode(s), {}</td><td>{}</td></tr>\n",
+        escape(url), escape(&root.display().to_string()),
+        poll.last_polled.map_or_else(|| "never".to_string(), time),
+        escape(&state),
+        archived.len(), eta::bytes(size),
+        poll.latest_episode.map_or_else(|| "none".to_string(), |latest| latest.with_timezone(&Local).format("%Y-%m-%d").to_string()))
+}
+
+fn time(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+}
@@ -0,0 +1,67 @@
+//! `poarder add`, `remove` and `update`: a list of subscribed feeds kept in
+//! the config directory, so the feeds needn't be given on every run. The
+//! file has one feed URL per line, the same format as `--feeds-file`.
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::discover;
+
+/// `$XDG_CONFIG_HOME/poarder/subscriptions`, or under `~/.config`.
+pub fn path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(crate::config::config_dir()?.join("subscriptions"))
+}
+
+fn save(path: &Path, lines: &[String]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, text)?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn read_lines(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(text.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("could not read {}: {}", path.display(), e).into())
+    }
+}
+
+/// Subscribe to each of `urls`, which may also be Apple Podcasts links or
+/// the show's website.
+pub async fn add(client: &reqwest::Client, urls: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = path()?;
+    let mut lines = read_lines(&path)?;
+    for url in urls {
+        let feed_url = discover::resolve_feed_url(client, url).await
+            .map_err(|e| format!("could not find a feed for {}: {}", url, e))?;
+        if lines.iter().any(|line| line.trim() == feed_url) {
+            println!("Already subscribed to {}", feed_url);
+            continue
+        }
+        lines.push(feed_url.clone());
+        println!("Subscribed to {}", feed_url);
+    }
+    save(&path, &lines)
+}
+
+/// Unsubscribe from each of `urls`. Their archives are left alone.
+pub fn remove(urls: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = path()?;
+    let mut lines = read_lines(&path)?;
+    for url in urls {
+        let before = lines.len();
+        lines.retain(|line| line.trim() != url.as_str());
+        match lines.len() < before {
+            true => println!("Unsubscribed from {}", url),
+            false => println!("Not subscribed to {}", url)
+        }
+    }
+    save(&path, &lines)
+}
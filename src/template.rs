@@ -0,0 +1,62 @@
+//! `poarder template test`: show what a feed's episodes would be called
+//! under a proposed `--name-template`, before renaming an archive to it.
+
+use log::info;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use poarder_core::naming::NameTemplate;
+use poarder_core::{parse_feed_with, FeedFormat, ItemStep};
+
+/// Print the current and proposed names of the first `count` episodes of
+/// `feed`, a URL or a saved feed file.
+pub async fn run(client: &reqwest::Client, feed: &str, current: &NameTemplate, proposed: &NameTemplate, count: usize) -> Result<(), Box<dyn Error>> {
+    let (document, content_type) = match reqwest::Url::parse(feed) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            info!("Downloading {}", feed);
+            let resp = client.get(url).send().await?.error_for_status()?;
+            let content_type = resp.headers().get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            (resp.text().await?, content_type)
+        },
+        _ => (fs::read_to_string(feed)?, None)
+    };
+
+    let format = FeedFormat::detect(content_type.as_deref(), &document);
+    let mut seen = 0;
+    let episodes = parse_feed_with(&document, format, false, |_| ItemStep::Parse, |_| {
+        seen += 1;
+        seen >= count
+    }, |_| ())?;
+    if episodes.is_empty() {
+        return Err(format!("{} has no episodes", feed).into())
+    }
+
+    let names: Vec<(String, String)> = episodes.iter()
+        .map(|episode| (current.file_name(episode), proposed.file_name(episode)))
+        .collect();
+    let width = names.iter().map(|(current, _)| current.len()).max().unwrap_or(0);
+    for (current, proposed) in &names {
+        match current == proposed {
+            true => println!("{:width$}  (unchanged)", current, width = width),
+            false => println!("{:width$}  -> {}", current, proposed, width = width),
+        }
+    }
+
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for (_, proposed) in &names {
+        *uses.entry(proposed.as_str()).or_default() += 1;
+    }
+    let mut clashes: Vec<(&str, usize)> = uses.into_iter().filter(|(_, uses)| *uses > 1).collect();
+    clashes.sort();
+    if !clashes.is_empty() {
+        println!();
+        for (name, uses) in &clashes {
+            println!("{} episodes would be named {}", uses, name);
+        }
+        return Err("the template doesn't tell these episodes apart; add {date} or {guid}".into())
+    }
+    Ok(())
+}
@@ -0,0 +1,50 @@
+//! `--include-regex` and `--exclude-regex`: skip episodes by title, such as
+//! reruns, trailers or teasers for paid episodes.
+
+use regex::Regex;
+
+use crate::Episode;
+
+/// Check `--include-regex` and `--exclude-regex` when they are given.
+pub fn parse_regex(text: &str) -> Result<String, regex::Error> {
+    Regex::new(text).map(|_| text.to_string())
+}
+
+/// Which episodes to archive by their title, and with `descriptions`, by
+/// their description too.
+#[derive(Debug, Clone, Default)]
+pub struct TitleFilter {
+    pub include: Option<Regex>,
+    pub exclude: Option<Regex>,
+    pub descriptions: bool,
+}
+
+impl TitleFilter {
+    pub fn new(include: Option<&str>, exclude: Option<&str>, descriptions: bool) -> Result<TitleFilter, regex::Error> {
+        Ok(TitleFilter {
+            include: include.map(Regex::new).transpose()?,
+            exclude: exclude.map(Regex::new).transpose()?,
+            descriptions,
+        })
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.include.is_some() || self.exclude.is_some()
+    }
+
+    /// Why `episode` isn't archived, if it isn't.
+    pub fn exclusion(&self, episode: &Episode) -> Option<&'static str> {
+        let matches = |regex: &Regex| regex.is_match(&episode.title)
+            || (self.descriptions && episode.description.as_deref().is_some_and(|description| regex.is_match(description)));
+        match (&self.include, &self.exclude) {
+            (Some(include), _) if !matches(include) => Some("not matched by --include-regex"),
+            (_, Some(exclude)) if matches(exclude) => Some("matched by --exclude-regex"),
+            _ => None
+        }
+    }
+
+    /// `episodes` without those the filter excludes.
+    pub fn retain(&self, episodes: Vec<Episode>) -> Vec<Episode> {
+        episodes.into_iter().filter(|episode| self.exclusion(episode).is_none()).collect()
+    }
+}
@@ -0,0 +1,138 @@
+//! `poarder self-update`: replace the running binary with the latest GitHub
+//! release after checking its minisign signature.
+//!
+//! Release assets are expected to be named `poarder-<arch>-<os>` with a
+//! detached `poarder-<arch>-<os>.minisig` signature next to them, whose
+//! trusted comment names the release's version and the asset, e.g.
+//! `poarder 1.4.0 poarder-x86_64-linux`, so that a validly signed binary of
+//! another release or platform isn't taken for this one. The public key is
+//! compiled in from `POARDER_RELEASE_KEY`; builds without it refuse to update
+//! rather than install something unverified.
+
+use log::info;
+use minisign_verify::{PublicKey, Signature};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/drew-buckley/poarder/releases/latest";
+const RELEASE_PUBLIC_KEY: Option<&str> = option_env!("POARDER_RELEASE_KEY");
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset_url(&self, name: &str) -> Result<&str, Box<dyn Error>> {
+        self.assets.iter()
+            .find(|a| a.name == name)
+            .map(|a| a.browser_download_url.as_str())
+            .ok_or_else(|| format!("release {} has no asset named {}", self.tag_name, name).into())
+    }
+}
+
+pub async fn run(client: &reqwest::Client, check_only: bool, force: bool, allow_downgrade: bool) -> Result<(), Box<dyn Error>> {
+    let release: Release = serde_json::from_str(&client.get(RELEASES_URL)
+        .header(reqwest::header::USER_AGENT, concat!("poarder/", env!("CARGO_PKG_VERSION")))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?)?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = release.tag_name.trim_start_matches('v');
+    if !force && !is_newer(latest, current) {
+        println!("poarder {} is up to date (latest release is {})", current, latest);
+        return Ok(())
+    }
+
+    if is_newer(current, latest) && !allow_downgrade {
+        return Err(format!("the latest release, {}, is older than this poarder ({}); add --allow-downgrade to install it anyway", latest, current).into())
+    }
+
+    println!("poarder {} is available (running {})", latest, current);
+    if check_only {
+        return Ok(())
+    }
+
+    let public_key = match RELEASE_PUBLIC_KEY {
+        Some(key) => PublicKey::from_base64(key)?,
+        None => return Err("this build has no release signing key compiled in; update through your package source instead".into())
+    };
+
+    let asset_name = format!("poarder-{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    info!("Downloading {}", asset_name);
+    let binary = client.get(release.asset_url(&asset_name)?).send().await?.error_for_status()?.bytes().await?;
+    let signature = client.get(release.asset_url(&format!("{}.minisig", asset_name))?).send().await?.error_for_status()?.text().await?;
+
+    let signature = Signature::decode(&signature)?;
+    public_key.verify(&binary, &signature, false)?;
+    // The trusted comment is covered by the signature too.
+    if !names_release(signature.trusted_comment(), latest, &asset_name) {
+        return Err(format!("the signature of {} is for \"{}\", not {} {}; refusing to install it",
+            asset_name, signature.trusted_comment(), latest, asset_name).into())
+    }
+    info!("Signature verified");
+
+    let exe = std::env::current_exe()?;
+    let staged = exe.with_extension("new");
+    fs::write(&staged, &binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&staged, &exe)?;
+
+    println!("Updated {} to {}", exe.display(), latest);
+    Ok(())
+}
+
+/// Whether the trusted comment of a signature names `version` (with or
+/// without a `v`) and `asset`, as words, or as minisign's own `file:<asset>`.
+fn names_release(trusted_comment: &str, version: &str, asset: &str) -> bool {
+    let words: Vec<&str> = trusted_comment.split_whitespace().collect();
+    let tagged = format!("v{}", version);
+    words.iter().any(|word| *word == version || *word == tagged)
+        && words.iter().any(|word| *word == asset || word.strip_prefix("file:") == Some(asset))
+}
+
+/// Compare dotted numeric versions; anything unparsable counts as zero.
+fn is_newer(candidate: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['.', '-', '+']).take(3).map(|p| p.parse().unwrap_or(0)).collect()
+    };
+    parse(candidate) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trusted_comments() {
+        assert!(names_release("poarder 1.4.0 poarder-x86_64-linux", "1.4.0", "poarder-x86_64-linux"));
+        assert!(names_release("timestamp:1700000000\tfile:poarder-x86_64-linux\tv1.4.0", "1.4.0", "poarder-x86_64-linux"));
+        assert!(!names_release("poarder 1.3.0 poarder-x86_64-linux", "1.4.0", "poarder-x86_64-linux"));
+        assert!(!names_release("poarder 1.4.0 poarder-aarch64-linux", "1.4.0", "poarder-x86_64-linux"));
+        assert!(!names_release("poarder 1.4.00 poarder-x86_64-linux.old", "1.4.0", "poarder-x86_64-linux"));
+        assert!(!names_release("", "1.4.0", "poarder-x86_64-linux"));
+    }
+
+    #[test]
+    fn versions() {
+        assert!(is_newer("1.4.0", "1.3.9"));
+        assert!(is_newer("1.10.0", "1.9.0"));
+        assert!(!is_newer("1.4.0", "1.4.0"));
+        assert!(!is_newer("1.3.0", "1.4.0"));
+    }
+}
@@ -0,0 +1,106 @@
+//! Checksums of the archive. Every download is hashed as it streams in and
+//! its SHA-256 kept in the episode database and the episode's sidecar;
+//! `state/SHA256SUMS` lists them all in `sha256sum` format, and
+//! `poarder verify` hashes the files again to find ones that went bad or
+//! went missing since.
+
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use crate::db::{EpisodeDb, Record};
+use crate::disk::Permissions;
+use crate::layout::FeedLayout;
+
+pub const MANIFEST_FILE: &str = "SHA256SUMS";
+
+/// Write `layout`'s manifest from the checksums recorded in `db`,
+/// for files relative to its episodes directory, so that
+/// `cd episodes && sha256sum -c ../state/SHA256SUMS` checks them too.
+pub fn write_manifest(layout: &FeedLayout, db: &EpisodeDb, permissions: &Permissions) -> Result<(), Box<dyn Error>> {
+    let mut manifest = String::new();
+    for record in db.records()? {
+        if let Some(sha256) = &record.sha256 {
+            manifest += &format!("{}  {}\n", sha256, record.file);
+        }
+    }
+    let path = layout.state_dir().join(MANIFEST_FILE);
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, manifest)?;
+    fs::rename(&tmp_path, &path)?;
+    permissions.apply_file(&path)?;
+    Ok(())
+}
+
+enum Problem {
+    Missing,
+    /// The file's SHA-256 is now `found`, not the one recorded.
+    Corrupted { found: String },
+    Unreadable(io::Error),
+}
+
+/// Hash every archived episode of each of `archives`, each given as its
+/// directories (the primary, then its mirrors), and report those missing or
+/// no longer matching their checksum. A mirror without an episode database
+/// is checked against its primary's.
+pub fn run(archives: &[Vec<String>]) -> Result<(), Box<dyn Error>> {
+    let mut problems = 0;
+    for output_dirs in archives {
+        let mut primary: Option<Vec<Record>> = None;
+        for root in output_dirs {
+            let layout = FeedLayout::new(Path::new(root));
+            let records = match layout.state_dir().join("episodes.db").exists() {
+                true => EpisodeDb::open(&layout.state_dir())?.records()?,
+                false => match &primary {
+                    Some(records) => records.clone(),
+                    None => return Err(format!("{} has no episode database; run poarder against it first", root).into())
+                }
+            };
+            problems += check_archive(&layout, &records);
+            primary.get_or_insert(records);
+        }
+    }
+
+    match problems {
+        0 => Ok(()),
+        n => Err(format!("{} file(s) missing or corrupted", n).into())
+    }
+}
+
+fn check_archive(layout: &FeedLayout, records: &[Record]) -> usize {
+    let episodes_dir = layout.episodes_dir();
+    let mut verified = 0;
+    let mut unchecked = 0;
+    let mut problems = Vec::new();
+    for record in records {
+        let Some(expected) = &record.sha256 else {
+            unchecked += 1;
+            continue
+        };
+        let path = episodes_dir.join(&record.file);
+        match sha256_file(&path) {
+            Ok(found) if found == *expected => verified += 1,
+            Ok(found) => problems.push((record, Problem::Corrupted { found })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => problems.push((record, Problem::Missing)),
+            Err(e) => problems.push((record, Problem::Unreadable(e))),
+        }
+    }
+
+    println!("{}: {} verified, {} missing or corrupted, {} without a checksum", layout.root().display(), verified, problems.len(), unchecked);
+    for (record, problem) in &problems {
+        match problem {
+            Problem::Missing => println!("  missing    {}", record.file),
+            Problem::Corrupted { found } => println!("  corrupted  {} (SHA-256 {}, recorded {})", record.file, found, record.sha256.as_deref().unwrap_or_default()),
+            Problem::Unreadable(e) => println!("  unreadable {} ({})", record.file, e),
+        }
+    }
+    problems.len()
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,136 @@
+//! Sandboxed filter and rename plugins compiled to WebAssembly.
+//!
+//! Modules run in an interpreter with no imports, so they cannot touch the
+//! filesystem or network. A module must export:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, returning a buffer poarder fills with the
+//!   episode metadata as JSON (the same shape external plugins receive)
+//!
+//! and, depending on its `kind`, one of:
+//!
+//! - `filter(ptr: i32, len: i32) -> i32`: non-zero keeps the episode
+//! - `rename(ptr: i32, len: i32) -> i64`: returns `(ptr << 32) | len` of a
+//!   UTF-8 replacement title in memory, or `0` to keep the current title
+
+use log::{error, info};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use wasmi::{Engine, Instance, Linker, Memory, Module, Store, TypedFunc};
+
+use crate::Episode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WasmKind {
+    Filter,
+    Rename,
+}
+
+/// A `[[wasm-plugins]]` entry in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WasmPluginConfig {
+    pub path: String,
+    pub kind: WasmKind,
+    /// Feed URLs the module applies to; every feed when omitted.
+    #[serde(default)]
+    pub feeds: Option<Vec<String>>,
+}
+
+struct WasmPlugin {
+    config: WasmPluginConfig,
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    instance: Instance,
+}
+
+impl WasmPlugin {
+    fn load(engine: &Engine, config: &WasmPluginConfig) -> Result<WasmPlugin, Box<dyn Error>> {
+        let bytes = fs::read(&config.path)?;
+        let module = Module::new(engine, &bytes[..])?;
+        let mut store = Store::new(engine, ());
+        let linker = <Linker<()>>::new(engine);
+        let instance = linker.instantiate_and_start(&mut store, &module)?;
+
+        let memory = instance.get_memory(&store, "memory").ok_or("module does not export memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+
+        Ok(WasmPlugin { config: config.clone(), store, memory, alloc, instance })
+    }
+
+    fn applies_to(&self, feed_url: &str) -> bool {
+        match &self.config.feeds {
+            Some(feeds) => feeds.iter().any(|f| f == feed_url),
+            None => true
+        }
+    }
+
+    fn write_input(&mut self, episode: &Episode) -> Result<(i32, i32), Box<dyn Error>> {
+        let input = serde_json::to_vec(episode)?;
+        let len = i32::try_from(input.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory.write(&mut self.store, ptr as usize, &input)?;
+        Ok((ptr, len))
+    }
+
+    fn filter(&mut self, episode: &Episode) -> Result<bool, Box<dyn Error>> {
+        let func = self.instance.get_typed_func::<(i32, i32), i32>(&self.store, "filter")?;
+        let (ptr, len) = self.write_input(episode)?;
+        Ok(func.call(&mut self.store, (ptr, len))? != 0)
+    }
+
+    fn rename(&mut self, episode: &Episode) -> Result<Option<String>, Box<dyn Error>> {
+        let func = self.instance.get_typed_func::<(i32, i32), i64>(&self.store, "rename")?;
+        let (ptr, len) = self.write_input(episode)?;
+        let packed = func.call(&mut self.store, (ptr, len))? as u64;
+        if packed == 0 {
+            return Ok(None)
+        }
+
+        let mut title = vec![0u8; (packed & 0xffff_ffff) as usize];
+        self.memory.read(&self.store, (packed >> 32) as usize, &mut title)?;
+        Ok(Some(String::from_utf8(title)?))
+    }
+}
+
+pub struct WasmHost {
+    plugins: Vec<WasmPlugin>,
+}
+
+impl WasmHost {
+    pub fn load(configs: &[WasmPluginConfig]) -> Result<WasmHost, Box<dyn Error>> {
+        let engine = Engine::default();
+        let mut plugins = Vec::new();
+
+        for config in configs {
+            info!("Loading WASM {:?} module {}", config.kind, config.path);
+            plugins.push(WasmPlugin::load(&engine, config)?);
+        }
+
+        Ok(WasmHost { plugins })
+    }
+
+    /// Run the applicable modules over `episode`. Returns false if a filter
+    /// rejected it. A failing module is logged and treated as a no-op.
+    pub fn apply(&mut self, feed_url: &str, episode: &mut Episode) -> bool {
+        for plugin in self.plugins.iter_mut().filter(|p| p.applies_to(feed_url)) {
+            match plugin.config.kind {
+                WasmKind::Filter => match plugin.filter(episode) {
+                    Ok(true) => (),
+                    Ok(false) => return false,
+                    Err(e) => error!("WASM filter {} failed. Error: {}", plugin.config.path, e)
+                },
+                WasmKind::Rename => match plugin.rename(episode) {
+                    Ok(Some(title)) => episode.title = title,
+                    Ok(None) => (),
+                    Err(e) => error!("WASM renamer {} failed. Error: {}", plugin.config.path, e)
+                },
+            }
+        }
+
+        true
+    }
+}
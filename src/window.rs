@@ -0,0 +1,94 @@
+//! `--since`, `--until` and `--latest`: which of a feed's episodes a run
+//! archives, for keeping only recent episodes or a stretch of history.
+
+use chrono::{DateTime, Duration, FixedOffset, NaiveDate, Utc};
+
+use crate::Episode;
+
+/// A `--since` or `--until` time: fixed, or relative to the run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bound {
+    At(DateTime<Utc>),
+    Ago(Duration),
+}
+
+impl Bound {
+    fn at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Bound::At(at) => *at,
+            Bound::Ago(ago) => now - *ago,
+        }
+    }
+}
+
+/// Parse `--since`: RFC 3339, a date meaning its start (UTC), or an age
+/// such as `30d`.
+pub fn parse_since(text: &str) -> Result<Bound, String> {
+    parse_bound(text, false)
+}
+
+/// Parse `--until`: RFC 3339, a date meaning its end (UTC), or an age such
+/// as `1w`.
+pub fn parse_until(text: &str) -> Result<Bound, String> {
+    parse_bound(text, true)
+}
+
+fn parse_bound(text: &str, end_of_day: bool) -> Result<Bound, String> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(Bound::At(datetime.with_timezone(&Utc)))
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        let date = if end_of_day { date.succ_opt().unwrap_or(date) } else { date };
+        return Ok(Bound::At(date.and_hms_opt(0, 0, 0).unwrap().and_utc()))
+    }
+    match crate::parse_age(text).ok().and_then(|age| Duration::from_std(age).ok()) {
+        Some(age) => Ok(Bound::Ago(age)),
+        None => Err(format!("expected YYYY-MM-DD, an RFC 3339 time or an age such as 30d, got {}", text))
+    }
+}
+
+/// The episodes a run archives.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Window {
+    pub since: Option<Bound>,
+    pub until: Option<Bound>,
+    pub latest: Option<usize>,
+}
+
+impl Window {
+    pub fn is_set(&self) -> bool {
+        *self != Window::default()
+    }
+
+    /// For each of the episodes published at `published`, why it is
+    /// outside the window at `now`, if it is.
+    pub fn exclusions(&self, published: &[DateTime<FixedOffset>], now: DateTime<Utc>) -> Vec<Option<&'static str>> {
+        let since = self.since.map(|since| since.at(now));
+        let until = self.until.map(|until| until.at(now));
+        let mut exclusions: Vec<Option<&'static str>> = published.iter()
+            .map(|published| match (since, until) {
+                (Some(since), _) if *published < since => Some("published before --since"),
+                (_, Some(until)) if *published >= until => Some("published after --until"),
+                _ => None
+            })
+            .collect();
+
+        if let Some(latest) = self.latest {
+            let mut newest: Vec<usize> = (0..published.len()).filter(|i| exclusions[*i].is_none()).collect();
+            newest.sort_by_key(|i| std::cmp::Reverse(published[*i]));
+            for i in newest.into_iter().skip(latest) {
+                exclusions[i] = Some("not among the --latest");
+            }
+        }
+        exclusions
+    }
+
+    /// `episodes` without those outside the window at `now`.
+    pub fn retain(&self, episodes: Vec<Episode>, now: DateTime<Utc>) -> Vec<Episode> {
+        let published: Vec<DateTime<FixedOffset>> = episodes.iter().map(|episode| episode.published).collect();
+        episodes.into_iter()
+            .zip(self.exclusions(&published, now))
+            .filter_map(|(episode, exclusion)| exclusion.is_none().then_some(episode))
+            .collect()
+    }
+}